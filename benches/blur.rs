@@ -0,0 +1,87 @@
+use std::time::{Duration, Instant};
+
+use shade::{Canvas, Pixel};
+
+const RUNS: u32 = 5;
+
+// A 4K buffer with some structure, so the blur isn't averaging a flat color
+fn canvas() -> Canvas {
+    let (width, height) = (3840, 2160);
+    let mut canvas = Canvas::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            canvas.set_pixel(x, y, Pixel::new(x as u8, y as u8, (x ^ y) as u8));
+        }
+    }
+
+    canvas
+}
+
+// Canvas::blur as it was before the vertical pass went over column strips: the same kernel and
+// clamped edges, but walking one column at a time, so a cache line per tap
+fn column_blur(canvas: &mut Canvas, sigma: f32) {
+    let radius = (sigma * 3.0).ceil() as isize;
+    let mut kernel: Vec<f32> = (-radius..=radius)
+        .map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|k| *k /= sum);
+
+    let convolve = |center: usize, len: usize, sample: &dyn Fn(usize) -> [f32; 3]| {
+        let mut acc = [0f32; 3];
+        for (k, weight) in kernel.iter().enumerate() {
+            let value = sample((center + k).saturating_sub(kernel.len() / 2).min(len - 1));
+            acc[0] += value[0] * weight;
+            acc[1] += value[1] * weight;
+            acc[2] += value[2] * weight;
+        }
+        acc
+    };
+
+    let (width, height) = (canvas.width() as usize, canvas.height() as usize);
+    let mut scratch = vec![[0f32; 3]; canvas.len()];
+
+    for y in 0..height {
+        let row = &canvas[y * width..(y + 1) * width];
+        for x in 0..width {
+            scratch[y * width + x] = convolve(x, width, &|i| {
+                [row[i].r as f32, row[i].g as f32, row[i].b as f32]
+            });
+        }
+    }
+
+    for x in 0..width {
+        for y in 0..height {
+            let [r, g, b] = convolve(y, height, &|i| scratch[i * width + x])
+                .map(|v| v.round().clamp(0.0, 255.0) as u8);
+            canvas[y * width + x] = Pixel::new(r, g, b);
+        }
+    }
+}
+
+fn time(source: &Canvas, blur: impl Fn(&mut Canvas)) -> (Duration, Canvas) {
+    let mut total = Duration::ZERO;
+    let mut canvas = source.clone();
+
+    for _ in 0..RUNS {
+        canvas = source.clone();
+        let start = Instant::now();
+        blur(&mut canvas);
+        total += start.elapsed();
+    }
+
+    (total / RUNS, canvas)
+}
+
+fn main() {
+    let source = canvas();
+
+    for sigma in [2.0, 8.0] {
+        let (strips, blurred) = time(&source, |canvas| canvas.blur(sigma));
+        let (columns, reference) = time(&source, |canvas| column_blur(canvas, sigma));
+        assert!(blurred[..] == reference[..], "strips changed the output");
+
+        println!("blur 3840x2160 sigma {sigma}: {strips:?} per run, {columns:?} column by column");
+    }
+}
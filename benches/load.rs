@@ -0,0 +1,101 @@
+use std::{
+    path::Path,
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use shade::{LoadConfig, OpenMethod, Pixel, ScalingMethod, WallpaperSource};
+
+const RUNS: u32 = 20;
+
+// What a solid color should take from nothing to on screen, on a local server
+const TARGET: Duration = Duration::from_millis(50);
+
+// A display number unlikely to be taken, the server gets a 4K screen
+const XVFB_DISPLAY: u32 = 97;
+
+// Stops the server again however the benchmark ends
+struct Xvfb(Child);
+
+impl Drop for Xvfb {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+// Runs against a fresh Xvfb, so the first load really is a cold one. SHADE_BENCH_DISPLAY=1 uses
+// $DISPLAY instead, e.g. to measure a real server
+fn start_server() -> Option<Xvfb> {
+    if std::env::var_os("SHADE_BENCH_DISPLAY").is_some() {
+        return None;
+    }
+
+    let display = format!(":{XVFB_DISPLAY}");
+    let child = Command::new("Xvfb")
+        .args([&display, "-screen", "0", "3840x2160x24", "-nolisten", "tcp"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let server = match child {
+        Ok(child) => Xvfb(child),
+        Err(e) => {
+            eprintln!("Xvfb could not be started ({e}), set SHADE_BENCH_DISPLAY=1 to use $DISPLAY");
+            std::process::exit(0);
+        }
+    };
+
+    let socket = format!("/tmp/.X11-unix/X{XVFB_DISPLAY}");
+    let start = Instant::now();
+    while !Path::new(&socket).exists() {
+        if start.elapsed() > Duration::from_secs(5) {
+            eprintln!("Xvfb did not come up within 5s");
+            drop(server);
+            std::process::exit(1);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    std::env::set_var("DISPLAY", display);
+    Some(server)
+}
+
+fn set_color(color: Pixel) -> Duration {
+    let start = Instant::now();
+    shade::set_once(WallpaperSource::Color(color), ScalingMethod::Fill)
+        .expect("setting a solid color");
+    start.elapsed()
+}
+
+fn main() {
+    let _server = start_server();
+
+    let cold = set_color(Pixel::new(40, 40, 40));
+
+    let mut total = Duration::ZERO;
+    let mut worst = Duration::ZERO;
+    for run in 0..RUNS {
+        let took = set_color(Pixel::new(run as u8, 40, 40));
+        total += took;
+        worst = worst.max(took);
+    }
+
+    println!("solid color, cold: {cold:?}");
+    println!(
+        "solid color, warm: {:?} per run, {worst:?} at worst (target {TARGET:?})",
+        total / RUNS
+    );
+
+    // Loaded last, the handle keeps the wallpaper owned for as long as the process lives
+    let (_, report) = shade::load_with_report(
+        OpenMethod::MakeNew,
+        LoadConfig::default().clear_color(Pixel::new(40, 40, 40)),
+    )
+    .expect("loading a handle");
+
+    println!("phases of a warm load:");
+    for (phase, took) in &report.timings {
+        println!("  {phase}: {took:?}");
+    }
+}
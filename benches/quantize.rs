@@ -0,0 +1,44 @@
+use std::time::{Duration, Instant};
+
+use shade::{Canvas, Pixel};
+
+const RUNS: u32 = 5;
+
+// What quantizing a 1080p wallpaper to a small palette should take, dithered or not
+const TARGET: Duration = Duration::from_millis(100);
+
+// A 1080p buffer of gradients, so the dithering has error to carry
+fn canvas() -> Canvas {
+    let (width, height) = (1920, 1080);
+    let mut canvas = Canvas::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            canvas.set_pixel(x, y, Pixel::new(x as u8, y as u8, (x ^ y) as u8));
+        }
+    }
+
+    canvas
+}
+
+fn main() {
+    let source = canvas();
+    let palette = source.dominant_colors(16);
+
+    for dither in [false, true] {
+        let mut total = Duration::ZERO;
+
+        for _ in 0..RUNS {
+            let mut canvas = source.clone();
+            let start = Instant::now();
+            canvas.quantize(&palette, dither);
+            total += start.elapsed();
+        }
+
+        println!(
+            "quantize 1920x1080 to {} colors, dither {dither}: {:?} per run (target {TARGET:?})",
+            palette.len(),
+            total / RUNS
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Draws an analog clock in the middle of the wallpaper, redrawing it every second.
+//!
+//! Runs for the number of seconds given as its first argument, 60 by default. Shows UTC, as the
+//! standard library knows nothing about time zones. Stops ticking while a fullscreen window
+//! covers it.
+
+use std::{
+    env, thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use shade::{Canvas, FrameBudget, OpenMethod, PausePolicy, Pixel};
+
+const BACKGROUND: Pixel = Pixel {
+    r: 24,
+    g: 26,
+    b: 33,
+};
+const FACE: Pixel = Pixel {
+    r: 40,
+    g: 44,
+    b: 52,
+};
+const INK: Pixel = Pixel {
+    r: 220,
+    g: 223,
+    b: 228,
+};
+const ACCENT: Pixel = Pixel {
+    r: 224,
+    g: 108,
+    b: 117,
+};
+
+fn main() -> shade::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let seconds: u64 = env::args()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(60);
+
+    let handle = shade::load(OpenMethod::MakeNew)?;
+
+    let (face, x, y, radius) = {
+        let mut buffer = handle.buffer.lock().unwrap();
+        let (width, height) = (buffer.width(), buffer.height());
+        let radius = width.min(height) / 4;
+        let (cx, cy) = ((width / 2) as i32, (height / 2) as i32);
+
+        buffer.fill(BACKGROUND);
+        buffer.fill_circle(cx, cy, radius, FACE);
+        buffer.draw_circle(cx, cy, radius, radius / 40 + 1, INK);
+
+        for hour in 0..12 {
+            let (sx, sy) = hand(cx, cy, radius as f32 * 0.85, hour as f32 / 12.0);
+            let (ex, ey) = hand(cx, cy, radius as f32 * 0.95, hour as f32 / 12.0);
+            buffer.draw_line((sx, sy), (ex, ey), radius / 60 + 1, INK);
+        }
+
+        // Only the face changes from then on, so only the face needs to be kept and flushed
+        let (x, y) = (cx as u32 - radius, cy as u32 - radius);
+        (
+            buffer.crop(x, y, radius * 2 + 1, radius * 2 + 1),
+            x,
+            y,
+            radius,
+        )
+    };
+
+    handle.flush()?;
+
+    let policy = PausePolicy::new();
+    let mut budget = FrameBudget::new(1.0).log_overruns(true);
+
+    for _ in 0..seconds {
+        if handle.should_pause(&policy)? {
+            thread::sleep(Duration::from_millis(1000 - now_millis()));
+            continue;
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        budget.time(|| {
+            {
+                let mut buffer = handle.buffer.lock().unwrap();
+                draw_hands(&mut buffer, &face, x, y, radius, now % (12 * 3600));
+            }
+
+            let size = face.width() as u16;
+            handle.flush_region(x as u16, y as u16, size, size)
+        })?;
+
+        thread::sleep(Duration::from_millis(1000 - now_millis()));
+    }
+
+    Ok(())
+}
+
+fn draw_hands(buffer: &mut Canvas, face: &Canvas, x: u32, y: u32, radius: u32, seconds: u64) {
+    let (cx, cy) = ((x + radius) as i32, (y + radius) as i32);
+    let radius = radius as f32;
+
+    buffer.draw_canvas(face, x as i32, y as i32);
+
+    let hours = seconds as f32 / (12.0 * 3600.0);
+    let minutes = (seconds % 3600) as f32 / 3600.0;
+    let secs = (seconds % 60) as f32 / 60.0;
+
+    let width = (radius / 30.0) as u32 + 1;
+    buffer.draw_line((cx, cy), hand(cx, cy, radius * 0.5, hours), width * 2, INK);
+    buffer.draw_line((cx, cy), hand(cx, cy, radius * 0.75, minutes), width, INK);
+    buffer.draw_line((cx, cy), hand(cx, cy, radius * 0.8, secs), 1, ACCENT);
+    buffer.fill_circle(cx, cy, width * 2, ACCENT);
+}
+
+// End point of a hand of the given length, `turn` being the fraction of a full turn from 12
+fn hand(cx: i32, cy: i32, length: f32, turn: f32) -> (i32, i32) {
+    let angle = turn * std::f32::consts::TAU;
+    (
+        cx + (length * angle.sin()).round() as i32,
+        cy - (length * angle.cos()).round() as i32,
+    )
+}
+
+// Sleep until the next full second rather than a second from now, so the hand doesn't drift
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_millis() as u64
+}
@@ -0,0 +1,53 @@
+//! Prints what shade finds out about the X server: the capabilities probe, followed by the self
+//! test unless `--no-self-test` is given. Neither changes the wallpaper.
+//!
+//! With `--json` both go out as a single JSON object on one line, `{"capabilities": ...,
+//! "self_test": ...}`, as `shade::ProbeReport::to_json` writes it. That is what bug reports
+//! should include:
+//!
+//! ```text
+//! cargo run --example probe -- --json
+//! ```
+
+use std::{env, process::ExitCode};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let json = args.iter().any(|a| a == "--json");
+    let self_test = !args.iter().any(|a| a == "--no-self-test");
+
+    if !json {
+        tracing_subscriber::fmt::init();
+    }
+
+    match probe(json, self_test) {
+        Ok(output) => {
+            println!("{output}");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("probe failed: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn probe(json: bool, self_test: bool) -> shade::Result<String> {
+    let report = shade::ProbeReport {
+        capabilities: shade::capabilities()?,
+        self_test: self_test.then(shade::self_test).transpose()?,
+    };
+
+    if json {
+        return Ok(report.to_json());
+    }
+
+    let mut output = report.capabilities.to_string();
+    if let Err(e) = report.capabilities.check() {
+        output += &format!("\n\nshade cannot load on this server: {e}");
+    }
+    if let Some(self_test) = report.self_test {
+        output += &format!("\n\n{self_test}");
+    }
+    Ok(output)
+}
@@ -0,0 +1,61 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use shade::encode::{chunk_plan, ByteOrder, ServerFormat};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    bytes_per_pixel: u8,
+    scanline_pad: u8,
+    width: u16,
+    height: u16,
+    max_bytes: u32,
+}
+
+fuzz_target!(|input: Input| {
+    // Whole bytes per pixel and the pads servers announce, on a root small enough to plan fast
+    let format = ServerFormat {
+        depth: 24,
+        bits_per_pixel: (input.bytes_per_pixel % 4 + 1) * 8,
+        scanline_pad: [8, 16, 32][input.scanline_pad as usize % 3],
+        byte_order: ByteOrder::LsbFirst,
+        red_mask: 0xff0000,
+        green_mask: 0x00ff00,
+        blue_mask: 0x0000ff,
+    };
+    let (width, height) = (input.width % 4097, input.height % 257);
+    let max_bytes = input.max_bytes as usize;
+
+    let row_bytes = format.row_bytes(width as u32);
+    let Some(chunks) = chunk_plan(&format, width, height, max_bytes) else {
+        // Only refused where not even a single pad unit of a row fits
+        assert!(row_bytes > max_bytes);
+        return;
+    };
+
+    // The chunks follow each other without gaps or overlaps, and cover all of the data
+    let mut end = 0;
+    for chunk in &chunks {
+        assert_eq!(
+            chunk.bytes.start, end,
+            "{chunk:?} does not start where the last ended"
+        );
+        assert!(chunk.bytes.len() <= max_bytes, "{chunk:?} is too long");
+        assert!(chunk.rows >= 1 && chunk.y + chunk.rows <= height);
+        assert!(chunk.x + chunk.width <= width);
+
+        // Every chunk starts where its first pixel is
+        let start = chunk.y as usize * row_bytes + chunk.x as usize * format.bytes_per_pixel();
+        assert_eq!(chunk.bytes.start, start);
+
+        // Runs within a row are sent without a pad of their own, so they must end on one
+        if chunk.x + chunk.width < width {
+            let pad = format.scanline_pad as usize / 8;
+            assert_eq!(chunk.rows, 1);
+            assert_eq!(chunk.bytes.len() % pad, 0);
+        }
+        end = chunk.bytes.end;
+    }
+    assert_eq!(end, row_bytes * height as usize);
+});
@@ -0,0 +1,35 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use shade::x11::{parse_pixmap_property, PixmapProperty};
+
+// The PIXMAP type atom, predefined by the core protocol
+const ATOM_PIXMAP: u32 = 20;
+
+#[derive(Arbitrary, Debug)]
+struct Input<'a> {
+    pixmap: bool,
+    r#type: u32,
+    format: u8,
+    value: &'a [u8],
+}
+
+fuzz_target!(|input: Input| {
+    // Mostly pixmaps, anything else is turned away before the value is looked at
+    let r#type = if input.pixmap {
+        ATOM_PIXMAP
+    } else {
+        input.r#type
+    };
+
+    match parse_pixmap_property(r#type, input.format, input.value) {
+        PixmapProperty::Pixmap(id) => {
+            assert_eq!(r#type, ATOM_PIXMAP);
+            assert_eq!(id.to_ne_bytes(), input.value[..4]);
+        }
+        PixmapProperty::Malformed => assert_eq!(r#type, ATOM_PIXMAP),
+        PixmapProperty::NotAPixmap(found) => assert_eq!(found, r#type),
+        PixmapProperty::Unset => assert_eq!(r#type, 0),
+    }
+});
@@ -0,0 +1,61 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use shade::{compute_placement_with_aspect, PixelAspect, Rect, ScalingMethod};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    src_w: u32,
+    src_h: u32,
+    dst_w: u32,
+    dst_h: u32,
+    method: u8,
+    aspect_w: u32,
+    aspect_h: u32,
+}
+
+fn within(rect: &Rect, width: u32, height: u32) -> bool {
+    rect.x >= 0
+        && rect.y >= 0
+        && rect.x as u64 + rect.width as u64 <= width as u64
+        && rect.y as u64 + rect.height as u64 <= height as u64
+}
+
+fuzz_target!(|input: Input| {
+    let method = [
+        ScalingMethod::Center,
+        ScalingMethod::Fill,
+        ScalingMethod::Max,
+        ScalingMethod::Scale,
+        ScalingMethod::Tile,
+    ][input.method as usize % 5];
+    let aspect = PixelAspect::new(input.aspect_w, input.aspect_h);
+
+    let (crop, dst) = compute_placement_with_aspect(
+        input.src_w,
+        input.src_h,
+        input.dst_w,
+        input.dst_h,
+        method,
+        aspect,
+    );
+
+    if [input.src_w, input.src_h, input.dst_w, input.dst_h].contains(&0) {
+        assert_eq!((crop, dst), (Rect::default(), Rect::default()));
+        return;
+    }
+
+    // Both rectangles lie within their image and canvas, and neither is ever empty
+    assert!(
+        within(&crop, input.src_w, input.src_h),
+        "{input:?}: {crop:?}"
+    );
+    assert!(within(&dst, input.dst_w, input.dst_h), "{input:?}: {dst:?}");
+    assert!(crop.width > 0 && crop.height > 0, "{input:?}: {crop:?}");
+    assert!(dst.width > 0 && dst.height > 0, "{input:?}: {dst:?}");
+
+    if method == ScalingMethod::Fill {
+        assert_eq!(dst, Rect::new(0, 0, input.dst_w, input.dst_h));
+    }
+});
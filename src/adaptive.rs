@@ -0,0 +1,513 @@
+//! Tints the wallpaper towards the color of the focused window. Only built with the `adaptive`
+//! feature.
+//!
+//! [`run`] does everything, [`run_until`] until cancelled. Programs that learn about focus
+//! changes some other way can drive an [`AdaptiveTint`] from their own loop instead, and the
+//! [`Debounce`] deciding when a change has settled knows nothing about X at all.
+
+use std::time::{Duration, Instant};
+
+use xcb::{
+    x::{self, Atom, GetProperty, InternAtom, Window, ATOM_CARDINAL, ATOM_WINDOW, ATOM_WM_CLASS},
+    Connection, XidNew,
+};
+
+use crate::{BackgroundHandle, CancelToken, Canvas, Error, Pixel, Result, ShadeEvent};
+
+// How long to sleep between polls for events while a change is waiting to settle
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Enough for a 128x128 icon and its size, larger first icons are sampled from their top rows
+const MAX_ICON_LENGTH: u32 = 2 + 128 * 128;
+
+// Window titles and classes past this are not worth matching against, in 4 byte units
+const MAX_NAME_LENGTH: u32 = 256;
+
+/// Holds on to the latest of a burst of values until none has come in for a while.
+///
+/// Every [`push`](Debounce::push) restarts the wait, so alt-tabbing through a dozen windows
+/// settles on the last one rather than tinting towards each along the way.
+#[derive(Clone, Debug)]
+pub struct Debounce<T> {
+    delay: Duration,
+    pending: Option<(Instant, T)>,
+}
+
+impl<T> Debounce<T> {
+    pub fn new(delay: Duration) -> Debounce<T> {
+        Debounce {
+            delay,
+            pending: None,
+        }
+    }
+
+    /// Notes down `value` as of `now`, replacing whatever was still waiting.
+    pub fn push(&mut self, value: T, now: Instant) {
+        self.pending = Some((now + self.delay, value));
+    }
+
+    /// When the waiting value is due, `None` if there is none.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending.as_ref().map(|(due, _)| *due)
+    }
+
+    /// Takes the waiting value once nothing new came in for the delay.
+    pub fn poll(&mut self, now: Instant) -> Option<T> {
+        match self.deadline() {
+            Some(due) if due <= now => self.pending.take().map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+/// Options for [`run`] and [`AdaptiveTint`].
+#[derive(Clone, Debug)]
+pub struct AdaptiveOptions {
+    pub(crate) strength: f32,
+    pub(crate) debounce: Duration,
+    pub(crate) classes: Vec<(String, Pixel)>,
+    pub(crate) titles: Vec<(String, Pixel)>,
+    pub(crate) icon_colors: bool,
+}
+
+impl Default for AdaptiveOptions {
+    fn default() -> AdaptiveOptions {
+        AdaptiveOptions {
+            strength: 0.2,
+            debounce: Duration::from_millis(300),
+            classes: Vec::new(),
+            titles: Vec::new(),
+            icon_colors: true,
+        }
+    }
+}
+
+impl AdaptiveOptions {
+    pub fn new() -> AdaptiveOptions {
+        AdaptiveOptions::default()
+    }
+
+    /// How far to tint towards the window's color, as the opacity passed to [`Canvas::tint`].
+    /// 0.2 by default.
+    pub fn strength(mut self, strength: f32) -> AdaptiveOptions {
+        self.strength = strength.clamp(0.0, 1.0);
+        self
+    }
+
+    /// How long focus has to stay put before the wallpaper follows, 300ms by default.
+    pub fn debounce(mut self, delay: Duration) -> AdaptiveOptions {
+        self.debounce = delay;
+        self
+    }
+
+    /// Tints towards `color` for windows whose `WM_CLASS` instance or class name is `class`.
+    /// Checked first, in the order added.
+    pub fn class_color(mut self, class: impl Into<String>, color: Pixel) -> AdaptiveOptions {
+        self.classes.push((class.into(), color));
+        self
+    }
+
+    /// Tints towards `color` for windows whose `_NET_WM_NAME` contains `text`. Checked after the
+    /// classes, in the order added.
+    pub fn title_color(mut self, text: impl Into<String>, color: Pixel) -> AdaptiveOptions {
+        self.titles.push((text.into(), color));
+        self
+    }
+
+    /// Whether windows no rule matches are tinted towards the dominant color of their
+    /// `_NET_WM_ICON`, on by default. Otherwise they leave the wallpaper untinted.
+    pub fn icon_colors(mut self, enabled: bool) -> AdaptiveOptions {
+        self.icon_colors = enabled;
+        self
+    }
+}
+
+/// Re-tints the wallpaper as the focused window's color changes, without caring where the changes
+/// come from.
+///
+/// Tints always start over from the wallpaper as it was when the `AdaptiveTint` was made, so they
+/// never pile up.
+pub struct AdaptiveTint<'a> {
+    handle: &'a BackgroundHandle,
+    original: Canvas,
+    strength: f32,
+    debounce: Debounce<Option<Pixel>>,
+    shown: Option<Pixel>,
+}
+
+impl<'a> AdaptiveTint<'a> {
+    pub fn new(handle: &'a BackgroundHandle, options: &AdaptiveOptions) -> AdaptiveTint<'a> {
+        AdaptiveTint {
+            handle,
+            original: handle.lock_buffer().clone(),
+            strength: options.strength,
+            debounce: Debounce::new(options.debounce),
+            shown: None,
+        }
+    }
+
+    /// Notes the color of the newly focused window, `None` for one without any or for no window
+    /// at all. Nothing is drawn until [`tick`](AdaptiveTint::tick) finds it has settled.
+    pub fn focus_changed(&mut self, color: Option<Pixel>, now: Instant) {
+        self.debounce.push(color, now);
+    }
+
+    /// When [`tick`](AdaptiveTint::tick) next has something to do, `None` if nothing is waiting.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.debounce.deadline()
+    }
+
+    /// Tints and flushes once the last focus change has settled. Returns whether it did, which it
+    /// also skips if the color is the one already shown.
+    ///
+    /// Fails with [`Error::StaleSnapshot`] if the buffer has been replaced by one of another size
+    /// since the `AdaptiveTint` was made.
+    pub fn tick(&mut self, now: Instant) -> Result<bool> {
+        let Some(color) = self.debounce.poll(now) else {
+            return Ok(false);
+        };
+        if color == self.shown {
+            return Ok(false);
+        }
+
+        {
+            let mut buffer = self.handle.lock_buffer();
+
+            let taken = (self.original.width(), self.original.height());
+            let now = (buffer.width(), buffer.height());
+            if now != taken {
+                return Err(Error::StaleSnapshot { taken, now });
+            }
+
+            buffer.clone_from_slice(&self.original);
+            if let Some(color) = &color {
+                buffer.tint(color, self.strength);
+            }
+        }
+
+        debug!("Tinting towards {color:?}");
+        self.handle.flush()?;
+        self.shown = color;
+        Ok(true)
+    }
+}
+
+// The atoms of the properties read off the root and the focused window
+struct Atoms {
+    active_window: Atom,
+    wm_name: Atom,
+    wm_icon: Atom,
+    utf8_string: Atom,
+}
+
+impl Atoms {
+    fn intern(connection: &Connection) -> Result<Atoms> {
+        let intern = |name: &[u8]| -> Result<Atom> {
+            let cookie = connection.send_request(&InternAtom {
+                name,
+                only_if_exists: false,
+            });
+            Ok(connection.wait_for_reply(cookie)?.atom())
+        };
+
+        Ok(Atoms {
+            active_window: intern(b"_NET_ACTIVE_WINDOW")?,
+            wm_name: intern(b"_NET_WM_NAME")?,
+            wm_icon: intern(b"_NET_WM_ICON")?,
+            utf8_string: intern(b"UTF8_STRING")?,
+        })
+    }
+}
+
+/// Follows the focused window on `handle`, tinting the wallpaper it shows now towards each
+/// window's color as picked by `options`. Blocks for as long as the connection lives, so run it
+/// on a thread of its own.
+///
+/// Needs a window manager setting `_NET_ACTIVE_WINDOW`. Windows closing while being looked at
+/// count as having no color, leaving the wallpaper untinted.
+pub fn run(handle: &BackgroundHandle, options: &AdaptiveOptions) -> Result<()> {
+    follow(handle, options, None)
+}
+
+/// [`run`], returning once `cancel` is cancelled. The tint is left as far as it got, a whole
+/// frame of it.
+pub fn run_until(
+    handle: &BackgroundHandle,
+    options: &AdaptiveOptions,
+    cancel: &CancelToken,
+) -> Result<()> {
+    follow(handle, options, Some(cancel))
+}
+
+fn follow(
+    handle: &BackgroundHandle,
+    options: &AdaptiveOptions,
+    cancel: Option<&CancelToken>,
+) -> Result<()> {
+    handle.select_events()?;
+
+    let atoms = Atoms::intern(&handle.connection)?;
+    let mut tint = AdaptiveTint::new(handle, options);
+
+    let color = focused_color(handle, &atoms, options)?;
+    tint.focus_changed(color, Instant::now());
+
+    loop {
+        if cancel.is_some_and(CancelToken::is_cancelled) {
+            return Ok(());
+        }
+        if let Err(e) = tint.tick(Instant::now()) {
+            warn!("Failed to tint the wallpaper: {e}");
+        }
+
+        // Blocks outright when nothing is waiting to settle, xcb has no wait with a timeout
+        let events = match (tint.deadline(), cancel) {
+            (None, None) => handle.wait_events()?,
+            (None, Some(cancel)) => match handle.wait_events_until(cancel)? {
+                Some(events) => events,
+                None => return Ok(()),
+            },
+            (Some(_), _) => {
+                let events = handle.process_pending_events()?;
+                if events.is_empty() {
+                    match cancel {
+                        Some(cancel) => {
+                            cancel.wait_timeout(POLL_INTERVAL);
+                        }
+                        None => std::thread::sleep(POLL_INTERVAL),
+                    }
+                }
+                events
+            }
+        };
+
+        for event in events {
+            match event {
+                ShadeEvent::PropertyChanged { atom, .. } if atom == atoms.active_window => {
+                    let color = focused_color(handle, &atoms, options)?;
+                    tint.focus_changed(color, Instant::now());
+                }
+                ShadeEvent::RequestFailed(e) => warn!("Request failed: {e:?}"),
+                _ => {}
+            }
+        }
+    }
+}
+
+// The color `options` pick for the window `_NET_ACTIVE_WINDOW` names, if any
+fn focused_color(
+    handle: &BackgroundHandle,
+    atoms: &Atoms,
+    options: &AdaptiveOptions,
+) -> Result<Option<Pixel>> {
+    let connection = &handle.connection;
+
+    let active = read_property(connection, handle.root, atoms.active_window, ATOM_WINDOW, 1)?;
+    let window = match active.as_ref().and_then(|p| p.value::<u32>().first()) {
+        Some(&id) if id != 0 => {
+            // SAFETY: Only ever used to read properties off, which the server validates
+            unsafe { Window::new(id) }
+        }
+        _ => return Ok(None),
+    };
+
+    if !options.classes.is_empty() {
+        let class = read_property(
+            connection,
+            window,
+            ATOM_WM_CLASS,
+            x::ATOM_STRING,
+            MAX_NAME_LENGTH,
+        )?;
+        // Instance and class name, each terminated by a NUL
+        let names: Vec<&[u8]> = match &class {
+            Some(class) if class.format() == 8 => class.value::<u8>().split(|&b| b == 0).collect(),
+            _ => Vec::new(),
+        };
+
+        let matched = options
+            .classes
+            .iter()
+            .find(|(class, _)| names.contains(&class.as_bytes()));
+        if let Some((_, color)) = matched {
+            return Ok(Some(color.clone()));
+        }
+    }
+
+    if !options.titles.is_empty() {
+        let name = read_property(
+            connection,
+            window,
+            atoms.wm_name,
+            atoms.utf8_string,
+            MAX_NAME_LENGTH,
+        )?;
+        let title = match &name {
+            Some(name) if name.format() == 8 => String::from_utf8_lossy(name.value::<u8>()),
+            _ => Default::default(),
+        };
+
+        let matched = options
+            .titles
+            .iter()
+            .find(|(text, _)| title.contains(text.as_str()));
+        if let Some((_, color)) = matched {
+            return Ok(Some(color.clone()));
+        }
+    }
+
+    if !options.icon_colors {
+        return Ok(None);
+    }
+
+    let icon = read_property(
+        connection,
+        window,
+        atoms.wm_icon,
+        ATOM_CARDINAL,
+        MAX_ICON_LENGTH,
+    )?;
+    Ok(icon
+        .filter(|icon| icon.format() == 32)
+        .and_then(|icon| icon_color(icon.value::<u32>())))
+}
+
+// `None` for properties that are not set, of another type, or on a window that is gone already
+fn read_property(
+    connection: &Connection,
+    window: Window,
+    property: Atom,
+    r#type: Atom,
+    long_length: u32,
+) -> Result<Option<x::GetPropertyReply>> {
+    let cookie = connection.send_request(&GetProperty {
+        delete: false,
+        window,
+        property,
+        r#type,
+        long_offset: 0,
+        long_length,
+    });
+
+    match connection.wait_for_reply(cookie) {
+        Ok(reply) if reply.r#type() == r#type => Ok(Some(reply)),
+        Ok(_) => Ok(None),
+        Err(xcb::Error::Protocol(_)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// The dominant color of the first icon in a `_NET_WM_ICON`: width, height, then that many
+// non-premultiplied ARGB pixels, row by row
+fn icon_color(data: &[u32]) -> Option<Pixel> {
+    let [width, height, pixels @ ..] = data else {
+        return None;
+    };
+    let len = (*width as usize).saturating_mul(*height as usize);
+
+    dominant_color(pixels.get(..len).unwrap_or(pixels))
+}
+
+// Sorts opaque enough pixels into buckets of similar colors, counting colorful pixels for more so
+// a grey frame around a small logo does not drown it out, and averages the heaviest bucket
+fn dominant_color(argb: &[u32]) -> Option<Pixel> {
+    // 4 bits per channel, summed up as weight, r, g and b
+    let mut buckets = vec![[0u64; 4]; 1 << 12];
+
+    for &pixel in argb {
+        let [b, g, r, a] = pixel.to_le_bytes();
+        if a < 128 {
+            continue;
+        }
+
+        let chroma = r.max(g).max(b) - r.min(g).min(b);
+        let weight = chroma as u64 + 8;
+
+        let index = (r as usize >> 4) << 8 | (g as usize >> 4) << 4 | b as usize >> 4;
+        let bucket = &mut buckets[index];
+        bucket[0] += weight;
+        bucket[1] += r as u64 * weight;
+        bucket[2] += g as u64 * weight;
+        bucket[3] += b as u64 * weight;
+    }
+
+    let [weight, r, g, b] = buckets.into_iter().max_by_key(|bucket| bucket[0])?;
+    if weight == 0 {
+        return None;
+    }
+
+    Some(Pixel::new(
+        (r / weight) as u8,
+        (g / weight) as u8,
+        (b / weight) as u8,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELAY: Duration = Duration::from_millis(300);
+
+    #[test]
+    fn nothing_pushed_nothing_due() {
+        let mut debounce = Debounce::<u32>::new(DELAY);
+        assert_eq!(debounce.deadline(), None);
+        assert_eq!(debounce.poll(Instant::now() + DELAY * 10), None);
+    }
+
+    #[test]
+    fn held_for_the_delay() {
+        let start = Instant::now();
+        let mut debounce = Debounce::new(DELAY);
+        debounce.push(1, start);
+
+        assert_eq!(debounce.deadline(), Some(start + DELAY));
+        assert_eq!(debounce.poll(start), None);
+        assert_eq!(
+            debounce.poll(start + DELAY - Duration::from_millis(1)),
+            None
+        );
+        assert_eq!(debounce.poll(start + DELAY), Some(1));
+        // Taken, it does not come out twice
+        assert_eq!(debounce.deadline(), None);
+        assert_eq!(debounce.poll(start + DELAY * 2), None);
+    }
+
+    #[test]
+    fn a_burst_settles_on_its_last_value() {
+        let start = Instant::now();
+        let mut debounce = Debounce::new(DELAY);
+        // Faster than the delay, every push restarts the wait
+        let step = DELAY / 3;
+        for (i, value) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            let now = start + step * i as u32;
+            debounce.push(value, now);
+            assert_eq!(debounce.poll(now + step - Duration::from_millis(1)), None);
+        }
+
+        let last = start + step * 3;
+        assert_eq!(debounce.deadline(), Some(last + DELAY));
+        assert_eq!(debounce.poll(last + DELAY - Duration::from_millis(1)), None);
+        assert_eq!(debounce.poll(last + DELAY), Some("d"));
+    }
+
+    #[test]
+    fn late_polls_still_get_the_value() {
+        let start = Instant::now();
+        let mut debounce = Debounce::new(DELAY);
+        debounce.push(Some(Pixel::new(1, 2, 3)), start);
+        assert_eq!(
+            debounce.poll(start + DELAY * 100),
+            Some(Some(Pixel::new(1, 2, 3)))
+        );
+    }
+
+    #[test]
+    fn a_zero_delay_is_due_at_once() {
+        let now = Instant::now();
+        let mut debounce = Debounce::new(Duration::ZERO);
+        debounce.push(7, now);
+        assert_eq!(debounce.poll(now), Some(7));
+    }
+}
@@ -0,0 +1,70 @@
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+/// The longest anything taking a [`CancelToken`] takes to notice it was cancelled while idle.
+/// Sleeps between frames end the moment it is, but waiting for X events or for an
+/// [`Applier`](crate::Applier) submission checks back this often. Whatever frame or request is
+/// in flight is finished first either way, so nothing is left half drawn.
+pub const CANCEL_LATENCY: Duration = Duration::from_millis(50);
+
+/// Asks long running loops to stop: the [`Applier`](crate::Applier) and its transitions, and the
+/// watchers following desktops or the focused window. Cheap to clone, every clone cancels and
+/// sees the same token.
+///
+/// Cancelling cannot be taken back, start over with a new token.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    inner: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        *self.lock() = true;
+        self.inner.1.notify_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.lock()
+    }
+
+    /// Blocks until the token is cancelled.
+    pub fn wait(&self) {
+        let mut cancelled = self.lock();
+        while !*cancelled {
+            cancelled = self
+                .inner
+                .1
+                .wait(cancelled)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Sleeps for `timeout`, waking early if the token is cancelled meanwhile. Returns whether it
+    /// was.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut cancelled = self.lock();
+        while !*cancelled {
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            cancelled = self
+                .inner
+                .1
+                .wait_timeout(cancelled, left)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        *cancelled
+    }
+
+    fn lock(&self) -> MutexGuard<'_, bool> {
+        self.inner.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,138 @@
+use std::time::{Duration, Instant};
+
+/// Keeps count of animation frames that took longer than the frame rate allows.
+///
+/// There is no built in render loop, so the caller times each frame, drawing and flushing
+/// included, through [`FrameBudget::time`] or [`FrameBudget::record`]. A growing
+/// [`FrameBudget::over_budget`] count means the frame rate is too high for the machine.
+#[derive(Clone, Debug)]
+pub struct FrameBudget {
+    budget: Duration,
+    log_overruns: bool,
+    frames: u64,
+    over_budget: u64,
+    worst_overrun: Duration,
+}
+
+impl FrameBudget {
+    /// A budget of one `fps`th of a second per frame. A frame rate of 0 or less, or one that is not
+    /// a number, has no frame time to keep to, so no frame is ever over budget.
+    pub fn new(fps: f64) -> FrameBudget {
+        let budget = if fps > 0.0 {
+            Duration::try_from_secs_f64(1.0 / fps).unwrap_or(Duration::MAX)
+        } else {
+            Duration::MAX
+        };
+        FrameBudget::per_frame(budget)
+    }
+
+    pub fn per_frame(budget: Duration) -> FrameBudget {
+        FrameBudget {
+            budget,
+            log_overruns: false,
+            frames: 0,
+            over_budget: 0,
+            worst_overrun: Duration::ZERO,
+        }
+    }
+
+    /// Warns about every frame over budget along with by how much, off by default.
+    pub fn log_overruns(mut self, log_overruns: bool) -> FrameBudget {
+        self.log_overruns = log_overruns;
+        self
+    }
+
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    /// Changes the budget for frames recorded from now on, keeping the counts.
+    pub fn set_budget(&mut self, budget: Duration) {
+        self.budget = budget;
+    }
+
+    /// Runs one frame's worth of work and records how long it took.
+    pub fn time<T>(&mut self, frame: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = frame();
+        self.record(start.elapsed());
+        result
+    }
+
+    /// Records a frame that took `elapsed`, returning by how much it overran the budget if it
+    /// did.
+    pub fn record(&mut self, elapsed: Duration) -> Option<Duration> {
+        self.frames += 1;
+
+        let overrun = elapsed.checked_sub(self.budget).filter(|o| !o.is_zero())?;
+        self.over_budget += 1;
+        self.worst_overrun = self.worst_overrun.max(overrun);
+
+        if self.log_overruns {
+            warn!(
+                "Frame {} over budget by {overrun:?} ({elapsed:?} of {:?})",
+                self.frames, self.budget
+            );
+        }
+
+        Some(overrun)
+    }
+
+    /// Number of frames recorded so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Number of frames that took longer than the budget.
+    pub fn over_budget(&self) -> u64 {
+        self.over_budget
+    }
+
+    /// The largest overrun recorded so far, zero if every frame made it.
+    pub fn worst_overrun(&self) -> Duration {
+        self.worst_overrun
+    }
+
+    /// Starts counting afresh, keeping the budget.
+    pub fn reset(&mut self) {
+        self.frames = 0;
+        self.over_budget = 0;
+        self.worst_overrun = Duration::ZERO;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_rates_become_frame_times() {
+        assert_eq!(FrameBudget::new(50.0).budget(), Duration::from_millis(20));
+        assert_eq!(FrameBudget::new(0.5).budget(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn rates_of_nothing_are_never_over_budget() {
+        for fps in [0.0, -0.0, -60.0, f64::NAN, f64::NEG_INFINITY] {
+            let mut budget = FrameBudget::new(fps);
+            assert_eq!(budget.budget(), Duration::MAX, "{fps}");
+            assert_eq!(budget.record(Duration::from_secs(3600)), None);
+            assert_eq!(budget.over_budget(), 0);
+        }
+    }
+
+    #[test]
+    fn overruns_are_counted() {
+        let mut budget = FrameBudget::per_frame(Duration::from_millis(10));
+        assert_eq!(budget.record(Duration::from_millis(10)), None);
+        assert_eq!(
+            budget.record(Duration::from_millis(15)),
+            Some(Duration::from_millis(5))
+        );
+        assert_eq!(
+            budget.record(Duration::from_millis(12)),
+            Some(Duration::from_millis(2))
+        );
+        assert_eq!((budget.frames(), budget.over_budget()), (3, 2));
+    }
+}
@@ -0,0 +1,425 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use super::{
+    decode, default_pixel_limit, Canvas, MemoryBudget, MemoryConsumer, Pixel, Reservation, Scaling,
+};
+use crate::Result;
+
+/// A decoded image already laid out for a given geometry, shared between the cache and callers.
+pub type PreparedImage = Arc<Canvas>;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct CacheKey {
+    path: PathBuf,
+    mtime: SystemTime,
+    scaling: Scaling,
+}
+
+struct CacheEntry {
+    image: PreparedImage,
+    // The size of the image it was prepared from, to hold against a lowered pixel limit
+    source_pixels: u64,
+    last_use: u64,
+    _memory: Reservation,
+}
+
+/// A least recently used cache of [`PreparedImage`]s, bounded by the size of the pixel data it
+/// holds. A capacity of 0 disables caching altogether.
+///
+/// Entries are only ever prepared for a single geometry: asking for a different one drops
+/// everything cached for the previous one.
+pub struct ImageCache {
+    capacity: usize,
+    budget: MemoryBudget,
+    max_pixels: Option<u64>,
+    geometry: (u32, u32),
+    entries: HashMap<CacheKey, CacheEntry>,
+    bytes: usize,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl ImageCache {
+    /// Creates a cache holding at most `megabytes` MiB of pixel data.
+    pub fn new(megabytes: usize) -> ImageCache {
+        ImageCache {
+            capacity: megabytes.saturating_mul(1024 * 1024),
+            budget: MemoryBudget::unlimited(),
+            max_pixels: None,
+            geometry: (0, 0),
+            entries: HashMap::new(),
+            bytes: 0,
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn set_capacity(&mut self, megabytes: usize) {
+        self.capacity = megabytes.saturating_mul(1024 * 1024);
+        self.evict(0);
+    }
+
+    /// Counts what the cache holds against `budget` as well, dropping everything cached so far.
+    /// Least recently used entries are evicted to make room in it just like for the capacity,
+    /// images that fit in neither are returned without being cached.
+    pub fn set_budget(&mut self, budget: MemoryBudget) {
+        self.clear();
+        self.budget = budget;
+    }
+
+    /// Refuses to decode images of more than `pixels` pixels, `None` going with what
+    /// [`Canvas::from_path`] refuses. Images already cached that the new limit would refuse are
+    /// dropped.
+    pub fn set_max_pixels(&mut self, pixels: Option<u64>) {
+        self.max_pixels = pixels;
+
+        let limit = self.pixel_limit();
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| entry.source_pixels <= limit);
+        if self.entries.len() != before {
+            self.bytes = self
+                .entries
+                .values()
+                .map(|entry| footprint(&entry.image))
+                .sum();
+        }
+    }
+
+    fn pixel_limit(&self) -> u64 {
+        let (width, height) = self.geometry;
+        self.max_pixels
+            .unwrap_or_else(|| default_pixel_limit(width, height))
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.bytes = 0;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            entries: self.entries.len(),
+            bytes: self.bytes,
+        }
+    }
+
+    /// Returns the image at `path` laid out for `width` x `height`, decoding it only if no
+    /// up to date copy is cached. A file modified since it was cached is decoded again.
+    pub fn get_or_prepare(
+        &mut self,
+        path: impl AsRef<Path>,
+        width: u32,
+        height: u32,
+        scaling: impl Into<Scaling>,
+    ) -> Result<PreparedImage> {
+        let path = path.as_ref();
+
+        if self.geometry != (width, height) {
+            self.clear();
+            self.geometry = (width, height);
+        }
+
+        let key = CacheKey {
+            path: path.to_path_buf(),
+            mtime: std::fs::metadata(path)?.modified()?,
+            scaling: scaling.into(),
+        };
+
+        self.tick += 1;
+
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.last_use = self.tick;
+            self.hits += 1;
+            return Ok(entry.image.clone());
+        }
+
+        self.misses += 1;
+        let decoded = decode::open(path, self.pixel_limit())?;
+        let source_pixels = decoded.width() as u64 * decoded.height() as u64;
+        let image = Arc::new(Canvas::from_image(
+            &decoded,
+            width,
+            height,
+            key.scaling.clone(),
+        ));
+        let size = footprint(&image);
+
+        if size <= self.capacity {
+            self.evict(size);
+            if let Some(memory) = self.reserve(size) {
+                self.bytes += size;
+                self.entries.insert(
+                    key,
+                    CacheEntry {
+                        image: image.clone(),
+                        source_pixels,
+                        last_use: self.tick,
+                        _memory: memory,
+                    },
+                );
+            }
+        }
+
+        Ok(image)
+    }
+
+    // Evicts the least recently used entries until `size` bytes fit the budget, `None` if they
+    // do not even once the cache is empty
+    fn reserve(&mut self, size: usize) -> Option<Reservation> {
+        loop {
+            match self.budget.reserve(MemoryConsumer::Cache, size) {
+                Ok(reservation) => return Some(reservation),
+                Err(_) if !self.evict_oldest() => return None,
+                Err(_) => {}
+            }
+        }
+    }
+
+    // Drops the least recently used entries until `incoming` more bytes fit
+    fn evict(&mut self, incoming: usize) {
+        while self.bytes + incoming > self.capacity && self.evict_oldest() {}
+    }
+
+    // Drops the least recently used entry, false if there was none
+    fn evict_oldest(&mut self) -> bool {
+        let Some(oldest) = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_use)
+            .map(|(key, _)| key.clone())
+        else {
+            return false;
+        };
+
+        if let Some(entry) = self.entries.remove(&oldest) {
+            self.bytes -= footprint(&entry.image);
+        }
+        true
+    }
+}
+
+fn footprint(image: &Canvas) -> usize {
+    std::mem::size_of_val::<[Pixel]>(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+    use crate::ScalingMethod;
+
+    // 8x8 pixels of 3 bytes each
+    const IMAGE: usize = 192;
+
+    fn dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("shade-cache-{}-{test}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn save(path: &Path, size: u32, color: [u8; 3]) {
+        RgbImage::from_pixel(size, size, Rgb(color))
+            .save(path)
+            .unwrap();
+    }
+
+    // Two small images in a directory of this test's own
+    fn images(test: &str) -> (PathBuf, PathBuf) {
+        let dir = dir(test);
+        let paths = (dir.join("a.png"), dir.join("b.png"));
+        save(&paths.0, 8, [255, 0, 0]);
+        save(&paths.1, 8, [0, 0, 255]);
+        paths
+    }
+
+    fn hits_and_misses(cache: &ImageCache) -> (u64, u64) {
+        (cache.stats().hits, cache.stats().misses)
+    }
+
+    #[test]
+    fn least_recently_used_make_room_in_the_budget() {
+        let (a, b) = images("budget");
+        let budget = MemoryBudget::bytes(IMAGE + IMAGE / 2);
+        let mut cache = ImageCache::new(1);
+        cache.set_budget(budget.clone());
+
+        cache.get_or_prepare(&a, 8, 8, ScalingMethod::Fill).unwrap();
+        assert_eq!(budget.report().cache, IMAGE);
+        cache.get_or_prepare(&b, 8, 8, ScalingMethod::Fill).unwrap();
+        assert_eq!(budget.report().cache, IMAGE);
+        assert_eq!(cache.stats().entries, 1);
+
+        // b pushed a out
+        cache.get_or_prepare(&b, 8, 8, ScalingMethod::Fill).unwrap();
+        cache.get_or_prepare(&a, 8, 8, ScalingMethod::Fill).unwrap();
+        assert_eq!((cache.stats().hits, cache.stats().misses), (1, 3));
+
+        cache.clear();
+        assert_eq!(budget.report().cache, 0);
+        let _ = fs::remove_dir_all(a.parent().unwrap());
+    }
+
+    #[test]
+    fn images_beyond_the_budget_come_back_uncached() {
+        let (a, _) = images("uncached");
+        let budget = MemoryBudget::bytes(IMAGE);
+        let _snapshot = budget.reserve(MemoryConsumer::Snapshots, 1).unwrap();
+        let mut cache = ImageCache::new(1);
+        cache.set_budget(budget.clone());
+
+        let image = cache.get_or_prepare(&a, 8, 8, ScalingMethod::Fill).unwrap();
+        assert_eq!(image.get_pixel(0, 0), Some(&Pixel::new(255, 0, 0)));
+        assert_eq!(cache.stats().entries, 0);
+        assert_eq!(budget.report().cache, 0);
+        assert_eq!(budget.report().snapshots, 1);
+        let _ = fs::remove_dir_all(a.parent().unwrap());
+    }
+
+    #[test]
+    fn other_geometries_drop_the_cache() {
+        let (a, b) = images("geometry");
+        let mut cache = ImageCache::new(1);
+
+        cache.get_or_prepare(&a, 8, 8, ScalingMethod::Fill).unwrap();
+        cache.get_or_prepare(&b, 8, 8, ScalingMethod::Fill).unwrap();
+        assert_eq!(cache.stats().entries, 2);
+
+        let small = cache.get_or_prepare(&a, 4, 2, ScalingMethod::Fill).unwrap();
+        assert_eq!((small.width(), small.height()), (4, 2));
+        assert_eq!(cache.stats().entries, 1);
+        assert_eq!(cache.stats().bytes, 4 * 2 * 3);
+
+        // Back to the first geometry, which was dropped along the way
+        cache.get_or_prepare(&b, 8, 8, ScalingMethod::Fill).unwrap();
+        assert_eq!(hits_and_misses(&cache), (0, 4));
+        assert_eq!(cache.stats().entries, 1);
+        let _ = fs::remove_dir_all(a.parent().unwrap());
+    }
+
+    #[test]
+    fn modified_files_are_decoded_again() {
+        let (a, _) = images("mtime");
+        let mut cache = ImageCache::new(1);
+
+        cache.get_or_prepare(&a, 8, 8, ScalingMethod::Fill).unwrap();
+        cache.get_or_prepare(&a, 8, 8, ScalingMethod::Fill).unwrap();
+        assert_eq!(hits_and_misses(&cache), (1, 1));
+
+        // Not every filesystem tells apart writes this close together, so the time moves on
+        save(&a, 8, [0, 255, 0]);
+        let later = fs::metadata(&a).unwrap().modified().unwrap() + Duration::from_secs(10);
+        fs::File::options()
+            .write(true)
+            .open(&a)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+
+        let image = cache.get_or_prepare(&a, 8, 8, ScalingMethod::Fill).unwrap();
+        assert_eq!(image.get_pixel(0, 0), Some(&Pixel::new(0, 255, 0)));
+        assert_eq!(hits_and_misses(&cache), (1, 2));
+        let _ = fs::remove_dir_all(a.parent().unwrap());
+    }
+
+    #[test]
+    fn least_recently_used_make_room_in_the_capacity() {
+        // 400x400 takes 480000 bytes, two fit in a MiB but not three
+        let dir = dir("capacity");
+        let [a, b, c] = ["a.png", "b.png", "c.png"].map(|name| dir.join(name));
+        for path in [&a, &b, &c] {
+            save(path, 400, [1, 2, 3]);
+        }
+        let mut cache = ImageCache::new(1);
+
+        cache
+            .get_or_prepare(&a, 400, 400, ScalingMethod::Fill)
+            .unwrap();
+        cache
+            .get_or_prepare(&b, 400, 400, ScalingMethod::Fill)
+            .unwrap();
+        // Used last, a outlives b
+        cache
+            .get_or_prepare(&a, 400, 400, ScalingMethod::Fill)
+            .unwrap();
+        cache
+            .get_or_prepare(&c, 400, 400, ScalingMethod::Fill)
+            .unwrap();
+        assert_eq!(cache.stats().entries, 2);
+        assert_eq!(cache.stats().bytes, 2 * 480000);
+
+        cache
+            .get_or_prepare(&a, 400, 400, ScalingMethod::Fill)
+            .unwrap();
+        cache
+            .get_or_prepare(&c, 400, 400, ScalingMethod::Fill)
+            .unwrap();
+        assert_eq!(hits_and_misses(&cache), (3, 3));
+        cache
+            .get_or_prepare(&b, 400, 400, ScalingMethod::Fill)
+            .unwrap();
+        assert_eq!(hits_and_misses(&cache), (3, 4));
+
+        // Shrinking the capacity evicts right away, oldest first
+        cache.set_capacity(0);
+        assert_eq!(cache.stats().entries, 0);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn lowered_pixel_limits_drop_larger_images() {
+        let dir = dir("limit");
+        let (small, large) = (dir.join("small.png"), dir.join("large.png"));
+        save(&small, 8, [1, 2, 3]);
+        save(&large, 16, [4, 5, 6]);
+        let budget = MemoryBudget::unlimited();
+        let mut cache = ImageCache::new(1);
+        cache.set_budget(budget.clone());
+
+        // Both are laid out at the same size, only where they came from differs
+        cache
+            .get_or_prepare(&small, 8, 8, ScalingMethod::Fill)
+            .unwrap();
+        cache
+            .get_or_prepare(&large, 8, 8, ScalingMethod::Fill)
+            .unwrap();
+        assert_eq!(budget.report().cache, 2 * IMAGE);
+
+        cache.set_max_pixels(Some(100));
+        assert_eq!(cache.stats().entries, 1);
+        assert_eq!(cache.stats().bytes, IMAGE);
+        assert_eq!(budget.report().cache, IMAGE);
+        cache
+            .get_or_prepare(&small, 8, 8, ScalingMethod::Fill)
+            .unwrap();
+        assert_eq!(hits_and_misses(&cache), (1, 2));
+        assert!(matches!(
+            cache.get_or_prepare(&large, 8, 8, ScalingMethod::Fill),
+            Err(crate::Error::ImageTooLarge { .. })
+        ));
+
+        // Raising it again drops nothing
+        cache.set_max_pixels(None);
+        assert_eq!(cache.stats().entries, 1);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,142 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::Canvas;
+
+/// A single in-place operation of a [`FilterChain`], each mapping to the [`Canvas`] method of
+/// the same name.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Filter {
+    AutoLevels,
+    Grayscale,
+    Blur(f32),
+    Brightness(i16),
+    Vignette(f32),
+}
+
+/// An ordered list of filters, applied first to last.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct FilterChain {
+    filters: Vec<Filter>,
+}
+
+impl FilterChain {
+    pub fn new() -> FilterChain {
+        FilterChain::default()
+    }
+
+    pub fn then(mut self, filter: Filter) -> FilterChain {
+        self.filters.push(filter);
+        self
+    }
+
+    pub fn push(&mut self, filter: Filter) {
+        self.filters.push(filter);
+    }
+
+    pub fn filters(&self) -> &[Filter] {
+        &self.filters
+    }
+}
+
+impl From<Vec<Filter>> for FilterChain {
+    fn from(filters: Vec<Filter>) -> FilterChain {
+        FilterChain { filters }
+    }
+}
+
+impl Canvas {
+    pub fn apply(&mut self, filter: &Filter) {
+        match *filter {
+            Filter::AutoLevels => self.auto_levels(),
+            Filter::Grayscale => self.grayscale(),
+            Filter::Blur(sigma) => self.blur(sigma),
+            Filter::Brightness(amount) => self.brightness(amount),
+            Filter::Vignette(strength) => self.vignette(strength),
+        }
+    }
+
+    pub fn apply_chain(&mut self, chain: &FilterChain) {
+        for filter in chain.filters() {
+            self.apply(filter);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pixel;
+
+    // A dark, low contrast ramp, which auto levels stretches and brightness lifts
+    fn ramp() -> Canvas {
+        let mut canvas = Canvas::new(8, 1);
+        for x in 0..8 {
+            let v = 40 + x as u8 * 4;
+            canvas.set_pixel(x, 0, Pixel::new(v, v / 2, v));
+        }
+        canvas
+    }
+
+    #[test]
+    fn filters_apply_first_to_last() {
+        let chain = FilterChain::new()
+            .then(Filter::Brightness(100))
+            .then(Filter::AutoLevels);
+
+        let mut chained = ramp();
+        chained.apply_chain(&chain);
+
+        let mut by_hand = ramp();
+        by_hand.brightness(100);
+        by_hand.auto_levels();
+        assert_eq!(chained, by_hand);
+
+        let mut reversed = ramp();
+        reversed.apply_chain(&FilterChain::from(vec![
+            Filter::AutoLevels,
+            Filter::Brightness(100),
+        ]));
+        assert_ne!(chained, reversed);
+    }
+
+    #[test]
+    fn pushing_and_chaining_agree() {
+        let mut pushed = FilterChain::new();
+        pushed.push(Filter::Grayscale);
+        pushed.push(Filter::Blur(1.5));
+
+        let chained = FilterChain::new()
+            .then(Filter::Grayscale)
+            .then(Filter::Blur(1.5));
+        assert_eq!(pushed, chained);
+        assert_eq!(chained.filters(), [Filter::Grayscale, Filter::Blur(1.5)]);
+    }
+
+    #[test]
+    fn empty_chains_change_nothing() {
+        let mut canvas = ramp();
+        canvas.apply_chain(&FilterChain::new());
+        assert_eq!(canvas, ramp());
+        assert!(FilterChain::default().filters().is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn chains_round_trip_through_serde() {
+        use crate::serde_value::round_trip;
+
+        let chain = FilterChain::from(vec![
+            Filter::AutoLevels,
+            Filter::Grayscale,
+            Filter::Blur(2.5),
+            Filter::Brightness(-40),
+            Filter::Vignette(0.75),
+        ]);
+        assert_eq!(round_trip(&chain), chain);
+        assert_eq!(round_trip(&FilterChain::new()), FilterChain::new());
+    }
+}
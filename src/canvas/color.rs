@@ -0,0 +1,64 @@
+use once_cell::sync::Lazy;
+
+// sRGB encoded channel value to linear light in [0, 1], for every possible byte
+static SRGB_TO_LINEAR: Lazy<[f32; 256]> = Lazy::new(|| {
+    let mut lut = [0f32; 256];
+    for (v, out) in lut.iter_mut().enumerate() {
+        let c = v as f32 / 255.0;
+        *out = if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        };
+    }
+    lut
+});
+
+pub(crate) fn srgb_to_linear(value: u8) -> f32 {
+    SRGB_TO_LINEAR[value as usize]
+}
+
+// Linear light to sRGB at LINEAR_STEPS points, fine enough that the darkest steps, where the
+// curve is steepest, still land within half a level of the exact value
+const LINEAR_STEPS: usize = 1 << 14;
+
+static LINEAR_TO_SRGB: Lazy<Box<[u8]>> = Lazy::new(|| {
+    (0..LINEAR_STEPS)
+        .map(|i| linear_to_srgb(i as f32 / (LINEAR_STEPS - 1) as f32))
+        .collect()
+});
+
+// Like linear_to_srgb, through a table, for when whole images have to be converted back.
+pub(crate) fn linear_to_srgb_fast(value: f32) -> u8 {
+    let i = (value.clamp(0.0, 1.0) * (LINEAR_STEPS - 1) as f32).round() as usize;
+    LINEAR_TO_SRGB[i]
+}
+
+pub(crate) fn linear_to_srgb(value: f32) -> u8 {
+    let c = value.clamp(0.0, 1.0);
+    let encoded = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_level_survives_the_round_trip() {
+        for v in 0..=255 {
+            assert_eq!(linear_to_srgb(srgb_to_linear(v)), v);
+            assert_eq!(linear_to_srgb_fast(srgb_to_linear(v)), v);
+        }
+    }
+
+    #[test]
+    fn out_of_range_light_is_clamped() {
+        assert_eq!(linear_to_srgb_fast(-0.2), 0);
+        assert_eq!(linear_to_srgb_fast(1.3), 255);
+    }
+}
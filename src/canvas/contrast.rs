@@ -0,0 +1,220 @@
+use super::{color::srgb_to_linear, Canvas, Pixel, Rect};
+
+/// The contrast ratio [`Canvas::suggest_text_color`] aims for, WCAG's minimum for body text.
+pub const MIN_TEXT_CONTRAST: f64 = 4.5;
+
+// WCAG's minimum for large text. Text that falls below it against the darkest or lightest parts
+// of its area gets a scrim
+const MIN_BUSY_CONTRAST: f64 = 3.0;
+
+// Accents that are all but gray are no accent at all, black or white read better
+const MIN_ACCENT_CHROMA: u8 = 64;
+
+// How many of the most common colors of the canvas are considered as accents
+const ACCENTS: usize = 8;
+
+// Every n-th pixel of the area is looked at, for at most about this many samples
+const SAMPLES: usize = 1 << 16;
+
+/// What [`Canvas::suggest_text_color`] suggests for text drawn over an area.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextColor {
+    /// Black, white, or an accent out of the canvas' [dominant colors](Canvas::dominant_colors).
+    pub color: Pixel,
+    /// The WCAG contrast ratio of `color` against the area on average, from 1 to 21.
+    pub contrast: f64,
+    /// How much the area's relative luminance varies, its standard deviation from 0 to 0.5.
+    pub deviation: f64,
+    /// A color to put a translucent backdrop of behind the text, should the area be too busy
+    /// for any color to read on its own: parts of it come out too close to `color`.
+    pub scrim: Option<Pixel>,
+}
+
+impl Canvas {
+    /// The color text drawn over `area` reads best in, going by the area's luminance.
+    ///
+    /// An accent out of the canvas' most common colors is suggested if one reaches
+    /// [`MIN_TEXT_CONTRAST`] against the area, so text can match the wallpaper, black or white
+    /// otherwise, whichever contrasts more. Areas that are dark in places and light in others
+    /// leave every color unreadable somewhere, those come with a [scrim](TextColor::scrim).
+    /// Whatever of `area` lies outside the canvas is ignored, an area entirely outside counts as
+    /// black.
+    pub fn suggest_text_color(&self, area: Rect) -> TextColor {
+        let area = area.intersect(&self.bounds());
+        let step = (area.width as usize * area.height as usize / SAMPLES).max(1);
+        let mut luminances: Vec<f64> = (area.y..area.y + area.height as i32)
+            .flat_map(|y| (area.x..area.x + area.width as i32).map(move |x| (x as u32, y as u32)))
+            .step_by(step)
+            .filter_map(|(x, y)| self.get_pixel(x, y))
+            .map(relative_luminance)
+            .collect();
+        if luminances.is_empty() {
+            luminances.push(0.0);
+        }
+        luminances.sort_by(f64::total_cmp);
+
+        let count = luminances.len() as f64;
+        let mean = luminances.iter().sum::<f64>() / count;
+        let deviation = (luminances.iter().map(|l| (l - mean).powi(2)).sum::<f64>() / count).sqrt();
+        // The darkest and lightest twentieth, leaving out specks no text would notice
+        let percentile = |p: f64| luminances[((count - 1.0) * p).round() as usize];
+        let (dark, light) = (percentile(0.05), percentile(0.95));
+
+        let black = Pixel::new(0, 0, 0);
+        let white = Pixel::new(255, 255, 255);
+        let accent = self
+            .dominant_colors(ACCENTS)
+            .into_iter()
+            .filter(|p| chroma(p) >= MIN_ACCENT_CHROMA)
+            .map(|p| (contrast_ratio(relative_luminance(&p), mean), p))
+            .filter(|&(ratio, _)| ratio >= MIN_TEXT_CONTRAST)
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+
+        let (ratio, color) = accent.unwrap_or_else(|| {
+            let on_black = contrast_ratio(0.0, mean);
+            let on_white = contrast_ratio(1.0, mean);
+            match on_white >= on_black {
+                true => (on_white, white.clone()),
+                false => (on_black, black.clone()),
+            }
+        });
+
+        let text = relative_luminance(&color);
+        let worst = contrast_ratio(text, dark).min(contrast_ratio(text, light));
+        let scrim = match (worst < MIN_BUSY_CONTRAST, text > 0.5) {
+            (false, _) => None,
+            (true, true) => Some(black),
+            (true, false) => Some(white),
+        };
+
+        TextColor {
+            color,
+            contrast: ratio,
+            deviation,
+            scrim,
+        }
+    }
+}
+
+/// WCAG's relative luminance of an sRGB color, from 0 for black to 1 for white.
+pub fn relative_luminance(pixel: &Pixel) -> f64 {
+    0.2126 * srgb_to_linear(pixel.r) as f64
+        + 0.7152 * srgb_to_linear(pixel.g) as f64
+        + 0.0722 * srgb_to_linear(pixel.b) as f64
+}
+
+/// WCAG's contrast ratio between two relative luminances, from 1 for none to 21 for black on
+/// white. The order does not matter.
+pub fn contrast_ratio(a: f64, b: f64) -> f64 {
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+fn chroma(pixel: &Pixel) -> u8 {
+    let channels = [pixel.r, pixel.g, pixel.b];
+    channels.iter().max().unwrap_or(&0) - channels.iter().min().unwrap_or(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Pixel = Pixel { r: 0, g: 0, b: 0 };
+    const WHITE: Pixel = Pixel {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+
+    #[test]
+    fn light_areas_get_dark_text() {
+        let canvas = Canvas::filled(64, 64, Pixel::new(230, 230, 220));
+        let text = canvas.suggest_text_color(Rect::new(8, 8, 32, 16));
+        assert_eq!(text.color, BLACK);
+        assert!(text.contrast >= MIN_TEXT_CONTRAST);
+        assert!(text.deviation < 1e-9);
+        assert_eq!(text.scrim, None);
+    }
+
+    #[test]
+    fn dark_areas_get_light_text() {
+        let canvas = Canvas::filled(64, 64, Pixel::new(20, 24, 30));
+        let text = canvas.suggest_text_color(Rect::new(8, 8, 32, 16));
+        assert_eq!(text.color, WHITE);
+        assert!(text.contrast >= MIN_TEXT_CONTRAST);
+        assert_eq!(text.scrim, None);
+    }
+
+    #[test]
+    fn only_the_area_counts() {
+        // Light on the left, dark on the right
+        let mut canvas = Canvas::filled(64, 64, WHITE);
+        canvas.fill_rect(32, 0, 32, 64, BLACK);
+        assert_eq!(
+            canvas.suggest_text_color(Rect::new(0, 0, 32, 64)).color,
+            BLACK
+        );
+        assert_eq!(
+            canvas.suggest_text_color(Rect::new(32, 0, 32, 64)).color,
+            WHITE
+        );
+    }
+
+    #[test]
+    fn areas_partly_outside_are_clipped() {
+        let canvas = Canvas::filled(64, 64, WHITE);
+        // Only the white inside is looked at, not the nothing beyond the edges
+        for area in [Rect::new(48, 48, 500, 500), Rect::new(-100, -100, 120, 120)] {
+            let text = canvas.suggest_text_color(area);
+            assert_eq!((text.color, text.scrim), (BLACK, None), "{area:?}");
+        }
+    }
+
+    #[test]
+    fn areas_entirely_outside_count_as_black() {
+        let canvas = Canvas::filled(64, 64, WHITE);
+        for area in [
+            Rect::new(64, 0, 10, 10),
+            Rect::new(-20, -20, 10, 10),
+            Rect::new(0, 0, 0, 0),
+        ] {
+            assert_eq!(canvas.suggest_text_color(area).color, WHITE, "{area:?}");
+        }
+    }
+
+    #[test]
+    fn accents_come_from_the_whole_canvas() {
+        // A deep blue wallpaper with a light panel, text on the panel can be the blue
+        let blue = Pixel::new(0, 0, 160);
+        let mut canvas = Canvas::filled(64, 64, blue.clone());
+        canvas.fill_rect(48, 0, 16, 64, WHITE);
+        let text = canvas.suggest_text_color(Rect::new(48, 0, 16, 64));
+        assert_eq!(text.color, blue);
+        assert!(text.contrast >= MIN_TEXT_CONTRAST);
+    }
+
+    #[test]
+    fn busy_areas_get_a_scrim() {
+        // Large black and white squares, whichever color is picked vanishes over half of them
+        let mut canvas = Canvas::filled(64, 64, WHITE);
+        for y in (0..64).step_by(8) {
+            for x in (0..64).step_by(8) {
+                if (x + y) / 8 % 2 == 0 {
+                    canvas.fill_rect(x, y, 8, 8, BLACK);
+                }
+            }
+        }
+        let text = canvas.suggest_text_color(canvas.bounds());
+        assert!(text.deviation > 0.4);
+        let scrim = text.scrim.expect("no scrim");
+        assert_ne!(scrim, text.color);
+    }
+
+    #[test]
+    fn contrast_ratios() {
+        assert_eq!(contrast_ratio(1.0, 0.0), 21.0);
+        assert_eq!(contrast_ratio(0.0, 1.0), 21.0);
+        assert_eq!(contrast_ratio(0.3, 0.3), 1.0);
+        assert_eq!(relative_luminance(&WHITE), 1.0);
+        assert_eq!(relative_luminance(&BLACK), 0.0);
+    }
+}
@@ -0,0 +1,117 @@
+use std::{io::Cursor, path::Path};
+
+use image::{io::Reader, DynamicImage, ImageError};
+
+use crate::{Error, Result};
+
+// Never below four 4K screens' worth, so that small canvases such as thumbnails still take any
+// ordinary photo
+const MIN_PIXEL_LIMIT: u64 = 4 * 3840 * 2160;
+
+/// How many pixels an image laid out on a `width` x `height` canvas may have unless told
+/// otherwise: four times the canvas, and at least four 4K screens.
+pub(crate) fn default_pixel_limit(width: u32, height: u32) -> u64 {
+    (4 * width as u64 * height as u64).max(MIN_PIXEL_LIMIT)
+}
+
+fn check((width, height): (u32, u32), limit: u64) -> Result<()> {
+    if width as u64 * height as u64 > limit {
+        return Err(Error::ImageTooLarge {
+            width,
+            height,
+            limit,
+        });
+    }
+    Ok(())
+}
+
+// Decoders allocate for whatever size the header claims, so only the header is read before an
+// image is known to be within `limit` pixels. A corrupt or hostile file would otherwise take
+// gigabytes before scaling ever gets to shrink it
+
+/// Decodes the image at `path` if it has at most `limit` pixels.
+pub(crate) fn open(path: &Path, limit: u64) -> Result<DynamicImage> {
+    check(image::image_dimensions(path)?, limit)?;
+    Ok(image::open(path)?)
+}
+
+/// Decodes the encoded image `bytes` if it has at most `limit` pixels, wrapping decoding errors
+/// with `wrap`.
+pub(crate) fn load_from_memory(
+    bytes: &[u8],
+    limit: u64,
+    wrap: fn(ImageError) -> Error,
+) -> Result<DynamicImage> {
+    let dimensions = Reader::new(Cursor::new(bytes))
+        .with_guessed_format()?
+        .into_dimensions()
+        .map_err(wrap)?;
+    check(dimensions, limit)?;
+
+    image::load_from_memory(bytes).map_err(wrap)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    // A PNG whose header claims `width` x `height` RGB pixels, but with no image data to go with
+    // them. Decoding it for real would first allocate the lot, then fail for want of pixels
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut header = width.to_be_bytes().to_vec();
+        header.extend(height.to_be_bytes());
+        // 8 bit RGB, no interlacing
+        header.extend([8, 2, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (kind, data) in [(b"IHDR", &header[..]), (b"IDAT", &[]), (b"IEND", &[])] {
+            let mut chunk = kind.to_vec();
+            chunk.extend(data);
+            png.extend((data.len() as u32).to_be_bytes());
+            png.extend(&chunk);
+            png.extend(crc32(&chunk).to_be_bytes());
+        }
+        png
+    }
+
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in bytes {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+            }
+        }
+        !crc
+    }
+
+    #[test]
+    fn huge_headers_are_refused_before_decoding() {
+        let png = png_header(60000, 60000);
+        let limit = default_pixel_limit(1920, 1080);
+
+        assert!(matches!(
+            load_from_memory(&png, limit, Error::Image),
+            Err(Error::ImageTooLarge {
+                width: 60000,
+                height: 60000,
+                limit: l,
+            }) if l == limit
+        ));
+
+        let path = std::env::temp_dir().join(format!("shade-decode-{}.png", std::process::id()));
+        fs::write(&path, &png).unwrap();
+        let opened = open(&path, limit);
+        let _ = fs::remove_file(&path);
+        assert!(matches!(opened, Err(Error::ImageTooLarge { .. })));
+    }
+
+    #[test]
+    fn headers_within_the_limit_go_on_to_decode() {
+        // Only the missing image data stops it, not the limit
+        let result = load_from_memory(&png_header(4, 4), 16, Error::Image);
+        assert!(matches!(result, Err(Error::Image(_))));
+    }
+}
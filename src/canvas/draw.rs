@@ -0,0 +1,490 @@
+use super::{Canvas, Pixel};
+
+/// An axis aligned rectangle in canvas coordinates, possibly reaching past the canvas edges.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    // Exclusive edges, in i64 so nothing a u32 sized rectangle at an i32 offset can overflow
+    fn right(&self) -> i64 {
+        self.x as i64 + self.width as i64
+    }
+
+    fn bottom(&self) -> i64 {
+        self.y as i64 + self.height as i64
+    }
+
+    fn contains(&self, x: i64, y: i64) -> bool {
+        x >= self.x as i64 && y >= self.y as i64 && x < self.right() && y < self.bottom()
+    }
+
+    /// The area both rectangles cover, empty (but placed inside `self`) if they don't overlap.
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right()).max(x as i64);
+        let bottom = self.bottom().min(other.bottom()).max(y as i64);
+
+        Rect::new(x, y, (right - x as i64) as u32, (bottom - y as i64) as u32)
+    }
+}
+
+/// How the shapes drawn on a canvas combine with what is already there, see
+/// [`Canvas::set_blend_mode`]. Every mode saturates rather than wraps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// The shape's color replaces the pixel.
+    #[default]
+    Replace,
+    /// The shape's color goes over the pixel at the given opacity, 255 being opaque. Pixels carry
+    /// no alpha of their own, so the whole shape shares it.
+    AlphaOver(u8),
+    /// The colors are summed, for glows and light.
+    Add,
+    /// The colors are multiplied as fractions of 255, for shadows and shading.
+    Multiply,
+}
+
+impl BlendMode {
+    /// `src` combined onto `dst`.
+    pub fn blend(self, dst: &Pixel, src: &Pixel) -> Pixel {
+        let channel = |d: u8, s: u8| -> u8 {
+            match self {
+                BlendMode::Replace => s,
+                BlendMode::AlphaOver(a) => {
+                    let (d, s, a) = (d as u32, s as u32, a as u32);
+                    ((s * a + d * (255 - a) + 127) / 255) as u8
+                }
+                BlendMode::Add => d.saturating_add(s),
+                BlendMode::Multiply => ((d as u32 * s as u32 + 127) / 255) as u8,
+            }
+        };
+
+        Pixel::new(
+            channel(dst.r, src.r),
+            channel(dst.g, src.g),
+            channel(dst.b, src.b),
+        )
+    }
+}
+
+// Shapes take signed coordinates and may lie partially or entirely offscreen, whatever falls
+// outside the canvas (or the clip rectangle of a view) is clipped away
+impl Canvas {
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    /// The blend mode everything drawn, `draw_*` and `fill_*`, is drawn with from now on,
+    /// [`BlendMode::Replace`] by default. What is set rather than drawn, [`Canvas::set_pixel`],
+    /// [`Canvas::fill`], [`Canvas::paste`] and the filters, always writes pixels as they are.
+    pub fn set_blend_mode(&mut self, mode: BlendMode) {
+        self.blend = mode;
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend
+    }
+
+    // The one place drawing writes pixels, so every primitive blends the same way
+    fn put(&mut self, clip: &Rect, x: i64, y: i64, pixel: &Pixel) {
+        if !clip.contains(x, y) {
+            return;
+        }
+
+        let i = y as usize * self.width as usize + x as usize;
+        self.pixels[i] = match self.blend {
+            BlendMode::Replace => pixel.clone(),
+            mode => mode.blend(&self.pixels[i], pixel),
+        };
+    }
+
+    /// A copy of the given rectangle, clipped to the canvas.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Canvas {
+        let x0 = x.min(self.width);
+        let y0 = y.min(self.height);
+        let x1 = x.saturating_add(width).min(self.width);
+        let y1 = y.saturating_add(height).min(self.height);
+        let stride = self.width as usize;
+
+        let mut pixels = Vec::with_capacity((x1 - x0) as usize * (y1 - y0) as usize);
+        for row in y0..y1 {
+            let start = row as usize * stride;
+            pixels.extend_from_slice(&self.pixels[start + x0 as usize..start + x1 as usize]);
+        }
+
+        Canvas {
+            width: x1 - x0,
+            height: y1 - y0,
+            pixels: pixels.into_boxed_slice(),
+            blend: BlendMode::default(),
+        }
+    }
+
+    /// Copies `other` onto this canvas with its top left corner at (x, y), pixels as they are
+    /// whatever the blend mode, clipping whatever falls outside.
+    pub fn paste(&mut self, other: &Canvas, x: i32, y: i32) {
+        let area = Rect::new(x, y, other.width, other.height).intersect(&self.bounds());
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+
+        let (sx, sy) = ((area.x - x) as usize, (area.y - y) as usize);
+        let width = area.width as usize;
+        for row in 0..area.height as usize {
+            let from = (sy + row) * other.width as usize + sx;
+            let to = (area.y as usize + row) * self.width as usize + area.x as usize;
+            self.pixels[to..to + width].clone_from_slice(&other.pixels[from..from + width]);
+        }
+    }
+
+    /// A view drawing relative to the top left corner of `area`, and only within it. Handy to
+    /// draw on a single monitor without translating every coordinate.
+    pub fn view(&mut self, area: Rect) -> CanvasView<'_> {
+        CanvasView {
+            clip: area.intersect(&self.bounds()),
+            area,
+            canvas: self,
+        }
+    }
+
+    /// A single pixel, blended like any other shape unlike [`Canvas::set_pixel`].
+    pub fn draw_pixel(&mut self, x: i32, y: i32, pixel: Pixel) {
+        self.put(&self.bounds(), x as i64, y as i64, &pixel);
+    }
+
+    /// Copies `other` onto this canvas with its top left corner at (x, y).
+    pub fn draw_canvas(&mut self, other: &Canvas, x: i32, y: i32) {
+        self.draw_canvas_in(&self.bounds(), other, x as i64, y as i64);
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, pixel: Pixel) {
+        self.fill_rect_in(&self.bounds(), x as i64, y as i64, width, height, &pixel);
+    }
+
+    pub fn fill_circle(&mut self, cx: i32, cy: i32, radius: u32, pixel: Pixel) {
+        self.draw_circle(cx, cy, radius, radius + 1, pixel);
+    }
+
+    /// A circle outline of the given radius, `thickness` pixels wide towards the center.
+    pub fn draw_circle(&mut self, cx: i32, cy: i32, radius: u32, thickness: u32, pixel: Pixel) {
+        self.draw_circle_in(
+            &self.bounds(),
+            (cx as i64, cy as i64),
+            radius,
+            thickness,
+            &pixel,
+        );
+    }
+
+    /// A straight line between both points, `thickness` pixels wide with rounded ends.
+    pub fn draw_line(
+        &mut self,
+        (x0, y0): (i32, i32),
+        (x1, y1): (i32, i32),
+        thickness: u32,
+        pixel: Pixel,
+    ) {
+        self.draw_line_in(
+            &self.bounds(),
+            (x0 as i64, y0 as i64),
+            (x1 as i64, y1 as i64),
+            thickness,
+            &pixel,
+        );
+    }
+
+    fn draw_canvas_in(&mut self, clip: &Rect, other: &Canvas, x: i64, y: i64) {
+        let width = other.width.max(1) as usize;
+        for (i, pixel) in other.iter().enumerate() {
+            self.put(clip, x + (i % width) as i64, y + (i / width) as i64, pixel);
+        }
+    }
+
+    fn fill_rect_in(
+        &mut self,
+        clip: &Rect,
+        x: i64,
+        y: i64,
+        width: u32,
+        height: u32,
+        pixel: &Pixel,
+    ) {
+        // Clip first, so a huge rectangle costs no more than the canvas
+        let x0 = x.max(clip.x as i64);
+        let y0 = y.max(clip.y as i64);
+        let x1 = (x + width as i64).min(clip.right());
+        let y1 = (y + height as i64).min(clip.bottom());
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                self.put(clip, x, y, pixel);
+            }
+        }
+    }
+
+    fn draw_circle_in(
+        &mut self,
+        clip: &Rect,
+        (cx, cy): (i64, i64),
+        radius: u32,
+        thickness: u32,
+        pixel: &Pixel,
+    ) {
+        let r = radius as i64;
+        let outer = r * r + r;
+        let inner = match r - thickness as i64 {
+            i if i < 0 => -1,
+            i => i * i + i,
+        };
+
+        for y in (cy - r).max(clip.y as i64)..=(cy + r).min(clip.bottom() - 1) {
+            for x in (cx - r).max(clip.x as i64)..=(cx + r).min(clip.right() - 1) {
+                let d = (x - cx).pow(2) + (y - cy).pow(2);
+                if d <= outer && d > inner {
+                    self.put(clip, x, y, pixel);
+                }
+            }
+        }
+    }
+
+    fn draw_line_in(
+        &mut self,
+        clip: &Rect,
+        (x0, y0): (i64, i64),
+        (x1, y1): (i64, i64),
+        thickness: u32,
+        pixel: &Pixel,
+    ) {
+        let half = thickness.max(1) as f32 / 2.0;
+        let (ax, ay, bx, by) = (x0 as f32, y0 as f32, x1 as f32, y1 as f32);
+        let (dx, dy) = (bx - ax, by - ay);
+        let length = dx * dx + dy * dy;
+
+        let left = ((ax.min(bx) - half).floor() as i64).max(clip.x as i64);
+        let top = ((ay.min(by) - half).floor() as i64).max(clip.y as i64);
+        let right = ((ax.max(bx) + half).ceil() as i64).min(clip.right() - 1);
+        let bottom = ((ay.max(by) + half).ceil() as i64).min(clip.bottom() - 1);
+
+        for y in top..=bottom {
+            for x in left..=right {
+                let (px, py) = (x as f32 - ax, y as f32 - ay);
+
+                // Distance from the pixel to the closest point of the segment
+                let t = if length == 0.0 {
+                    0.0
+                } else {
+                    ((px * dx + py * dy) / length).clamp(0.0, 1.0)
+                };
+                let (ex, ey) = (px - t * dx, py - t * dy);
+
+                if ex * ex + ey * ey <= half * half {
+                    self.put(clip, x, y, pixel);
+                }
+            }
+        }
+    }
+}
+
+/// A window onto part of a [`Canvas`], see [`Canvas::view`]. Coordinates are relative to the
+/// top left corner of the area it was made for, and nothing is drawn outside of that area.
+pub struct CanvasView<'a> {
+    canvas: &'a mut Canvas,
+    area: Rect,
+    clip: Rect,
+}
+
+impl CanvasView<'_> {
+    pub fn width(&self) -> u32 {
+        self.area.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.area.height
+    }
+
+    fn translate(&self, x: i32, y: i32) -> (i64, i64) {
+        (self.area.x as i64 + x as i64, self.area.y as i64 + y as i64)
+    }
+
+    pub fn draw_pixel(&mut self, x: i32, y: i32, pixel: Pixel) {
+        let (x, y) = self.translate(x, y);
+        self.canvas.put(&self.clip, x, y, &pixel);
+    }
+
+    /// Fills the whole view as [`CanvasView::fill_rect`] would, blending unlike [`Canvas::fill`].
+    pub fn fill(&mut self, pixel: Pixel) {
+        self.fill_rect(0, 0, self.area.width, self.area.height, pixel);
+    }
+
+    pub fn draw_canvas(&mut self, other: &Canvas, x: i32, y: i32) {
+        let (x, y) = self.translate(x, y);
+        self.canvas.draw_canvas_in(&self.clip, other, x, y);
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, pixel: Pixel) {
+        let (x, y) = self.translate(x, y);
+        self.canvas
+            .fill_rect_in(&self.clip, x, y, width, height, &pixel);
+    }
+
+    pub fn fill_circle(&mut self, cx: i32, cy: i32, radius: u32, pixel: Pixel) {
+        self.draw_circle(cx, cy, radius, radius + 1, pixel);
+    }
+
+    pub fn draw_circle(&mut self, cx: i32, cy: i32, radius: u32, thickness: u32, pixel: Pixel) {
+        let center = self.translate(cx, cy);
+        self.canvas
+            .draw_circle_in(&self.clip, center, radius, thickness, &pixel);
+    }
+
+    pub fn draw_line(
+        &mut self,
+        (x0, y0): (i32, i32),
+        (x1, y1): (i32, i32),
+        thickness: u32,
+        pixel: Pixel,
+    ) {
+        let (from, to) = (self.translate(x0, y0), self.translate(x1, y1));
+        self.canvas
+            .draw_line_in(&self.clip, from, to, thickness, &pixel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [BlendMode; 6] = [
+        BlendMode::Replace,
+        BlendMode::AlphaOver(0),
+        BlendMode::AlphaOver(128),
+        BlendMode::AlphaOver(255),
+        BlendMode::Add,
+        BlendMode::Multiply,
+    ];
+
+    fn gray(v: u8) -> Pixel {
+        Pixel::new(v, v, v)
+    }
+
+    // Every 5th level, both ends included
+    fn levels() -> impl Iterator<Item = u8> + Clone {
+        (0..=255).step_by(5)
+    }
+
+    #[test]
+    fn identities() {
+        for d in levels() {
+            let dst = Pixel::new(d, 255 - d, d / 2);
+            assert_eq!(BlendMode::AlphaOver(0).blend(&dst, &gray(77)), dst);
+            assert_eq!(BlendMode::Add.blend(&dst, &gray(0)), dst);
+            assert_eq!(BlendMode::Multiply.blend(&dst, &gray(255)), dst);
+
+            let src = dst.clone();
+            assert_eq!(BlendMode::Replace.blend(&gray(77), &src), src);
+            assert_eq!(BlendMode::AlphaOver(255).blend(&gray(77), &src), src);
+        }
+    }
+
+    #[test]
+    fn results_stay_in_range() {
+        for (d, s) in levels().flat_map(|d| levels().map(move |s| (d, s))) {
+            let (dst, src) = (gray(d), gray(s));
+            assert_eq!(BlendMode::Add.blend(&dst, &src), gray(d.saturating_add(s)));
+
+            // Darker than both, as multiplying fractions of 1 is
+            let multiplied = BlendMode::Multiply.blend(&dst, &src).r;
+            assert!(multiplied <= d.min(s), "{d} * {s} = {multiplied}");
+
+            // Somewhere between the two
+            let over = BlendMode::AlphaOver(128).blend(&dst, &src).r;
+            assert!(
+                (d.min(s)..=d.max(s)).contains(&over),
+                "{d} over {s} = {over}"
+            );
+        }
+    }
+
+    #[test]
+    fn clamping_at_the_ends() {
+        assert_eq!(BlendMode::Add.blend(&gray(200), &gray(100)), gray(255));
+        assert_eq!(BlendMode::Add.blend(&gray(255), &gray(255)), gray(255));
+        assert_eq!(BlendMode::Multiply.blend(&gray(255), &gray(255)), gray(255));
+        assert_eq!(BlendMode::Multiply.blend(&gray(0), &gray(255)), gray(0));
+        assert_eq!(
+            BlendMode::AlphaOver(128).blend(&gray(0), &gray(255)),
+            gray(128)
+        );
+        for mode in MODES {
+            assert_eq!(mode.blend(&gray(0), &gray(0)), gray(0), "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn shapes_blend_with_the_canvas_mode() {
+        let mut canvas = Canvas::filled(4, 4, gray(100));
+        canvas.set_blend_mode(BlendMode::Add);
+        canvas.fill_rect(0, 0, 2, 4, gray(100));
+        canvas.fill_rect(1, 0, 2, 4, gray(100));
+
+        let row: Vec<u8> = canvas[..4].iter().map(|p| p.r).collect();
+        assert_eq!(row, [200, 255, 200, 100]);
+
+        let mut stamp = Canvas::filled(2, 2, gray(128));
+        stamp.set_blend_mode(BlendMode::Add);
+        canvas.set_blend_mode(BlendMode::Multiply);
+        canvas.draw_canvas(&stamp, 2, 2);
+        assert_eq!(canvas.get_pixel(3, 3), Some(&gray(50)));
+        // The mode belongs to the canvas drawn on, not the one drawn
+        assert_eq!(canvas.get_pixel(2, 2), Some(&gray(100)));
+    }
+
+    #[test]
+    fn views_blend_like_their_canvas() {
+        let mut canvas = Canvas::filled(4, 4, gray(100));
+        canvas.set_blend_mode(BlendMode::AlphaOver(0));
+        let mut view = canvas.view(Rect::new(1, 1, 2, 2));
+        view.draw_pixel(0, 0, gray(255));
+        view.fill(gray(255));
+        assert_eq!(canvas, Canvas::filled(4, 4, gray(100)));
+    }
+
+    #[test]
+    fn set_pixels_ignore_the_mode() {
+        let mut canvas = Canvas::filled(4, 4, gray(100));
+        canvas.set_blend_mode(BlendMode::Add);
+
+        canvas.set_pixel(0, 0, gray(10));
+        assert_eq!(canvas.get_pixel(0, 0), Some(&gray(10)));
+        canvas.draw_pixel(0, 1, gray(10));
+        assert_eq!(canvas.get_pixel(0, 1), Some(&gray(110)));
+        canvas.draw_pixel(-1, 4, gray(10));
+        canvas.paste(&Canvas::filled(1, 1, gray(20)), 1, 0);
+        assert_eq!(canvas.get_pixel(1, 0), Some(&gray(20)));
+        canvas.fill(gray(30));
+        assert_eq!(canvas, Canvas::filled(4, 4, gray(30)));
+    }
+
+    #[test]
+    fn the_mode_is_not_part_of_the_pixels() {
+        let mut canvas = Canvas::filled(4, 4, gray(1));
+        canvas.set_blend_mode(BlendMode::Multiply);
+        assert_eq!(canvas, Canvas::filled(4, 4, gray(1)));
+        assert_eq!(canvas.crop(0, 0, 2, 2).blend_mode(), BlendMode::Replace);
+    }
+}
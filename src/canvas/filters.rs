@@ -0,0 +1,390 @@
+use image::{GrayImage, RgbImage};
+
+use super::{Canvas, Pixel};
+use crate::{Error, Result};
+
+// Fraction of pixels ignored on each end of a channel's histogram by auto_levels, so a handful of
+// dead or blown out pixels can't pin the range
+const AUTO_LEVELS_CLIP: f64 = 0.005;
+
+// Width in pixels of the column strips the vertical blur pass works through
+const BLUR_STRIP: usize = 256;
+
+impl Canvas {
+    /// Stretches every channel so its 0.5th and 99.5th percentiles map to 0 and 255.
+    pub fn auto_levels(&mut self) {
+        let mut histograms = [[0usize; 256]; 3];
+
+        for pixel in self.iter() {
+            histograms[0][pixel.r as usize] += 1;
+            histograms[1][pixel.g as usize] += 1;
+            histograms[2][pixel.b as usize] += 1;
+        }
+
+        let clip = (self.len() as f64 * AUTO_LEVELS_CLIP) as usize;
+        let [r, g, b] = histograms.map(|histogram| levels_lut(&histogram, clip));
+
+        for pixel in self.iter_mut() {
+            pixel.r = r[pixel.r as usize];
+            pixel.g = g[pixel.g as usize];
+            pixel.b = b[pixel.b as usize];
+        }
+    }
+
+    /// Maps every channel through its own lookup table, in a single pass.
+    pub fn apply_curves(&mut self, r: &[u8; 256], g: &[u8; 256], b: &[u8; 256]) {
+        for pixel in self.iter_mut() {
+            pixel.r = r[pixel.r as usize];
+            pixel.g = g[pixel.g as usize];
+            pixel.b = b[pixel.b as usize];
+        }
+    }
+
+    /// Reduces every channel to the precision a visual of the given depth stores it with, to
+    /// preview on a deep screen how the buffer would look on a shallow one. Depths without a
+    /// known channel layout leave the buffer untouched, see [`channel_bits`].
+    pub fn reduce_depth(&mut self, depth: u8) {
+        let Some(bits) = channel_bits(depth) else {
+            return;
+        };
+        let [r, g, b] = bits.map(reduction_lut);
+
+        self.apply_curves(&r, &g, &b);
+    }
+
+    pub fn grayscale(&mut self) {
+        for pixel in self.iter_mut() {
+            let luma = luma(pixel);
+            *pixel = Pixel::new(luma, luma, luma);
+        }
+    }
+
+    /// Adds `amount` to every channel, saturating at 0 and 255.
+    pub fn brightness(&mut self, amount: i16) {
+        let shift = |v: u8| (v as i16 + amount).clamp(0, 255) as u8;
+
+        for pixel in self.iter_mut() {
+            *pixel = Pixel::new(shift(pixel.r), shift(pixel.g), shift(pixel.b));
+        }
+    }
+
+    /// Blends `color` over every pixel, an `opacity` of 1 replacing the buffer with it and 0
+    /// leaving it untouched. Tinting towards black dims a wallpaper, towards orange warms it.
+    pub fn tint(&mut self, color: &Pixel, opacity: f32) {
+        let opacity = opacity.clamp(0.0, 1.0);
+        let lerp =
+            |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * opacity).round() as u8;
+
+        for pixel in self.iter_mut() {
+            *pixel = Pixel::new(
+                lerp(pixel.r, color.r),
+                lerp(pixel.g, color.g),
+                lerp(pixel.b, color.b),
+            );
+        }
+    }
+
+    /// Composites `a` and `b` into the buffer, each pixel of `mask` picking how much of `b` shows
+    /// through: 0 is all `a`, 255 all `b`. A gradient mask makes for e.g. a day/night blend. All
+    /// three images have to be the size of the buffer.
+    pub fn blend_with_mask(&mut self, a: &RgbImage, b: &RgbImage, mask: &GrayImage) -> Result<()> {
+        let expected = (self.width, self.height);
+        for found in [a.dimensions(), b.dimensions(), mask.dimensions()] {
+            if found != expected {
+                return Err(Error::ImageSizeMismatch { expected, found });
+            }
+        }
+
+        let lerp = |from: u8, to: u8, t: u8| {
+            ((from as u32 * (255 - t as u32) + to as u32 * t as u32 + 127) / 255) as u8
+        };
+
+        for (pixel, ((a, b), t)) in self
+            .iter_mut()
+            .zip(a.pixels().zip(b.pixels()).zip(mask.pixels()))
+        {
+            let t = t[0];
+            *pixel = Pixel::new(
+                lerp(a[0], b[0], t),
+                lerp(a[1], b[1], t),
+                lerp(a[2], b[2], t),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Darkens the buffer towards its corners. A `strength` of 1 turns the corners black, 0
+    /// leaves the buffer untouched.
+    pub fn vignette(&mut self, strength: f32) {
+        let strength = strength.clamp(0.0, 1.0);
+        let (cx, cy) = (self.width as f32 / 2.0, self.height as f32 / 2.0);
+        let max_distance = cx * cx + cy * cy;
+
+        if max_distance == 0.0 {
+            return;
+        }
+
+        let width = self.width as usize;
+        for (i, pixel) in self.iter_mut().enumerate() {
+            let dx = (i % width) as f32 + 0.5 - cx;
+            let dy = (i / width) as f32 + 0.5 - cy;
+            let factor = 1.0 - strength * (dx * dx + dy * dy) / max_distance;
+            let scale = |v: u8| (v as f32 * factor).round().clamp(0.0, 255.0) as u8;
+
+            *pixel = Pixel::new(scale(pixel.r), scale(pixel.g), scale(pixel.b));
+        }
+    }
+
+    /// Gaussian blur with the given standard deviation in pixels, applied as two separable
+    /// passes. Edges are extended by clamping.
+    pub fn blur(&mut self, sigma: f32) {
+        if sigma <= 0.0 || self.is_empty() {
+            return;
+        }
+
+        let kernel = gaussian_kernel(sigma);
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut scratch = vec![[0f32; 3]; self.len()];
+
+        for y in 0..height {
+            let row = &self.pixels[y * width..(y + 1) * width];
+            for x in 0..width {
+                scratch[y * width + x] = convolve(&kernel, x, width, |i| {
+                    [row[i].r as f32, row[i].g as f32, row[i].b as f32]
+                });
+            }
+        }
+
+        // Walking a single column would touch a new cache line for every tap of the kernel. A
+        // strip of neighbouring columns shares those lines instead, while the rows the kernel
+        // spans stay small enough to remain cached
+        for x0 in (0..width).step_by(BLUR_STRIP) {
+            let strip = x0..(x0 + BLUR_STRIP).min(width);
+
+            for y in 0..height {
+                for x in strip.clone() {
+                    let [r, g, b] = convolve(&kernel, y, height, |i| scratch[i * width + x])
+                        .map(|v| v.round().clamp(0.0, 255.0) as u8);
+                    self.pixels[y * width + x] = Pixel::new(r, g, b);
+                }
+            }
+        }
+    }
+}
+
+/// Bits per red, green and blue channel of the usual TrueColor layout for `depth`, or `None` for
+/// depths shade does not know how to pack.
+pub fn channel_bits(depth: u8) -> Option<[u32; 3]> {
+    match depth {
+        8 => Some([3, 3, 2]),
+        15 => Some([5, 5, 5]),
+        16 => Some([5, 6, 5]),
+        24 | 32 => Some([8, 8, 8]),
+        _ => None,
+    }
+}
+
+// Truncates to the top `bits` bits, then spreads the result back over the full range so white
+// stays white
+fn reduction_lut(bits: u32) -> [u8; 256] {
+    let max = (1u32 << bits) - 1;
+    let mut lut = [0u8; 256];
+
+    for (v, out) in lut.iter_mut().enumerate() {
+        let reduced = v as u32 >> (8 - bits);
+        *out = ((reduced * 255 + max / 2) / max) as u8;
+    }
+
+    lut
+}
+
+/// A lookup table raising each value, normalized to `[0, 1]`, to the power of `1 / gamma`. A
+/// `gamma` above 1 brightens, below 1 darkens.
+pub fn gamma_lut(gamma: f32) -> [u8; 256] {
+    let exponent = 1.0 / gamma.max(f32::EPSILON);
+    let mut lut = [0u8; 256];
+
+    for (v, out) in lut.iter_mut().enumerate() {
+        *out = ((v as f32 / 255.0).powf(exponent) * 255.0)
+            .round()
+            .clamp(0.0, 255.0) as u8;
+    }
+
+    lut
+}
+
+// Rec. 601 weights, integer only
+pub(crate) fn luma(pixel: &Pixel) -> u8 {
+    ((pixel.r as u32 * 299 + pixel.g as u32 * 587 + pixel.b as u32 * 114 + 500) / 1000) as u8
+}
+
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let radius = (sigma * 3.0).ceil() as isize;
+    let mut kernel: Vec<f32> = (-radius..=radius)
+        .map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect();
+
+    let sum: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|k| *k /= sum);
+    kernel
+}
+
+// Weighted sum of the samples around `center`, clamping reads to [0, len)
+fn convolve(
+    kernel: &[f32],
+    center: usize,
+    len: usize,
+    sample: impl Fn(usize) -> [f32; 3],
+) -> [f32; 3] {
+    let radius = kernel.len() / 2;
+    let mut acc = [0f32; 3];
+
+    for (k, weight) in kernel.iter().enumerate() {
+        let value = sample((center + k).saturating_sub(radius).min(len - 1));
+        acc[0] += value[0] * weight;
+        acc[1] += value[1] * weight;
+        acc[2] += value[2] * weight;
+    }
+
+    acc
+}
+
+fn levels_lut(histogram: &[usize; 256], clip: usize) -> [u8; 256] {
+    let mut seen = 0;
+    let low = (0..256)
+        .find(|&v| {
+            seen += histogram[v];
+            seen > clip
+        })
+        .unwrap_or(0);
+
+    seen = 0;
+    let high = (0..256)
+        .rev()
+        .find(|&v| {
+            seen += histogram[v];
+            seen > clip
+        })
+        .unwrap_or(255);
+
+    let mut lut = [0u8; 256];
+
+    // A flat channel has no range to stretch, leave it alone
+    if high <= low {
+        for (v, out) in lut.iter_mut().enumerate() {
+            *out = v as u8;
+        }
+        return lut;
+    }
+
+    let range = high - low;
+    for (v, out) in lut.iter_mut().enumerate() {
+        let v = v.clamp(low, high) - low;
+        *out = ((v * 255 + range / 2) / range) as u8;
+    }
+
+    lut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A horizontal gradient through every level from `low` to `high`, the same on all channels
+    fn gradient(low: u8, high: u8) -> Canvas {
+        let mut canvas = Canvas::new(256, 4);
+        let span = (high - low) as u32;
+        for y in 0..4 {
+            for x in 0..256 {
+                let v = low + (x * span / 255) as u8;
+                canvas.set_pixel(x, y, Pixel::new(v, v, v));
+            }
+        }
+        canvas
+    }
+
+    fn range(canvas: &Canvas) -> (u8, u8) {
+        let values = canvas.iter().map(|p| p.r);
+        (values.clone().min().unwrap(), values.max().unwrap())
+    }
+
+    #[test]
+    fn auto_levels_stretches_to_the_full_range() {
+        let mut canvas = gradient(100, 150);
+        canvas.auto_levels();
+
+        // 0.5% of the pixels are clipped on either end, the rest spans 0 to 255
+        assert_eq!(range(&canvas), (0, 255));
+        let row: Vec<u8> = canvas[..256].iter().map(|p| p.r).collect();
+        assert!(row.windows(2).all(|w| w[0] <= w[1]), "levels out of order");
+        assert!(canvas.iter().all(|p| p.r == p.g && p.g == p.b));
+    }
+
+    #[test]
+    fn auto_levels_ignores_outliers() {
+        let mut canvas = gradient(100, 150);
+        // A dead and a stuck pixel, far fewer than the clipped 0.5%
+        canvas.set_pixel(0, 0, Pixel::new(0, 0, 0));
+        canvas.set_pixel(255, 3, Pixel::new(255, 255, 255));
+        canvas.auto_levels();
+
+        // Without clipping they would have pinned the range and nothing would have changed
+        let middle = canvas.get_pixel(128, 1).unwrap().r;
+        assert!((120..=135).contains(&middle), "{middle}");
+        assert_eq!(canvas.get_pixel(0, 1).unwrap().r, 0);
+        assert_eq!(canvas.get_pixel(255, 1).unwrap().r, 255);
+    }
+
+    #[test]
+    fn auto_levels_stretches_each_channel_on_its_own() {
+        let mut canvas = gradient(40, 90);
+        for pixel in canvas.iter_mut() {
+            pixel.g = 200;
+        }
+        canvas.auto_levels();
+
+        assert_eq!(range(&canvas), (0, 255));
+        // Flat channels have nothing to stretch
+        assert!(canvas.iter().all(|p| p.g == 200));
+    }
+
+    #[test]
+    fn auto_levels_leaves_solid_canvases_alone() {
+        let mut canvas = Canvas::filled(8, 8, Pixel::new(9, 99, 199));
+        canvas.auto_levels();
+        assert_eq!(canvas, Canvas::filled(8, 8, Pixel::new(9, 99, 199)));
+    }
+
+    #[test]
+    fn tinting_white_halfway_to_black_is_mid_gray() {
+        let mut canvas = Canvas::filled(4, 4, Pixel::new(255, 255, 255));
+        canvas.tint(&Pixel::new(0, 0, 0), 0.5);
+        // 127.5 rounds up
+        assert_eq!(canvas, Canvas::filled(4, 4, Pixel::new(128, 128, 128)));
+    }
+
+    #[test]
+    fn tinting_moves_each_channel_towards_the_color() {
+        let mut canvas = Canvas::filled(2, 2, Pixel::new(0, 100, 200));
+        canvas.tint(&Pixel::new(200, 100, 0), 0.25);
+        assert_eq!(canvas[0], Pixel::new(50, 100, 150));
+    }
+
+    #[test]
+    fn tint_opacity_is_clamped() {
+        let original = gradient(0, 255);
+        let orange = Pixel::new(255, 128, 0);
+
+        for opacity in [0.0, -1.0, f32::NEG_INFINITY] {
+            let mut canvas = original.clone();
+            canvas.tint(&orange, opacity);
+            assert_eq!(canvas, original, "{opacity}");
+        }
+        for opacity in [1.0, 2.5, f32::INFINITY] {
+            let mut canvas = original.clone();
+            canvas.tint(&orange, opacity);
+            assert!(canvas.iter().all(|p| *p == orange), "{opacity}");
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::fmt::Debug;
+
+use image::RgbaImage;
+
+use super::{BlendMode, Canvas, Pixel, Rect};
+
+/// The pixels a [`Canvas`] can be made of: [`Rgb8`], what the screen shows and the default, or
+/// [`Rgba8`] for images that keep their transparency.
+pub trait PixelFormat: Clone + Debug + Default + PartialEq + Send + Sync + 'static {
+    /// Bytes per pixel in memory.
+    const BYTES: usize;
+
+    fn from_pixel(pixel: &Pixel) -> Self;
+
+    /// The color with any alpha dropped, not applied.
+    fn to_pixel(&self) -> Pixel;
+
+    /// 255 for formats without alpha.
+    fn alpha(&self) -> u8;
+}
+
+/// The plain 3 byte pixel every canvas used to be made of.
+pub type Rgb8 = Pixel;
+
+impl PixelFormat for Pixel {
+    const BYTES: usize = 3;
+
+    fn from_pixel(pixel: &Pixel) -> Pixel {
+        pixel.clone()
+    }
+
+    fn to_pixel(&self) -> Pixel {
+        self.clone()
+    }
+
+    fn alpha(&self) -> u8 {
+        255
+    }
+}
+
+/// A pixel with straight (not premultiplied) alpha, 0 being fully transparent. Defaults to
+/// [`Rgba8::TRANSPARENT`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rgba8 {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Rgba8 {
+    pub const TRANSPARENT: Rgba8 = Rgba8::new(0, 0, 0, 0);
+
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Rgba8 {
+        Rgba8 { r, g, b, a }
+    }
+}
+
+impl PixelFormat for Rgba8 {
+    const BYTES: usize = 4;
+
+    fn from_pixel(pixel: &Pixel) -> Rgba8 {
+        Rgba8::new(pixel.r, pixel.g, pixel.b, 255)
+    }
+
+    fn to_pixel(&self) -> Pixel {
+        Pixel::new(self.r, self.g, self.b)
+    }
+
+    fn alpha(&self) -> u8 {
+        self.a
+    }
+}
+
+impl Canvas<Rgba8> {
+    /// A canvas nothing shows through yet, e.g. for a layer to draw onto.
+    pub fn transparent(width: u32, height: u32) -> Canvas<Rgba8> {
+        Canvas::filled_with(width, height, Rgba8::TRANSPARENT)
+    }
+
+    /// Takes an image over as is, alpha included. [`Canvas::from_image`] flattens it instead.
+    pub fn from_rgba_image(image: &RgbaImage) -> Canvas<Rgba8> {
+        Canvas {
+            width: image.width(),
+            height: image.height(),
+            pixels: image
+                .pixels()
+                .map(|p| Rgba8::new(p[0], p[1], p[2], p[3]))
+                .collect(),
+            blend: BlendMode::default(),
+        }
+    }
+
+    /// The canvas composited onto a solid `background`, leaving no alpha.
+    pub fn flatten(&self, background: &Pixel) -> Canvas {
+        let mut flat = Canvas::filled(self.width, self.height, background.clone());
+        flat.composite(self, 0, 0);
+        flat
+    }
+}
+
+impl Canvas {
+    /// The canvas as fully opaque [`Rgba8`] pixels.
+    pub fn to_rgba(&self) -> Canvas<Rgba8> {
+        Canvas {
+            width: self.width,
+            height: self.height,
+            pixels: self.iter().map(Rgba8::from_pixel).collect(),
+            blend: BlendMode::default(),
+        }
+    }
+
+    /// Draws `layer` over the canvas with its top left corner at (x, y), each pixel going over at
+    /// its own alpha. Whatever falls outside is clipped, and the canvas' blend mode is not used.
+    pub fn composite(&mut self, layer: &Canvas<Rgba8>, x: i32, y: i32) {
+        let area = Rect::new(x, y, layer.width, layer.height).intersect(&self.bounds());
+        let stride = self.width as usize;
+
+        for row in 0..area.height as usize {
+            let dst_y = area.y as usize + row;
+            let src_y = (area.y - y) as usize + row;
+
+            for column in 0..area.width as usize {
+                let dst_x = area.x as usize + column;
+                let src_x = (area.x - x) as usize + column;
+
+                let src = &layer.pixels[src_y * layer.width as usize + src_x];
+                let dst = &mut self.pixels[dst_y * stride + dst_x];
+                *dst = match src.a {
+                    0 => continue,
+                    255 => src.to_pixel(),
+                    a => BlendMode::AlphaOver(a).blend(dst, &src.to_pixel()),
+                };
+            }
+        }
+    }
+}
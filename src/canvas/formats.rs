@@ -0,0 +1,45 @@
+use image::{
+    error::{ImageFormatHint, UnsupportedErrorKind},
+    ImageError, ImageFormat,
+};
+
+// Every format the image crate knows of, whether or not its decoder is compiled in
+const KNOWN_FORMATS: [ImageFormat; 15] = [
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::Gif,
+    ImageFormat::WebP,
+    ImageFormat::Pnm,
+    ImageFormat::Tiff,
+    ImageFormat::Tga,
+    ImageFormat::Dds,
+    ImageFormat::Bmp,
+    ImageFormat::Ico,
+    ImageFormat::Hdr,
+    ImageFormat::OpenExr,
+    ImageFormat::Farbfeld,
+    ImageFormat::Avif,
+    ImageFormat::Qoi,
+];
+
+/// File extensions of every format this build can decode, lowercase and without the dot.
+pub fn supported_formats() -> Vec<&'static str> {
+    KNOWN_FORMATS
+        .into_iter()
+        .filter(|&format| can_decode(format))
+        .flat_map(ImageFormat::extensions_str)
+        .copied()
+        .collect()
+}
+
+// ImageFormat::can_read ignores which codecs are compiled in, but asking a disabled codec to decode
+// anything fails with an unsupported format error before a single byte is read
+fn can_decode(format: ImageFormat) -> bool {
+    match image::load_from_memory_with_format(&[], format) {
+        Err(ImageError::Unsupported(e)) => !matches!(
+            e.kind(),
+            UnsupportedErrorKind::Format(ImageFormatHint::Exact(f)) if f == format
+        ),
+        _ => true,
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::VecDeque;
+
+use super::{BlendMode, Canvas, MemoryBudget, MemoryConsumer, Pixel, Reservation};
+use crate::{Error, Result};
+
+/// A saved copy of a canvas, either verbatim or run-length encoded.
+///
+/// Generated wallpapers tend to be made of large flat areas and smooth gradients drawn row by
+/// row, where runs of identical pixels compress well for next to no cost. Photos do not, so
+/// compression is left to the caller's judgement.
+pub(crate) enum Snapshot {
+    Raw(Canvas),
+    Runs {
+        width: u32,
+        height: u32,
+        runs: Vec<(u32, Pixel)>,
+    },
+}
+
+impl Snapshot {
+    pub(crate) fn capture(canvas: &Canvas, compress: bool) -> Snapshot {
+        if !compress {
+            return Snapshot::Raw(canvas.clone());
+        }
+
+        let mut runs: Vec<(u32, Pixel)> = Vec::new();
+        for pixel in canvas.iter() {
+            match runs.last_mut() {
+                Some((count, last)) if last == pixel && *count < u32::MAX => *count += 1,
+                _ => runs.push((1, pixel.clone())),
+            }
+        }
+
+        Snapshot::Runs {
+            width: canvas.width,
+            height: canvas.height,
+            runs,
+        }
+    }
+
+    fn footprint(&self) -> usize {
+        match self {
+            Snapshot::Raw(canvas) => std::mem::size_of_val::<[Pixel]>(canvas),
+            Snapshot::Runs { runs, .. } => std::mem::size_of_val::<[(u32, Pixel)]>(runs),
+        }
+    }
+
+    pub(crate) fn geometry(&self) -> (u32, u32) {
+        match self {
+            Snapshot::Raw(canvas) => (canvas.width, canvas.height),
+            Snapshot::Runs { width, height, .. } => (*width, *height),
+        }
+    }
+
+    pub(crate) fn restore(self) -> Canvas {
+        match self {
+            Snapshot::Raw(canvas) => canvas,
+            Snapshot::Runs {
+                width,
+                height,
+                runs,
+            } => {
+                let mut pixels = Vec::with_capacity(width as usize * height as usize);
+                for (count, pixel) in runs {
+                    pixels.extend(std::iter::repeat_n(pixel, count as usize));
+                }
+
+                Canvas {
+                    width,
+                    height,
+                    pixels: pixels.into_boxed_slice(),
+                    blend: BlendMode::default(),
+                }
+            }
+        }
+    }
+}
+
+/// A bounded stack of snapshots, the oldest being dropped once `depth` is exceeded or to make room
+/// in the memory budget. A depth of 0 keeps nothing.
+#[derive(Default)]
+pub(crate) struct History {
+    pub(crate) depth: usize,
+    pub(crate) compress: bool,
+    budget: MemoryBudget,
+    snapshots: VecDeque<(Snapshot, Reservation)>,
+}
+
+impl History {
+    pub(crate) fn new(budget: MemoryBudget) -> History {
+        History {
+            budget,
+            ..History::default()
+        }
+    }
+
+    pub(crate) fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        while self.snapshots.len() > depth {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// Drops the oldest snapshots until the new one fits the budget. Fails with
+    /// [`Error::MemoryBudgetExceeded`] if it would not fit even with all of them gone, keeping
+    /// them in that case.
+    pub(crate) fn push(&mut self, canvas: &Canvas) -> Result<()> {
+        if self.depth == 0 {
+            return Ok(());
+        }
+
+        let snapshot = Snapshot::capture(canvas, self.compress);
+        let size = snapshot.footprint();
+
+        let kept: usize = self.snapshots.iter().map(|(s, _)| s.footprint()).sum();
+        if let Some(available) = self.budget.report().available() {
+            if available.saturating_add(kept) < size {
+                return Err(Error::MemoryBudgetExceeded {
+                    consumer: MemoryConsumer::History,
+                    requested: size,
+                    available,
+                });
+            }
+        }
+
+        if self.snapshots.len() == self.depth {
+            self.snapshots.pop_front();
+        }
+        // Others sharing the budget may still take what was counted on above
+        let reservation = loop {
+            match self.budget.reserve(MemoryConsumer::History, size) {
+                Ok(reservation) => break reservation,
+                Err(e) if self.snapshots.is_empty() => return Err(e),
+                Err(_) => self.snapshots.pop_front(),
+            };
+        };
+
+        self.snapshots.push_back((snapshot, reservation));
+        Ok(())
+    }
+
+    /// The most recent snapshot taken at the given geometry. Snapshots of any other size can never
+    /// be restored again and are dropped along the way.
+    pub(crate) fn pop(&mut self, width: u32, height: u32) -> Option<Canvas> {
+        self.snapshots
+            .retain(|(s, _)| s.geometry() == (width, height));
+        self.snapshots.pop_back().map(|(s, _)| s.restore())
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 16 pixels of 3 bytes each, uncompressed
+    const FRAME: usize = 48;
+
+    fn frame(shade: u8) -> Canvas {
+        Canvas::filled(4, 4, Pixel::new(shade, shade, shade))
+    }
+
+    fn history(budget: &MemoryBudget, depth: usize) -> History {
+        let mut history = History::new(budget.clone());
+        history.set_depth(depth);
+        history
+    }
+
+    #[test]
+    fn oldest_snapshots_make_room_in_the_budget() {
+        let budget = MemoryBudget::bytes(2 * FRAME);
+        let mut history = history(&budget, 5);
+        for shade in 1..=3 {
+            history.push(&frame(shade)).unwrap();
+        }
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(budget.report().history, 2 * FRAME);
+        assert_eq!(history.pop(4, 4), Some(frame(3)));
+        assert_eq!(history.pop(4, 4), Some(frame(2)));
+        assert_eq!(history.pop(4, 4), None);
+        assert_eq!(budget.report().history, 0);
+    }
+
+    #[test]
+    fn snapshots_too_large_for_the_budget_are_denied() {
+        let budget = MemoryBudget::bytes(FRAME + FRAME / 2);
+        let mut history = history(&budget, 5);
+        history.push(&frame(1)).unwrap();
+
+        assert!(matches!(
+            history.push(&Canvas::new(8, 8)),
+            Err(Error::MemoryBudgetExceeded {
+                consumer: MemoryConsumer::History,
+                ..
+            })
+        ));
+        // What was kept before stays
+        assert_eq!(history.len(), 1);
+        assert_eq!(budget.report().history, FRAME);
+    }
+
+    // Others holding on to the budget are never evicted for the history's sake
+    #[test]
+    fn others_on_the_budget_are_left_alone() {
+        let budget = MemoryBudget::bytes(2 * FRAME);
+        let _snapshot = budget.reserve(MemoryConsumer::Snapshots, FRAME).unwrap();
+        let mut history = history(&budget, 5);
+        history.push(&frame(1)).unwrap();
+        history.push(&frame(2)).unwrap();
+
+        assert_eq!(history.len(), 1);
+        assert_eq!(budget.report().snapshots, FRAME);
+        assert_eq!(history.pop(4, 4), Some(frame(2)));
+    }
+
+    #[test]
+    fn compressed_snapshots_count_their_runs() {
+        let budget = MemoryBudget::unlimited();
+        let mut history = history(&budget, 1);
+        history.compress = true;
+        history.push(&frame(7)).unwrap();
+
+        // One run of all 16 pixels
+        assert_eq!(budget.report().history, std::mem::size_of::<(u32, Pixel)>());
+        assert_eq!(history.pop(4, 4), Some(frame(7)));
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{Error, Result};
+
+/// What holds on to memory counted against a [`MemoryBudget`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MemoryConsumer {
+    /// Snapshots taken with `BackgroundHandle::snapshot`, for as long as any copy of them lives.
+    Snapshots,
+    /// The undo history.
+    History,
+    /// Images kept by an [`ImageCache`](super::ImageCache).
+    Cache,
+    /// The copy of the previous wallpaper `LoadConfig::keep_previous` keeps. It lives on the
+    /// server, but costs a screen's worth of memory all the same.
+    Previous,
+}
+
+impl MemoryConsumer {
+    const ALL: [MemoryConsumer; 4] = [
+        MemoryConsumer::Snapshots,
+        MemoryConsumer::History,
+        MemoryConsumer::Cache,
+        MemoryConsumer::Previous,
+    ];
+}
+
+impl fmt::Display for MemoryConsumer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MemoryConsumer::Snapshots => "snapshot",
+            MemoryConsumer::History => "undo history",
+            MemoryConsumer::Cache => "image cache",
+            MemoryConsumer::Previous => "previous wallpaper",
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Ledger {
+    limit: Option<usize>,
+    used: [usize; MemoryConsumer::ALL.len()],
+}
+
+impl Ledger {
+    fn total(&self) -> usize {
+        self.used.iter().sum()
+    }
+}
+
+/// A cap on the memory snapshots, the undo history, the image cache and the previous wallpaper
+/// take together, each of which can be a screen's worth on its own. Set it with
+/// `LoadConfig::memory_budget`, or [`ImageCache::set_budget`](super::ImageCache::set_budget) for
+/// caches of your own.
+///
+/// Clones share one budget, so a single one can cap several handles and caches at once. Once it
+/// runs out, the cache and the history evict their own oldest entries to make room. Snapshots
+/// and the previous wallpaper are denied instead, with [`Error::MemoryBudgetExceeded`] or a
+/// load warning, as nothing else they could drop is theirs to drop. Without a budget usage is
+/// still counted, only never denied.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryBudget {
+    ledger: Arc<Mutex<Ledger>>,
+}
+
+impl MemoryBudget {
+    /// A budget of `megabytes` MiB.
+    pub fn new(megabytes: usize) -> MemoryBudget {
+        MemoryBudget::bytes(megabytes.saturating_mul(1024 * 1024))
+    }
+
+    pub fn bytes(bytes: usize) -> MemoryBudget {
+        MemoryBudget {
+            ledger: Arc::new(Mutex::new(Ledger {
+                limit: Some(bytes),
+                ..Ledger::default()
+            })),
+        }
+    }
+
+    /// Counts what is used without ever denying anything, the default.
+    pub fn unlimited() -> MemoryBudget {
+        MemoryBudget::default()
+    }
+
+    /// The cap in bytes, `None` if unlimited.
+    pub fn limit(&self) -> Option<usize> {
+        self.lock().limit
+    }
+
+    /// What is counted against the budget right now, by whom.
+    pub fn report(&self) -> MemoryReport {
+        let ledger = self.lock();
+        let used = |consumer: MemoryConsumer| ledger.used[consumer as usize];
+
+        MemoryReport {
+            limit: ledger.limit,
+            snapshots: used(MemoryConsumer::Snapshots),
+            history: used(MemoryConsumer::History),
+            cache: used(MemoryConsumer::Cache),
+            previous: used(MemoryConsumer::Previous),
+            buffer: 0,
+        }
+    }
+
+    /// Counts `bytes` against the budget for as long as the reservation lives, failing with
+    /// [`Error::MemoryBudgetExceeded`] if they do not fit.
+    pub(crate) fn reserve(&self, consumer: MemoryConsumer, bytes: usize) -> Result<Reservation> {
+        let mut ledger = self.lock();
+        let total = ledger.total();
+        if let Some(limit) = ledger.limit {
+            if total.saturating_add(bytes) > limit {
+                return Err(Error::MemoryBudgetExceeded {
+                    consumer,
+                    requested: bytes,
+                    available: limit.saturating_sub(total),
+                });
+            }
+        }
+
+        ledger.used[consumer as usize] += bytes;
+        Ok(Reservation {
+            budget: self.clone(),
+            consumer,
+            bytes,
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Ledger> {
+        self.ledger.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Two handles on the same budget, not two budgets of the same size
+impl PartialEq for MemoryBudget {
+    fn eq(&self, other: &MemoryBudget) -> bool {
+        Arc::ptr_eq(&self.ledger, &other.ledger)
+    }
+}
+
+impl Eq for MemoryBudget {}
+
+/// Memory counted against a [`MemoryBudget`], given back when dropped.
+pub(crate) struct Reservation {
+    budget: MemoryBudget,
+    consumer: MemoryConsumer,
+    bytes: usize,
+}
+
+// A clone holds a copy of whatever the reservation covers, counted whether it fits or not:
+// cloning cannot fail
+impl Clone for Reservation {
+    fn clone(&self) -> Reservation {
+        self.budget.lock().used[self.consumer as usize] += self.bytes;
+        Reservation {
+            budget: self.budget.clone(),
+            consumer: self.consumer,
+            bytes: self.bytes,
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget.lock().used[self.consumer as usize] -= self.bytes;
+    }
+}
+
+impl fmt::Debug for Reservation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Reservation({} bytes of {})", self.bytes, self.consumer)
+    }
+}
+
+/// Memory in use, in bytes, see [`MemoryBudget::report`] and `BackgroundHandle::memory_usage`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// The budget's cap, `None` if unlimited.
+    pub limit: Option<usize>,
+    pub snapshots: usize,
+    pub history: usize,
+    pub cache: usize,
+    pub previous: usize,
+    /// A handle's buffer and layer, which it always has and no budget counts. Always 0 in
+    /// reports of the budget alone.
+    pub buffer: usize,
+}
+
+impl MemoryReport {
+    /// Everything counted against the budget.
+    pub fn budgeted(&self) -> usize {
+        self.snapshots + self.history + self.cache + self.previous
+    }
+
+    /// What is left of the budget, `None` if unlimited.
+    pub fn available(&self) -> Option<usize> {
+        self.limit
+            .map(|limit| limit.saturating_sub(self.budgeted()))
+    }
+
+    pub fn total(&self) -> usize {
+        self.budgeted() + self.buffer
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mib = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+
+        write!(f, "{:.1} MiB budgeted", mib(self.budgeted()))?;
+        if let Some(limit) = self.limit {
+            write!(f, " of {:.1} MiB", mib(limit))?;
+        }
+        write!(
+            f,
+            " (snapshots {:.1}, history {:.1}, cache {:.1}, previous {:.1}), buffer {:.1} MiB",
+            mib(self.snapshots),
+            mib(self.history),
+            mib(self.cache),
+            mib(self.previous),
+            mib(self.buffer)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reservations_count_until_dropped() {
+        let budget = MemoryBudget::bytes(100);
+        let snapshot = budget.reserve(MemoryConsumer::Snapshots, 30).unwrap();
+        let cached = budget.reserve(MemoryConsumer::Cache, 50).unwrap();
+
+        let report = budget.report();
+        assert_eq!(
+            (report.snapshots, report.cache, report.history),
+            (30, 50, 0)
+        );
+        assert_eq!(report.budgeted(), 80);
+        assert_eq!(report.available(), Some(20));
+
+        drop(snapshot);
+        assert_eq!(budget.report().snapshots, 0);
+        drop(cached);
+        assert_eq!(
+            budget.report(),
+            MemoryReport {
+                limit: Some(100),
+                ..MemoryReport::default()
+            }
+        );
+    }
+
+    #[test]
+    fn denied_once_it_runs_out() {
+        let budget = MemoryBudget::bytes(100);
+        let _kept = budget.reserve(MemoryConsumer::Previous, 60).unwrap();
+
+        assert!(matches!(
+            budget.reserve(MemoryConsumer::Snapshots, 41),
+            Err(Error::MemoryBudgetExceeded {
+                consumer: MemoryConsumer::Snapshots,
+                requested: 41,
+                available: 40,
+            })
+        ));
+        // Denying counts nothing, what is left still fits exactly
+        assert_eq!(budget.report().snapshots, 0);
+        assert!(budget.reserve(MemoryConsumer::Snapshots, 40).is_ok());
+    }
+
+    #[test]
+    fn clones_share_one_budget() {
+        let budget = MemoryBudget::bytes(100);
+        let shared = budget.clone();
+        assert_eq!(budget, shared);
+        assert_ne!(budget, MemoryBudget::bytes(100));
+
+        let history = shared.reserve(MemoryConsumer::History, 70).unwrap();
+        assert_eq!(budget.report().history, 70);
+        assert!(budget.reserve(MemoryConsumer::Cache, 40).is_err());
+
+        // A cloned reservation stands for a second copy and counts twice, over budget or not
+        let copy = history.clone();
+        assert_eq!(budget.report().history, 140);
+        drop(history);
+        drop(copy);
+        assert_eq!(budget.report().history, 0);
+    }
+
+    #[test]
+    fn unlimited_budgets_count_without_denying() {
+        let budget = MemoryBudget::unlimited();
+        let _huge = budget
+            .reserve(MemoryConsumer::Cache, usize::MAX / 2)
+            .unwrap();
+        let _more = budget
+            .reserve(MemoryConsumer::Cache, usize::MAX / 4)
+            .unwrap();
+
+        let report = budget.report();
+        assert_eq!(report.limit, None);
+        assert_eq!(report.available(), None);
+        assert_eq!(report.cache, usize::MAX / 2 + usize::MAX / 4);
+    }
+
+    #[test]
+    fn reports_in_mib() {
+        let report = MemoryReport {
+            limit: Some(8 * 1024 * 1024),
+            snapshots: 1024 * 1024,
+            history: 0,
+            cache: 2 * 1024 * 1024,
+            previous: 0,
+            buffer: 4 * 1024 * 1024,
+        };
+        assert_eq!(report.total(), 7 * 1024 * 1024);
+        assert_eq!(
+            report.to_string(),
+            "3.0 MiB budgeted of 8.0 MiB (snapshots 1.0, history 0.0, cache 2.0, previous 0.0), \
+             buffer 4.0 MiB"
+        );
+    }
+}
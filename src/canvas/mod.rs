@@ -0,0 +1,282 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+mod budget;
+mod cache;
+mod chain;
+mod color;
+mod contrast;
+pub(crate) mod decode;
+mod draw;
+mod filters;
+mod format;
+mod formats;
+#[cfg(feature = "x11")]
+mod history;
+mod memory;
+#[cfg(feature = "net")]
+mod net;
+mod quantize;
+mod record;
+mod scale;
+mod scaler;
+mod source;
+mod thumbnail;
+mod transform;
+
+pub use budget::FrameBudget;
+pub use cache::{CacheStats, ImageCache, PreparedImage};
+pub use chain::{Filter, FilterChain};
+pub use contrast::{contrast_ratio, relative_luminance, TextColor, MIN_TEXT_CONTRAST};
+pub(crate) use decode::default_pixel_limit;
+pub use draw::{BlendMode, CanvasView, Rect};
+pub use filters::{channel_bits, gamma_lut};
+pub use format::{PixelFormat, Rgb8, Rgba8};
+pub use formats::supported_formats;
+#[cfg(feature = "x11")]
+pub(crate) use history::History;
+pub(crate) use memory::Reservation;
+pub use memory::{MemoryBudget, MemoryConsumer, MemoryReport};
+#[cfg(feature = "x11")]
+pub(crate) use quantize::Colors;
+pub use record::{Frame, FrameRecorder};
+pub use scale::{compute_placement, compute_placement_with_aspect, Placement};
+pub use scaler::{CatmullRom, IntegerScaler, Resampling, Scaler};
+pub use source::WallpaperSource;
+
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pixel {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Pixel {
+    pub fn new(r: u8, g: u8, b: u8) -> Pixel {
+        Pixel { r, g, b }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScalingMethod {
+    Center,
+    Fill,
+    Max,
+    Scale,
+    Tile,
+}
+
+/// How an image is scaled onto a canvas: the [`ScalingMethod`], plus whether to resample in
+/// linear light and the shape of the pixels it ends up on.
+///
+/// Resampling the gamma encoded sRGB values directly, the default, darkens fine high contrast
+/// detail when downscaling. Linear light gets it right but is noticeably slower. Anywhere a
+/// `Scaling` is taken a plain [`ScalingMethod`] works too.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Scaling {
+    pub method: ScalingMethod,
+    pub linear_light: bool,
+    pub pixel_aspect: PixelAspect,
+    pub resampling: Resampling,
+}
+
+impl Scaling {
+    pub fn new(method: ScalingMethod) -> Scaling {
+        Scaling {
+            method,
+            linear_light: false,
+            pixel_aspect: PixelAspect::SQUARE,
+            resampling: Resampling::Builtin,
+        }
+    }
+
+    pub fn linear_light(mut self, linear_light: bool) -> Scaling {
+        self.linear_light = linear_light;
+        self
+    }
+
+    /// Lays images out for pixels of this shape, so that they keep their proportions on the
+    /// glass rather than in pixels. Only [`ScalingMethod::Fill`] and [`ScalingMethod::Max`]
+    /// compensate, the others place pixels one to one or stretch anyway. Square by default.
+    pub fn pixel_aspect(mut self, pixel_aspect: PixelAspect) -> Scaling {
+        self.pixel_aspect = pixel_aspect;
+        self
+    }
+
+    /// Resamples with `scaler` rather than the built in [`CatmullRom`], e.g. [`IntegerScaler`]
+    /// for pixel art. Placement stays up to the method, only the resampling is replaced, and
+    /// [`Scaling::linear_light`] no longer applies.
+    pub fn scaler(mut self, scaler: impl Scaler + 'static) -> Scaling {
+        self.resampling = Resampling::Custom(Arc::new(scaler));
+        self
+    }
+}
+
+/// The shape of a display's pixels as they show on the glass: their physical width to their
+/// height. Projectors and some TVs stretch a mode over a panel of another aspect ratio, leaving
+/// pixels that are not square.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PixelAspect {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for PixelAspect {
+    fn default() -> PixelAspect {
+        PixelAspect::SQUARE
+    }
+}
+
+impl PixelAspect {
+    pub const SQUARE: PixelAspect = PixelAspect {
+        width: 1,
+        height: 1,
+    };
+
+    /// Pixels `width` wide for every `height` tall, reduced to lowest terms. Square if either is
+    /// 0.
+    pub fn new(width: u32, height: u32) -> PixelAspect {
+        PixelAspect::reduced(width as u64, height as u64)
+    }
+
+    /// The pixel shape of `pixels` (width, height) shown on `millimeters` (width, height) of
+    /// glass. Square if the physical size is unknown, i.e. 0.
+    pub fn from_physical(pixels: (u32, u32), millimeters: (u32, u32)) -> PixelAspect {
+        // A pixel is mm_width / width wide and mm_height / height tall
+        PixelAspect::reduced(
+            millimeters.0 as u64 * pixels.1 as u64,
+            millimeters.1 as u64 * pixels.0 as u64,
+        )
+    }
+
+    fn reduced(width: u64, height: u64) -> PixelAspect {
+        if width == 0 || height == 0 {
+            return PixelAspect::SQUARE;
+        }
+
+        let gcd = gcd(width, height);
+        match (u32::try_from(width / gcd), u32::try_from(height / gcd)) {
+            (Ok(width), Ok(height)) => PixelAspect { width, height },
+            // Only nonsense physical sizes get here
+            _ => PixelAspect::SQUARE,
+        }
+    }
+
+    pub fn is_square(&self) -> bool {
+        self.width == self.height
+    }
+
+    // Width over height, exactly 1 for square pixels
+    pub(crate) fn ratio(&self) -> f64 {
+        match self.is_square() {
+            true => 1.0,
+            false => self.width as f64 / self.height as f64,
+        }
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
+
+impl From<ScalingMethod> for Scaling {
+    fn from(method: ScalingMethod) -> Scaling {
+        Scaling::new(method)
+    }
+}
+
+/// A row-major pixel buffer with a known geometry.
+///
+/// Dereferences to the underlying `[Pixel]` slice so it can be indexed like the plain buffer it
+/// replaces. Canvases of [`Rgba8`] pixels carry alpha, to be composited onto a plain one with
+/// [`Canvas::composite`]; everything else is only implemented for the plain kind.
+#[derive(Clone, Debug)]
+pub struct Canvas<P: PixelFormat = Pixel> {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) pixels: Box<[P]>,
+    pub(crate) blend: BlendMode,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Canvas {
+        Canvas::filled(width, height, Pixel::default())
+    }
+
+    pub fn filled(width: u32, height: u32, pixel: Pixel) -> Canvas {
+        Canvas::filled_with(width, height, pixel)
+    }
+
+    /// The buffer as `r, g, b` byte triples, row-major from the top left, with no row padding.
+    pub fn to_raw_rgb(&self) -> Vec<u8> {
+        self.iter().flat_map(|p| [p.r, p.g, p.b]).collect()
+    }
+}
+
+impl<P: PixelFormat> Canvas<P> {
+    /// A canvas of any pixel format, e.g. `Canvas::filled_with(w, h, Rgba8::TRANSPARENT)`.
+    pub fn filled_with(width: u32, height: u32, pixel: P) -> Canvas<P> {
+        Canvas {
+            width,
+            height,
+            pixels: vec![pixel; width as usize * height as usize].into_boxed_slice(),
+            blend: BlendMode::default(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn index_of(&self, x: u32, y: u32) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| y as usize * self.width as usize + x as usize)
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<&P> {
+        self.index_of(x, y).map(|i| &self.pixels[i])
+    }
+
+    // Out of bounds writes are ignored, so callers can draw partially offscreen shapes freely. The
+    // pixel is written as it is, Canvas::draw_pixel blends it
+    pub fn set_pixel(&mut self, x: u32, y: u32, pixel: P) {
+        if let Some(i) = self.index_of(x, y) {
+            self.pixels[i] = pixel;
+        }
+    }
+
+    pub fn fill(&mut self, pixel: P) {
+        self.pixels.fill(pixel);
+    }
+}
+
+// Canvases are equal when their pixels are, however they are set up to draw
+impl<P: PixelFormat> PartialEq for Canvas<P> {
+    fn eq(&self, other: &Canvas<P>) -> bool {
+        (self.width, self.height) == (other.width, other.height) && self.pixels == other.pixels
+    }
+}
+
+impl<P: PixelFormat> Deref for Canvas<P> {
+    type Target = [P];
+
+    fn deref(&self) -> &[P] {
+        &self.pixels
+    }
+}
+
+impl<P: PixelFormat> DerefMut for Canvas<P> {
+    fn deref_mut(&mut self) -> &mut [P] {
+        &mut self.pixels
+    }
+}
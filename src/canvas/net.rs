@@ -0,0 +1,71 @@
+use std::{io::Read, time::Duration};
+
+use super::{decode, default_pixel_limit, Canvas, Scaling};
+use crate::{Error, Result};
+
+// Generous for any wallpaper, and small enough that a hostile or broken server can't exhaust memory
+pub(crate) const DOWNLOAD_LIMIT: u64 = 64 * 1024 * 1024;
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl Canvas {
+    /// Downloads the image at `url` and lays it out like [`Canvas::from_image`]. Downloads larger
+    /// than 64 MiB or slower than 30 seconds are aborted, and images too large are refused like
+    /// [`Canvas::from_path`] does.
+    pub fn from_url(
+        url: &str,
+        width: u32,
+        height: u32,
+        scaling: impl Into<Scaling>,
+    ) -> Result<Canvas> {
+        let limit = default_pixel_limit(width, height);
+        Canvas::from_url_within(url, width, height, scaling, limit)
+    }
+
+    /// [`Canvas::from_url`], refusing images of more than `max_pixels` pixels.
+    pub(crate) fn from_url_within(
+        url: &str,
+        width: u32,
+        height: u32,
+        scaling: impl Into<Scaling>,
+        max_pixels: u64,
+    ) -> Result<Canvas> {
+        let bytes = download(url, DOWNLOAD_LIMIT)?;
+
+        // Decoders are picked by magic bytes, servers are too often wrong about content types
+        let image = decode::load_from_memory(&bytes, max_pixels, Error::Image)?;
+        Ok(Canvas::from_image(&image, width, height, scaling))
+    }
+}
+
+fn download(url: &str, limit: u64) -> Result<Vec<u8>> {
+    let response = ureq::AgentBuilder::new()
+        .timeout(DOWNLOAD_TIMEOUT)
+        .build()
+        .get(url)
+        .call()
+        .map_err(|e| match e {
+            ureq::Error::Status(status, _) => Error::Http { status },
+            ureq::Error::Transport(e) => Error::Net(Box::new(e)),
+        })?;
+
+    let announced = response
+        .header("Content-Length")
+        .and_then(|len| len.parse::<u64>().ok());
+    if announced.is_some_and(|len| len > limit) {
+        return Err(Error::DownloadTooLarge { limit });
+    }
+
+    // Content-Length may be missing or lying, so the limit is enforced on the bytes themselves,
+    // reading one past it to tell a download that fits exactly from one that doesn't
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .take(limit + 1)
+        .read_to_end(&mut bytes)?;
+
+    if bytes.len() as u64 > limit {
+        return Err(Error::DownloadTooLarge { limit });
+    }
+
+    Ok(bytes)
+}
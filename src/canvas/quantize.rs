@@ -0,0 +1,259 @@
+use super::{Canvas, Pixel};
+
+impl Canvas {
+    /// Maps every pixel to the closest color of `palette`, optionally spreading the rounding error
+    /// over the neighbouring pixels with Floyd-Steinberg dithering. An empty palette leaves the
+    /// buffer untouched.
+    ///
+    /// The dithering diffuses error rather than adding noise, so the same canvas and palette
+    /// always come out the same, there is no seed to pin down.
+    pub fn quantize(&mut self, palette: &[Pixel], dither: bool) {
+        if palette.is_empty() {
+            return;
+        }
+
+        let mut colors = Colors::new(palette);
+        if !dither {
+            for pixel in self.iter_mut() {
+                *pixel =
+                    palette[colors.nearest([pixel.r, pixel.g, pixel.b].map(|v| v as i32))].clone();
+            }
+            return;
+        }
+
+        let width = self.width as usize;
+        // Error carried into the current and the next row, with a column of slack on either side
+        let mut current = vec![[0i32; 3]; width + 2];
+        let mut next = vec![[0i32; 3]; width + 2];
+
+        for row in self.pixels.chunks_exact_mut(width.max(1)) {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let error = current[x + 1];
+                let wanted = [
+                    (pixel.r as i32 + error[0] / 16).clamp(0, 255),
+                    (pixel.g as i32 + error[1] / 16).clamp(0, 255),
+                    (pixel.b as i32 + error[2] / 16).clamp(0, 255),
+                ];
+
+                let chosen = &palette[colors.nearest(wanted)];
+                let diff = [
+                    wanted[0] - chosen.r as i32,
+                    wanted[1] - chosen.g as i32,
+                    wanted[2] - chosen.b as i32,
+                ];
+
+                for c in 0..3 {
+                    current[x + 2][c] += diff[c] * 7;
+                    next[x][c] += diff[c] * 3;
+                    next[x + 1][c] += diff[c] * 5;
+                    next[x + 2][c] += diff[c];
+                }
+
+                *pixel = chosen.clone();
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            next.fill([0; 3]);
+        }
+    }
+
+    /// Up to `count` colors that cover most of the canvas, most common first, e.g. to theme other
+    /// programs after the wallpaper or as a palette for [`Canvas::quantize`]. Similar colors are
+    /// counted together and averaged, and large canvases are sampled rather than read in full.
+    pub fn dominant_colors(&self, count: usize) -> Vec<Pixel> {
+        // Every n-th pixel, for at most about this many samples
+        const SAMPLES: usize = 1 << 16;
+        let step = (self.pixels.len() / SAMPLES).max(1);
+
+        // 4 bits per channel, summed up as samples, r, g and b
+        let mut buckets = vec![[0u64; 4]; 1 << 12];
+        for pixel in self.pixels.iter().step_by(step) {
+            let index =
+                (pixel.r as usize >> 4) << 8 | (pixel.g as usize >> 4) << 4 | pixel.b as usize >> 4;
+            let bucket = &mut buckets[index];
+            bucket[0] += 1;
+            bucket[1] += pixel.r as u64;
+            bucket[2] += pixel.g as u64;
+            bucket[3] += pixel.b as u64;
+        }
+
+        buckets.retain(|bucket| bucket[0] > 0);
+        buckets.sort_by(|a, b| b[0].cmp(&a[0]));
+        buckets
+            .iter()
+            .take(count)
+            .map(|&[n, r, g, b]| Pixel::new((r / n) as u8, (g / n) as u8, (b / n) as u8))
+            .collect()
+    }
+
+    /// The color every pixel of the canvas has, `None` if they differ or there are none. Stops
+    /// at the first pixel that differs, so canvases that are not solid take next to no time.
+    pub fn solid_color(&self) -> Option<Pixel> {
+        let first = self.pixels.first()?;
+        self.pixels
+            .iter()
+            .all(|p| p == first)
+            .then(|| first.clone())
+    }
+}
+
+// A palette's channels kept apart, which the distances to all of its colors at once vectorize
+// well over
+pub(crate) struct Colors {
+    r: Vec<i32>,
+    g: Vec<i32>,
+    b: Vec<i32>,
+    distances: Vec<i32>,
+}
+
+impl Colors {
+    pub(crate) fn new(palette: &[Pixel]) -> Colors {
+        Colors {
+            r: palette.iter().map(|p| p.r as i32).collect(),
+            g: palette.iter().map(|p| p.g as i32).collect(),
+            b: palette.iter().map(|p| p.b as i32).collect(),
+            distances: vec![0; palette.len()],
+        }
+    }
+
+    // The index of the palette color closest to `rgb` by "redmean" weighted euclidean distance,
+    // a cheap but much closer fit to perceived difference than plain RGB distance. The first of
+    // equally close ones, 0 for an empty palette
+    pub(crate) fn nearest(&mut self, [r, g, b]: [i32; 3]) -> usize {
+        let channels = self.r.iter().zip(&self.g).zip(&self.b);
+        for (d, ((&pr, &pg), &pb)) in self.distances.iter_mut().zip(channels) {
+            let mean = (r + pr) / 2;
+            let (dr, dg, db) = (r - pr, g - pg, b - pb);
+            *d = (((512 + mean) * dr * dr) >> 8) + 4 * dg * dg + (((767 - mean) * db * db) >> 8);
+        }
+
+        let mut best = 0;
+        for (i, &d) in self.distances.iter().enumerate() {
+            if d < self.distances[best] {
+                best = i;
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solid_canvases_have_a_color() {
+        let color = Pixel::new(1, 2, 3);
+        assert_eq!(
+            Canvas::filled(1, 1, color.clone()).solid_color(),
+            Some(color.clone())
+        );
+        assert_eq!(
+            Canvas::filled(1920, 1080, color.clone()).solid_color(),
+            Some(color)
+        );
+    }
+
+    #[test]
+    fn a_single_differing_pixel_is_enough() {
+        for (x, y) in [(0, 0), (7, 3), (15, 15)] {
+            let mut canvas = Canvas::filled(16, 16, Pixel::new(1, 2, 3));
+            canvas.set_pixel(x, y, Pixel::new(1, 2, 4));
+            assert_eq!(canvas.solid_color(), None, "({x}, {y})");
+        }
+    }
+
+    fn gray(v: u8) -> Pixel {
+        Pixel::new(v, v, v)
+    }
+
+    fn mean(canvas: &Canvas) -> f64 {
+        canvas.iter().map(|p| p.r as f64).sum::<f64>() / canvas.len() as f64
+    }
+
+    #[test]
+    fn pixels_take_the_nearest_color() {
+        let palette = [
+            Pixel::new(255, 0, 0),
+            Pixel::new(0, 0, 255),
+            gray(0),
+            gray(255),
+        ];
+        let mut canvas = Canvas::new(5, 1);
+        for (x, pixel) in [
+            Pixel::new(200, 30, 20),
+            Pixel::new(10, 40, 180),
+            gray(60),
+            gray(230),
+            Pixel::new(255, 0, 0),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            canvas.set_pixel(x as u32, 0, pixel);
+        }
+
+        canvas.quantize(&palette, false);
+        let expected = [
+            &palette[0],
+            &palette[1],
+            &palette[2],
+            &palette[3],
+            &palette[0],
+        ];
+        assert_eq!(canvas.iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn empty_palettes_change_nothing() {
+        let mut canvas = Canvas::filled(4, 4, Pixel::new(1, 2, 3));
+        canvas.set_pixel(1, 1, gray(200));
+        let before = canvas.clone();
+
+        canvas.quantize(&[], false);
+        assert_eq!(canvas, before);
+        canvas.quantize(&[], true);
+        assert_eq!(canvas, before);
+    }
+
+    #[test]
+    fn dithering_keeps_the_average() {
+        let palette = [gray(0), gray(255)];
+        for level in [40, 100, 128, 220] {
+            let mut plain = Canvas::filled(64, 64, gray(level));
+            plain.quantize(&palette, false);
+            // Every pixel rounds the same way without dithering
+            assert!(plain.solid_color().is_some());
+
+            let mut dithered = Canvas::filled(64, 64, gray(level));
+            dithered.quantize(&palette, true);
+            assert!(dithered.iter().all(|p| palette.contains(p)));
+            let mean = mean(&dithered);
+            assert!(
+                (mean - level as f64).abs() < 4.0,
+                "{level} came out as {mean}"
+            );
+        }
+    }
+
+    #[test]
+    fn dithering_is_deterministic() {
+        let mut canvas = Canvas::new(32, 8);
+        for y in 0..8 {
+            for x in 0..32 {
+                canvas.set_pixel(x, y, Pixel::new((x * 8) as u8, (y * 32) as u8, 77));
+            }
+        }
+        let palette = canvas.dominant_colors(6);
+
+        let mut once = canvas.clone();
+        once.quantize(&palette, true);
+        canvas.quantize(&palette, true);
+        assert_eq!(once, canvas);
+    }
+
+    #[test]
+    fn empty_canvases_have_none() {
+        assert_eq!(Canvas::new(0, 10).solid_color(), None);
+    }
+}
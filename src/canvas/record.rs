@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+use super::Canvas;
+use crate::Result;
+
+/// One recorded frame, as handed to a [`FrameRecorder`] callback.
+pub struct Frame<'a> {
+    pub index: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed RGB bytes, see [`Canvas::to_raw_rgb`].
+    pub data: &'a [u8],
+}
+
+enum FrameSink {
+    Callback(Box<dyn FnMut(Frame) -> Result<()> + Send>),
+    Files(PathBuf),
+}
+
+/// Captures successive frames of an animation as raw RGB, for an external encoder to turn into a
+/// clip.
+pub struct FrameRecorder {
+    sink: FrameSink,
+    index: u64,
+}
+
+impl FrameRecorder {
+    pub fn with_callback(callback: impl FnMut(Frame) -> Result<()> + Send + 'static) -> Self {
+        FrameRecorder {
+            sink: FrameSink::Callback(Box::new(callback)),
+            index: 0,
+        }
+    }
+
+    /// Writes every frame to `directory/frame_NNNNNN.rgb`. Since the frames carry no header they
+    /// can be concatenated straight into an encoder, e.g.
+    /// `cat frame_*.rgb | ffmpeg -f rawvideo -pix_fmt rgb24 -s WxH -i - out.mp4`.
+    pub fn to_files(directory: impl Into<PathBuf>) -> Self {
+        FrameRecorder {
+            sink: FrameSink::Files(directory.into()),
+            index: 0,
+        }
+    }
+
+    /// Number of frames recorded so far, which is also the index of the next one.
+    pub fn frames(&self) -> u64 {
+        self.index
+    }
+
+    pub fn record(&mut self, canvas: &Canvas) -> Result<()> {
+        self.write(canvas.width(), canvas.height(), &canvas.to_raw_rgb())
+    }
+
+    pub(crate) fn write(&mut self, width: u32, height: u32, data: &[u8]) -> Result<()> {
+        let frame = Frame {
+            index: self.index,
+            width,
+            height,
+            data,
+        };
+
+        match &mut self.sink {
+            FrameSink::Callback(callback) => callback(frame)?,
+            FrameSink::Files(directory) => {
+                std::fs::write(directory.join(format!("frame_{:06}.rgb", self.index)), data)?
+            }
+        }
+
+        self.index += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,601 @@
+use std::path::Path;
+
+use image::{imageops, DynamicImage, RgbImage};
+
+use super::{
+    decode::{self, default_pixel_limit},
+    Canvas, Pixel, PixelAspect, Rect, Resampling, Scaling, ScalingMethod,
+};
+use crate::Result;
+
+impl Canvas {
+    /// Decodes the image at `path` and lays it out on a `width` x `height` canvas.
+    ///
+    /// Images of more than four times the canvas' pixels, though at least four 4K screens worth,
+    /// are refused with [`Error::ImageTooLarge`](crate::Error::ImageTooLarge) going by their
+    /// header alone, before anything their size is allocated.
+    pub fn from_path(
+        path: impl AsRef<Path>,
+        width: u32,
+        height: u32,
+        scaling: impl Into<Scaling>,
+    ) -> Result<Canvas> {
+        let limit = default_pixel_limit(width, height);
+        Canvas::from_path_within(path.as_ref(), width, height, scaling, limit)
+    }
+
+    /// [`Canvas::from_path`], refusing images of more than `max_pixels` pixels.
+    pub(crate) fn from_path_within(
+        path: &Path,
+        width: u32,
+        height: u32,
+        scaling: impl Into<Scaling>,
+        max_pixels: u64,
+    ) -> Result<Canvas> {
+        Ok(Canvas::from_image(
+            &decode::open(path, max_pixels)?,
+            width,
+            height,
+            scaling,
+        ))
+    }
+
+    /// Lays `image` out on a `width` x `height` canvas. Areas the image does not cover are left
+    /// black.
+    ///
+    /// The canvas has no alpha channel, so transparent images are flattened onto black first,
+    /// just like the uncovered areas.
+    pub fn from_image(
+        image: &DynamicImage,
+        width: u32,
+        height: u32,
+        scaling: impl Into<Scaling>,
+    ) -> Canvas {
+        let Scaling {
+            method,
+            linear_light,
+            pixel_aspect,
+            resampling,
+        } = scaling.into();
+        let mut canvas = Canvas::new(width, height);
+        let image = flatten(image);
+
+        if image.width() == 0 || image.height() == 0 || canvas.is_empty() {
+            return canvas;
+        }
+
+        // Solid images, such as the 1x1 ones theme packs like to ship, come out the same however
+        // they are resampled, so they are not. Custom scalers may well do something else
+        let first = image.get_pixel(0, 0);
+        let solid = resampling == Resampling::Builtin && image.pixels().all(|p| p == first);
+        let color = Pixel::new(first[0], first[1], first[2]);
+
+        if method == ScalingMethod::Tile {
+            match solid {
+                true => canvas.fill(color),
+                false => canvas.tile(&image),
+            }
+            return canvas;
+        }
+
+        let Placement {
+            source: crop,
+            target: dst,
+        } = Placement::compute(
+            (image.width(), image.height()),
+            (width, height),
+            method,
+            pixel_aspect,
+        );
+        if solid {
+            canvas.fill_rect(dst.x, dst.y, dst.width, dst.height, color);
+            return canvas;
+        }
+
+        let cropped = imageops::crop_imm(
+            &image,
+            crop.x as u32,
+            crop.y as u32,
+            crop.width,
+            crop.height,
+        )
+        .to_image();
+
+        let placed = resampling.scale(&cropped, dst.width, dst.height, linear_light);
+        canvas.blit(&placed, dst.x as i64, dst.y as i64);
+
+        canvas
+    }
+
+    // Copies `image` with its top left corner at (x, y), clipping whatever falls outside
+    fn blit(&mut self, image: &RgbImage, x: i64, y: i64) {
+        for (ix, iy, rgb) in image.enumerate_pixels() {
+            let cx = u32::try_from(x + ix as i64);
+            let cy = u32::try_from(y + iy as i64);
+            if let (Ok(cx), Ok(cy)) = (cx, cy) {
+                self.set_pixel(cx, cy, Pixel::new(rgb[0], rgb[1], rgb[2]));
+            }
+        }
+    }
+
+    fn tile(&mut self, image: &RgbImage) {
+        let width = self.width as usize;
+        for (i, pixel) in self.iter_mut().enumerate() {
+            let rgb = image.get_pixel(
+                (i % width) as u32 % image.width(),
+                (i / width) as u32 % image.height(),
+            );
+            *pixel = Pixel::new(rgb[0], rgb[1], rgb[2]);
+        }
+    }
+}
+
+fn flatten(image: &DynamicImage) -> RgbImage {
+    if !image.color().has_alpha() {
+        return image.to_rgb8();
+    }
+
+    warn!("Image has an alpha channel, flattening it onto black");
+
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let over = |v: u8| ((v as u32 * a as u32 + 127) / 255) as u8;
+        image::Rgb([over(r), over(g), over(b)])
+    })
+}
+
+/// Where an image goes when laid out: the part of it that is used and where that ends up, as
+/// [`compute_placement`] has it.
+///
+/// This is the very placement images are drawn with, so previews built on it, such as in a
+/// wallpaper picker, match what shows on screen to the pixel. [`ScalingMethod::Tile`] repeats
+/// the single tile it places across the whole canvas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Placement {
+    /// The part of the image that is used, in image coordinates.
+    pub source: Rect,
+    /// Where it is scaled into, in canvas coordinates.
+    pub target: Rect,
+}
+
+impl Placement {
+    pub(crate) fn compute(
+        (src_w, src_h): (u32, u32),
+        (dst_w, dst_h): (u32, u32),
+        method: ScalingMethod,
+        pixel_aspect: PixelAspect,
+    ) -> Placement {
+        let (source, target) =
+            compute_placement_with_aspect(src_w, src_h, dst_w, dst_h, method, pixel_aspect);
+        Placement { source, target }
+    }
+
+    /// How many canvas pixels one image pixel spans, horizontally and vertically. 0 for an empty
+    /// placement.
+    pub fn scale(&self) -> (f64, f64) {
+        let ratio = |to: u32, from: u32| match from {
+            0 => 0.0,
+            from => to as f64 / from as f64,
+        };
+        (
+            ratio(self.target.width, self.source.width),
+            ratio(self.target.height, self.source.height),
+        )
+    }
+
+    /// Where the point (x, y) of the image ends up on the canvas, which may be outside the
+    /// target for points the crop leaves out.
+    pub fn to_canvas(self, x: f64, y: f64) -> (f64, f64) {
+        let (sx, sy) = self.scale();
+        (
+            self.target.x as f64 + (x - self.source.x as f64) * sx,
+            self.target.y as f64 + (y - self.source.y as f64) * sy,
+        )
+    }
+
+    /// Where the point (x, y) of the canvas comes from in the image, `None` for an empty
+    /// placement.
+    pub fn to_image(self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let (sx, sy) = self.scale();
+        if sx == 0.0 || sy == 0.0 {
+            return None;
+        }
+        Some((
+            self.source.x as f64 + (x - self.target.x as f64) / sx,
+            self.source.y as f64 + (y - self.target.y as f64) / sy,
+        ))
+    }
+
+    /// The placement moved by (dx, dy) on the canvas, e.g. onto a monitor at that offset.
+    pub fn offset(mut self, dx: i32, dy: i32) -> Placement {
+        self.target.x += dx;
+        self.target.y += dy;
+        self
+    }
+}
+
+impl Scaling {
+    /// Where a `src_w` x `src_h` image goes when laid out on a `dst_w` x `dst_h` canvas with
+    /// this scaling, see [`Placement`].
+    pub fn placement(&self, src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) -> Placement {
+        Placement::compute(
+            (src_w, src_h),
+            (dst_w, dst_h),
+            self.method,
+            self.pixel_aspect,
+        )
+    }
+}
+
+/// Where a `src_w` x `src_h` image goes on a `dst_w` x `dst_h` canvas: the part of the image
+/// that is used, in image coordinates, and the rectangle of the canvas it is scaled into.
+///
+/// Both rectangles always lie within their image and canvas. [`ScalingMethod::Fill`] covers the
+/// whole canvas by cropping the image to the canvas's aspect ratio, [`ScalingMethod::Max`] keeps
+/// the whole image and never reaches past the canvas, and [`ScalingMethod::Center`] crops
+/// without scaling. Leftover margins are split evenly, the extra pixel of an odd one going to
+/// the right or bottom. [`ScalingMethod::Tile`] places a single, unscaled tile at the origin.
+/// Either side being empty makes for two empty rectangles.
+pub fn compute_placement(
+    src_w: u32,
+    src_h: u32,
+    dst_w: u32,
+    dst_h: u32,
+    method: ScalingMethod,
+) -> (Rect, Rect) {
+    compute_placement_with_aspect(src_w, src_h, dst_w, dst_h, method, PixelAspect::SQUARE)
+}
+
+/// [`compute_placement`] for a canvas shown with pixels of the given shape, keeping the image's
+/// proportions on the glass. [`ScalingMethod::Fill`] and [`ScalingMethod::Max`] compensate for
+/// it, the other methods place the same rectangles whatever the pixels look like. Square pixels
+/// give exactly what `compute_placement` does.
+pub fn compute_placement_with_aspect(
+    src_w: u32,
+    src_h: u32,
+    dst_w: u32,
+    dst_h: u32,
+    method: ScalingMethod,
+    pixel_aspect: PixelAspect,
+) -> (Rect, Rect) {
+    if src_w == 0 || src_h == 0 || dst_w == 0 || dst_h == 0 {
+        return (Rect::default(), Rect::default());
+    }
+
+    let whole = Rect::new(0, 0, src_w, src_h);
+    let (sw, sh, dh) = (src_w as f64, src_h as f64, dst_h as f64);
+    // The canvas' width in units as wide as its pixels are tall. Multiplying and dividing by
+    // exactly 1 changes nothing, square pixels get the very same rectangles
+    let par = pixel_aspect.ratio();
+    let dw = dst_w as f64 * par;
+    // At least a pixel, at most `max`: a sliver of a very wide image still shows as a line
+    let fit = |v: f64, max: u32| (v.round() as u32).clamp(1, max);
+    let centered = |size: u32, within: u32| ((within - size) / 2) as i32;
+
+    match method {
+        ScalingMethod::Scale => (whole, Rect::new(0, 0, dst_w, dst_h)),
+        ScalingMethod::Tile => (whole, Rect::new(0, 0, src_w.min(dst_w), src_h.min(dst_h))),
+        ScalingMethod::Center => {
+            let (w, h) = (src_w.min(dst_w), src_h.min(dst_h));
+            (
+                Rect::new(centered(w, src_w), centered(h, src_h), w, h),
+                Rect::new(centered(w, dst_w), centered(h, dst_h), w, h),
+            )
+        }
+        ScalingMethod::Fill => {
+            // The largest part of the image with the canvas's aspect ratio, stretched over all
+            // of it
+            let factor = (dw / sw).max(dh / sh);
+            let (w, h) = (fit(dw / factor, src_w), fit(dh / factor, src_h));
+            (
+                Rect::new(centered(w, src_w), centered(h, src_h), w, h),
+                Rect::new(0, 0, dst_w, dst_h),
+            )
+        }
+        ScalingMethod::Max => {
+            let factor = (dw / sw).min(dh / sh);
+            let (w, h) = (fit(sw * factor / par, dst_w), fit(sh * factor, dst_h));
+            (
+                whole,
+                Rect::new(centered(w, dst_w), centered(h, dst_h), w, h),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CatmullRom;
+
+    // Widths and heights covering single pixels, odd sizes, common screens and the X limit
+    const SIZES: [u32; 10] = [1, 2, 3, 7, 100, 641, 1080, 1920, 3840, 32767];
+
+    fn aspects() -> [PixelAspect; 4] {
+        [
+            PixelAspect::SQUARE,
+            PixelAspect::new(2, 1),
+            PixelAspect::new(1, 2),
+            PixelAspect::new(8, 9),
+        ]
+    }
+
+    // Every image size on every target size with every pixel shape
+    fn each_placement(method: ScalingMethod, mut check: impl FnMut([u32; 4], Rect, Rect)) {
+        for aspect in aspects() {
+            for src_w in SIZES {
+                for src_h in SIZES {
+                    for dst_w in SIZES {
+                        for dst_h in SIZES {
+                            let (src, dst) = compute_placement_with_aspect(
+                                src_w, src_h, dst_w, dst_h, method, aspect,
+                            );
+                            check([src_w, src_h, dst_w, dst_h], src, dst);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn within(rect: Rect, width: u32, height: u32) -> bool {
+        rect.x >= 0
+            && rect.y >= 0
+            && rect.width > 0
+            && rect.height > 0
+            && rect.x as u32 + rect.width <= width
+            && rect.y as u32 + rect.height <= height
+    }
+
+    // Equally far from both edges, or a pixel further from the far one when the leftover is odd
+    fn centered(offset: i32, size: u32, within: u32) -> bool {
+        let leftover = within - size;
+        offset as u32 == leftover / 2
+    }
+
+    #[test]
+    fn fill_covers_the_target() {
+        each_placement(
+            ScalingMethod::Fill,
+            |[src_w, src_h, dst_w, dst_h], src, dst| {
+                assert_eq!(dst, Rect::new(0, 0, dst_w, dst_h));
+                assert!(within(src, src_w, src_h), "{src:?} outside the image");
+                assert!(centered(src.x, src.width, src_w) && centered(src.y, src.height, src_h));
+            },
+        );
+    }
+
+    #[test]
+    fn fill_crops_to_the_target_shape() {
+        for src_w in SIZES {
+            for src_h in SIZES {
+                let (src, _) = compute_placement(src_w, src_h, 1920, 1080, ScalingMethod::Fill);
+                // The crop keeps all of one side of the image
+                assert!(src.width == src_w || src.height == src_h, "{src:?}");
+                // Rounding to whole pixels is all that is off, unless clamped to a single one
+                if src.width > 1 && src.height > 1 {
+                    let (w, h) = (src.width as f64, src.height as f64);
+                    assert!((w - h * 16.0 / 9.0).abs() <= 16.0 / 9.0, "{src:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn max_stays_within_the_target() {
+        each_placement(
+            ScalingMethod::Max,
+            |[src_w, src_h, dst_w, dst_h], src, dst| {
+                assert_eq!(src, Rect::new(0, 0, src_w, src_h));
+                assert!(within(dst, dst_w, dst_h), "{dst:?} outside the target");
+                assert!(centered(dst.x, dst.width, dst_w) && centered(dst.y, dst.height, dst_h));
+            },
+        );
+    }
+
+    #[test]
+    fn max_touches_two_edges_with_square_pixels() {
+        for src_w in SIZES {
+            for src_h in SIZES {
+                for (dst_w, dst_h) in [(1920, 1080), (1080, 1920), (7, 3)] {
+                    let (_, dst) =
+                        compute_placement(src_w, src_h, dst_w, dst_h, ScalingMethod::Max);
+                    assert!(dst.width == dst_w || dst.height == dst_h, "{dst:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn center_is_symmetric() {
+        each_placement(
+            ScalingMethod::Center,
+            |[src_w, src_h, dst_w, dst_h], src, dst| {
+                let (w, h) = (src_w.min(dst_w), src_h.min(dst_h));
+                assert_eq!((src.width, src.height), (w, h));
+                assert_eq!((dst.width, dst.height), (w, h));
+                // Cropped equally off both sides of the image, bordered equally on both sides of
+                // the target
+                assert!(
+                    centered(src.x, w, src_w) && centered(src.y, h, src_h),
+                    "{src:?}"
+                );
+                assert!(
+                    centered(dst.x, w, dst_w) && centered(dst.y, h, dst_h),
+                    "{dst:?}"
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn pixel_shape_only_matters_to_fill_and_max() {
+        for method in [
+            ScalingMethod::Center,
+            ScalingMethod::Scale,
+            ScalingMethod::Tile,
+        ] {
+            for aspect in aspects() {
+                assert_eq!(
+                    compute_placement_with_aspect(641, 100, 1920, 1080, method, aspect),
+                    compute_placement(641, 100, 1920, 1080, method)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn empty_sizes_place_nothing() {
+        let nothing = (Rect::default(), Rect::default());
+        assert_eq!(
+            compute_placement(0, 10, 10, 10, ScalingMethod::Fill),
+            nothing
+        );
+        assert_eq!(
+            compute_placement(10, 10, 10, 0, ScalingMethod::Max),
+            nothing
+        );
+    }
+
+    // Solid images through the fast path and through the general one, which a custom scaler
+    // forces, for comparison
+    fn both_paths(image: &DynamicImage, method: ScalingMethod, linear: bool) -> (Canvas, Canvas) {
+        let scaling = Scaling::new(method).linear_light(linear);
+        let general = scaling.clone().scaler(CatmullRom {
+            linear_light: linear,
+        });
+        (
+            Canvas::from_image(image, 64, 36, scaling),
+            Canvas::from_image(image, 64, 36, general),
+        )
+    }
+
+    #[test]
+    fn solid_images_come_out_as_scaled() {
+        let color = image::Rgb([200, 30, 90]);
+        for (width, height) in [(1, 1), (3, 2), (17, 40), (640, 360)] {
+            let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, color));
+            for method in [
+                ScalingMethod::Center,
+                ScalingMethod::Fill,
+                ScalingMethod::Max,
+                ScalingMethod::Scale,
+                ScalingMethod::Tile,
+            ] {
+                for linear in [false, true] {
+                    let (fast, general) = both_paths(&image, method, linear);
+                    assert_eq!(
+                        fast, general,
+                        "{width}x{height} {method:?}, linear {linear}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nearly_solid_images_take_the_general_path() {
+        let mut image = RgbImage::from_pixel(16, 16, image::Rgb([10, 10, 10]));
+        image.put_pixel(15, 15, image::Rgb([250, 250, 250]));
+        let canvas = Canvas::from_image(
+            &DynamicImage::ImageRgb8(image),
+            16,
+            16,
+            ScalingMethod::Scale,
+        );
+        assert_eq!(canvas.get_pixel(15, 15), Some(&Pixel::new(250, 250, 250)));
+        assert_eq!(canvas.solid_color(), None);
+    }
+
+    // Red and green hold each pixel's own column and row, so a canvas pixel drawn unscaled tells
+    // where in the image it came from
+    fn coordinates(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([x as u8, y as u8, 255])
+        }))
+    }
+
+    // Every pixel of the placement's target shows the image, every other one is left black
+    fn drawn_within_target(image: &DynamicImage, (dst_w, dst_h): (u32, u32), scaling: Scaling) {
+        let placement = scaling.placement(image.width(), image.height(), dst_w, dst_h);
+        let canvas = Canvas::from_image(image, dst_w, dst_h, scaling);
+        let target = placement.target;
+
+        for y in 0..dst_h {
+            for x in 0..dst_w {
+                let inside = (x as i32) >= target.x
+                    && (x as i32) < target.x + target.width as i32
+                    && (y as i32) >= target.y
+                    && (y as i32) < target.y + target.height as i32;
+                let drawn = canvas.get_pixel(x, y).unwrap().b != 0;
+                assert_eq!(drawn, inside, "({x}, {y}) against {placement:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn placements_match_what_is_drawn() {
+        for (src, method) in [
+            ((40, 10), ScalingMethod::Max),
+            ((10, 40), ScalingMethod::Max),
+            ((33, 17), ScalingMethod::Max),
+            ((100, 100), ScalingMethod::Center),
+            ((20, 30), ScalingMethod::Center),
+            ((21, 31), ScalingMethod::Center),
+            ((200, 50), ScalingMethod::Fill),
+            ((64, 48), ScalingMethod::Scale),
+        ] {
+            let image = coordinates(src.0, src.1);
+            drawn_within_target(&image, (64, 48), method.into());
+            drawn_within_target(
+                &image,
+                (64, 48),
+                Scaling::new(method).pixel_aspect(PixelAspect::new(2, 1)),
+            );
+        }
+    }
+
+    // Unscaled, every pixel of the target is the image pixel the placement maps it to
+    #[test]
+    fn centered_placements_are_pixel_exact() {
+        for (src_w, src_h) in [(100, 100), (20, 30), (65, 47), (64, 48)] {
+            let image = coordinates(src_w, src_h);
+            let placement = Scaling::from(ScalingMethod::Center).placement(src_w, src_h, 64, 48);
+            let canvas = Canvas::from_image(&image, 64, 48, ScalingMethod::Center);
+            let Placement { source, target } = placement;
+            assert_eq!((source.width, source.height), (target.width, target.height));
+
+            for j in 0..target.height {
+                for i in 0..target.width {
+                    let (x, y) = (source.x as u32 + i, source.y as u32 + j);
+                    let at = placement.to_canvas(x as f64, y as f64);
+                    assert_eq!(
+                        at,
+                        ((target.x as u32 + i) as f64, (target.y as u32 + j) as f64)
+                    );
+
+                    let pixel = canvas.get_pixel(at.0 as u32, at.1 as u32).unwrap();
+                    assert_eq!((pixel.r, pixel.g), (x as u8, y as u8));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn offset_placements_move_the_target_only() {
+        let placement = Scaling::from(ScalingMethod::Max).placement(40, 10, 64, 48);
+        let moved = placement.offset(1920, -5);
+        assert_eq!(moved.source, placement.source);
+        assert_eq!(
+            moved.target,
+            Rect::new(
+                placement.target.x + 1920,
+                placement.target.y - 5,
+                placement.target.width,
+                placement.target.height
+            )
+        );
+        assert_eq!(moved.scale(), placement.scale());
+    }
+}
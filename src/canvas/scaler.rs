@@ -0,0 +1,254 @@
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use image::{imageops, imageops::FilterType, ImageBuffer, Rgb, RgbImage};
+
+use super::color::{linear_to_srgb_fast, srgb_to_linear};
+
+/// The resampling step of laying an image out: turning the part of the image that is used into
+/// the rectangle it covers. Where that part and rectangle are is up to the [`ScalingMethod`],
+/// see [`compute_placement`].
+///
+/// [`ScalingMethod`]: super::ScalingMethod
+/// [`compute_placement`]: super::compute_placement
+pub trait Scaler: Send + Sync {
+    /// `src` resampled to `dst_w` x `dst_h`. Images of another size are clipped, or leave black
+    /// where they fall short.
+    fn scale(&self, src: &RgbImage, dst_w: u32, dst_h: u32) -> RgbImage;
+}
+
+/// Which [`Scaler`] a [`Scaling`](super::Scaling) resamples with.
+#[derive(Clone, Default)]
+pub enum Resampling {
+    /// [`CatmullRom`], in linear light if the scaling says so.
+    #[default]
+    Builtin,
+    Custom(Arc<dyn Scaler>),
+}
+
+impl Resampling {
+    pub(crate) fn scale(&self, src: &RgbImage, dst_w: u32, dst_h: u32, linear: bool) -> RgbImage {
+        match self {
+            Resampling::Builtin => CatmullRom {
+                linear_light: linear,
+            }
+            .scale(src, dst_w, dst_h),
+            Resampling::Custom(scaler) => scaler.scale(src, dst_w, dst_h),
+        }
+    }
+}
+
+// Custom scalers are told apart by identity, there is nothing else to compare. Clones of the
+// same Arc are equal and cache as one
+impl PartialEq for Resampling {
+    fn eq(&self, other: &Resampling) -> bool {
+        match (self, other) {
+            (Resampling::Builtin, Resampling::Builtin) => true,
+            (Resampling::Custom(a), Resampling::Custom(b)) => {
+                Arc::as_ptr(a) as *const () == Arc::as_ptr(b) as *const ()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Resampling {}
+
+impl Hash for Resampling {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Resampling::Builtin => state.write_u8(0),
+            Resampling::Custom(scaler) => {
+                state.write_u8(1);
+                (Arc::as_ptr(scaler) as *const () as usize).hash(state);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Resampling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resampling::Builtin => write!(f, "Builtin"),
+            Resampling::Custom(scaler) => write!(f, "Custom({:p})", Arc::as_ptr(scaler)),
+        }
+    }
+}
+
+/// Catmull-Rom resampling, what shade scales with unless told otherwise. Sharp, at the cost of
+/// slight halos around hard edges.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CatmullRom {
+    /// Resamples linear light rather than the gamma encoded values, see
+    /// [`Scaling::linear_light`](super::Scaling::linear_light).
+    pub linear_light: bool,
+}
+
+impl Scaler for CatmullRom {
+    fn scale(&self, src: &RgbImage, dst_w: u32, dst_h: u32) -> RgbImage {
+        if (dst_w, dst_h) == src.dimensions() {
+            return src.clone();
+        }
+        if !self.linear_light {
+            return imageops::resize(src, dst_w, dst_h, FilterType::CatmullRom);
+        }
+
+        let linear: ImageBuffer<Rgb<f32>, Vec<f32>> =
+            ImageBuffer::from_fn(src.width(), src.height(), |x, y| {
+                Rgb(src.get_pixel(x, y).0.map(srgb_to_linear))
+            });
+        let resized = imageops::resize(&linear, dst_w, dst_h, FilterType::CatmullRom);
+
+        // CatmullRom overshoots around edges, the table clamps it away
+        RgbImage::from_fn(dst_w, dst_h, |x, y| {
+            Rgb(resized.get_pixel(x, y).0.map(linear_to_srgb_fast))
+        })
+    }
+}
+
+/// Nearest neighbor scaling by whole multiples only, for pixel art: every source pixel becomes
+/// the same square block. The largest multiple that fits is used and centered, leaving a black
+/// margin where the target is not an exact multiple. Images larger than the target are shrunk by
+/// the smallest whole divisor that fits instead.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IntegerScaler;
+
+impl Scaler for IntegerScaler {
+    fn scale(&self, src: &RgbImage, dst_w: u32, dst_h: u32) -> RgbImage {
+        let (src_w, src_h) = src.dimensions();
+        let mut dst = RgbImage::new(dst_w, dst_h);
+        if src_w == 0 || src_h == 0 || dst_w == 0 || dst_h == 0 {
+            return dst;
+        }
+
+        // Either a block of `factor` pixels per source pixel, or one pixel per `divisor`
+        let factor = (dst_w / src_w).min(dst_h / src_h);
+        let divisor = match factor {
+            0 => src_w.div_ceil(dst_w).max(src_h.div_ceil(dst_h)),
+            _ => 1,
+        };
+        let factor = factor.max(1);
+
+        let (w, h) = (src_w / divisor * factor, src_h / divisor * factor);
+        let (left, top) = ((dst_w - w) / 2, (dst_h - h) / 2);
+        for y in 0..h {
+            for x in 0..w {
+                let pixel = src.get_pixel(x / factor * divisor, y / factor * divisor);
+                dst.put_pixel(left + x, top + y, *pixel);
+            }
+        }
+
+        dst
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Single pixel black and white squares, half the light of a white image
+    fn checker(size: u32) -> RgbImage {
+        RgbImage::from_fn(size, size, |x, y| match (x + y) % 2 {
+            0 => Rgb([0, 0, 0]),
+            _ => Rgb([255, 255, 255]),
+        })
+    }
+
+    // The gray the inside of a 64 x 64 checker comes out as when downscaled to 16 x 16, away
+    // from the edges where the filter runs out of pixels
+    fn downscaled_gray(linear_light: bool) -> u8 {
+        let scaled = CatmullRom { linear_light }.scale(&checker(64), 16, 16);
+        let grays: Vec<u8> = (4..12)
+            .flat_map(|y| (4..12).map(move |x| (x, y)))
+            .map(|(x, y)| scaled.get_pixel(x, y).0[0])
+            .collect();
+        let (min, max) = (grays.iter().min().unwrap(), grays.iter().max().unwrap());
+        assert!(max - min <= 1, "uneven gray from {min} to {max}");
+        grays[0]
+    }
+
+    #[test]
+    fn linear_light_keeps_a_checker_as_bright() {
+        // Half the light is encoded as 188, averaging the encoded values gives a far darker 128
+        let gamma = downscaled_gray(false);
+        let linear = downscaled_gray(true);
+        assert!((126..=129).contains(&gamma), "{gamma}");
+        assert!((186..=189).contains(&linear), "{linear}");
+        assert!((srgb_to_linear(linear) - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn same_size_is_left_alone() {
+        let image = checker(8);
+        for linear_light in [false, true] {
+            assert_eq!(CatmullRom { linear_light }.scale(&image, 8, 8), image);
+        }
+    }
+
+    // Every pixel tells where it came from, and none is black
+    fn numbered(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| Rgb([x as u8 + 1, y as u8 + 1, 7]))
+    }
+
+    const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+
+    #[test]
+    fn whole_multiples_are_centered() {
+        let scaled = IntegerScaler.scale(&numbered(4, 4), 10, 10);
+        assert_eq!(scaled.dimensions(), (10, 10));
+
+        for y in 0..10 {
+            for x in 0..10 {
+                let expected = match (x, y) {
+                    (1..=8, 1..=8) => Rgb([((x - 1) / 2) as u8 + 1, ((y - 1) / 2) as u8 + 1, 7]),
+                    _ => BLACK,
+                };
+                assert_eq!(*scaled.get_pixel(x, y), expected, "({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn larger_images_shrink_by_a_whole_divisor() {
+        // 16 does not fit 5 by 3, by 4 it does and leaves a column and row over
+        let scaled = IntegerScaler.scale(&numbered(16, 16), 5, 5);
+        assert_eq!(scaled.dimensions(), (5, 5));
+
+        for y in 0..5 {
+            for x in 0..5 {
+                let expected = match (x, y) {
+                    (0..=3, 0..=3) => Rgb([(x * 4) as u8 + 1, (y * 4) as u8 + 1, 7]),
+                    _ => BLACK,
+                };
+                assert_eq!(*scaled.get_pixel(x, y), expected, "({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn the_tighter_side_sets_the_factor() {
+        // 3 times fits the width but only twice the height
+        let scaled = IntegerScaler.scale(&numbered(2, 4), 6, 8);
+        assert_eq!(*scaled.get_pixel(1, 0), Rgb([1, 1, 7]));
+        assert_eq!(*scaled.get_pixel(4, 7), Rgb([2, 4, 7]));
+        assert_eq!(*scaled.get_pixel(0, 0), BLACK);
+        assert_eq!(*scaled.get_pixel(5, 0), BLACK);
+    }
+
+    #[test]
+    fn nothing_to_scale_from_or_to() {
+        for (src, dst) in [
+            ((0, 4), (8, 8)),
+            ((4, 0), (8, 8)),
+            ((4, 4), (0, 8)),
+            ((4, 4), (8, 0)),
+        ] {
+            let scaled = IntegerScaler.scale(&numbered(src.0, src.1), dst.0, dst.1);
+            assert_eq!(scaled.dimensions(), dst);
+            assert!(scaled.pixels().all(|&p| p == BLACK), "{src:?} to {dst:?}");
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::{fmt, path::PathBuf};
+
+use image::DynamicImage;
+
+use super::{decode, default_pixel_limit, Canvas, Pixel, Scaling};
+use crate::{Error, Result};
+
+/// Anything a wallpaper can be made from.
+pub enum WallpaperSource {
+    File(PathBuf),
+    /// An encoded image, in any of the [`supported_formats`](super::supported_formats).
+    Bytes(Vec<u8>),
+    /// Like [`WallpaperSource::Bytes`], but borrowed for the whole program, e.g. a default
+    /// wallpaper built into the binary with `include_bytes!`.
+    Embedded(&'static [u8]),
+    Image(DynamicImage),
+    Color(Pixel),
+    /// Draws onto a canvas already sized to the screen and cleared to black.
+    Generator(Box<dyn Fn(&mut Canvas) + Send + Sync>),
+}
+
+impl fmt::Debug for WallpaperSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WallpaperSource::File(path) => f.debug_tuple("File").field(path).finish(),
+            WallpaperSource::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            WallpaperSource::Embedded(bytes) => write!(f, "Embedded({} bytes)", bytes.len()),
+            WallpaperSource::Image(image) => {
+                write!(f, "Image({}x{})", image.width(), image.height())
+            }
+            WallpaperSource::Color(color) => f.debug_tuple("Color").field(color).finish(),
+            WallpaperSource::Generator(_) => f.write_str("Generator"),
+        }
+    }
+}
+
+impl From<PathBuf> for WallpaperSource {
+    fn from(path: PathBuf) -> WallpaperSource {
+        WallpaperSource::File(path)
+    }
+}
+
+impl From<Vec<u8>> for WallpaperSource {
+    fn from(bytes: Vec<u8>) -> WallpaperSource {
+        WallpaperSource::Bytes(bytes)
+    }
+}
+
+impl From<&'static [u8]> for WallpaperSource {
+    fn from(bytes: &'static [u8]) -> WallpaperSource {
+        WallpaperSource::Embedded(bytes)
+    }
+}
+
+impl From<DynamicImage> for WallpaperSource {
+    fn from(image: DynamicImage) -> WallpaperSource {
+        WallpaperSource::Image(image)
+    }
+}
+
+impl From<Pixel> for WallpaperSource {
+    fn from(color: Pixel) -> WallpaperSource {
+        WallpaperSource::Color(color)
+    }
+}
+
+impl Canvas {
+    /// Renders `source` onto a `width` x `height` canvas. `scaling` only matters for the image
+    /// backed sources, which are refused when too large like [`Canvas::from_path`] does.
+    pub fn from_source(
+        source: &WallpaperSource,
+        width: u32,
+        height: u32,
+        scaling: impl Into<Scaling>,
+    ) -> Result<Canvas> {
+        let limit = default_pixel_limit(width, height);
+        Canvas::from_source_within(source, width, height, scaling, limit)
+    }
+
+    /// [`Canvas::from_source`], refusing encoded images of more than `max_pixels` pixels.
+    pub(crate) fn from_source_within(
+        source: &WallpaperSource,
+        width: u32,
+        height: u32,
+        scaling: impl Into<Scaling>,
+        max_pixels: u64,
+    ) -> Result<Canvas> {
+        let scaling = scaling.into();
+        Ok(match source {
+            WallpaperSource::File(path) => {
+                Canvas::from_path_within(path, width, height, scaling, max_pixels)?
+            }
+            WallpaperSource::Bytes(bytes) => {
+                let image = decode::load_from_memory(bytes, max_pixels, Error::Image)?;
+                Canvas::from_image(&image, width, height, scaling)
+            }
+            WallpaperSource::Embedded(bytes) => {
+                let image = decode::load_from_memory(bytes, max_pixels, Error::EmbeddedImage)?;
+                Canvas::from_image(&image, width, height, scaling)
+            }
+            WallpaperSource::Image(image) => Canvas::from_image(image, width, height, scaling),
+            WallpaperSource::Color(color) => Canvas::filled(width, height, color.clone()),
+            WallpaperSource::Generator(generate) => {
+                let mut canvas = Canvas::new(width, height);
+                generate(&mut canvas);
+                canvas
+            }
+        })
+    }
+}
@@ -0,0 +1,49 @@
+use image::RgbImage;
+
+use super::{
+    color::{linear_to_srgb, srgb_to_linear},
+    Canvas,
+};
+
+impl Canvas {
+    /// Downscales the canvas to fit within `max_width` x `max_height`, keeping its aspect ratio.
+    /// Never upscales.
+    ///
+    /// Each thumbnail pixel is the average of every canvas pixel it covers, taken in linear light:
+    /// averaging sRGB values directly darkens fine detail like text or noise.
+    pub fn thumbnail(&self, max_width: u32, max_height: u32) -> RgbImage {
+        if self.is_empty() || max_width == 0 || max_height == 0 {
+            return RgbImage::new(0, 0);
+        }
+
+        let factor = (max_width as f64 / self.width as f64)
+            .min(max_height as f64 / self.height as f64)
+            .min(1.0);
+        let tw = ((self.width as f64 * factor).round() as u32).max(1);
+        let th = ((self.height as f64 * factor).round() as u32).max(1);
+
+        // Boundaries of the source span each thumbnail column and row covers
+        let span = |i: u32, from: u32, to: u32| {
+            let start = (i as u64 * from as u64 / to as u64) as u32;
+            let end = ((i as u64 + 1) * from as u64 / to as u64) as u32;
+            start..end.max(start + 1)
+        };
+
+        RgbImage::from_fn(tw, th, |tx, ty| {
+            let (xs, ys) = (span(tx, self.width, tw), span(ty, self.height, th));
+            let mut sum = [0f32; 3];
+
+            for y in ys.clone() {
+                let row = y as usize * self.width as usize;
+                for pixel in &self.pixels[row + xs.start as usize..row + xs.end as usize] {
+                    sum[0] += srgb_to_linear(pixel.r);
+                    sum[1] += srgb_to_linear(pixel.g);
+                    sum[2] += srgb_to_linear(pixel.b);
+                }
+            }
+
+            let count = (xs.len() * ys.len()) as f32;
+            image::Rgb(sum.map(|s| linear_to_srgb(s / count)))
+        })
+    }
+}
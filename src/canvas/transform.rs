@@ -0,0 +1,174 @@
+use super::Canvas;
+
+// Flips and the half turn keep the geometry and work in place. Quarter turns swap width and
+// height, which a screen sized buffer cannot do, so they hand back a new canvas to be pasted
+// wherever it fits
+
+impl Canvas {
+    /// A copy of the canvas turned a quarter clockwise, `height` x `width` pixels.
+    ///
+    /// To turn part of a handle's buffer, rotate a [`Snapshot`](crate::Snapshot) of it and
+    /// [`paste`](crate::BackgroundHandle::paste) the result back. Decoded images can be turned
+    /// before they are laid out with [`DynamicImage::rotate90`](image::DynamicImage::rotate90).
+    pub fn rotate90(&self) -> Canvas {
+        self.rotated(|x, y| (self.height - 1 - y, x))
+    }
+
+    /// A copy of the canvas turned a quarter counterclockwise, `height` x `width` pixels. See
+    /// [`Canvas::rotate90`].
+    pub fn rotate270(&self) -> Canvas {
+        self.rotated(|x, y| (y, self.width - 1 - x))
+    }
+
+    // Builds the turned canvas from where each pixel ends up, `to` mapping source coordinates
+    // onto the new canvas
+    fn rotated(&self, to: impl Fn(u32, u32) -> (u32, u32)) -> Canvas {
+        // Blending is how a canvas draws rather than what it shows, the copy keeps it
+        let mut rotated = Canvas::new(self.height, self.width);
+        rotated.blend = self.blend;
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (rx, ry) = to(x, y);
+                let i = ry as usize * rotated.width as usize + rx as usize;
+                rotated.pixels[i] =
+                    self.pixels[y as usize * self.width as usize + x as usize].clone();
+            }
+        }
+
+        rotated
+    }
+
+    /// Turns the canvas by half a turn, in place.
+    pub fn rotate180(&mut self) {
+        self.pixels.reverse();
+    }
+
+    /// Mirrors the canvas left to right, in place.
+    pub fn flip_h(&mut self) {
+        if self.width == 0 {
+            return;
+        }
+
+        for row in self.pixels.chunks_exact_mut(self.width as usize) {
+            row.reverse();
+        }
+    }
+
+    /// Mirrors the canvas top to bottom, in place.
+    pub fn flip_v(&mut self) {
+        let width = self.width as usize;
+        let height = self.height as usize;
+
+        for y in 0..height / 2 {
+            let (top, bottom) = self.pixels.split_at_mut((height - 1 - y) * width);
+            top[y * width..(y + 1) * width].swap_with_slice(&mut bottom[..width]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pixel;
+
+    // Every pixel tells where it started out
+    fn numbered(width: u32, height: u32) -> Canvas {
+        let mut canvas = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                canvas.set_pixel(x, y, Pixel::new(x as u8, y as u8, 0));
+            }
+        }
+        canvas
+    }
+
+    fn at(canvas: &Canvas, x: u32, y: u32) -> (u8, u8) {
+        let pixel = canvas.get_pixel(x, y).unwrap();
+        (pixel.r, pixel.g)
+    }
+
+    #[test]
+    fn quarter_turns_swap_the_dimensions() {
+        let canvas = numbered(3, 2);
+
+        let right = canvas.rotate90();
+        assert_eq!((right.width(), right.height()), (2, 3));
+        // The bottom left corner ends up top left, the top left one top right
+        assert_eq!(at(&right, 0, 0), (0, 1));
+        assert_eq!(at(&right, 1, 0), (0, 0));
+        assert_eq!(at(&right, 1, 2), (2, 0));
+        assert_eq!(at(&right, 0, 2), (2, 1));
+
+        let left = canvas.rotate270();
+        assert_eq!((left.width(), left.height()), (2, 3));
+        // The top right corner ends up top left, the top left one bottom left
+        assert_eq!(at(&left, 0, 0), (2, 0));
+        assert_eq!(at(&left, 0, 2), (0, 0));
+        assert_eq!(at(&left, 1, 2), (0, 1));
+        assert_eq!(at(&left, 1, 0), (2, 1));
+    }
+
+    #[test]
+    fn turns_add_up() {
+        let canvas = numbered(4, 3);
+        assert_eq!(canvas.rotate90().rotate270(), canvas);
+        assert_eq!(canvas.rotate270().rotate90(), canvas);
+
+        let mut half = canvas.clone();
+        half.rotate180();
+        assert_eq!((half.width(), half.height()), (4, 3));
+        assert_eq!(at(&half, 0, 0), (3, 2));
+        assert_eq!(at(&half, 3, 2), (0, 0));
+        assert_eq!(half, canvas.rotate90().rotate90());
+
+        half.rotate180();
+        assert_eq!(half, canvas);
+    }
+
+    #[test]
+    fn flips_leave_the_middle_alone() {
+        let canvas = numbered(3, 5);
+
+        let mut flipped = canvas.clone();
+        flipped.flip_v();
+        for x in 0..3 {
+            assert_eq!(at(&flipped, x, 2), (x as u8, 2));
+            assert_eq!(at(&flipped, x, 0), (x as u8, 4));
+            assert_eq!(at(&flipped, x, 4), (x as u8, 0));
+        }
+        flipped.flip_v();
+        assert_eq!(flipped, canvas);
+
+        let mut mirrored = canvas.clone();
+        mirrored.flip_h();
+        for y in 0..5 {
+            assert_eq!(at(&mirrored, 1, y), (1, y as u8));
+            assert_eq!(at(&mirrored, 0, y), (2, y as u8));
+        }
+        mirrored.flip_h();
+        assert_eq!(mirrored, canvas);
+    }
+
+    #[test]
+    fn empty_canvases_turn_and_flip() {
+        let mut canvas = Canvas::new(0, 3);
+        canvas.flip_h();
+        canvas.flip_v();
+        canvas.rotate180();
+        let turned = canvas.rotate90();
+        assert_eq!((turned.width(), turned.height()), (3, 0));
+    }
+
+    #[test]
+    fn turned_canvases_paste_clipped() {
+        let mut canvas = Canvas::filled(4, 4, Pixel::new(9, 9, 9));
+        // 2x3 once turned, hanging off the right and top edges
+        canvas.paste(&numbered(3, 2).rotate90(), 3, -1);
+
+        assert_eq!(at(&canvas, 3, 0), (1, 1));
+        assert_eq!(at(&canvas, 3, 1), (2, 1));
+        assert_eq!(at(&canvas, 3, 2), (9, 9));
+        assert_eq!(at(&canvas, 2, 0), (9, 9));
+    }
+}
@@ -0,0 +1,216 @@
+//! A classic wallpaper daemon: apply once, then re-apply on signals. Only built with the
+//! `signals` feature.
+//!
+//! [`run`] does everything. Programs that wait on more than signals can drive a [`Daemon`]
+//! from their own loop instead, feeding it [`DaemonEvent`]s from wherever they come from.
+
+use std::{ffi::OsString, ops::ControlFlow, process::Command};
+
+use signal_hook::{
+    consts::{SIGINT, SIGTERM, SIGUSR1, SIGUSR2},
+    iterator::Signals,
+};
+
+use crate::{
+    AppliedInfo, AppliedSource, BackgroundHandle, LoadConfig, OpenMethod, Pixel, Result, Scaling,
+    WallpaperSource,
+};
+
+// How many dominant colors the applied command is told about
+const APPLIED_COLORS: usize = 8;
+
+/// Every signal [`DaemonEvent::from_signal`] understands.
+pub const SIGNALS: &[i32] = &[SIGUSR1, SIGUSR2, SIGTERM, SIGINT];
+
+/// What a [`Daemon`] can be asked to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DaemonEvent {
+    /// Renders and flushes the source again, picking up e.g. a changed file. SIGUSR1.
+    Reapply,
+    /// Goes back to what was shown before the last re-apply, see [`BackgroundHandle::undo`].
+    /// SIGUSR2.
+    Undo,
+    /// Stops handling events. The wallpaper stays, as it always outlives the connection. SIGTERM
+    /// and SIGINT.
+    Terminate,
+}
+
+impl DaemonEvent {
+    pub fn from_signal(signal: i32) -> Option<DaemonEvent> {
+        match signal {
+            SIGUSR1 => Some(DaemonEvent::Reapply),
+            SIGUSR2 => Some(DaemonEvent::Undo),
+            SIGTERM | SIGINT => Some(DaemonEvent::Terminate),
+            _ => None,
+        }
+    }
+}
+
+/// Options for [`run`].
+#[derive(Clone, Debug)]
+pub struct DaemonOptions {
+    pub(crate) scaling: Scaling,
+    pub(crate) load_config: LoadConfig,
+    pub(crate) history_depth: usize,
+    pub(crate) applied_command: Option<(OsString, Vec<OsString>)>,
+}
+
+impl DaemonOptions {
+    pub fn new(scaling: impl Into<Scaling>) -> DaemonOptions {
+        DaemonOptions {
+            scaling: scaling.into(),
+            load_config: LoadConfig::default(),
+            history_depth: 8,
+            applied_command: None,
+        }
+    }
+
+    pub fn load_config(mut self, config: LoadConfig) -> DaemonOptions {
+        self.load_config = config;
+        self
+    }
+
+    /// How many re-applies [`DaemonEvent::Undo`] can step back through, 8 by default.
+    pub fn history_depth(mut self, depth: usize) -> DaemonOptions {
+        self.history_depth = depth;
+        self
+    }
+
+    /// Runs `program` with `args` whenever a wallpaper went up, see
+    /// [`BackgroundHandle::on_applied`], e.g. to regenerate a color scheme and restart a bar. It
+    /// learns about the wallpaper through its environment:
+    ///
+    /// - `SHADE_SOURCE`: the image's path, or the color as `#rrggbb`, unset for anything else
+    /// - `SHADE_PIXMAP`: the pixmap id showing it
+    /// - `SHADE_MONITORS`: the outputs showing it, separated by commas
+    /// - `SHADE_COLORS`: its dominant colors as `#rrggbb`, most common first, separated by spaces
+    ///
+    /// The daemon does not wait for it to finish, and only logs failures to start it.
+    pub fn applied_command<I, S>(mut self, program: impl Into<OsString>, args: I) -> DaemonOptions
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        let args = args.into_iter().map(Into::into).collect();
+        self.applied_command = Some((program.into(), args));
+        self
+    }
+}
+
+/// Applies a source to a handle and reacts to [`DaemonEvent`]s, without caring where they come
+/// from.
+pub struct Daemon<'a> {
+    handle: &'a BackgroundHandle,
+    source: WallpaperSource,
+    scaling: Scaling,
+}
+
+impl<'a> Daemon<'a> {
+    pub fn new(
+        handle: &'a BackgroundHandle,
+        source: WallpaperSource,
+        scaling: impl Into<Scaling>,
+    ) -> Daemon<'a> {
+        Daemon {
+            handle,
+            source,
+            scaling: scaling.into(),
+        }
+    }
+
+    /// Renders and flushes the source, keeping what was shown before for
+    /// [`DaemonEvent::Undo`].
+    pub fn apply(&self) -> Result<()> {
+        // Not being able to undo is no reason not to apply
+        if let Err(e) = self.handle.push_history() {
+            warn!("Not keeping the current wallpaper for undo: {e}");
+        }
+        self.handle.set_source(&self.source, self.scaling.clone())?;
+        self.handle.flush()
+    }
+
+    /// Handles one event, breaking once it was asked to terminate.
+    pub fn dispatch(&self, event: DaemonEvent) -> Result<ControlFlow<()>> {
+        debug!("Handling {event:?}");
+
+        match event {
+            DaemonEvent::Reapply => self.apply()?,
+            DaemonEvent::Undo => {
+                if !self.handle.undo()? {
+                    info!("Nothing to go back to");
+                }
+            }
+            DaemonEvent::Terminate => return Ok(ControlFlow::Break(())),
+        }
+
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+/// Loads the handle, applies `source` and then blocks, handling [`SIGNALS`] until told to
+/// terminate.
+///
+/// Signals are only ever noted down by the handler and acted upon here, outside of signal
+/// context. A failing re-apply, e.g. of a file deleted in the meantime, is logged and the daemon
+/// keeps running; only loading and the first apply fail the call.
+pub fn run(source: WallpaperSource, options: DaemonOptions) -> Result<()> {
+    let mut signals = Signals::new(SIGNALS)?;
+
+    let handle = crate::load_with(OpenMethod::MakeNew, options.load_config)?;
+    if let Some((program, args)) = options.applied_command {
+        handle.on_applied(move |info| spawn_applied_command(&program, &args, info));
+    }
+
+    let daemon = Daemon::new(handle, source, options.scaling);
+    daemon.apply()?;
+    // Only now, there is nothing worth going back to before the first apply
+    handle.set_history_depth(options.history_depth);
+    info!("Wallpaper applied, waiting for signals");
+
+    for event in signals.forever().filter_map(DaemonEvent::from_signal) {
+        match daemon.dispatch(event) {
+            Ok(ControlFlow::Break(())) => break,
+            Ok(ControlFlow::Continue(())) => {}
+            Err(e) => warn!("Failed to handle {event:?}: {e}"),
+        }
+    }
+
+    info!("Terminating");
+    Ok(())
+}
+
+fn spawn_applied_command(program: &OsString, args: &[OsString], info: &AppliedInfo) {
+    let hex = |p: &Pixel| format!("#{:02x}{:02x}{:02x}", p.r, p.g, p.b);
+
+    let mut command = Command::new(program);
+    command.args(args);
+    match &info.source {
+        AppliedSource::File(path) => command.env("SHADE_SOURCE", path),
+        AppliedSource::Color(color) => command.env("SHADE_SOURCE", hex(color)),
+        AppliedSource::Other => command.env_remove("SHADE_SOURCE"),
+    };
+
+    let monitors: Vec<&str> = info
+        .monitors
+        .iter()
+        .flat_map(|m| m.names.iter().map(String::as_str))
+        .collect();
+    let colors: Vec<String> = info
+        .dominant_colors(APPLIED_COLORS)
+        .iter()
+        .map(hex)
+        .collect();
+
+    command
+        .env("SHADE_PIXMAP", info.pixmap.to_string())
+        .env("SHADE_MONITORS", monitors.join(","))
+        .env("SHADE_COLORS", colors.join(" "));
+
+    match command.spawn() {
+        // Reaped on a thread of its own, the daemon is not going to wait for it
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => warn!("Failed to run the applied command {program:?}: {e}"),
+    }
+}
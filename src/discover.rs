@@ -0,0 +1,223 @@
+use std::{
+    collections::HashSet,
+    env,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::supported_formats;
+
+/// Recursively collects every image shade can decode below `dirs`, sorted by path.
+///
+/// Without `dirs`, looks in `$XDG_PICTURES_DIR/Wallpapers` and `/usr/share/backgrounds`.
+/// Directories that can't be read are skipped, and symlinked directories are followed once.
+pub fn discover_wallpapers(dirs: Option<&[PathBuf]>) -> Vec<PathBuf> {
+    let dirs = match dirs {
+        Some(dirs) => dirs.to_vec(),
+        None => default_dirs(),
+    };
+
+    let extensions = supported_formats();
+    let mut visited = HashSet::new();
+    let mut found = Vec::new();
+
+    for dir in &dirs {
+        walk(dir, &extensions, &mut visited, &mut found);
+    }
+
+    found.sort();
+    found.dedup();
+    found
+}
+
+fn default_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::with_capacity(2);
+    if let Some(pictures) = pictures_dir() {
+        dirs.push(pictures.join("Wallpapers"));
+    }
+    dirs.push(PathBuf::from("/usr/share/backgrounds"));
+    dirs
+}
+
+// The environment wins over user-dirs.dirs, which wins over ~/Pictures, like xdg-user-dir does
+fn pictures_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_PICTURES_DIR") {
+        return Some(dir.into());
+    }
+
+    let home = PathBuf::from(env::var_os("HOME")?);
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".config"));
+
+    let from_user_dirs = fs::read_to_string(config.join("user-dirs.dirs"))
+        .ok()
+        .and_then(|dirs| {
+            dirs.lines().find_map(|line| {
+                let value = line.trim().strip_prefix("XDG_PICTURES_DIR=")?;
+                let value = value.trim_matches('"');
+                Some(match value.strip_prefix("$HOME") {
+                    Some(rest) => home.join(rest.trim_start_matches('/')),
+                    None => PathBuf::from(value),
+                })
+            })
+        });
+
+    Some(from_user_dirs.unwrap_or_else(|| home.join("Pictures")))
+}
+
+fn walk(dir: &Path, extensions: &[&str], visited: &mut HashSet<PathBuf>, found: &mut Vec<PathBuf>) {
+    // Following symlinks can lead back into a directory we are already in
+    let Ok(canonical) = dir.canonicalize() else {
+        return;
+    };
+    if !visited.insert(canonical) {
+        return;
+    }
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Skipping {}: {e}", dir.display());
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        // fs::metadata follows symlinks, entry.file_type() would not
+        match fs::metadata(&path) {
+            Ok(meta) if meta.is_dir() => walk(&path, extensions, visited, found),
+            Ok(meta) if meta.is_file() && is_image(&path, extensions) => found.push(path),
+            _ => {}
+        }
+    }
+}
+
+// Extension first since it is free, then the magic bytes to weed out misnamed files
+fn is_image(path: &Path, extensions: &[&str]) -> bool {
+    let known_extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| extensions.contains(&e.to_ascii_lowercase().as_str()));
+
+    if !known_extension {
+        return false;
+    }
+
+    let mut magic = Vec::with_capacity(16);
+    match File::open(path).and_then(|f| f.take(16).read_to_end(&mut magic)) {
+        Ok(_) => image::guess_format(&magic).is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    const JPEG: &[u8] = b"\xff\xd8\xff\xe0\0\x10JFIF\0";
+
+    // A directory of its own for each test, removed again however the test ends
+    struct Tree(PathBuf);
+
+    impl Tree {
+        fn new(test: &str) -> Tree {
+            let dir = env::temp_dir().join(format!("shade-discover-{}-{test}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Tree(dir)
+        }
+
+        fn file(&self, path: &str, contents: &[u8]) -> PathBuf {
+            let path = self.0.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+            path
+        }
+
+        fn discover(&self) -> Vec<PathBuf> {
+            discover_wallpapers(Some(std::slice::from_ref(&self.0)))
+        }
+    }
+
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            let _ = fs::set_permissions(self.0.join("locked"), fs::Permissions::from_mode(0o755));
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn images_are_told_by_extension_and_magic() {
+        let tree = Tree::new("magic");
+        let png = tree.file("a.png", PNG);
+        let jpeg = tree.file("B.JPG", JPEG);
+        tree.file("not-an-image.png", b"just some text, long enough");
+        tree.file("empty.png", b"");
+        tree.file("png-without-extension", PNG);
+        tree.file("notes.txt", PNG);
+
+        // Uppercase sorts first, by path rather than by name
+        assert_eq!(tree.discover(), [jpeg, png]);
+    }
+
+    #[test]
+    fn found_sorted_across_directories() {
+        let tree = Tree::new("sorted");
+        let deep = tree.file("b/c/deep.png", PNG);
+        let late = tree.file("z.png", PNG);
+        let early = tree.file("a.png", PNG);
+        let nested = tree.file("b/nested.png", PNG);
+
+        assert_eq!(tree.discover(), [early, deep, nested, late]);
+    }
+
+    #[test]
+    fn overlapping_directories_list_images_once() {
+        let tree = Tree::new("dedup");
+        let top = tree.file("top.png", PNG);
+        let nested = tree.file("sub/nested.png", PNG);
+
+        let dirs = [tree.0.join("sub"), tree.0.clone(), tree.0.join("sub")];
+        assert_eq!(discover_wallpapers(Some(&dirs)), [nested, top]);
+    }
+
+    #[test]
+    fn symlinks_back_up_the_tree_are_followed_once() {
+        let tree = Tree::new("loop");
+        let top = tree.file("top.png", PNG);
+        let nested = tree.file("sub/nested.png", PNG);
+        symlink(&tree.0, tree.0.join("sub/up")).unwrap();
+        symlink("..", tree.0.join("sub/parent")).unwrap();
+
+        assert_eq!(tree.discover(), [nested, top]);
+    }
+
+    #[test]
+    fn unreadable_directories_are_skipped() {
+        let tree = Tree::new("locked");
+        let open = tree.file("open.png", PNG);
+        let locked = tree.file("locked/hidden.png", PNG);
+        fs::set_permissions(tree.0.join("locked"), fs::Permissions::from_mode(0o000)).unwrap();
+
+        // Root reads through any mode, everyone else is kept out
+        let expected = match fs::read_dir(tree.0.join("locked")) {
+            Ok(_) => vec![locked, open],
+            Err(_) => vec![open],
+        };
+        assert_eq!(tree.discover(), expected);
+    }
+
+    #[test]
+    fn missing_directories_find_nothing() {
+        let tree = Tree::new("missing");
+        assert!(discover_wallpapers(Some(&[tree.0.join("nowhere")])).is_empty());
+        assert!(discover_wallpapers(Some(&[])).is_empty());
+    }
+}
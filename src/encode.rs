@@ -0,0 +1,436 @@
+//! Turning pixel rows into ZPixmap bytes for PutImage, and splitting them over requests.
+//!
+//! Everything here is a pure function of a [`ServerFormat`] and the pixels, with no connection or
+//! [`BackgroundHandle`](crate::BackgroundHandle) involved, so other X clients can share the
+//! encoding shade flushes with.
+
+use std::ops::Range;
+
+use crate::Pixel;
+
+/// Size of a PutImage request without its data, counting the extra length field BIG-REQUESTS
+/// adds. It counts towards the maximum request length along with the image data.
+pub const PUT_IMAGE_HEADER_BYTES: usize = 28;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteOrder {
+    LsbFirst,
+    MsbFirst,
+}
+
+/// How a server lays out ZPixmap data for one depth.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ServerFormat {
+    pub depth: u8,
+    /// Whole bytes only, sub byte formats are not supported.
+    pub bits_per_pixel: u8,
+    /// Rows are padded to a multiple of this many bits.
+    pub scanline_pad: u8,
+    pub byte_order: ByteOrder,
+    pub red_mask: u32,
+    pub green_mask: u32,
+    pub blue_mask: u32,
+}
+
+impl ServerFormat {
+    /// The format the server announced for `depth`, with the channel masks of `visual`. `None` if
+    /// the server has no pixmap format for `depth`.
+    #[cfg(feature = "x11")]
+    pub fn from_setup(
+        setup: &xcb::x::Setup,
+        depth: u8,
+        visual: &xcb::x::Visualtype,
+    ) -> Option<ServerFormat> {
+        let format = setup.pixmap_formats().iter().find(|f| f.depth() == depth)?;
+
+        Some(ServerFormat {
+            depth,
+            bits_per_pixel: format.bits_per_pixel(),
+            scanline_pad: format.scanline_pad(),
+            byte_order: match setup.image_byte_order() {
+                xcb::x::ImageOrder::LsbFirst => ByteOrder::LsbFirst,
+                xcb::x::ImageOrder::MsbFirst => ByteOrder::MsbFirst,
+            },
+            red_mask: visual.red_mask(),
+            green_mask: visual.green_mask(),
+            blue_mask: visual.blue_mask(),
+        })
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        (self.bits_per_pixel as usize).div_ceil(8)
+    }
+
+    /// Bytes one row of `width` pixels takes, padding included.
+    pub fn row_bytes(&self, width: u32) -> usize {
+        let pad = (self.scanline_pad as usize / 8).max(1);
+        (width as usize * self.bytes_per_pixel()).div_ceil(pad) * pad
+    }
+
+    /// `pixel` with each channel scaled into the bits its mask covers.
+    pub fn pixel_value(&self, pixel: &Pixel) -> u32 {
+        pack(pixel, [self.red_mask, self.green_mask, self.blue_mask])
+    }
+}
+
+// Place each 8 bit channel into the bits its mask covers
+pub(crate) fn pack(pixel: &Pixel, [red, green, blue]: [u32; 3]) -> u32 {
+    fn channel(value: u8, mask: u32) -> u32 {
+        if mask == 0 {
+            return 0;
+        }
+
+        let shift = mask.trailing_zeros();
+        let bits = (mask >> shift).count_ones();
+        let value = if bits >= 8 {
+            (value as u32) << (bits - 8)
+        } else {
+            (value as u32) >> (8 - bits)
+        };
+
+        (value << shift) & mask
+    }
+
+    channel(pixel.r, red) | channel(pixel.g, green) | channel(pixel.b, blue)
+}
+
+/// Encodes `rows`, all of the same width, one after the other in `format`, each padded to
+/// [`ServerFormat::row_bytes`].
+pub fn encode_rows<'a>(
+    format: &ServerFormat,
+    rows: impl IntoIterator<Item = &'a [Pixel]>,
+) -> Vec<u8> {
+    let bytes_per_pixel = format.bytes_per_pixel();
+    let mut data = Vec::new();
+
+    for row in rows {
+        let start = data.len();
+        data.resize(start + format.row_bytes(row.len() as u32), 0);
+
+        for (pixel, out) in row
+            .iter()
+            .zip(data[start..].chunks_exact_mut(bytes_per_pixel))
+        {
+            let value = format.pixel_value(pixel).to_le_bytes();
+            match format.byte_order {
+                ByteOrder::LsbFirst => out.copy_from_slice(&value[..bytes_per_pixel]),
+                ByteOrder::MsbFirst => {
+                    for (o, v) in out.iter_mut().zip(value[..bytes_per_pixel].iter().rev()) {
+                        *o = *v;
+                    }
+                }
+            }
+        }
+    }
+
+    data
+}
+
+/// One request's worth of an encoded image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chunk {
+    /// First row of the chunk, counted from the top of the image.
+    pub y: u16,
+    pub rows: u16,
+    /// First column of the chunk. Chunks span whole rows, starting at 0, unless a single row is
+    /// too long for one request.
+    pub x: u16,
+    pub width: u16,
+    /// Where the chunk's pixels are in the encoded data, a valid PutImage payload on their own.
+    pub bytes: Range<usize>,
+}
+
+/// Splits a `width` x `height` image encoded in `format` into chunks, each at most `max_bytes`
+/// long. For a chunk per request, pass the maximum request length in bytes less
+/// [`PUT_IMAGE_HEADER_BYTES`].
+///
+/// Chunks are made of as many whole rows as fit. Where not even a single row does, as with a wide
+/// root on a server without BIG-REQUESTS, every row is split into runs of columns instead. Each
+/// run but a row's last ends on a scanline pad boundary, so it needs no padding of its own.
+/// `None` if `max_bytes` is too small for even that, the few pixels it takes to fill one pad
+/// unit.
+pub fn chunk_plan(
+    format: &ServerFormat,
+    width: u16,
+    height: u16,
+    max_bytes: usize,
+) -> Option<Vec<Chunk>> {
+    let row_bytes = format.row_bytes(width as u32);
+
+    if row_bytes <= max_bytes {
+        let rows = (max_bytes / row_bytes.max(1)).clamp(1, u16::MAX as usize) as u16;
+
+        return Some(
+            (0..height)
+                .step_by(rows as usize)
+                .map(|y| {
+                    let rows = rows.min(height - y);
+                    let start = y as usize * row_bytes;
+                    Chunk {
+                        y,
+                        rows,
+                        x: 0,
+                        width,
+                        bytes: start..start + rows as usize * row_bytes,
+                    }
+                })
+                .collect(),
+        );
+    }
+
+    let bytes_per_pixel = format.bytes_per_pixel();
+    let pad = (format.scanline_pad as usize / 8).max(1);
+    let unit = bytes_per_pixel * pad / gcd(bytes_per_pixel, pad);
+    let run = (max_bytes / unit * (unit / bytes_per_pixel)).min(width as usize) as u16;
+    if run == 0 {
+        return None;
+    }
+
+    let mut chunks = Vec::new();
+    for y in 0..height {
+        let row_start = y as usize * row_bytes;
+
+        for x in (0..width).step_by(run as usize) {
+            let run = run.min(width - x);
+            let start = row_start + x as usize * bytes_per_pixel;
+            // The last run of a row takes the row's padding along
+            let end = match x + run == width {
+                true => row_start + row_bytes,
+                false => start + run as usize * bytes_per_pixel,
+            };
+
+            chunks.push(Chunk {
+                y,
+                rows: 1,
+                x,
+                width: run,
+                bytes: start..end,
+            });
+        }
+    }
+
+    Some(chunks)
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A depth 24 root with 4 bytes per pixel, the most common layout
+    const DEPTH_24: ServerFormat = ServerFormat {
+        depth: 24,
+        bits_per_pixel: 32,
+        scanline_pad: 32,
+        byte_order: ByteOrder::LsbFirst,
+        red_mask: 0xff0000,
+        green_mask: 0x00ff00,
+        blue_mask: 0x0000ff,
+    };
+
+    // Packed 3 bytes per pixel, rows still padded to 4 bytes, so pad boundaries fall every 4 pixels
+    const PACKED_24: ServerFormat = ServerFormat {
+        bits_per_pixel: 24,
+        ..DEPTH_24
+    };
+
+    // 5-6-5 with rows padded to 4 bytes, most significant byte first
+    const DEPTH_16_MSB: ServerFormat = ServerFormat {
+        depth: 16,
+        bits_per_pixel: 16,
+        scanline_pad: 32,
+        byte_order: ByteOrder::MsbFirst,
+        red_mask: 0xf800,
+        green_mask: 0x07e0,
+        blue_mask: 0x001f,
+    };
+
+    // The largest request a server without BIG-REQUESTS may have, and the pathological 16 KB one
+    const MAX_REQUEST: usize = 262_140 - PUT_IMAGE_HEADER_BYTES;
+    const TINY_REQUEST: usize = 16 * 1024 - PUT_IMAGE_HEADER_BYTES;
+
+    // Plans `width` x `height`, checking what every plan has to hold: chunks no larger than
+    // `max_bytes`, together covering every row and every byte exactly once and in order
+    fn plan(format: &ServerFormat, width: u16, height: u16, max_bytes: usize) -> Vec<Chunk> {
+        let chunks = chunk_plan(format, width, height, max_bytes).expect("no plan");
+        let row_bytes = format.row_bytes(width as u32);
+        let pad = format.scanline_pad as usize / 8;
+
+        let mut next = (0, 0);
+        let mut end = 0;
+        for chunk in &chunks {
+            assert_eq!((chunk.y, chunk.x), next, "{chunk:?} out of order");
+            assert_eq!(chunk.bytes.start, end, "{chunk:?} leaves a gap");
+            assert!(chunk.bytes.len() <= max_bytes, "{chunk:?} too long");
+            assert!(chunk.rows > 0 && chunk.width > 0);
+
+            // Bytes for exactly its pixels, with the padding only where the chunk ends a row
+            let ends_row = chunk.x + chunk.width == width;
+            let expected = match (chunk.x, ends_row) {
+                (0, true) => row_bytes * chunk.rows as usize,
+                (_, true) => row_bytes - chunk.x as usize * format.bytes_per_pixel(),
+                _ => chunk.width as usize * format.bytes_per_pixel(),
+            };
+            assert_eq!(
+                chunk.bytes.len(),
+                expected,
+                "{chunk:?} has the wrong length"
+            );
+            if !ends_row {
+                assert_eq!(
+                    chunk.bytes.len() % pad,
+                    0,
+                    "{chunk:?} ends off a pad boundary"
+                );
+            }
+
+            next = match ends_row {
+                true => (chunk.y + chunk.rows, 0),
+                false => (chunk.y, chunk.x + chunk.width),
+            };
+            end = chunk.bytes.end;
+        }
+        assert_eq!(next, (height, 0), "rows left out");
+        assert_eq!(end, row_bytes * height as usize, "bytes left out");
+        chunks
+    }
+
+    fn row(width: u8) -> Vec<Pixel> {
+        (0..width).map(|x| Pixel::new(x, 0x80, 0xff - x)).collect()
+    }
+
+    #[test]
+    fn encoding_a_single_pixel() {
+        let pixel = [Pixel::new(0x12, 0x34, 0x56)];
+        assert_eq!(encode_rows(&DEPTH_24, [&pixel[..]]), [0x56, 0x34, 0x12, 0]);
+        // Padded out to the 4 byte scanline
+        assert_eq!(encode_rows(&PACKED_24, [&pixel[..]]), [0x56, 0x34, 0x12, 0]);
+        // 0x12 >> 3, 0x34 >> 2 and 0x56 >> 3 as 00010 001101 01010
+        assert_eq!(encode_rows(&DEPTH_16_MSB, [&pixel[..]]), [0x11, 0xaa, 0, 0]);
+    }
+
+    #[test]
+    fn encoding_a_single_column() {
+        let column = [
+            Pixel::new(1, 2, 3),
+            Pixel::new(4, 5, 6),
+            Pixel::new(7, 8, 9),
+        ];
+        let data = encode_rows(&PACKED_24, column.chunks(1));
+        assert_eq!(data, [3, 2, 1, 0, 6, 5, 4, 0, 9, 8, 7, 0]);
+    }
+
+    #[test]
+    fn rows_keep_their_last_pixel_before_the_padding() {
+        // 5 packed pixels take 15 bytes, padded to 16
+        let row = row(5);
+        let data = encode_rows(&PACKED_24, [&row[..], &row[..]]);
+        assert_eq!(data.len(), 2 * 16);
+        for start in [0, 16] {
+            assert_eq!(data[start + 12..start + 16], [0xfb, 0x80, 4, 0]);
+        }
+    }
+
+    #[test]
+    fn rows_start_where_the_previous_padding_ends() {
+        // 3 pixels of 16 bits take 6 bytes, padded to 8
+        let rows = [row(3), row(3).into_iter().rev().collect()];
+        let data = encode_rows(&DEPTH_16_MSB, rows.iter().map(Vec::as_slice));
+        assert_eq!(data.len(), 16);
+        assert_eq!(data[6..8], [0, 0]);
+        assert_eq!(
+            data[8..10],
+            encode_rows(&DEPTH_16_MSB, [&rows[1][..1]])[..2]
+        );
+    }
+
+    #[test]
+    fn byte_order_swaps_whole_pixels() {
+        let row = row(2);
+        let lsb = encode_rows(&DEPTH_24, [&row[..]]);
+        let msb_format = ServerFormat {
+            byte_order: ByteOrder::MsbFirst,
+            ..DEPTH_24
+        };
+        let msb = encode_rows(&msb_format, [&row[..]]);
+        for (lsb, msb) in lsb.chunks(4).zip(msb.chunks(4)) {
+            assert!(lsb.iter().eq(msb.iter().rev()));
+        }
+    }
+
+    #[test]
+    fn whole_rows_when_they_fit() {
+        let chunks = plan(&DEPTH_24, 1920, 1080, MAX_REQUEST);
+        // 34 rows of 7680 bytes fit
+        assert!(chunks.iter().all(|c| c.x == 0 && c.width == 1920));
+        assert_eq!(chunks[0].rows, 34);
+        assert_eq!(chunks.len(), 1080usize.div_ceil(34));
+        assert_eq!(chunks.last().unwrap().rows, 1080 % 34);
+    }
+
+    #[test]
+    fn everything_in_one_chunk() {
+        let chunks = plan(&DEPTH_24, 64, 64, usize::MAX);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].rows, 64);
+    }
+
+    #[test]
+    fn rows_too_long_for_16k_are_split() {
+        // 32000 bytes a row, twice the limit
+        let chunks = plan(&DEPTH_24, 8000, 4, TINY_REQUEST);
+        assert!(chunks.iter().all(|c| c.rows == 1));
+        let row: Vec<_> = chunks.iter().take_while(|c| c.y == 0).collect();
+        assert_eq!(row.len(), 2);
+        assert_eq!((row[0].x, row[0].width), (0, 4089));
+        assert_eq!((row[1].x, row[1].width), (4089, 8000 - 4089));
+        assert_eq!(chunks.len(), 2 * 4);
+    }
+
+    #[test]
+    fn split_runs_end_on_pad_boundaries() {
+        // 4 pixels are the fewest that end on one
+        let chunks = plan(&PACKED_24, 8000, 2, TINY_REQUEST);
+        assert!(chunks
+            .iter()
+            .filter(|c| c.x + c.width < 8000)
+            .all(|c| c.width % 4 == 0));
+
+        // A row of 7 packed pixels takes 24 bytes, the last run 3 pixels and the padding
+        let chunks = plan(&PACKED_24, 7, 1, 12);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].bytes, 12..24);
+    }
+
+    #[test]
+    fn limits_below_a_pad_unit_have_no_plan() {
+        assert_eq!(chunk_plan(&PACKED_24, 8000, 1, 11), None);
+        assert_eq!(chunk_plan(&DEPTH_24, 8000, 1, 3), None);
+        assert!(chunk_plan(&DEPTH_24, 8000, 1, 4).is_some());
+    }
+
+    #[test]
+    fn ultra_wide_roots() {
+        // 131040 bytes a row, beyond what u16 math would survive
+        let chunks = plan(&DEPTH_24, 32760, 2160, MAX_REQUEST);
+        assert!(chunks.iter().all(|c| c.x == 0 && c.width == 32760));
+        assert_eq!(chunks[0].rows, 2);
+
+        let chunks = plan(&PACKED_24, 32760, 2160, TINY_REQUEST);
+        assert!(chunks.iter().all(|c| c.rows == 1));
+        assert_eq!(chunks.len(), 2160 * 32760usize.div_ceil(5452));
+    }
+
+    #[test]
+    fn empty_images_send_nothing() {
+        assert_eq!(chunk_plan(&DEPTH_24, 1920, 0, MAX_REQUEST), Some(vec![]));
+        let chunks = chunk_plan(&DEPTH_24, 0, 3, MAX_REQUEST).unwrap();
+        assert!(chunks.iter().all(|c| c.bytes.is_empty()));
+    }
+}
@@ -1,363 +1,182 @@
-use once_cell::sync::OnceCell;
-use std::{path::Path, sync::Mutex};
 use thiserror::Error;
-use tracing::{info, warn};
 
-use xcb::{
-    x::{
-        Atom, ChangeProperty, ChangeWindowAttributes, CloseDown::RetainPermanent, CreateGc,
-        CreatePixmap, Cw, Drawable, Gcontext, GetProperty, ImageFormat::ZPixmap, InternAtom,
-        KillClient, Pixmap, PutImage, SetCloseDownMode, Window, ATOM_ANY, ATOM_NONE, ATOM_PIXMAP, Gc,
-    },
-    Connection, Xid,
+#[macro_use]
+mod log;
+
+#[cfg(feature = "adaptive")]
+pub mod adaptive;
+mod cancel;
+pub mod canvas;
+#[cfg(feature = "signals")]
+pub mod daemon;
+mod discover;
+pub mod encode;
+pub mod patterns;
+#[cfg(feature = "power")]
+pub mod power;
+pub mod prelude;
+#[cfg(all(test, feature = "serde"))]
+mod serde_value;
+#[cfg(feature = "x11")]
+pub mod x11;
+
+pub use cancel::{CancelToken, CANCEL_LATENCY};
+pub use canvas::{
+    channel_bits, compute_placement, compute_placement_with_aspect, contrast_ratio, gamma_lut,
+    relative_luminance, supported_formats, BlendMode, CacheStats, Canvas, CanvasView, CatmullRom,
+    Filter, FilterChain, Frame, FrameBudget, FrameRecorder, ImageCache, IntegerScaler,
+    MemoryBudget, MemoryConsumer, MemoryReport, Pixel, PixelAspect, PixelFormat, Placement,
+    PreparedImage, Rect, Resampling, Rgb8, Rgba8, Scaler, Scaling, ScalingMethod, TextColor,
+    WallpaperSource, MIN_TEXT_CONTRAST,
+};
+pub use discover::discover_wallpapers;
+#[cfg(feature = "x11")]
+pub use x11::{
+    capabilities, compositor_active, current_metadata, load, load_with, load_with_report, plan,
+    plan_with, preview, probe_visuals, screens, self_test, set_once, AppliedInfo, AppliedSource,
+    Applier, ApplyOutcome, BackgroundHandle, Capabilities, ClearPolicy, Completion,
+    DesktopWallpapers, FlushMode, FlushOptions, GammaReport, GcConfig, LazyHandle, LoadConfig,
+    LoadPlan, LoadReport, LoadWarning, Metadata, Monitor, MonitorAspect, OpenMethod, PausePolicy,
+    PlanEntry, PlanTarget, PreviousWallpaper, ProbeReport, RootProperties, ScreenInfo,
+    SelfTestReport, ServerGrab, ShadeEvent, Shutdown, Snapshot, Span, Transition, VisualInfo,
+    WallpaperPlan, PROBE_SCHEMA,
 };
-
-// Send a request without reply, check it, and return the error converted into an xcb::Error if
-// there is one
-macro_rules! void_request {
-    ($connection: expr, $request:expr ) => {
-        xcb::Connection::send_and_check_request($connection, $request).map_err(xcb::Error::from)
-    };
-}
-
-// Send a request with reply and wait foro it, check it, and return the error converted into an xcb::Error if
-// there is one
-macro_rules! cookie_request {
-    ($connection: expr, $request:expr) => {{
-        let cookie = xcb::Connection::send_request($connection, $request);
-        xcb::Connection::wait_for_reply($connection, cookie)
-    }};
-}
 
 #[derive(Error, Debug)]
 pub enum Error {
+    #[error(
+        "DISPLAY is not set but WAYLAND_DISPLAY is, shade needs an X server (or XWayland) \
+         to draw to"
+    )]
+    NoX11,
+
     #[error("Xorg roots iterator did not provided any screens")]
     NoScreenFound,
 
+    #[error("Screen {screen} does not exist, the display has {count} screens")]
+    ScreenOutOfRange { screen: i32, count: usize },
+
+    #[error("Screen reports an unusable geometry of {width}x{height}")]
+    InvalidGeometry { width: u16, height: u16 },
+
+    #[error("Screen is {now:?} now but was {kept:?} when the handle was loaded")]
+    GeometryChanged { kept: (u16, u16), now: (u16, u16) },
+
+    #[error("Image is {found:?} but has to match the {expected:?} buffer")]
+    ImageSizeMismatch {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+
+    #[error("Got {found} pixels but the buffer holds {expected}")]
+    PixelCountMismatch { expected: usize, found: usize },
+
+    #[error("Snapshot was taken of a {taken:?} buffer, which is {now:?} now")]
+    StaleSnapshot { taken: (u32, u32), now: (u32, u32) },
+
+    #[error(
+        "A {consumer} of {requested} bytes does not fit the memory budget, \
+         {available} bytes are left"
+    )]
+    MemoryBudgetExceeded {
+        consumer: MemoryConsumer,
+        requested: usize,
+        available: usize,
+    },
+
+    #[error("Unsupported target depth {0}")]
+    UnsupportedDepth(u8),
+
+    #[error("Screen does not list its root visual among its allowed depths")]
+    NoVisualFound,
+
+    #[error("Window manager does not report the current desktop through _NET_CURRENT_DESKTOP")]
+    NoCurrentDesktop,
+
+    #[error("Handle has no RGBA layer, it was loaded without LoadConfig::rgba")]
+    NoRgbaLayer,
+
+    #[cfg(feature = "x11")]
+    #[error("Server announces no pixmap format for depth {0}")]
+    NoPixmapFormat(u8),
+
+    #[cfg(feature = "x11")]
+    #[error("Unsupported root visual class {0:?}, only TrueColor and DirectColor can be drawn to")]
+    UnsupportedVisual(xcb::x::VisualClass),
+
+    #[error(
+        "Not even a few pixels of the {bytes} byte image fit into the {max} bytes the server \
+         accepts in a single request"
+    )]
+    ImageTooLargeForRequest { bytes: usize, max: usize },
+
+    #[error("Another shade instance (pid {pid_hint:?}) already owns the wallpaper")]
+    AlreadyOwned { pid_hint: Option<u32> },
+
+    #[error(
+        "Flush failed after updating {updated_rows} of {rows} rows, the rest is sent again with \
+         the next flush: {source}"
+    )]
+    FlushIncomplete {
+        updated_rows: u16,
+        rows: u16,
+        source: Box<Error>,
+    },
+
     #[error("Failed to create root pixmap atoms")]
     FailedRootAtomCreation,
 
+    #[error("{0} does not name the new wallpaper's pixmap after setting it")]
+    RootPropertyMismatch(&'static str),
+
+    #[cfg(feature = "x11")]
     #[error("XCB Interal error: {0}")]
     XCBInteral(#[from] xcb::Error),
 
+    #[cfg(feature = "testing")]
+    #[error("Load failed on purpose, see shade::x11::testing::fail_loads")]
+    InjectedFailure,
+
+    #[error("Loading failed earlier: {0}")]
+    LoadFailed(std::sync::Arc<Error>),
+
     #[error("IO Error: {0}")]
     IO(#[from] std::io::Error),
 
+    #[cfg(feature = "net")]
+    #[error("HTTP request failed with status {status}")]
+    Http { status: u16 },
+
+    #[cfg(feature = "net")]
+    #[error("Network error: {0}")]
+    Net(Box<ureq::Transport>),
+
+    #[cfg(feature = "net")]
+    #[error("Download exceeds the limit of {limit} bytes")]
+    DownloadTooLarge { limit: u64 },
+
+    #[error("Image of {width}x{height} exceeds the limit of {limit} pixels")]
+    ImageTooLarge { width: u32, height: u32, limit: u64 },
+
     #[error("Image Error: {0}")]
     Image(#[from] image::error::ImageError),
+
+    #[error("Embedded wallpaper could not be decoded: {0}")]
+    EmbeddedImage(image::error::ImageError),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+#[cfg(feature = "x11")]
 trait AsByteSlice {
     fn as_byte_slice(&self) -> &[u8];
 }
 
+#[cfg(feature = "x11")]
 impl<T> AsByteSlice for [T] {
     fn as_byte_slice(&self) -> &[u8] {
         let buffer_ptr = self as *const _ as *const u8;
 
-        unsafe {
-            std::slice::from_raw_parts::<u8>(buffer_ptr, std::mem::size_of::<T>() * self.len())
-        }
+        unsafe { std::slice::from_raw_parts::<u8>(buffer_ptr, std::mem::size_of_val(self)) }
     }
 }
-
-#[repr(C)]
-#[derive(Clone, Debug, Default, PartialEq)]
-pub struct Pixel {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
-}
-
-impl Pixel {
-    pub fn new(r: u8, g: u8, b: u8) -> Pixel {
-        Pixel { r, g, b }
-    }
-}
-
-pub enum ScalingMethod {
-    Center,
-    Fill,
-    Max,
-    Scale,
-    Tile,
-}
-
-pub enum OpenMethod<'a> {
-    KeepExisting,
-    MakeNew,
-    LoadFromFile(ScalingMethod, &'a dyn AsRef<Path>),
-}
-
-pub struct BackgroundHandle {
-    pub(crate) context: Gcontext,
-    pub(crate) background_pixmap: Pixmap,
-    pub(crate) connection: Connection,
-    pub(crate) root: Window,
-    pub(crate) width: u16,
-    pub(crate) height: u16,
-    pub(crate) depth: u8,
-    pub buffer: Mutex<Box<[Pixel]>>,
-}
-
-impl BackgroundHandle {
-    pub fn flush(&self) -> Result<()> {
-        let buffer = self.buffer.lock().unwrap_or_else(|e| e.into_inner());
-
-        void_request!(
-            &self.connection,
-            &PutImage {
-                gc: self.context,
-                format: ZPixmap,
-                data: buffer.as_byte_slice(),
-                width: self.width,
-                height: self.height,
-                dst_x: 0,
-                dst_y: 0,
-                depth: self.depth,
-                drawable: Drawable::Pixmap(self.background_pixmap),
-                left_pad: 0,
-            }
-        )?;
-
-        Ok(())
-    }
-}
-
-fn resolve_atom(conn: &Connection, window: Window, atom: Atom) -> xcb::Result<Option<u32>> {
-    if atom == ATOM_NONE {
-        warn!("Atom {} is NOT SET", atom.resource_id());
-        return Ok(None);
-    } else {
-        let cookie = conn.send_request(&GetProperty {
-            r#type: ATOM_ANY,
-            delete: false,
-            window,
-            property: atom,
-            long_offset: 0,
-            long_length: 1,
-        });
-
-        let property = conn.wait_for_reply(cookie)?;
-
-        if property.r#type() != ATOM_PIXMAP {
-            warn!("Atom {} is NOT a pixmap", atom.resource_id());
-            Ok(None)
-        } else {
-            let id = match property.format() {
-                32 => property.value::<u32>()[0],
-                16 => {
-                    let bytes = property.value::<u16>().as_byte_slice().try_into().unwrap();
-                    u32::from_ne_bytes(bytes)
-                }
-                8 => {
-                    let bytes = property.value::<u8>().try_into().unwrap();
-                    u32::from_ne_bytes(bytes)
-                }
-                _ => unreachable!(),
-            };
-            Ok(Some(id))
-        }
-    }
-}
-
-fn kill_pmap_atoms(
-    connection: &Connection,
-    root: Window,
-    atom_xroot_pmap: Atom,
-    atom_esetroot_pmap: Atom,
-) -> xcb::Result<()> {
-    // Resolve the ids of the current pixmaps. If anyone is currently drawing to our beloved
-    // screen...
-    let xrootid = resolve_atom(&connection, root, atom_xroot_pmap)?;
-    let esetrootid = resolve_atom(&connection, root, atom_esetroot_pmap)?;
-
-    info!("Foreign pixmaps are X: {xrootid:?} | E: {esetrootid:?}");
-
-    // we MUST kill them
-    match (xrootid, esetrootid) {
-        (Some(x), Some(e)) => {
-            if x == e {
-                void_request!(connection, &KillClient { resource: x })?;
-            } else {
-                void_request!(connection, &KillClient { resource: x })?;
-                void_request!(connection, &KillClient { resource: e })?;
-            }
-        }
-        (Some(x), None) => {
-            void_request!(connection, &KillClient { resource: x })?;
-        }
-
-        (None, Some(e)) => {
-            void_request!(connection, &KillClient { resource: e })?;
-        }
-
-        (None, None) => {}
-    };
-
-    Ok(())
-}
-
-fn inner_load(open_method: OpenMethod) -> Result<BackgroundHandle> {
-    info!("Connecting to the Xorg Server");
-    let (connection, screen_number) = Connection::connect(None).map_err(xcb::Error::from)?;
-
-    let screen = connection
-        .get_setup()
-        .roots()
-        .nth(screen_number as usize)
-        .ok_or(Error::NoScreenFound)?;
-
-    let root = screen.root();
-    let width = screen.width_in_pixels();
-    let height = screen.height_in_pixels();
-    let depth = screen.root_depth();
-
-    info!(
-        "Root window with id: {}, width: {}, height: {} and depth: {}",
-        root.resource_id(),
-        width,
-        height,
-        depth
-    );
-
-    let shade_pmap = {
-        let pid = connection.generate_id();
-        let request = CreatePixmap {
-            depth,
-            pid,
-            width,
-            height,
-            drawable: Drawable::Window(root),
-        };
-
-        void_request!(&connection, &request)?;
-        info!("Allocated shade pixmap with id {:?}", pid);
-
-        pid
-    };
-
-    let gc = {
-        let cid = connection.generate_id();
-        let request = CreateGc {
-            drawable: Drawable::Pixmap(shade_pmap),
-            cid,
-            value_list: &[
-                Gc::Foreground(screen.white_pixel()),
-                Gc::Background(screen.black_pixel())
-            ],
-        };
-        void_request!(&connection, &request)?;
-        info!("Allocated shade gc with id {:?}", cid);
-        cid
-    };
-
-    let mut atom_xroot_pmap = cookie_request!(
-        &connection,
-        &InternAtom {
-            name: b"_XROOTPMAP_ID",
-            only_if_exists: true,
-        }
-    )?
-    .atom();
-
-    let mut atom_esetroot_pmap = cookie_request!(
-        &connection,
-        &InternAtom {
-            name: b"ESETROOT_PMAP_ID",
-            only_if_exists: true,
-        }
-    )?
-    .atom();
-
-    kill_pmap_atoms(&connection, root, atom_xroot_pmap, atom_esetroot_pmap)?;
-
-    // Create these if they did not exist before (e.g. the previous InternAtom request returned ATOM_NONE)
-    atom_xroot_pmap = cookie_request!(
-        &connection,
-        &InternAtom {
-            name: b"_XROOTPMAP_ID",
-            only_if_exists: false,
-        }
-    )?
-    .atom();
-
-    atom_esetroot_pmap = cookie_request!(
-        &connection,
-        &InternAtom {
-            name: b"ESETROOT_PMAP_ID",
-            only_if_exists: false,
-        }
-    )?
-    .atom();
-
-    if atom_xroot_pmap == ATOM_NONE || atom_esetroot_pmap == ATOM_NONE {
-        return Err(Error::FailedRootAtomCreation);
-    }
-
-    void_request!(
-        &connection,
-        &ChangeProperty {
-            property: atom_xroot_pmap,
-            mode: xcb::x::PropMode::Replace,
-            r#type: ATOM_PIXMAP,
-            window: root,
-            data: &[shade_pmap.resource_id()],
-        }
-    )?;
-
-    void_request!(
-        &connection,
-        &ChangeProperty {
-            property: atom_esetroot_pmap,
-            mode: xcb::x::PropMode::Replace,
-            r#type: ATOM_PIXMAP,
-            window: root,
-            data: &[shade_pmap.resource_id()],
-        }
-    )?;
-
-    // TODO This might not work on multi monitor setups
-    // TODO This also requires the monitor to be cleared
-
-    void_request!(
-        &connection,
-        &ChangeWindowAttributes {
-            window: root,
-            value_list: &[Cw::BackPixmap(shade_pmap)],
-        }
-    )?;
-
-    void_request!(
-        &connection,
-        &SetCloseDownMode {
-            mode: RetainPermanent
-        }
-    )?;
-
-    connection.flush().map_err(xcb::Error::from)?;
-
-    let handle = BackgroundHandle {
-        connection,
-        width,
-        height,
-        depth,
-        root,
-        buffer: Mutex::new(
-            vec![Pixel::default(); height as usize * width as usize].into_boxed_slice(),
-        ),
-        background_pixmap: shade_pmap,
-        context: gc,
-    };
-
-    info!("Created handle");
-
-    Ok(handle)
-}
-
-pub fn load(options: OpenMethod) -> Result<&'static BackgroundHandle> {
-    static HANDLE: OnceCell<BackgroundHandle> = OnceCell::new();
-    HANDLE.get_or_try_init(|| inner_load(options))
-}
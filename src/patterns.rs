@@ -0,0 +1,109 @@
+//! Test patterns with known pixel values, for checking what happens to colors on their way to the
+//! screen.
+//!
+//! shade never applies a gamma curve or any other lookup table: the buffer's 8 bit sRGB values
+//! are what gets uploaded, bit for bit, and what the server stores. Whatever washes colors out or
+//! darkens them happens further along, in the compositor, the monitor's settings or a remote
+//! display proxy. Showing a pattern with [`BackgroundHandle::flush`] lets you judge by eye,
+//! [`BackgroundHandle::verify_gamma`] checks the upload itself without touching the wallpaper.
+//!
+//! [`BackgroundHandle::flush`]: crate::BackgroundHandle::flush
+//! [`BackgroundHandle::verify_gamma`]: crate::BackgroundHandle::verify_gamma
+
+use crate::{Canvas, Pixel};
+
+/// Width of [`srgb_ramp`], one column per 8 bit level.
+pub const RAMP_LEVELS: u32 = 256;
+
+/// Rows of [`srgb_ramp`], top to bottom.
+pub const RAMP_ROWS: [RampRow; 4] = [RampRow::Gray, RampRow::Red, RampRow::Green, RampRow::Blue];
+
+/// What a row of [`srgb_ramp`] ramps up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RampRow {
+    /// All three channels at once, from black to white.
+    Gray,
+    /// The red channel alone, green and blue staying 0.
+    Red,
+    Green,
+    Blue,
+}
+
+impl RampRow {
+    /// The pixel of this row at `level`.
+    pub fn pixel(self, level: u8) -> Pixel {
+        match self {
+            RampRow::Gray => Pixel::new(level, level, level),
+            RampRow::Red => Pixel::new(level, 0, 0),
+            RampRow::Green => Pixel::new(0, level, 0),
+            RampRow::Blue => Pixel::new(0, 0, level),
+        }
+    }
+}
+
+/// Every 8 bit sRGB level in a row of its own column, once for gray and once for each channel
+/// alone, as laid out by [`RAMP_ROWS`]. The values are the encoded ones, column `x` holds level
+/// `x`, so the gray row only looks like an even ramp on a display that decodes sRGB correctly.
+pub fn srgb_ramp() -> Canvas {
+    srgb_ramp_sized(1)
+}
+
+/// [`srgb_ramp`] with every level `scale` pixels wide and tall, large enough to make out on a
+/// screen.
+pub fn srgb_ramp_sized(scale: u32) -> Canvas {
+    let scale = scale.max(1);
+    let mut canvas = Canvas::new(RAMP_LEVELS * scale, RAMP_ROWS.len() as u32 * scale);
+
+    for (y, row) in RAMP_ROWS.into_iter().enumerate() {
+        for level in 0..RAMP_LEVELS {
+            canvas.fill_rect(
+                (level * scale) as i32,
+                (y as u32 * scale) as i32,
+                scale,
+                scale,
+                row.pixel(level as u8),
+            );
+        }
+    }
+
+    canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_level_once_per_row() {
+        let ramp = srgb_ramp();
+        assert_eq!((ramp.width(), ramp.height()), (256, 4));
+
+        for (y, row) in RAMP_ROWS.into_iter().enumerate() {
+            for level in 0..=255u8 {
+                assert_eq!(
+                    ramp.get_pixel(level as u32, y as u32),
+                    Some(&row.pixel(level))
+                );
+            }
+        }
+        assert_eq!(ramp.get_pixel(200, 0), Some(&Pixel::new(200, 200, 200)));
+        assert_eq!(ramp.get_pixel(200, 3), Some(&Pixel::new(0, 0, 200)));
+    }
+
+    #[test]
+    fn sized_ramps_scale_every_level_up() {
+        let ramp = srgb_ramp_sized(3);
+        assert_eq!((ramp.width(), ramp.height()), (768, 12));
+        assert_eq!(
+            ramp.get_pixel(3 * 17 + 2, 3 + 2),
+            Some(&Pixel::new(17, 0, 0))
+        );
+        for (x, y) in [(0, 0), (767, 11), (300, 7)] {
+            let (level, row) = ((x / 3) as u8, RAMP_ROWS[y as usize / 3]);
+            assert_eq!(ramp.get_pixel(x, y), Some(&row.pixel(level)));
+        }
+
+        // Nothing smaller than a pixel per level
+        assert_eq!(srgb_ramp_sized(0), srgb_ramp());
+    }
+}
@@ -0,0 +1,345 @@
+//! Animation frame rates that follow the power supply, so animated wallpapers go easy on laptop
+//! batteries. Only built with the `power` feature.
+//!
+//! The supply is read from `/sys/class/power_supply`, polled rather than watched, so no D-Bus or
+//! udev is involved. Systems without those nodes, desktops and anything not Linux, count as on
+//! AC all the time.
+//!
+//! There is no built in render loop, the animation loop asks a [`PowerMonitor`] every frame, or
+//! has it retune its [`FrameBudget`] through [`PowerMonitor::update`].
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::FrameBudget;
+
+const SYSFS_POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+/// Where the machine draws its power from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerSource {
+    Ac,
+    Battery,
+}
+
+/// Reads the power supply once. On battery as soon as any battery reports it is discharging, on
+/// AC otherwise, including when there is no battery or nothing to read at all.
+pub fn power_source() -> PowerSource {
+    read_source(Path::new(SYSFS_POWER_SUPPLY))
+}
+
+fn read_source(dir: &Path) -> PowerSource {
+    let Ok(supplies) = fs::read_dir(dir) else {
+        return PowerSource::Ac;
+    };
+
+    let read = |supply: &Path, name| fs::read_to_string(supply.join(name)).unwrap_or_default();
+    let discharging = supplies.flatten().any(|supply| {
+        let supply = supply.path();
+        read(&supply, "type").trim() == "Battery" && read(&supply, "status").trim() == "Discharging"
+    });
+
+    match discharging {
+        true => PowerSource::Battery,
+        false => PowerSource::Ac,
+    }
+}
+
+/// The frame rate to animate at for each [`PowerSource`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PowerPolicy {
+    pub(crate) on_ac_fps: f64,
+    pub(crate) on_battery_fps: f64,
+    pub(crate) pause_on_battery: bool,
+}
+
+impl PowerPolicy {
+    /// Animates at `on_ac_fps` on AC and at a quarter of it on battery. A frame rate of 0 or less,
+    /// or one that is not a number, pauses instead.
+    pub fn new(on_ac_fps: f64) -> PowerPolicy {
+        PowerPolicy {
+            on_ac_fps,
+            on_battery_fps: on_ac_fps / 4.0,
+            pause_on_battery: false,
+        }
+    }
+
+    /// The frame rate on battery, pausing for 0 or less like [`PowerPolicy::new`].
+    pub fn on_battery_fps(mut self, fps: f64) -> PowerPolicy {
+        self.on_battery_fps = fps;
+        self
+    }
+
+    /// Stops animating altogether on battery, off by default.
+    pub fn pause_on_battery(mut self, pause: bool) -> PowerPolicy {
+        self.pause_on_battery = pause;
+        self
+    }
+
+    /// The frame rate for `source`, `None` if animations should pause.
+    pub fn fps(&self, source: PowerSource) -> Option<f64> {
+        let fps = match source {
+            PowerSource::Ac => self.on_ac_fps,
+            PowerSource::Battery if self.pause_on_battery => return None,
+            PowerSource::Battery => self.on_battery_fps,
+        };
+        // Not a rate anything can be animated at, and no frame time to go with it either
+        (fps.is_finite() && fps > 0.0).then_some(fps)
+    }
+}
+
+/// Keeps track of the power supply for an animation loop, applying a [`PowerPolicy`].
+///
+/// The supply is read at most once per poll interval, 5 seconds by default, however often the
+/// monitor is asked. A change only takes effect once every reading agreed on it for the settle
+/// time, 10 seconds by default, so a flaky battery status or plugging the charger in for a
+/// moment does not make the frame rate flap back and forth.
+#[derive(Clone, Debug)]
+pub struct PowerMonitor {
+    policy: PowerPolicy,
+    dir: PathBuf,
+    poll_interval: Duration,
+    settle_time: Duration,
+    source: PowerSource,
+    // A reading that differs from `source`, and since when readings have agreed on it
+    candidate: Option<(PowerSource, Instant)>,
+    last_poll: Instant,
+    forced: Option<PowerSource>,
+}
+
+impl PowerMonitor {
+    /// Reads the supply right away, taking it as it is without waiting for it to settle.
+    pub fn new(policy: PowerPolicy) -> PowerMonitor {
+        PowerMonitor::reading(policy, PathBuf::from(SYSFS_POWER_SUPPLY))
+    }
+
+    // A monitor on the supplies under `dir` rather than the system's
+    fn reading(policy: PowerPolicy, dir: PathBuf) -> PowerMonitor {
+        PowerMonitor {
+            policy,
+            source: read_source(&dir),
+            dir,
+            poll_interval: Duration::from_secs(5),
+            settle_time: Duration::from_secs(10),
+            candidate: None,
+            last_poll: Instant::now(),
+            forced: None,
+        }
+    }
+
+    pub fn poll_interval(mut self, interval: Duration) -> PowerMonitor {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// How long a changed supply has to stay that way before the frame rate follows it. Zero
+    /// follows every reading at once.
+    pub fn settle_time(mut self, settle: Duration) -> PowerMonitor {
+        self.settle_time = settle;
+        self
+    }
+
+    pub fn policy(&self) -> PowerPolicy {
+        self.policy
+    }
+
+    /// Replaces the policy, taking effect with the next frame.
+    pub fn set_policy(&mut self, policy: PowerPolicy) {
+        self.policy = policy;
+    }
+
+    /// Acts as if on `source` whatever the supply says, e.g. for a "save battery" toggle, until
+    /// called again with `None`.
+    pub fn force_source(&mut self, source: Option<PowerSource>) {
+        self.forced = source;
+    }
+
+    /// The power source the policy is currently applied for, reading the supply again if the
+    /// poll interval has passed.
+    pub fn source(&mut self) -> PowerSource {
+        self.poll();
+        self.forced.unwrap_or(self.source)
+    }
+
+    /// The frame rate to animate at right now, `None` if animations should pause.
+    pub fn fps(&mut self) -> Option<f64> {
+        let source = self.source();
+        self.policy.fps(source)
+    }
+
+    /// Sets `budget` to the frame rate to animate at right now. Returns `false`, leaving the
+    /// budget alone, if animations should pause instead.
+    pub fn update(&mut self, budget: &mut FrameBudget) -> bool {
+        let Some(fps) = self.fps() else {
+            return false;
+        };
+
+        // Rates too low for a Duration to hold their frame time are as good as paused
+        let Ok(frame) = Duration::try_from_secs_f64(1.0 / fps) else {
+            return false;
+        };
+        if budget.budget() != frame {
+            budget.set_budget(frame);
+        }
+        true
+    }
+
+    fn poll(&mut self) {
+        let now = Instant::now();
+        if now.duration_since(self.last_poll) < self.poll_interval {
+            return;
+        }
+        self.last_poll = now;
+
+        let reading = read_source(&self.dir);
+        if reading == self.source {
+            self.candidate = None;
+            return;
+        }
+
+        let since = match self.candidate {
+            Some((candidate, since)) if candidate == reading => since,
+            _ => now,
+        };
+        if now.duration_since(since) >= self.settle_time {
+            info!("Power source changed to {reading:?}");
+            self.source = reading;
+            self.candidate = None;
+        } else {
+            self.candidate = Some((reading, since));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    // A power_supply directory of its own for each test, with a mains supply and a battery
+    struct Sysfs(PathBuf);
+
+    impl Sysfs {
+        fn new(test: &str) -> Sysfs {
+            let dir =
+                std::env::temp_dir().join(format!("shade-power-{}-{test}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            for (supply, kind) in [("AC", "Mains"), ("BAT0", "Battery")] {
+                fs::create_dir_all(dir.join(supply)).unwrap();
+                fs::write(dir.join(supply).join("type"), format!("{kind}\n")).unwrap();
+            }
+            let sysfs = Sysfs(dir);
+            sysfs.battery("Charging");
+            sysfs
+        }
+
+        fn battery(&self, status: &str) {
+            fs::write(self.0.join("BAT0/status"), format!("{status}\n")).unwrap();
+        }
+
+        fn monitor(&self, policy: PowerPolicy) -> PowerMonitor {
+            PowerMonitor::reading(policy, self.0.clone()).poll_interval(Duration::ZERO)
+        }
+    }
+
+    impl Drop for Sysfs {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn discharging_batteries_mean_battery() {
+        let sysfs = Sysfs::new("discharging");
+        assert_eq!(read_source(&sysfs.0), PowerSource::Ac);
+        for (status, source) in [
+            ("Discharging", PowerSource::Battery),
+            ("Full", PowerSource::Ac),
+            ("Not charging", PowerSource::Ac),
+        ] {
+            sysfs.battery(status);
+            assert_eq!(read_source(&sysfs.0), source, "{status}");
+        }
+    }
+
+    #[test]
+    fn nothing_to_read_means_ac() {
+        let sysfs = Sysfs::new("missing");
+        assert_eq!(read_source(&sysfs.0.join("nowhere")), PowerSource::Ac);
+
+        // A mains supply that says it is discharging is no battery
+        fs::write(sysfs.0.join("AC/status"), "Discharging\n").unwrap();
+        assert_eq!(read_source(&sysfs.0), PowerSource::Ac);
+    }
+
+    #[test]
+    fn changes_wait_for_the_settle_time() {
+        let sysfs = Sysfs::new("settle");
+        let mut monitor = sysfs
+            .monitor(PowerPolicy::new(60.0))
+            .settle_time(Duration::from_millis(200));
+        assert_eq!(monitor.source(), PowerSource::Ac);
+
+        sysfs.battery("Discharging");
+        assert_eq!(monitor.source(), PowerSource::Ac);
+        // Back before it settled, the change never happened
+        sysfs.battery("Charging");
+        assert_eq!(monitor.source(), PowerSource::Ac);
+
+        sysfs.battery("Discharging");
+        assert_eq!(monitor.source(), PowerSource::Ac);
+        thread::sleep(Duration::from_millis(250));
+        assert_eq!(monitor.source(), PowerSource::Battery);
+        assert_eq!(monitor.fps(), Some(15.0));
+    }
+
+    #[test]
+    fn without_a_settle_time_every_reading_counts() {
+        let sysfs = Sysfs::new("unsettled");
+        let mut monitor = sysfs
+            .monitor(PowerPolicy::new(60.0))
+            .settle_time(Duration::ZERO);
+        sysfs.battery("Discharging");
+        assert_eq!(monitor.source(), PowerSource::Battery);
+        sysfs.battery("Full");
+        assert_eq!(monitor.source(), PowerSource::Ac);
+
+        monitor.force_source(Some(PowerSource::Battery));
+        assert_eq!(monitor.source(), PowerSource::Battery);
+    }
+
+    #[test]
+    fn pausing_on_battery_leaves_the_budget_alone() {
+        let sysfs = Sysfs::new("pause");
+        let mut monitor = sysfs
+            .monitor(PowerPolicy::new(50.0).pause_on_battery(true))
+            .settle_time(Duration::ZERO);
+        let mut budget = FrameBudget::new(10.0);
+
+        assert!(monitor.update(&mut budget));
+        assert_eq!(budget.budget(), Duration::from_millis(20));
+
+        sysfs.battery("Discharging");
+        assert!(!monitor.update(&mut budget));
+        assert_eq!(budget.budget(), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn rates_of_nothing_pause() {
+        let mut budget = FrameBudget::new(10.0);
+        for fps in [0.0, -30.0, f64::NAN, f64::INFINITY, f64::MIN_POSITIVE] {
+            let policy = PowerPolicy::new(60.0).on_battery_fps(fps);
+            let mut monitor = PowerMonitor::reading(policy, PathBuf::new());
+            monitor.force_source(Some(PowerSource::Battery));
+
+            assert!(!monitor.update(&mut budget), "{fps}");
+            assert_eq!(budget.budget(), Duration::from_millis(100));
+        }
+        assert_eq!(PowerPolicy::new(0.0).fps(PowerSource::Ac), None);
+        assert_eq!(PowerPolicy::new(f64::NAN).fps(PowerSource::Battery), None);
+    }
+}
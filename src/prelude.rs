@@ -0,0 +1,10 @@
+//! The handful of items most programs setting a wallpaper need, for a single
+//! `use shade::prelude::*`.
+
+#[cfg(feature = "x11")]
+pub use crate::{load, load_with, BackgroundHandle, LoadConfig, OpenMethod};
+pub use crate::{Canvas, Pixel, Rect, Scaling, ScalingMethod, WallpaperSource};
+
+/// [`LoadConfig`], the options [`load_with`] takes on top of the [`OpenMethod`].
+#[cfg(feature = "x11")]
+pub use crate::LoadConfig as Options;
@@ -0,0 +1,312 @@
+use std::{
+    mem,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use super::BackgroundHandle;
+use crate::{BlendMode, CancelToken, Error, Result, Scaling, WallpaperSource, CANCEL_LATENCY};
+
+/// How an [`Applier`] goes from one wallpaper to the next.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Transition {
+    /// Shows the new wallpaper at once.
+    #[default]
+    Cut,
+    /// Crossfades from the old wallpaper to the new one in `steps` flushes spread over
+    /// `duration`. A submission arriving meanwhile cuts the fade short and fades on from
+    /// wherever it got to.
+    Fade { duration: Duration, steps: u32 },
+}
+
+/// What became of a submission to an [`Applier`], see [`Applier::completions`].
+#[derive(Debug)]
+pub enum ApplyOutcome {
+    /// Rendered and flushed.
+    Applied,
+    /// A later submission replaced it before it went up, or cut its fade short.
+    Superseded,
+    /// Rendering or flushing failed. The worker carries on with the next submission.
+    Failed(Error),
+    /// Still pending when the applier was shut down with [`Shutdown::DropPending`], or its
+    /// [`CancelToken`] cancelled.
+    Dropped,
+}
+
+/// A submission's [`ApplyOutcome`], along with the ticket [`Applier::submit`] returned for it.
+#[derive(Debug)]
+pub struct Completion {
+    pub ticket: u64,
+    pub outcome: ApplyOutcome,
+}
+
+/// What [`Applier::shutdown`] does with a submission that did not go up yet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Shutdown {
+    /// Applies it before stopping, its transition included.
+    ApplyPending,
+    /// Reports it as [`ApplyOutcome::Dropped`] and stops right away.
+    #[default]
+    DropPending,
+}
+
+struct Submission {
+    ticket: u64,
+    source: WallpaperSource,
+}
+
+#[derive(Default)]
+struct State {
+    next_ticket: u64,
+    pending: Option<Submission>,
+    shutdown: Option<Shutdown>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    wake: Condvar,
+    // Superseded submissions are reported by whoever replaces them, everything else by the worker
+    completions: Sender<Completion>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn report(&self, ticket: u64, outcome: ApplyOutcome) {
+        // Only fails once the applier, and with it the receiver, is gone
+        let _ = self.completions.send(Completion { ticket, outcome });
+    }
+}
+
+/// Applies wallpapers on a thread of its own, for callers that submit them in bursts, such as
+/// config file watchers. Only the latest submission still waiting is applied, everything it
+/// replaced is reported as [`ApplyOutcome::Superseded`] without ever being decoded.
+///
+/// The applier owns the handle while it runs, one from [`BackgroundHandle::open`], and
+/// [`Applier::shutdown`] hands it back. Dropping the applier shuts it down with
+/// [`Shutdown::DropPending`].
+pub struct Applier {
+    shared: Arc<Shared>,
+    cancel: CancelToken,
+    completions: Receiver<Completion>,
+    worker: Option<JoinHandle<BackgroundHandle>>,
+}
+
+impl Applier {
+    /// Starts the worker, which renders every submission with `scaling` and shows it with
+    /// `transition`.
+    pub fn new(
+        handle: BackgroundHandle,
+        scaling: impl Into<Scaling>,
+        transition: Transition,
+    ) -> Applier {
+        Applier::with_cancel(handle, scaling, transition, CancelToken::new())
+    }
+
+    /// [`Applier::new`], stopping as [`Shutdown::DropPending`] would once `cancel` is cancelled:
+    /// a fade under way ends on the frame it got to, within [`CANCEL_LATENCY`] even while idle.
+    /// Submissions made after that are dropped right away. [`Applier::shutdown`] still hands the
+    /// handle back.
+    pub fn with_cancel(
+        handle: BackgroundHandle,
+        scaling: impl Into<Scaling>,
+        transition: Transition,
+        cancel: CancelToken,
+    ) -> Applier {
+        let (sender, completions) = mpsc::channel();
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            wake: Condvar::new(),
+            completions: sender,
+        });
+
+        let worker = Worker {
+            handle,
+            scaling: scaling.into(),
+            transition,
+            shared: shared.clone(),
+            cancel: cancel.clone(),
+        };
+        let worker = thread::Builder::new()
+            .name("shade-applier".into())
+            .spawn(move || worker.run())
+            .expect("failed to spawn the applier thread");
+
+        Applier {
+            shared,
+            cancel,
+            completions,
+            worker: Some(worker),
+        }
+    }
+
+    /// Queues `source` to be applied, replacing whatever was still waiting. Returns the ticket
+    /// its [`Completion`] will carry.
+    pub fn submit(&self, source: WallpaperSource) -> u64 {
+        let mut state = self.shared.lock();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+
+        if self.cancel.is_cancelled() {
+            self.shared.report(ticket, ApplyOutcome::Dropped);
+            return ticket;
+        }
+
+        if let Some(replaced) = state.pending.replace(Submission { ticket, source }) {
+            debug!("Submission {} superseded by {ticket}", replaced.ticket);
+            self.shared
+                .report(replaced.ticket, ApplyOutcome::Superseded);
+        }
+
+        self.shared.wake.notify_one();
+        ticket
+    }
+
+    /// Every submission's outcome, in the order they were decided. Nothing is lost if they are
+    /// never read, they just pile up.
+    pub fn completions(&self) -> &Receiver<Completion> {
+        &self.completions
+    }
+
+    /// Stops the worker once it finished what it is doing, dealing with any pending submission
+    /// as `pending` says, and hands the handle back. Every submission has its completion sent by
+    /// the time this returns, those not read from [`Applier::completions`] yet come along.
+    pub fn shutdown(mut self, pending: Shutdown) -> (BackgroundHandle, Vec<Completion>) {
+        match self.stop(pending) {
+            Some(Ok(handle)) => (handle, self.completions.try_iter().collect()),
+            // The handle went down with the thread
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            None => unreachable!("the applier is only ever stopped once"),
+        }
+    }
+
+    fn stop(&mut self, pending: Shutdown) -> Option<thread::Result<BackgroundHandle>> {
+        let worker = self.worker.take()?;
+
+        self.shared.lock().shutdown = Some(pending);
+        self.shared.wake.notify_one();
+        let handle = worker.join();
+
+        // Left behind by a worker that stopped on being cancelled
+        if let Some(submission) = self.shared.lock().pending.take() {
+            self.shared.report(submission.ticket, ApplyOutcome::Dropped);
+        }
+        Some(handle)
+    }
+}
+
+impl Drop for Applier {
+    fn drop(&mut self) {
+        // A panicked worker was logged by the panic hook already
+        let _ = self.stop(Shutdown::DropPending);
+    }
+}
+
+struct Worker {
+    handle: BackgroundHandle,
+    scaling: Scaling,
+    transition: Transition,
+    shared: Arc<Shared>,
+    cancel: CancelToken,
+}
+
+impl Worker {
+    fn run(self) -> BackgroundHandle {
+        loop {
+            let submission = {
+                let mut state = self.shared.lock();
+                loop {
+                    match (state.pending.take(), self.shutdown(&state)) {
+                        (Some(submission), Some(Shutdown::DropPending)) => {
+                            self.shared.report(submission.ticket, ApplyOutcome::Dropped);
+                        }
+                        (Some(submission), _) => break submission,
+                        (None, Some(_)) => return self.handle,
+                        (None, None) => {
+                            // Cancelling cannot wake the worker, it checks back instead
+                            state = self
+                                .shared
+                                .wake
+                                .wait_timeout(state, CANCEL_LATENCY)
+                                .unwrap_or_else(|e| e.into_inner())
+                                .0
+                        }
+                    }
+                }
+            };
+
+            let outcome = self.apply(&submission.source).unwrap_or_else(|e| {
+                warn!("Failed to apply submission {}: {e}", submission.ticket);
+                ApplyOutcome::Failed(e)
+            });
+            self.shared.report(submission.ticket, outcome);
+        }
+    }
+
+    fn apply(&self, source: &WallpaperSource) -> Result<ApplyOutcome> {
+        let (duration, steps) = match self.transition {
+            Transition::Fade { duration, steps } if steps > 1 => (duration, steps),
+            _ => {
+                self.handle.set_source(source, self.scaling.clone())?;
+                self.handle.flush()?;
+                return Ok(ApplyOutcome::Applied);
+            }
+        };
+
+        let old = self.handle.lock_buffer().clone();
+        self.handle.set_source(source, self.scaling.clone())?;
+        let new = mem::replace(&mut *self.handle.lock_buffer(), old.clone());
+
+        // The hook is only told, and the wallpaper only tagged, once the fade ends on the new one
+        let applied = self.handle.take_pending_applied();
+        let metadata = self.handle.take_pending_metadata();
+        for step in 1..steps {
+            if let Some(outcome) = self.interrupted() {
+                return Ok(outcome);
+            }
+
+            let alpha = BlendMode::AlphaOver((step as u64 * 255 / steps as u64) as u8);
+            for ((pixel, old), new) in self
+                .handle
+                .lock_buffer()
+                .iter_mut()
+                .zip(old.iter())
+                .zip(new.iter())
+            {
+                *pixel = alpha.blend(old, new);
+            }
+            self.handle.flush()?;
+            self.cancel.wait_timeout(duration / steps);
+        }
+
+        *self.handle.lock_buffer() = new;
+        self.handle.restore_pending_applied(applied);
+        self.handle.restore_pending_metadata(metadata);
+        self.handle.flush()?;
+        Ok(ApplyOutcome::Applied)
+    }
+
+    // Being cancelled stops the worker just like shutting down without the pending submission
+    fn shutdown(&self, state: &State) -> Option<Shutdown> {
+        match self.cancel.is_cancelled() {
+            true => Some(Shutdown::DropPending),
+            false => state.shutdown,
+        }
+    }
+
+    // Fades end early for a newer submission, or to shut down without applying anything further
+    fn interrupted(&self) -> Option<ApplyOutcome> {
+        let state = self.shared.lock();
+        match (&state.pending, self.shutdown(&state)) {
+            (_, Some(Shutdown::DropPending)) => Some(ApplyOutcome::Dropped),
+            (Some(_), _) => Some(ApplyOutcome::Superseded),
+            (None, _) => None,
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use xcb::{
+    x::{
+        self, Atom, ChangeProperty, GetProperty, InternAtom, KillClient, PropMode, Window,
+        ATOM_ANY, ATOM_NONE, ATOM_PIXMAP,
+    },
+    Connection, ProtocolError, Xid,
+};
+
+use super::{registry, LoadReport, LoadWarning, ROOT_PMAP_ATOMS};
+use crate::{AsByteSlice, Error, Result};
+
+/// Interns all `names` at once, waiting on the replies only after every request went out, so the
+/// whole batch takes a single round trip.
+pub(crate) fn intern_atoms<const N: usize>(
+    conn: &Connection,
+    names: [&[u8]; N],
+    only_if_exists: bool,
+) -> xcb::Result<[Atom; N]> {
+    let cookies = names.map(|name| {
+        conn.send_request(&InternAtom {
+            name,
+            only_if_exists,
+        })
+    });
+
+    let mut atoms = [ATOM_NONE; N];
+    for (atom, cookie) in atoms.iter_mut().zip(cookies) {
+        *atom = conn.wait_for_reply(cookie)?.atom();
+    }
+    Ok(atoms)
+}
+
+/// Which of the root pixmap properties shade points at its pixmap, and kills the owners of the
+/// pixmaps they name, see [`LoadConfig::root_properties`](super::LoadConfig::root_properties).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RootProperties {
+    /// `_XROOTPMAP_ID` and `ESETROOT_PMAP_ID`, the default.
+    #[default]
+    Both,
+    /// `_XROOTPMAP_ID` alone.
+    XRootOnly,
+    /// `ESETROOT_PMAP_ID` alone.
+    EsetrootOnly,
+}
+
+impl RootProperties {
+    /// `atoms`, as interned from `ROOT_PMAP_ATOMS`, paired with their names: those shade sets,
+    /// then those it leaves alone.
+    pub(crate) fn partition(self, atoms: [Atom; 2]) -> (RootAtoms, RootAtoms) {
+        let chosen = match self {
+            RootProperties::Both => [true, true],
+            RootProperties::XRootOnly => [true, false],
+            RootProperties::EsetrootOnly => [false, true],
+        };
+
+        let (mut set, mut left) = (Vec::new(), Vec::new());
+        for ((name, atom), chosen) in ROOT_PMAP_NAMES.into_iter().zip(atoms).zip(chosen) {
+            match chosen {
+                true => set.push((name, atom)),
+                false => left.push((name, atom)),
+            }
+        }
+        (set, left)
+    }
+}
+
+// The names of ROOT_PMAP_ATOMS, for messages and warnings
+const ROOT_PMAP_NAMES: [&str; 2] = ["_XROOTPMAP_ID", "ESETROOT_PMAP_ID"];
+
+/// Root pixmap properties along with their names, for messages and warnings.
+pub(crate) type RootAtoms = Vec<(&'static str, Atom)>;
+
+/// What a root pixmap property such as `_XROOTPMAP_ID` holds, see [`parse_pixmap_property`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixmapProperty {
+    /// The property does not exist.
+    Unset,
+    /// The property holds something of this type atom instead.
+    NotAPixmap(u32),
+    /// The property is a pixmap, but its value is too short to hold an id.
+    Malformed,
+    Pixmap(u32),
+}
+
+/// Reads the pixmap id out of the reply to a GetProperty of a root pixmap property, given its
+/// type atom, format and value. The value is in host byte order, as xcb hands it out, and only
+/// its first 4 bytes are looked at whatever the format.
+///
+/// The reply is only as trustworthy as whichever client set the property, so nothing about it is
+/// assumed: every input gives an answer rather than a panic.
+pub fn parse_pixmap_property(r#type: u32, format: u8, value: &[u8]) -> PixmapProperty {
+    if r#type == ATOM_NONE.resource_id() {
+        return PixmapProperty::Unset;
+    }
+    if r#type != ATOM_PIXMAP.resource_id() {
+        return PixmapProperty::NotAPixmap(r#type);
+    }
+
+    // Whatever the format, an id is 4 bytes: one item of 32, two of 16 or four of 8
+    match (format, value.get(..4)) {
+        (8 | 16 | 32, Some(bytes)) => {
+            PixmapProperty::Pixmap(u32::from_ne_bytes(bytes.try_into().unwrap()))
+        }
+        _ => PixmapProperty::Malformed,
+    }
+}
+
+pub(crate) fn resolve_atom(
+    conn: &Connection,
+    window: Window,
+    (name, atom): (&'static str, Atom),
+    report: &mut LoadReport,
+) -> xcb::Result<Option<u32>> {
+    if atom == ATOM_NONE {
+        report.warn(LoadWarning::PropertyNotSet(name));
+        return Ok(None);
+    }
+
+    let cookie = conn.send_request(&GetProperty {
+        r#type: ATOM_ANY,
+        delete: false,
+        window,
+        property: atom,
+        long_offset: 0,
+        long_length: 1,
+    });
+    let property = conn.wait_for_reply(cookie)?;
+
+    // The value is only handed out typed by its format
+    let value = match property.format() {
+        32 => property.value::<u32>().as_byte_slice(),
+        16 => property.value::<u16>().as_byte_slice(),
+        8 => property.value::<u8>(),
+        _ => &[],
+    };
+
+    match parse_pixmap_property(property.r#type().resource_id(), property.format(), value) {
+        PixmapProperty::Pixmap(id) => Ok(Some(id)),
+        PixmapProperty::Unset => {
+            report.warn(LoadWarning::PropertyNotSet(name));
+            Ok(None)
+        }
+        PixmapProperty::NotAPixmap(r#type) => {
+            report.warn(LoadWarning::NotAPixmap {
+                property: name,
+                r#type,
+            });
+            Ok(None)
+        }
+        PixmapProperty::Malformed => {
+            report.warn(LoadWarning::MalformedProperty(name));
+            Ok(None)
+        }
+    }
+}
+
+/// Points the root pixmap `properties`, usually both `_XROOTPMAP_ID` and `ESETROOT_PMAP_ID`, at
+/// `pixmap` and reads them back, all in a single round trip. Fails with
+/// [`Error::RootPropertyMismatch`] should any not name `pixmap` afterwards. Properties not
+/// listed are neither set nor checked.
+///
+/// Meant to run under a server grab, so that no client ever finds the two disagreeing, as some
+/// consumers only read one of them.
+pub(crate) fn set_pmap_atoms(
+    conn: &Connection,
+    root: Window,
+    properties: &[(&'static str, Atom)],
+    pixmap: u32,
+) -> Result<()> {
+    let changes: Vec<_> = properties
+        .iter()
+        .map(|&(_, property)| {
+            conn.send_request_checked(&ChangeProperty {
+                property,
+                mode: PropMode::Replace,
+                r#type: ATOM_PIXMAP,
+                window: root,
+                data: &[pixmap],
+            })
+        })
+        .collect();
+    // Answered after the changes went through, requests of a connection are handled in order
+    let reads: Vec<_> = properties
+        .iter()
+        .map(|&(_, property)| {
+            conn.send_request(&GetProperty {
+                r#type: ATOM_PIXMAP,
+                delete: false,
+                window: root,
+                property,
+                long_offset: 0,
+                long_length: 1,
+            })
+        })
+        .collect();
+
+    for cookie in changes {
+        conn.check_request(cookie).map_err(xcb::Error::from)?;
+    }
+    for (&(name, _), cookie) in properties.iter().zip(reads) {
+        let property = conn.wait_for_reply(cookie)?;
+        let found = match property.format() {
+            32 => property.value::<u32>().first().copied(),
+            _ => None,
+        };
+        if found != Some(pixmap) {
+            return Err(Error::RootPropertyMismatch(name));
+        }
+    }
+
+    Ok(())
+}
+
+/// Ids of the pixmaps the root properties shade sets as `properties` says currently name,
+/// without creating the atoms if they don't exist yet. Pixmaps the other property names as well
+/// are left out, they stay in use.
+pub(crate) fn foreign_pixmaps(
+    connection: &Connection,
+    root: Window,
+    properties: RootProperties,
+    report: &mut LoadReport,
+) -> xcb::Result<Vec<u32>> {
+    let atoms = intern_atoms(connection, ROOT_PMAP_ATOMS, true)?;
+    let (set, left_alone) = properties.partition(atoms);
+
+    let kept = resolve_quietly(connection, root, &left_alone)?;
+    let mut ids = Vec::with_capacity(2);
+    for property in set {
+        if let Some(id) = resolve_atom(connection, root, property, report)? {
+            if !ids.contains(&id) && !kept.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+
+    Ok(ids)
+}
+
+// The pixmaps `properties` name, without warning about any that name none: shade leaves these
+// properties alone, whatever they hold is none of its business
+fn resolve_quietly(
+    connection: &Connection,
+    root: Window,
+    properties: &[(&'static str, Atom)],
+) -> xcb::Result<Vec<u32>> {
+    let mut ids = Vec::new();
+    for &property in properties {
+        ids.extend(resolve_atom(
+            connection,
+            root,
+            property,
+            &mut LoadReport::default(),
+        )?);
+    }
+    Ok(ids)
+}
+
+// How many times the properties are re-read after killing their owners, in case one of them was
+// replaced by yet another client in between
+const KILL_ATTEMPTS: usize = 3;
+
+/// Kills the owners of the pixmaps the root properties shade sets as `properties` says name,
+/// `atoms` being interned from `ROOT_PMAP_ATOMS`. A pixmap the other property
+/// names as well is left alone, killing its owner would free it from under that property.
+pub(crate) fn kill_pmap_atoms(
+    connection: &Connection,
+    root: Window,
+    atoms: [Atom; 2],
+    properties: RootProperties,
+    report: &mut LoadReport,
+) -> xcb::Result<()> {
+    let (set, left_alone) = properties.partition(atoms);
+
+    for _ in 0..KILL_ATTEMPTS {
+        // Resolve the ids of the current pixmaps. If anyone is currently drawing to our beloved
+        // screen...
+        let mut ids = Vec::with_capacity(set.len());
+        for &property in &set {
+            ids.push(resolve_atom(connection, root, property, report)?);
+        }
+        let kept = resolve_quietly(connection, root, &left_alone)?;
+
+        info!("Foreign pixmaps are {ids:?}, left alone {kept:?}");
+
+        let pending = new_owners(ids, &kept, report);
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        // we MUST kill them, unless they are our own
+        for id in pending {
+            if registry::is_ours(connection, id) {
+                info!("Pixmap {id} belongs to this process, leaving its owner alive");
+                report.spared.push(id);
+                continue;
+            }
+
+            kill_client(connection, id, report)?;
+            report.killed.push(id);
+        }
+    }
+
+    report.warn(LoadWarning::PropertiesStillChanging {
+        attempts: KILL_ATTEMPTS,
+    });
+    Ok(())
+}
+
+// The pixmaps among `ids` whose owners are still to be killed, each once. A killed client's
+// pixmap id lingers in the properties until they are overwritten, only ids not seen yet are new
+// owners, and pixmaps a property left alone names are kept along with their owners
+fn new_owners(ids: Vec<Option<u32>>, kept: &[u32], report: &LoadReport) -> Vec<u32> {
+    let mut pending: Vec<u32> = ids
+        .into_iter()
+        .flatten()
+        .filter(|id| !report.killed.contains(id) && !report.spared.contains(id))
+        .filter(|id| !kept.contains(id))
+        .collect();
+    pending.dedup();
+    pending
+}
+
+// The owner may have exited on its own since its pixmap id was read, in which case the id no
+// longer names anything and the server answers with BadValue
+fn kill_client(connection: &Connection, resource: u32, report: &mut LoadReport) -> xcb::Result<()> {
+    match void_request!(connection, &KillClient { resource }) {
+        Err(xcb::Error::Protocol(ProtocolError::X(x::Error::Value(_), _))) => {
+            report.warn(LoadWarning::OwnerGone(resource));
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(killed: &[u32], spared: &[u32]) -> LoadReport {
+        LoadReport {
+            killed: killed.to_vec(),
+            spared: spared.to_vec(),
+            ..LoadReport::default()
+        }
+    }
+
+    #[test]
+    fn both_properties_naming_one_pixmap_kill_once() {
+        let pending = new_owners(vec![Some(7), Some(7)], &[], &LoadReport::default());
+        assert_eq!(pending, [7]);
+    }
+
+    #[test]
+    fn unset_properties_have_no_owner() {
+        assert!(new_owners(vec![None, None], &[], &LoadReport::default()).is_empty());
+        assert_eq!(
+            new_owners(vec![None, Some(3)], &[], &LoadReport::default()),
+            [3]
+        );
+    }
+
+    // After a kill the properties still name the pixmap until overwritten: read again, they say
+    // nothing new and killing stops
+    #[test]
+    fn lingering_ids_of_killed_owners_are_done() {
+        let report = report(&[7], &[]);
+        assert!(new_owners(vec![Some(7), Some(7)], &[], &report).is_empty());
+    }
+
+    // Another client set a wallpaper of its own between the kill and the re-read
+    #[test]
+    fn owners_replacing_killed_ones_are_new() {
+        let report = report(&[7], &[]);
+        assert_eq!(new_owners(vec![Some(9), Some(7)], &[], &report), [9]);
+        assert_eq!(new_owners(vec![Some(9), Some(11)], &[], &report), [9, 11]);
+    }
+
+    #[test]
+    fn spared_and_kept_pixmaps_are_left_alone() {
+        let report = report(&[], &[5]);
+        assert!(new_owners(vec![Some(5), Some(5)], &[], &report).is_empty());
+        assert_eq!(new_owners(vec![Some(6), Some(8)], &[8], &report), [6]);
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    path::Path,
+    sync::{atomic::Ordering, MutexGuard},
+};
+
+use super::{AppliedSource, BackgroundHandle};
+use crate::{
+    canvas::{default_pixel_limit, ImageCache},
+    CacheStats, Canvas, Result, Scaling, WallpaperSource,
+};
+
+// Enough for a handful of 4K wallpapers
+pub(crate) const DEFAULT_CACHE_MEGABYTES: usize = 128;
+
+impl BackgroundHandle {
+    fn lock_cache(&self) -> MutexGuard<'_, ImageCache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Decodes and scales the image at `path` into the buffer, reusing a cached copy if this file
+    /// was already prepared the same way. Call [`BackgroundHandle::flush`] to show it.
+    pub fn set_image(&self, path: impl AsRef<Path>, scaling: impl Into<Scaling>) -> Result<()> {
+        let scaling = scaling.into();
+        let image = self.lock_cache().get_or_prepare(
+            path.as_ref(),
+            self.width as u32,
+            self.height as u32,
+            scaling.clone(),
+        )?;
+
+        self.lock_buffer().clone_from_slice(&image);
+        self.note_solid(image.solid_color());
+        self.tag_metadata(Some(path.as_ref()), &scaling);
+        self.note_applied(AppliedSource::File(path.as_ref().to_owned()), None);
+        Ok(())
+    }
+
+    /// Renders `source` into the buffer, files going through the image cache like
+    /// [`BackgroundHandle::set_image`]. Call [`BackgroundHandle::flush`] to show it.
+    pub fn set_source(&self, source: &WallpaperSource, scaling: impl Into<Scaling>) -> Result<()> {
+        if let WallpaperSource::File(path) = source {
+            return self.set_image(path, scaling);
+        }
+
+        let scaling = scaling.into();
+        let image = Canvas::from_source_within(
+            source,
+            self.width as u32,
+            self.height as u32,
+            scaling.clone(),
+            self.max_image_pixels(),
+        )?;
+        self.note_solid(image.solid_color());
+        *self.lock_buffer() = image;
+        self.tag_metadata(None, &scaling);
+        self.note_applied(source.into(), None);
+        Ok(())
+    }
+
+    /// Downloads the image at `url` into the buffer, see [`Canvas::from_url`]. Downloads bypass
+    /// the image cache. Call [`BackgroundHandle::flush`] to show it.
+    #[cfg(feature = "net")]
+    pub fn set_from_url(&self, url: &str, scaling: impl Into<Scaling>) -> Result<()> {
+        let scaling = scaling.into();
+        let image = Canvas::from_url_within(
+            url,
+            self.width as u32,
+            self.height as u32,
+            scaling.clone(),
+            self.max_image_pixels(),
+        )?;
+
+        self.note_solid(image.solid_color());
+        *self.lock_buffer() = image;
+        self.tag_metadata(None, &scaling);
+        self.note_applied(AppliedSource::Other, None);
+        Ok(())
+    }
+
+    /// Bounds the image cache to `megabytes` MiB of pixel data, evicting as needed. 0 disables
+    /// it.
+    pub fn set_cache_capacity(&self, megabytes: usize) {
+        self.lock_cache().set_capacity(megabytes);
+    }
+
+    /// Refuses to decode images of more than `pixels` pixels, failing with
+    /// [`Error::ImageTooLarge`](crate::Error::ImageTooLarge) before they are decoded. 0, the
+    /// default, allows four times the screen's pixels and at least four 4K screens worth. Cached
+    /// images the new limit would refuse are dropped from the image cache.
+    ///
+    /// Worth lowering when wallpapers come from untrusted places, such as downloads or a slideshow
+    /// over a directory anyone can write to.
+    pub fn set_max_image_pixels(&self, pixels: u64) {
+        self.max_image_pixels.store(pixels, Ordering::Relaxed);
+        self.lock_cache()
+            .set_max_pixels((pixels != 0).then_some(pixels));
+    }
+
+    pub(crate) fn max_image_pixels(&self) -> u64 {
+        match self.max_image_pixels.load(Ordering::Relaxed) {
+            0 => default_pixel_limit(self.width as u32, self.height as u32),
+            pixels => pixels,
+        }
+    }
+
+    pub fn clear_cache(&self) {
+        self.lock_cache().clear();
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.lock_cache().stats()
+    }
+}
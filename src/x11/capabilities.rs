@@ -0,0 +1,435 @@
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+use xcb::{
+    randr, shm,
+    x::{GetSelectionOwner, QueryExtension, ATOM_NONE, WINDOW_NONE},
+    xinerama, Connection, Extension,
+};
+
+use super::{
+    atoms::{foreign_pixmaps, intern_atoms},
+    connect_with_extensions, drawable_geometry,
+    json::Json,
+    owner::OwnerMarker,
+    screen, LoadReport, RootProperties, SelfTestReport, VisualInfo,
+};
+use crate::{Error, Result};
+
+/// Version of the layout [`Capabilities`] and [`SelfTestReport`](super::SelfTestReport) are put
+/// in JSON and serde with, their `"schema"` field. Fields may be added within a version, it only
+/// goes up when one is removed, renamed or changes its meaning.
+pub const PROBE_SCHEMA: u32 = 1;
+
+/// What the X server offers and what is already going on on its screen, as found out by
+/// [`capabilities`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub vendor: String,
+    pub release: u32,
+    /// Major and minor version of the core protocol.
+    pub protocol: (u16, u16),
+    /// The largest request the server takes, in bytes. Uploads are split to fit.
+    pub max_request_bytes: u64,
+    pub big_requests: bool,
+    /// Major and minor version of MIT-SHM, if the server has it.
+    pub shm: Option<(u16, u16)>,
+    /// Whether MIT-SHM can back pixmaps with shared memory as well as images.
+    pub shm_pixmaps: bool,
+    /// Major and minor version of RandR, if the server has it. Without it the whole screen is
+    /// a single monitor.
+    pub randr: Option<(u32, u32)>,
+    /// Major and minor version of Xinerama, if the server has it.
+    pub xinerama: Option<(u16, u16)>,
+    /// Whether Xinerama actually joins several screens into one.
+    pub xinerama_active: bool,
+    pub width: u16,
+    pub height: u16,
+    pub visual: VisualInfo,
+    /// Whether a compositing manager runs, see [`compositor_active`](super::compositor_active).
+    pub compositor: bool,
+    /// Process id of the running shade instance owning the wallpaper, `Some(None)` if one does
+    /// but its pid is unknown.
+    pub shade_owner: Option<Option<u32>>,
+    /// Pixmaps currently set as the wallpaper, by shade or anyone else.
+    pub root_pixmaps: Vec<u32>,
+}
+
+impl Capabilities {
+    /// Fails with the error [`load`](super::load) would fail with for this server, if any. A
+    /// PseudoColor root fails here even though
+    /// [`LoadConfig::pseudo_color`](super::LoadConfig::pseudo_color) lets it load.
+    pub fn check(&self) -> Result<()> {
+        if !drawable_geometry(self.width, self.height) {
+            return Err(Error::InvalidGeometry {
+                width: self.width,
+                height: self.height,
+            });
+        }
+        self.visual.check_class()
+    }
+
+    /// The report as a single JSON object, laid out as [`PROBE_SCHEMA`] describes: `"schema"`
+    /// first, then the same field names as the struct. Versions are `[major, minor]` arrays,
+    /// absent extensions `null`. The visual is an object of its own with the class as a string,
+    /// `shade_owner` is `null` or an object with a `"pid"` that may be `null` itself.
+    ///
+    /// Serializing with serde, under the `serde` feature, comes out the same.
+    pub fn to_json(&self) -> String {
+        self.to_value().to_string()
+    }
+
+    pub(crate) fn to_value(&self) -> Json {
+        let owner = self.shade_owner.map_or(Json::Null, |pid| {
+            Json::Object(vec![(
+                "pid",
+                pid.map_or(Json::Null, |pid| Json::Number(pid.into())),
+            )])
+        });
+
+        Json::Object(vec![
+            ("schema", Json::Number(PROBE_SCHEMA.into())),
+            ("vendor", Json::String(self.vendor.clone())),
+            ("release", Json::Number(self.release.into())),
+            ("protocol", Json::version(Some(self.protocol))),
+            ("max_request_bytes", Json::Number(self.max_request_bytes)),
+            ("big_requests", Json::Bool(self.big_requests)),
+            ("shm", Json::version(self.shm)),
+            ("shm_pixmaps", Json::Bool(self.shm_pixmaps)),
+            ("randr", Json::version(self.randr)),
+            ("xinerama", Json::version(self.xinerama)),
+            ("xinerama_active", Json::Bool(self.xinerama_active)),
+            ("width", Json::Number(self.width.into())),
+            ("height", Json::Number(self.height.into())),
+            ("visual", self.visual.to_value()),
+            ("compositor", Json::Bool(self.compositor)),
+            ("shade_owner", owner),
+            ("root_pixmaps", Json::numbers(&self.root_pixmaps)),
+        ])
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Capabilities {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.to_value().serialize(serializer)
+    }
+}
+
+/// Everything [`capabilities`] and [`self_test`](super::self_test) found out, together, as bug
+/// reports should include it.
+#[derive(Clone, Debug)]
+pub struct ProbeReport {
+    pub capabilities: Capabilities,
+    /// `None` if the self test was not run.
+    pub self_test: Option<SelfTestReport>,
+}
+
+impl ProbeReport {
+    /// Both reports as a single JSON object, `{"capabilities": ..., "self_test": ...}`, each laid
+    /// out as [`Capabilities::to_json`] and [`SelfTestReport::to_json`] have it. The self test is
+    /// `null` if it was not run.
+    ///
+    /// Serializing with serde, under the `serde` feature, comes out the same.
+    pub fn to_json(&self) -> String {
+        self.to_value().to_string()
+    }
+
+    fn to_value(&self) -> Json {
+        Json::Object(vec![
+            ("capabilities", self.capabilities.to_value()),
+            (
+                "self_test",
+                self.self_test
+                    .as_ref()
+                    .map_or(Json::Null, SelfTestReport::to_value),
+            ),
+        ])
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for ProbeReport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.to_value().serialize(serializer)
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn version<T: fmt::Display>(version: Option<(T, T)>) -> String {
+            version.map_or("absent".into(), |(major, minor)| format!("{major}.{minor}"))
+        }
+
+        writeln!(
+            f,
+            "server:     {} {}, protocol {}.{}",
+            self.vendor, self.release, self.protocol.0, self.protocol.1
+        )?;
+        writeln!(
+            f,
+            "requests:   up to {} bytes, BIG-REQUESTS {}",
+            self.max_request_bytes,
+            if self.big_requests { "on" } else { "absent" }
+        )?;
+        write!(f, "MIT-SHM:    {}", version(self.shm))?;
+        if self.shm_pixmaps {
+            write!(f, ", shared pixmaps")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "RandR:      {}", version(self.randr))?;
+        write!(f, "Xinerama:   {}", version(self.xinerama))?;
+        if self.xinerama_active {
+            write!(f, ", active")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "screen:     {}x{}", self.width, self.height)?;
+        writeln!(
+            f,
+            "visual:     {:#x} {:?}, depth {}, {} bpp, masks r {:#08x} g {:#08x} b {:#08x}",
+            self.visual.visual_id,
+            self.visual.class,
+            self.visual.depth,
+            self.visual.bits_per_pixel,
+            self.visual.red_mask,
+            self.visual.green_mask,
+            self.visual.blue_mask
+        )?;
+        writeln!(
+            f,
+            "compositor: {}",
+            if self.compositor { "running" } else { "none" }
+        )?;
+        match self.shade_owner {
+            Some(Some(pid)) => writeln!(f, "owner:      shade, pid {pid}")?,
+            Some(None) => writeln!(f, "owner:      shade, pid unknown")?,
+            None => writeln!(f, "owner:      none")?,
+        }
+        write!(f, "pixmaps:    {:?}", self.root_pixmaps)
+    }
+}
+
+/// Probes the X server on its own connection, without changing anything on it: not even atoms
+/// are created. Meant to be called once at startup, or to be attached to bug reports through
+/// [`Capabilities::to_json`] or its Debug output.
+pub fn capabilities() -> Result<Capabilities> {
+    let (connection, screen_number) =
+        connect_with_extensions(&[Extension::RandR, Extension::Shm, Extension::Xinerama])?;
+    let setup = connection.get_setup();
+    let screen = screen(setup, screen_number)?;
+    let root = screen.root();
+
+    let visual = screen
+        .allowed_depths()
+        .flat_map(|d| d.visuals())
+        .find(|v| v.visual_id() == screen.root_visual())
+        .ok_or(Error::NoVisualFound)?;
+    let visual = VisualInfo::new(setup, screen.root_depth(), visual);
+
+    // Every query goes out before the first reply is waited on
+    let big_requests = connection.send_request(&QueryExtension {
+        name: b"BIG-REQUESTS",
+    });
+    let has = |extension| connection.active_extensions().any(|e| e == extension);
+    let shm = has(Extension::Shm).then(|| connection.send_request(&shm::QueryVersion {}));
+    let randr = has(Extension::RandR).then(|| {
+        connection.send_request(&randr::QueryVersion {
+            major_version: 1,
+            minor_version: 6,
+        })
+    });
+    let xinerama = has(Extension::Xinerama).then(|| {
+        (
+            connection.send_request(&xinerama::QueryVersion { major: 1, minor: 1 }),
+            connection.send_request(&xinerama::IsActive {}),
+        )
+    });
+
+    let big_requests = connection.wait_for_reply(big_requests)?.present();
+    let (shm, shm_pixmaps) = match shm {
+        Some(cookie) => {
+            let reply = connection.wait_for_reply(cookie)?;
+            (
+                Some((reply.major_version(), reply.minor_version())),
+                reply.shared_pixmaps(),
+            )
+        }
+        None => (None, false),
+    };
+    let randr = match randr {
+        Some(cookie) => {
+            let reply = connection.wait_for_reply(cookie)?;
+            Some((reply.major_version(), reply.minor_version()))
+        }
+        None => None,
+    };
+    let (xinerama, xinerama_active) = match xinerama {
+        Some((version, active)) => {
+            let version = connection.wait_for_reply(version)?;
+            let active = connection.wait_for_reply(active)?;
+            (
+                Some((version.major(), version.minor())),
+                active.state() != 0,
+            )
+        }
+        None => (None, false),
+    };
+
+    let shade_owner = match OwnerMarker::existing(&connection, screen_number)? {
+        Some(marker) => marker.owner(&connection, root)?.map(|owner| owner.pid),
+        None => None,
+    };
+
+    Ok(Capabilities {
+        vendor: setup.vendor().to_utf8().into_owned(),
+        release: setup.release_number(),
+        protocol: (
+            setup.protocol_major_version(),
+            setup.protocol_minor_version(),
+        ),
+        // Enables BIG-REQUESTS on this connection if there is any, which only ever concerns it
+        max_request_bytes: connection.get_maximum_request_length() as u64 * 4,
+        big_requests,
+        shm,
+        shm_pixmaps,
+        randr,
+        xinerama,
+        xinerama_active,
+        width: screen.width_in_pixels(),
+        height: screen.height_in_pixels(),
+        visual,
+        compositor: compositor_running(&connection, screen_number)?,
+        shade_owner,
+        root_pixmaps: foreign_pixmaps(
+            &connection,
+            root,
+            RootProperties::Both,
+            &mut LoadReport::default(),
+        )?,
+    })
+}
+
+// compositor_active, but without creating the selection's atom. An atom nobody created has no
+// owner either
+fn compositor_running(connection: &Connection, screen_number: i32) -> Result<bool> {
+    let name = format!("_NET_WM_CM_S{screen_number}");
+    let [selection] = intern_atoms(connection, [name.as_bytes()], true)?;
+    if selection == ATOM_NONE {
+        return Ok(false);
+    }
+
+    let owner = cookie_request!(connection, &GetSelectionOwner { selection })?.owner();
+    Ok(owner != WINDOW_NONE)
+}
+
+#[cfg(test)]
+mod tests {
+    use xcb::x::VisualClass;
+
+    use super::*;
+    use crate::{Canvas, Pixel};
+
+    fn visual() -> VisualInfo {
+        VisualInfo {
+            visual_id: 33,
+            depth: 24,
+            bits_per_pixel: 32,
+            red_mask: 0xff0000,
+            green_mask: 0xff00,
+            blue_mask: 0xff,
+            class: VisualClass::TrueColor,
+        }
+    }
+
+    const VISUAL: &str = "{\"visual_id\":33,\"depth\":24,\"bits_per_pixel\":32,\
+        \"red_mask\":16711680,\"green_mask\":65280,\"blue_mask\":255,\"class\":\"TrueColor\"}";
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            vendor: "The \"X\".Org Foundation".into(),
+            release: 12101004,
+            protocol: (11, 0),
+            max_request_bytes: 16777212,
+            big_requests: true,
+            shm: Some((1, 2)),
+            shm_pixmaps: false,
+            randr: Some((1, 6)),
+            xinerama: None,
+            xinerama_active: false,
+            width: 1920,
+            height: 1080,
+            visual: visual(),
+            compositor: true,
+            shade_owner: Some(None),
+            root_pixmaps: vec![4194305, 6291457],
+        }
+    }
+
+    fn self_test() -> SelfTestReport {
+        SelfTestReport {
+            visual: visual(),
+            expected: Canvas::filled(2, 2, Pixel::new(1, 2, 3)),
+            captured: None,
+            mismatched: 4,
+            mean_error: [0.5, f64::NAN, 0.0],
+            max_error: [1, 0, 255],
+            channel_order: "BGR",
+            hypotheses: vec!["red and blue\nswapped".into()],
+        }
+    }
+
+    #[test]
+    fn capabilities_layout() {
+        let expected = format!(
+            "{{\"schema\":1,\"vendor\":\"The \\\"X\\\".Org Foundation\",\"release\":12101004,\
+             \"protocol\":[11,0],\"max_request_bytes\":16777212,\"big_requests\":true,\
+             \"shm\":[1,2],\"shm_pixmaps\":false,\"randr\":[1,6],\"xinerama\":null,\
+             \"xinerama_active\":false,\"width\":1920,\"height\":1080,\"visual\":{VISUAL},\
+             \"compositor\":true,\"shade_owner\":{{\"pid\":null}},\
+             \"root_pixmaps\":[4194305,6291457]}}"
+        );
+        assert_eq!(capabilities().to_json(), expected);
+    }
+
+    #[test]
+    fn owner_pid_and_no_owner() {
+        let mut capabilities = capabilities();
+        capabilities.shade_owner = Some(Some(4321));
+        assert!(capabilities
+            .to_json()
+            .contains(",\"shade_owner\":{\"pid\":4321},"));
+        capabilities.shade_owner = None;
+        assert!(capabilities.to_json().contains(",\"shade_owner\":null,"));
+    }
+
+    #[test]
+    fn self_test_layout() {
+        let expected = format!(
+            "{{\"schema\":1,\"passed\":false,\"visual\":{VISUAL},\"captured\":false,\
+             \"pixels\":4,\"mismatched\":4,\"mean_error\":[0.5,null,0],\
+             \"max_error\":[1,0,255],\"channel_order\":\"BGR\",\
+             \"hypotheses\":[\"red and blue\\u000aswapped\"]}}"
+        );
+        assert_eq!(self_test().to_json(), expected);
+    }
+
+    #[test]
+    fn probe_report_layout() {
+        let mut report = ProbeReport {
+            capabilities: capabilities(),
+            self_test: Some(self_test()),
+        };
+        assert_eq!(
+            report.to_json(),
+            format!(
+                "{{\"capabilities\":{},\"self_test\":{}}}",
+                capabilities().to_json(),
+                self_test().to_json()
+            )
+        );
+
+        report.self_test = None;
+        assert!(report.to_json().ends_with(",\"self_test\":null}"));
+    }
+}
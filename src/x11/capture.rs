@@ -0,0 +1,88 @@
+use xcb::{
+    x::{Drawable, GetImage, ImageFormat::ZPixmap, ImageOrder},
+    Connection,
+};
+
+use super::VisualInfo;
+use crate::{Canvas, Pixel, Result};
+
+/// Reads a rectangle of `drawable` back from the server, decoding it according to `visual`.
+///
+/// Meant for pixmaps. Reading a window, the root in particular, gets whatever the screen shows,
+/// which on servers drawing the cursor in software includes the cursor, and a wallpaper made
+/// from that keeps a ghost of it. Pixmaps are never drawn over by the cursor.
+pub(crate) fn capture(
+    connection: &Connection,
+    drawable: Drawable,
+    visual: &VisualInfo,
+    (x, y, width, height): (i16, i16, u16, u16),
+) -> Result<Canvas> {
+    let reply = cookie_request!(
+        connection,
+        &GetImage {
+            format: ZPixmap,
+            drawable,
+            x,
+            y,
+            width,
+            height,
+            plane_mask: u32::MAX,
+        }
+    )?;
+
+    let setup = connection.get_setup();
+    let scanline_pad = setup
+        .pixmap_formats()
+        .iter()
+        .find(|f| f.depth() == visual.depth)
+        .map_or(32, |f| f.scanline_pad() as usize);
+    let msb_first = setup.image_byte_order() == ImageOrder::MsbFirst;
+
+    let bytes_per_pixel = (visual.bits_per_pixel as usize).div_ceil(8);
+    let stride =
+        (width as usize * visual.bits_per_pixel as usize).div_ceil(scanline_pad) * scanline_pad / 8;
+
+    let data = reply.data();
+    let mut canvas = Canvas::new(width as u32, height as u32);
+
+    for row in 0..height as usize {
+        for column in 0..width as usize {
+            let start = row * stride + column * bytes_per_pixel;
+            let Some(bytes) = data.get(start..start + bytes_per_pixel) else {
+                continue;
+            };
+
+            let value = if msb_first {
+                bytes.iter().fold(0u32, |v, &b| v << 8 | b as u32)
+            } else {
+                bytes.iter().rev().fold(0u32, |v, &b| v << 8 | b as u32)
+            };
+
+            canvas.set_pixel(
+                column as u32,
+                row as u32,
+                Pixel::new(
+                    channel(value, visual.red_mask),
+                    channel(value, visual.green_mask),
+                    channel(value, visual.blue_mask),
+                ),
+            );
+        }
+    }
+
+    Ok(canvas)
+}
+
+// The bits `mask` covers, scaled to 8 bits. The inverse of gc::pixel_value
+fn channel(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let shift = mask.trailing_zeros();
+    let bits = (mask >> shift).count_ones();
+    let max = (1u64 << bits) - 1;
+    let raw = ((value & mask) >> shift) as u64;
+
+    ((raw * 255 + max / 2) / max) as u8
+}
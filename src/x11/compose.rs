@@ -0,0 +1,562 @@
+use std::{
+    path::Path,
+    sync::{Arc, MutexGuard},
+    thread,
+};
+
+#[cfg(feature = "serde")]
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "serde")]
+use std::path::PathBuf;
+
+use super::{monitors::monitor_scaling, AppliedSource, BackgroundHandle, Monitor};
+use crate::{
+    canvas::Reservation, Canvas, FilterChain, MemoryConsumer, Pixel, PixelAspect, Result, Scaling,
+    WallpaperSource,
+};
+
+/// Which monitors a [`WallpaperPlan`] entry is for.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PlanTarget {
+    /// Every monitor no [`PlanTarget::Output`] entry covers.
+    Default,
+    /// The monitor showing the output of this name, mirrored outputs answering to any of their
+    /// names.
+    Output(String),
+}
+
+/// What a [`WallpaperPlan`] shows on a monitor: a source, laid out as `scaling` says, then run
+/// through `filters`.
+#[derive(Clone, Debug)]
+pub struct PlanEntry {
+    pub source: Arc<WallpaperSource>,
+    pub scaling: Scaling,
+    pub filters: FilterChain,
+}
+
+impl PlanEntry {
+    pub fn new(source: impl Into<WallpaperSource>, scaling: impl Into<Scaling>) -> PlanEntry {
+        PlanEntry {
+            source: Arc::new(source.into()),
+            scaling: scaling.into(),
+            filters: FilterChain::new(),
+        }
+    }
+
+    pub fn filters(mut self, filters: impl Into<FilterChain>) -> PlanEntry {
+        self.filters = filters.into();
+        self
+    }
+
+    fn path(&self) -> Option<&Path> {
+        match &*self.source {
+            WallpaperSource::File(path) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+// Files are told apart by path and in-memory data by content, images and generators only by
+// being the very same one: comparing those would cost about as much as rendering them again
+impl PartialEq for PlanEntry {
+    fn eq(&self, other: &PlanEntry) -> bool {
+        let source = Arc::ptr_eq(&self.source, &other.source)
+            || match (&*self.source, &*other.source) {
+                (WallpaperSource::File(a), WallpaperSource::File(b)) => a == b,
+                (WallpaperSource::Bytes(a), WallpaperSource::Bytes(b)) => a == b,
+                (WallpaperSource::Embedded(a), WallpaperSource::Embedded(b)) => {
+                    std::ptr::eq(*a, *b)
+                }
+                (WallpaperSource::Color(a), WallpaperSource::Color(b)) => a == b,
+                _ => false,
+            };
+
+        source && self.scaling == other.scaling && self.filters == other.filters
+    }
+}
+
+/// The wallpaper of every monitor at once, for [`BackgroundHandle::apply_plan`].
+///
+/// Entries are kept per [`PlanTarget`], setting one again replaces it. Under the `serde`
+/// feature plans serialize as a list of entries, each with its `output` (`null` for the
+/// default), source, scaling method, linear light, pixel aspect and filters. Only file and
+/// color sources can be serialized, and custom resampling is not carried over.
+#[derive(Clone, Debug, Default)]
+pub struct WallpaperPlan {
+    pub(crate) entries: Vec<(PlanTarget, PlanEntry)>,
+}
+
+impl WallpaperPlan {
+    pub fn new() -> WallpaperPlan {
+        WallpaperPlan {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Shows `entry` on every monitor without an entry of its own.
+    pub fn default(self, entry: PlanEntry) -> WallpaperPlan {
+        self.with(PlanTarget::Default, entry)
+    }
+
+    /// Shows `entry` on the monitor showing output `name`.
+    pub fn output(self, name: &str, entry: PlanEntry) -> WallpaperPlan {
+        self.with(PlanTarget::Output(name.to_owned()), entry)
+    }
+
+    pub fn with(mut self, target: PlanTarget, entry: PlanEntry) -> WallpaperPlan {
+        self.set(target, entry);
+        self
+    }
+
+    pub fn set(&mut self, target: PlanTarget, entry: PlanEntry) {
+        match self.entries.iter_mut().find(|(t, _)| *t == target) {
+            Some((_, existing)) => *existing = entry,
+            None => self.entries.push((target, entry)),
+        }
+    }
+
+    pub fn remove(&mut self, target: &PlanTarget) -> Option<PlanEntry> {
+        let i = self.entries.iter().position(|(t, _)| t == target)?;
+        Some(self.entries.remove(i).1)
+    }
+
+    pub fn get(&self, target: &PlanTarget) -> Option<&PlanEntry> {
+        self.entries
+            .iter()
+            .find(|(t, _)| t == target)
+            .map(|(_, entry)| entry)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&PlanTarget, &PlanEntry)> {
+        self.entries.iter().map(|(target, entry)| (target, entry))
+    }
+
+    /// The entry `monitor` shows, its own output's if it has one, the default's otherwise.
+    pub fn entry_for(&self, monitor: &Monitor) -> Option<&PlanEntry> {
+        self.entries
+            .iter()
+            .find(|(t, _)| matches!(t, PlanTarget::Output(name) if monitor.has_name(name)))
+            .or_else(|| self.entries.iter().find(|(t, _)| *t == PlanTarget::Default))
+            .map(|(_, entry)| entry)
+    }
+
+    /// The targets whose entries differ between the plans, including those only one of them
+    /// has, in the order this plan lists them followed by those only `other` has.
+    pub fn diff(&self, other: &WallpaperPlan) -> Vec<PlanTarget> {
+        let changed = self
+            .entries
+            .iter()
+            .filter(|(target, entry)| other.get(target) != Some(entry));
+        let removed = other
+            .entries
+            .iter()
+            .filter(|(target, _)| self.get(target).is_none());
+
+        changed.chain(removed).map(|(t, _)| t.clone()).collect()
+    }
+}
+
+// Order does not matter, entries are looked up by target
+impl PartialEq for WallpaperPlan {
+    fn eq(&self, other: &WallpaperPlan) -> bool {
+        self.diff(other).is_empty()
+    }
+}
+
+// An entry rendered for monitors of one size and pixel shape, kept so the next plan does not
+// render it again if it still has it
+pub(crate) struct PlanRendering {
+    entry: PlanEntry,
+    key: (u16, u16, PixelAspect),
+    image: Arc<Canvas>,
+    _memory: Reservation,
+}
+
+impl BackgroundHandle {
+    /// Sets every monitor's wallpaper as `plan` says and flushes it, all monitors changing in
+    /// the same upload. Monitors the plan has no entry for keep what they show.
+    ///
+    /// Every entry is rendered on a thread of its own before anything is touched, once per
+    /// monitor size and pixel shape, then composed into a new buffer that replaces the old one
+    /// whole. Nothing shows a half applied plan, and should any entry fail to render, the
+    /// buffer is left as it was.
+    ///
+    /// Renderings are kept until the next plan is applied, counted against the
+    /// [memory budget](super::LoadConfig::memory_budget) as cache, so entries that did not
+    /// change since, see [`WallpaperPlan::diff`], are not decoded again. Files count as
+    /// unchanged as long as their path is, edit one in place and apply the plan with
+    /// [`BackgroundHandle::clear_plan_renderings`] called first.
+    pub fn apply_plan(&self, plan: &WallpaperPlan) -> Result<()> {
+        let monitors = self.monitors()?;
+        let mut kept = self.lock_plan_renderings();
+
+        // Monitors of the same size and pixel shape show the same rendering of an entry
+        let mut jobs: Vec<(&PlanEntry, &Monitor)> = Vec::new();
+        for monitor in &monitors {
+            let Some(entry) = plan.entry_for(monitor) else {
+                continue;
+            };
+            if !jobs
+                .iter()
+                .any(|&(e, m)| e == entry && rendering_key(m) == rendering_key(monitor))
+            {
+                jobs.push((entry, monitor));
+            }
+        }
+
+        let max_pixels = self.max_image_pixels();
+        let renderings = thread::scope(|scope| {
+            let pending: Vec<_> = jobs
+                .iter()
+                .map(|&(entry, monitor)| {
+                    let key = rendering_key(monitor);
+                    if let Some(kept) = kept.iter().find(|r| r.key == key && r.entry == *entry) {
+                        return Ok(kept.image.clone());
+                    }
+
+                    Err(scope.spawn(move || {
+                        let mut image = Canvas::from_source_within(
+                            &entry.source,
+                            monitor.width as u32,
+                            monitor.height as u32,
+                            monitor_scaling(entry.scaling.clone(), monitor),
+                            max_pixels,
+                        )?;
+                        image.apply_chain(&entry.filters);
+                        Ok(Arc::new(image))
+                    }))
+                })
+                .collect();
+
+            pending
+                .into_iter()
+                .map(|rendering| match rendering {
+                    Ok(image) => Ok(image),
+                    Err(thread) => thread
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic)),
+                })
+                .collect::<Result<Vec<Arc<Canvas>>>>()
+        })?;
+
+        let mut canvas = self.lock_buffer().clone();
+        for monitor in &monitors {
+            let Some(entry) = plan.entry_for(monitor) else {
+                continue;
+            };
+            let i = jobs
+                .iter()
+                .position(|&(e, m)| e == entry && rendering_key(m) == rendering_key(monitor))
+                .expect("every monitor with an entry has a job");
+            canvas.paste(&renderings[i], monitor.x as i32, monitor.y as i32);
+        }
+
+        self.note_solid(canvas.solid_color());
+        *self.lock_buffer() = canvas;
+
+        // Drop the old renderings before reserving the new ones, they would not both fit a
+        // budget sized for one set
+        let jobs: Vec<(PlanEntry, (u16, u16, PixelAspect))> = jobs
+            .into_iter()
+            .map(|(entry, monitor)| (entry.clone(), rendering_key(monitor)))
+            .collect();
+        kept.clear();
+        for ((entry, key), image) in jobs.into_iter().zip(renderings) {
+            let size = std::mem::size_of_val::<[Pixel]>(&image);
+            match self.memory_budget.reserve(MemoryConsumer::Cache, size) {
+                Ok(memory) => kept.push(PlanRendering {
+                    entry,
+                    key,
+                    image,
+                    _memory: memory,
+                }),
+                Err(e) => debug!("Not keeping a plan rendering: {e}"),
+            }
+        }
+        drop(kept);
+
+        // A plan showing one entry everywhere is as good as a single wallpaper
+        let shown: Vec<&PlanEntry> = monitors.iter().filter_map(|m| plan.entry_for(m)).collect();
+        match shown.split_first() {
+            Some((first, rest)) if rest.iter().all(|e| e == first) => {
+                self.tag_metadata(first.path(), &first.scaling);
+                self.note_applied((&*first.source).into(), None);
+            }
+            _ => self.note_applied(AppliedSource::Other, None),
+        }
+
+        self.flush()
+    }
+
+    /// Drops the renderings [`BackgroundHandle::apply_plan`] keeps, so the next plan renders
+    /// every entry afresh.
+    pub fn clear_plan_renderings(&self) {
+        self.lock_plan_renderings().clear();
+    }
+
+    fn lock_plan_renderings(&self) -> MutexGuard<'_, Vec<PlanRendering>> {
+        self.plan_renderings
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn rendering_key(monitor: &Monitor) -> (u16, u16, PixelAspect) {
+    (monitor.width, monitor.height, monitor.pixel_aspect)
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SourceRepr {
+    File(PathBuf),
+    Color([u8; 3]),
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct EntryRepr {
+    output: Option<String>,
+    source: SourceRepr,
+    method: crate::ScalingMethod,
+    #[serde(default)]
+    linear_light: bool,
+    #[serde(default = "square")]
+    pixel_aspect: [u32; 2],
+    #[serde(default)]
+    filters: FilterChain,
+}
+
+#[cfg(feature = "serde")]
+fn square() -> [u32; 2] {
+    [1, 1]
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for WallpaperPlan {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let entries = self
+            .entries
+            .iter()
+            .map(|(target, entry)| {
+                let source = match &*entry.source {
+                    WallpaperSource::File(path) => SourceRepr::File(path.clone()),
+                    WallpaperSource::Color(color) => SourceRepr::Color([color.r, color.g, color.b]),
+                    other => {
+                        return Err(ser::Error::custom(format_args!(
+                            "only file and color sources can be serialized, not {other:?}"
+                        )))
+                    }
+                };
+
+                Ok(EntryRepr {
+                    output: match target {
+                        PlanTarget::Default => None,
+                        PlanTarget::Output(name) => Some(name.clone()),
+                    },
+                    source,
+                    method: entry.scaling.method,
+                    linear_light: entry.scaling.linear_light,
+                    pixel_aspect: [
+                        entry.scaling.pixel_aspect.width,
+                        entry.scaling.pixel_aspect.height,
+                    ],
+                    filters: entry.filters.clone(),
+                })
+            })
+            .collect::<std::result::Result<Vec<_>, S::Error>>()?;
+
+        entries.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for WallpaperPlan {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let mut plan = WallpaperPlan::new();
+        for repr in Vec::<EntryRepr>::deserialize(deserializer)? {
+            let [width, height] = repr.pixel_aspect;
+            if width == 0 || height == 0 {
+                return Err(de::Error::custom("pixel aspect cannot be zero"));
+            }
+
+            let source = match repr.source {
+                SourceRepr::File(path) => WallpaperSource::File(path),
+                SourceRepr::Color([r, g, b]) => WallpaperSource::Color(Pixel::new(r, g, b)),
+            };
+            let scaling = Scaling {
+                linear_light: repr.linear_light,
+                pixel_aspect: PixelAspect::new(width, height),
+                ..Scaling::new(repr.method)
+            };
+            let target = match repr.output {
+                Some(name) => PlanTarget::Output(name),
+                None => PlanTarget::Default,
+            };
+
+            plan.set(
+                target,
+                PlanEntry::new(source, scaling).filters(repr.filters),
+            );
+        }
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Filter, ScalingMethod};
+
+    fn monitor(names: &[&str]) -> Monitor {
+        Monitor {
+            names: names.iter().map(|&name| name.to_owned()).collect(),
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            mm_width: 0,
+            mm_height: 0,
+            pixel_aspect: PixelAspect::SQUARE,
+        }
+    }
+
+    fn file(path: &str) -> PlanEntry {
+        PlanEntry::new(WallpaperSource::File(path.into()), ScalingMethod::Fill)
+    }
+
+    #[test]
+    fn monitors_get_their_own_entry_before_the_default() {
+        let plan = WallpaperPlan::new()
+            .default(file("default.png"))
+            .output("HDMI-1", file("hdmi.png"));
+
+        assert_eq!(
+            plan.entry_for(&monitor(&["HDMI-1"])),
+            Some(&file("hdmi.png"))
+        );
+        // Mirrored outputs answer to either name
+        let mirrored = monitor(&["DP-1", "HDMI-1"]);
+        assert_eq!(plan.entry_for(&mirrored), Some(&file("hdmi.png")));
+        assert_eq!(
+            plan.entry_for(&monitor(&["DP-2"])),
+            Some(&file("default.png"))
+        );
+        assert_eq!(plan.entry_for(&monitor(&[])), Some(&file("default.png")));
+
+        let without_default = WallpaperPlan::new().output("HDMI-1", file("hdmi.png"));
+        assert_eq!(without_default.entry_for(&monitor(&["DP-2"])), None);
+    }
+
+    #[test]
+    fn diffs_list_changed_added_and_removed_targets() {
+        let hdmi = PlanTarget::Output("HDMI-1".to_owned());
+        let dp = PlanTarget::Output("DP-1".to_owned());
+        let old = WallpaperPlan::new()
+            .default(file("default.png"))
+            .with(hdmi.clone(), file("hdmi.png"))
+            .with(dp.clone(), file("dp.png"));
+        let new = WallpaperPlan::new()
+            .with(dp.clone(), file("dp.png"))
+            .default(file("other.png"))
+            .output("eDP-1", file("laptop.png"));
+
+        assert_eq!(
+            new.diff(&old),
+            [
+                PlanTarget::Default,
+                PlanTarget::Output("eDP-1".to_owned()),
+                hdmi.clone(),
+            ]
+        );
+        assert_eq!(
+            old.diff(&new),
+            [
+                PlanTarget::Default,
+                hdmi,
+                PlanTarget::Output("eDP-1".to_owned()),
+            ]
+        );
+
+        // Filters and scaling count as much as the source
+        let filtered = new
+            .clone()
+            .with(dp.clone(), file("dp.png").filters(vec![Filter::Grayscale]));
+        assert_eq!(filtered.diff(&new), std::slice::from_ref(&dp));
+        let centered = new.clone().with(
+            dp.clone(),
+            PlanEntry::new(
+                WallpaperSource::File("dp.png".into()),
+                ScalingMethod::Center,
+            ),
+        );
+        assert_eq!(centered.diff(&new), [dp]);
+
+        assert!(new.diff(&new.clone()).is_empty());
+        assert_eq!(new, new.clone());
+    }
+
+    #[test]
+    fn entries_of_other_sources_only_match_themselves() {
+        let bytes = PlanEntry::new(WallpaperSource::Bytes(vec![1, 2, 3]), ScalingMethod::Fill);
+        assert_eq!(bytes, bytes.clone());
+
+        let image = PlanEntry::new(
+            WallpaperSource::Image(image::DynamicImage::new_rgb8(1, 1)),
+            ScalingMethod::Fill,
+        );
+        assert_eq!(image, image.clone());
+        let same_pixels = PlanEntry::new(
+            WallpaperSource::Image(image::DynamicImage::new_rgb8(1, 1)),
+            ScalingMethod::Fill,
+        );
+        assert_ne!(image, same_pixels);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn plans_round_trip_through_serde() {
+        use crate::serde_value::round_trip;
+
+        let scaling = Scaling {
+            linear_light: true,
+            pixel_aspect: PixelAspect::new(4, 3),
+            ..Scaling::new(ScalingMethod::Max)
+        };
+        let plan = WallpaperPlan::new()
+            .default(PlanEntry::new(
+                WallpaperSource::Color(Pixel::new(1, 2, 3)),
+                scaling,
+            ))
+            .output(
+                "HDMI-1",
+                file("hdmi.png").filters(vec![Filter::Blur(1.5), Filter::Brightness(-20)]),
+            );
+
+        let back = round_trip(&plan);
+        assert!(plan.diff(&back).is_empty());
+        assert_eq!(back.entries().count(), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialized_pixel_aspects_are_reduced() {
+        use crate::serde_value::round_trip;
+
+        let unreduced = Scaling {
+            pixel_aspect: PixelAspect {
+                width: 8,
+                height: 6,
+            },
+            ..Scaling::new(ScalingMethod::Fill)
+        };
+        let plan = WallpaperPlan::new().default(PlanEntry::new(
+            WallpaperSource::File("a.png".into()),
+            unreduced,
+        ));
+
+        let back = round_trip(&plan);
+        let entry = back.get(&PlanTarget::Default).unwrap();
+        assert_eq!(entry.scaling.pixel_aspect, PixelAspect::new(4, 3));
+    }
+}
@@ -0,0 +1,37 @@
+use xcb::{
+    x::{GetSelectionOwner, InternAtom, WINDOW_NONE},
+    Connection,
+};
+
+use super::BackgroundHandle;
+use crate::Result;
+
+/// Whether a compositing manager runs on screen `screen_number` of `connection`, i.e. whether
+/// anyone owns its `_NET_WM_CM_S<n>` selection as EWMH has compositors do.
+///
+/// For programs with a connection of their own, [`BackgroundHandle::compositor_active`] asks
+/// through the handle's.
+pub fn compositor_active(connection: &Connection, screen_number: i32) -> Result<bool> {
+    let selection = cookie_request!(
+        connection,
+        &InternAtom {
+            name: format!("_NET_WM_CM_S{screen_number}").as_bytes(),
+            only_if_exists: false,
+        }
+    )?
+    .atom();
+
+    let owner = cookie_request!(connection, &GetSelectionOwner { selection })?.owner();
+    Ok(owner != WINDOW_NONE)
+}
+
+impl BackgroundHandle {
+    /// Whether a compositing manager runs on the handle's screen, see [`compositor_active`].
+    ///
+    /// Only checked on the first call, a compositor started or stopped later goes unnoticed.
+    pub fn compositor_active(&self) -> Result<bool> {
+        self.compositor
+            .get_or_try_init(|| compositor_active(&self.connection, self.screen_number))
+            .copied()
+    }
+}
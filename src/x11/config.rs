@@ -0,0 +1,148 @@
+use super::RootProperties;
+use crate::{MemoryBudget, Pixel};
+
+/// Options for [`load_with`](super::load_with) that go beyond what
+/// [`OpenMethod`](super::OpenMethod) describes.
+#[derive(Clone, Debug)]
+pub struct LoadConfig {
+    pub(crate) kill_foreign: bool,
+    pub(crate) keep_previous: bool,
+    pub(crate) pseudo_color: bool,
+    pub(crate) clear_color: Pixel,
+    pub(crate) force: bool,
+    pub(crate) take_over_from_shade: bool,
+    pub(crate) tag_metadata: bool,
+    pub(crate) rgba: bool,
+    pub(crate) memory_budget: MemoryBudget,
+    pub(crate) screen: Option<i32>,
+    pub(crate) root_properties: RootProperties,
+}
+
+impl Default for LoadConfig {
+    fn default() -> LoadConfig {
+        LoadConfig {
+            kill_foreign: true,
+            keep_previous: false,
+            pseudo_color: false,
+            clear_color: Pixel::default(),
+            force: false,
+            take_over_from_shade: false,
+            tag_metadata: true,
+            rgba: false,
+            memory_budget: MemoryBudget::unlimited(),
+            screen: None,
+            root_properties: RootProperties::Both,
+        }
+    }
+}
+
+impl LoadConfig {
+    pub fn new() -> LoadConfig {
+        LoadConfig::default()
+    }
+
+    /// Whether to kill the clients owning the current root pixmaps, the default.
+    ///
+    /// Killing them is how their pixmaps get freed: a wallpaper setter that exited with
+    /// RetainPermanent is otherwise the only one who could, and it is gone. Leaving them alive
+    /// keeps another running wallpaper tool (or whatever else owns the pixmap) working, at the
+    /// cost of its pixmap staying allocated on the server for as long as the session lasts.
+    pub fn kill_foreign(mut self, kill: bool) -> LoadConfig {
+        self.kill_foreign = kill;
+        self
+    }
+
+    /// Copies the current wallpaper before taking over, for
+    /// [`BackgroundHandle::previous`](super::BackgroundHandle::previous) to restore later. Off by
+    /// default, as the copy costs a screen sized pixmap on the server.
+    pub fn keep_previous(mut self, keep: bool) -> LoadConfig {
+        self.keep_previous = keep;
+        self
+    }
+
+    /// Draws to PseudoColor roots through a private colormap holding a fixed palette, rather than
+    /// failing with [`Error::UnsupportedVisual`](crate::Error::UnsupportedVisual). Off by default:
+    /// installing the colormap shifts the colors of every other window still using the default
+    /// one, and the wallpaper is limited to at most 256 colors.
+    pub fn pseudo_color(mut self, allow: bool) -> LoadConfig {
+        self.pseudo_color = allow;
+        self
+    }
+
+    /// The color the new pixmap and the buffer start out as, black by default. The pixmap is
+    /// filled server side right after it is created, so the root shows this color rather than
+    /// whatever the server's memory held until the first flush. Server side fills also paint with
+    /// it until [`BackgroundHandle::configure_gc`](super::BackgroundHandle::configure_gc) says
+    /// otherwise.
+    pub fn clear_color(mut self, color: Pixel) -> LoadConfig {
+        self.clear_color = color;
+        self
+    }
+
+    /// Takes the wallpaper over even from another running shade instance, killing it like any
+    /// other owner. Without this (or [`LoadConfig::take_over_from_shade`]) loading fails with
+    /// [`Error::AlreadyOwned`](crate::Error::AlreadyOwned) instead, so two shade daemons cannot
+    /// keep killing each other.
+    pub fn force(mut self, force: bool) -> LoadConfig {
+        self.force = force;
+        self
+    }
+
+    /// Takes the wallpaper over from another running shade instance politely: its wallpaper is
+    /// read back into the new buffer and it is left alive rather than killed, only losing its
+    /// claim on the wallpaper. Its pixmap stays allocated for as long as it runs.
+    pub fn take_over_from_shade(mut self, take_over: bool) -> LoadConfig {
+        self.take_over_from_shade = take_over;
+        self
+    }
+
+    /// Whether to tag the root window with what was set, the default. Every image, source or
+    /// download set on the handle then records its path (for files), the time and the scaling
+    /// in `_SHADE_*` root properties, for [`current_metadata`](super::current_metadata) and
+    /// other programs to read back. The tags are written by the first flush that puts it up.
+    pub fn tag_metadata(mut self, tag: bool) -> LoadConfig {
+        self.tag_metadata = tag;
+        self
+    }
+
+    /// Whether the handle gets an RGBA [`layer`](super::BackgroundHandle::layer) over its buffer,
+    /// off by default. Flushes composite it over what they upload, so overlays can keep their
+    /// own transparency without the buffer ever being touched. Costs a screen sized buffer of
+    /// 4 byte pixels and a little work per flush.
+    pub fn rgba(mut self, rgba: bool) -> LoadConfig {
+        self.rgba = rgba;
+        self
+    }
+
+    /// Caps what snapshots, the undo history, the image cache and the
+    /// [previous wallpaper](LoadConfig::keep_previous) take together, unlimited by default.
+    /// The cache's own capacity still applies within it. A previous wallpaper that does not fit
+    /// is not kept, with a
+    /// [`LoadWarning::PreviousUnavailable`](super::LoadWarning::PreviousUnavailable) saying so.
+    ///
+    /// Handles loaded with clones of one budget share it, see
+    /// [`BackgroundHandle::memory_usage`](super::BackgroundHandle::memory_usage) for what it
+    /// goes to.
+    pub fn memory_budget(mut self, budget: MemoryBudget) -> LoadConfig {
+        self.memory_budget = budget;
+        self
+    }
+
+    /// Loads on screen `screen` of the display, rather than the one `DISPLAY` names. Fails with
+    /// [`Error::ScreenOutOfRange`](crate::Error::ScreenOutOfRange) if the display has no such
+    /// screen, see [`screens`](super::screens) for those it has.
+    pub fn screen(mut self, screen: i32) -> LoadConfig {
+        self.screen = Some(screen);
+        self
+    }
+
+    /// Which of `_XROOTPMAP_ID` and `ESETROOT_PMAP_ID` shade points at its pixmap, both by
+    /// default, for setups that have other clients read the two for different things. Only the
+    /// owners of pixmaps the chosen properties name are killed, and not even those if the other
+    /// property names the same pixmap. Restoring the
+    /// [previous wallpaper](super::BackgroundHandle::previous) sets the same ones.
+    pub fn root_properties(mut self, properties: RootProperties) -> LoadConfig {
+        self.root_properties = properties;
+        self
+    }
+}
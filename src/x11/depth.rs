@@ -0,0 +1,29 @@
+use std::sync::atomic::Ordering;
+
+use super::BackgroundHandle;
+use crate::{channel_bits, Error, Result};
+
+impl BackgroundHandle {
+    /// Reduces the buffer to the channel precision of `depth` on every flush, as if the screen
+    /// were that deep. Only what is uploaded changes: the pixmap keeps the depth and visual of the
+    /// root, and the buffer itself keeps its full precision.
+    ///
+    /// Meant for checking how a wallpaper holds up on shallower screens. Setting the root depth
+    /// (or anything deeper) turns the reduction off again.
+    pub fn set_target_depth(&self, depth: u8) -> Result<()> {
+        channel_bits(depth).ok_or(Error::UnsupportedDepth(depth))?;
+        self.target_depth.store(depth, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// The depth flushes are reduced to, the root depth unless [`set_target_depth`] said otherwise.
+    ///
+    /// [`set_target_depth`]: BackgroundHandle::set_target_depth
+    pub fn target_depth(&self) -> u8 {
+        // 0 stands for "not set"
+        match self.target_depth.load(Ordering::Relaxed) {
+            0 => self.depth,
+            depth => depth,
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use std::{path::Path, sync::Arc};
+
+use xcb::x::{self, GetProperty, InternAtom, ATOM_CARDINAL};
+
+use super::{AppliedSource, BackgroundHandle, ShadeEvent};
+use crate::{CancelToken, Canvas, Error, PreparedImage, Result, ScalingMethod, WallpaperSource};
+
+/// Which wallpaper to show on which EWMH desktop (workspace).
+///
+/// Desktops without a wallpaper of their own, and window managers that don't report the current
+/// desktop at all, get the fallback, or the first desktop's wallpaper if there is none. Following
+/// desktops with [`BackgroundHandle::follow_desktops`] needs a window manager that does report it.
+#[derive(Debug, Default)]
+pub struct DesktopWallpapers {
+    pub(crate) desktops: Vec<Option<WallpaperSource>>,
+    pub(crate) fallback: Option<WallpaperSource>,
+}
+
+impl DesktopWallpapers {
+    pub fn new() -> DesktopWallpapers {
+        DesktopWallpapers::default()
+    }
+
+    /// Shows `source` on the desktop numbered `index`, counting from 0 like
+    /// `_NET_CURRENT_DESKTOP` does.
+    pub fn desktop(
+        mut self,
+        index: usize,
+        source: impl Into<WallpaperSource>,
+    ) -> DesktopWallpapers {
+        if self.desktops.len() <= index {
+            self.desktops.resize_with(index + 1, || None);
+        }
+        self.desktops[index] = Some(source.into());
+        self
+    }
+
+    pub fn fallback(mut self, source: impl Into<WallpaperSource>) -> DesktopWallpapers {
+        self.fallback = Some(source.into());
+        self
+    }
+
+    pub(crate) fn source(&self, desktop: Option<u32>) -> Option<&WallpaperSource> {
+        pick(&self.desktops, self.fallback.as_ref(), desktop)
+    }
+}
+
+// The entry for `desktop`, going by the rules DesktopWallpapers documents
+fn pick<'a, T>(
+    desktops: &'a [Option<T>],
+    fallback: Option<&'a T>,
+    desktop: Option<u32>,
+) -> Option<&'a T> {
+    desktop
+        .and_then(|d| desktops.get(d as usize)?.as_ref())
+        .or(fallback)
+        .or_else(|| desktops.iter().flatten().next())
+}
+
+// A desktop's wallpaper rendered ahead of time, along with the file it came from for tagging
+struct Prepared<'a> {
+    image: PreparedImage,
+    path: Option<&'a Path>,
+}
+
+// Every wallpaper of a DesktopWallpapers rendered for the screen, laid out the same way
+struct PreparedDesktops<'a> {
+    desktops: Vec<Option<Prepared<'a>>>,
+    fallback: Option<Prepared<'a>>,
+}
+
+impl<'a> PreparedDesktops<'a> {
+    fn new(
+        handle: &BackgroundHandle,
+        wallpapers: &'a DesktopWallpapers,
+        method: ScalingMethod,
+    ) -> Result<PreparedDesktops<'a>> {
+        let prepare = |source: &'a WallpaperSource| -> Result<Prepared<'a>> {
+            let image = Canvas::from_source_within(
+                source,
+                handle.width as u32,
+                handle.height as u32,
+                method,
+                handle.max_image_pixels(),
+            )?;
+            let path = match source {
+                WallpaperSource::File(path) => Some(path.as_path()),
+                _ => None,
+            };
+
+            Ok(Prepared {
+                image: Arc::new(image),
+                path,
+            })
+        };
+
+        Ok(PreparedDesktops {
+            desktops: wallpapers
+                .desktops
+                .iter()
+                .map(|source| source.as_ref().map(prepare).transpose())
+                .collect::<Result<_>>()?,
+            fallback: wallpapers.fallback.as_ref().map(prepare).transpose()?,
+        })
+    }
+
+    fn get(&self, desktop: Option<u32>) -> Option<&Prepared<'a>> {
+        pick(&self.desktops, self.fallback.as_ref(), desktop)
+    }
+}
+
+impl BackgroundHandle {
+    /// The desktop the window manager currently shows, `None` if it doesn't implement EWMH.
+    pub fn current_desktop(&self) -> Result<Option<u32>> {
+        let atom = self.current_desktop_atom()?;
+        let property = cookie_request!(
+            &self.connection,
+            &GetProperty {
+                r#type: ATOM_CARDINAL,
+                delete: false,
+                window: self.root,
+                property: atom,
+                long_offset: 0,
+                long_length: 1,
+            }
+        )?;
+
+        // Also covers the property not being set at all
+        if property.format() != 32 {
+            return Ok(None);
+        }
+        Ok(property.value::<u32>().first().copied())
+    }
+
+    /// Sets and flushes the wallpaper `wallpapers` assigns to the current desktop.
+    pub fn apply_desktop_wallpaper(
+        &self,
+        wallpapers: &DesktopWallpapers,
+        method: ScalingMethod,
+    ) -> Result<()> {
+        let desktop = self.current_desktop()?;
+
+        if let Some(source) = wallpapers.source(desktop) {
+            info!("Applying wallpaper for desktop {desktop:?}");
+            self.set_source(source, method)?;
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies the current desktop's wallpaper, then keeps switching wallpapers along with the
+    /// desktop. Blocks for as long as the connection lives, so run it on a thread of its own.
+    ///
+    /// Every wallpaper is rendered up front, so switching desktops only takes a flush. Fails with
+    /// [`Error::NoCurrentDesktop`] right away if the window manager does not report the current
+    /// desktop, rather than waiting for changes that would never come.
+    pub fn follow_desktops(
+        &self,
+        wallpapers: &DesktopWallpapers,
+        method: ScalingMethod,
+    ) -> Result<()> {
+        self.follow(wallpapers, method, None)
+    }
+
+    /// [`BackgroundHandle::follow_desktops`], returning once `cancel` is cancelled. Switches
+    /// already under way are finished first.
+    pub fn follow_desktops_until(
+        &self,
+        wallpapers: &DesktopWallpapers,
+        method: ScalingMethod,
+        cancel: &CancelToken,
+    ) -> Result<()> {
+        self.follow(wallpapers, method, Some(cancel))
+    }
+
+    fn follow(
+        &self,
+        wallpapers: &DesktopWallpapers,
+        method: ScalingMethod,
+        cancel: Option<&CancelToken>,
+    ) -> Result<()> {
+        self.select_events()?;
+
+        let atom = self.current_desktop_atom()?;
+        let mut desktop = self.current_desktop()?;
+        if desktop.is_none() {
+            return Err(Error::NoCurrentDesktop);
+        }
+
+        let prepared = PreparedDesktops::new(self, wallpapers, method)?;
+        self.show_prepared(&prepared, desktop, method)?;
+
+        loop {
+            let events = match cancel {
+                None => self.wait_events()?,
+                Some(cancel) => match self.wait_events_until(cancel)? {
+                    Some(events) => events,
+                    None => return Ok(()),
+                },
+            };
+
+            for event in events {
+                match event {
+                    ShadeEvent::PropertyChanged { atom: changed, .. } if changed == atom => {
+                        let current = self.current_desktop()?;
+                        if current != desktop {
+                            desktop = current;
+                            self.show_prepared(&prepared, desktop, method)?;
+                        }
+                    }
+                    ShadeEvent::RequestFailed(e) => warn!("Request failed: {e:?}"),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn show_prepared(
+        &self,
+        prepared: &PreparedDesktops,
+        desktop: Option<u32>,
+        method: ScalingMethod,
+    ) -> Result<()> {
+        if let Some(wallpaper) = prepared.get(desktop) {
+            info!("Showing wallpaper for desktop {desktop:?}");
+            self.lock_buffer().clone_from_slice(&wallpaper.image);
+            self.tag_metadata(wallpaper.path, &method.into());
+            let source = match wallpaper.path {
+                Some(path) => AppliedSource::File(path.to_owned()),
+                None => AppliedSource::Other,
+            };
+            self.note_applied(source, None);
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    // Interned even if no window manager did so yet, so one that starts later is noticed
+    fn current_desktop_atom(&self) -> Result<x::Atom> {
+        Ok(cookie_request!(
+            &self.connection,
+            &InternAtom {
+                name: b"_NET_CURRENT_DESKTOP",
+                only_if_exists: false,
+            }
+        )?
+        .atom())
+    }
+}
@@ -0,0 +1,320 @@
+use xcb::x::{Drawable, Gcontext, ImageFormat::ZPixmap, PutImage};
+
+use super::PaletteMap;
+use crate::{
+    encode::{chunk_plan, encode_rows, ServerFormat},
+    Canvas,
+};
+
+// Everything that turns the buffer into PutImage requests lives here, so that offsets, padding
+// and splitting are decided in exactly one place. Requests always use ZPixmap, where left_pad
+// has to be 0 and the destination offsets alone place the image
+
+/// The given rectangle of `buffer` the way PutImage sends it to a root laid out as `format`,
+/// reduced to `target_depth` bits per channel when that is lower than the format's depth, or
+/// mapped to `palette` on PseudoColor roots.
+pub(crate) fn encode_region(
+    buffer: &Canvas,
+    (x, y, width, height): (u16, u16, u16, u16),
+    format: &ServerFormat,
+    target_depth: u8,
+    palette: Option<&PaletteMap>,
+) -> Vec<u8> {
+    if let Some(palette) = palette {
+        return palette.encode(buffer, (x, y, width, height));
+    }
+
+    let width = width as usize;
+    if target_depth < format.depth {
+        let mut region = buffer.crop(x as u32, y as u32, width as u32, height as u32);
+        region.reduce_depth(target_depth);
+        return encode_rows(format, region.chunks_exact(width.max(1)));
+    }
+
+    // Rows are encoded straight out of the buffer, there is nothing to change about them first
+    let stride = buffer.width() as usize;
+    encode_rows(
+        format,
+        (y as usize..y as usize + height as usize).map(|row| {
+            let start = row * stride + x as usize;
+            &buffer[start..start + width]
+        }),
+    )
+}
+
+/// One PutImage worth of rows of an encoded region.
+pub(crate) struct Band<'a> {
+    pub(crate) dst_x: i16,
+    pub(crate) dst_y: i16,
+    pub(crate) width: u16,
+    pub(crate) height: u16,
+    pub(crate) data: &'a [u8],
+}
+
+impl<'a> Band<'a> {
+    pub(crate) fn put_image(&self, drawable: Drawable, gc: Gcontext, depth: u8) -> PutImage<'a> {
+        PutImage {
+            format: ZPixmap,
+            drawable,
+            gc,
+            width: self.width,
+            height: self.height,
+            dst_x: self.dst_x,
+            dst_y: self.dst_y,
+            left_pad: 0,
+            depth,
+            data: self.data,
+        }
+    }
+}
+
+/// Splits `data`, the encoding of the rectangle `(x, y, width, height)` in `format` as made by
+/// [`encode_region`], into bands of at most `max_bytes` each, following [`chunk_plan`]. Bands start
+/// on a whole row, or on a pad boundary within one where rows are too long, so no band ever needs
+/// a left pad. `None` where [`chunk_plan`] is.
+pub(crate) fn bands<'a>(
+    data: &'a [u8],
+    format: &ServerFormat,
+    (x, y, width, height): (u16, u16, u16, u16),
+    max_bytes: usize,
+) -> Option<impl Iterator<Item = Band<'a>>> {
+    debug_assert_eq!(
+        format.row_bytes(width as u32) * height as usize,
+        data.len(),
+        "encoded data is not made of whole rows"
+    );
+
+    // Any position within a drawable_geometry fits an i16
+    let chunks = chunk_plan(format, width, height, max_bytes)?;
+    Some(chunks.into_iter().map(move |chunk| Band {
+        dst_x: (x + chunk.x) as i16,
+        dst_y: (y + chunk.y) as i16,
+        width: chunk.width,
+        height: chunk.rows,
+        data: &data[chunk.bytes],
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use xcb::Xid;
+
+    use super::*;
+    use crate::{encode::ByteOrder, Pixel};
+
+    // What a depth 24 TrueColor root usually looks like, 4 bytes per pixel
+    const DEPTH_24: ServerFormat = ServerFormat {
+        depth: 24,
+        bits_per_pixel: 32,
+        scanline_pad: 32,
+        byte_order: ByteOrder::LsbFirst,
+        red_mask: 0xff0000,
+        green_mask: 0x00ff00,
+        blue_mask: 0x0000ff,
+    };
+
+    fn gradient(width: u32, height: u32) -> Canvas {
+        let mut canvas = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                canvas.set_pixel(x, y, Pixel::new(x as u8, y as u8, (x + y) as u8));
+            }
+        }
+        canvas
+    }
+
+    #[test]
+    fn regions_go_out_in_the_server_format() {
+        let buffer = gradient(4, 2);
+        let data = encode_region(&buffer, (0, 0, 4, 2), &DEPTH_24, 24, None);
+
+        assert_eq!(data.len(), 4 * 2 * 4);
+        // B, G, R, unused for LSB first 0xRRGGBB
+        assert_eq!(&data[..4], &[0, 0, 0, 0]);
+        assert_eq!(&data[4..8], &[1, 0, 1, 0]);
+        assert_eq!(&data[16..20], &[1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn regions_match_encoding_their_rows() {
+        let buffer = gradient(7, 5);
+        let data = encode_region(&buffer, (3, 1, 4, 3), &DEPTH_24, 24, None);
+
+        let cropped = buffer.crop(3, 1, 4, 3);
+        assert_eq!(data, encode_rows(&DEPTH_24, cropped.chunks_exact(4)));
+    }
+
+    #[test]
+    fn target_depth_reduces_before_encoding() {
+        let buffer = Canvas::filled(2, 2, Pixel::new(0xff, 0x81, 0x7f));
+        let data = encode_region(&buffer, (0, 0, 2, 2), &DEPTH_24, 16, None);
+
+        let mut reduced = buffer.clone();
+        reduced.reduce_depth(16);
+        assert_eq!(data, encode_rows(&DEPTH_24, reduced.chunks_exact(2)));
+        assert_ne!(
+            data,
+            encode_region(&buffer, (0, 0, 2, 2), &DEPTH_24, 24, None)
+        );
+    }
+
+    // 7 bytes fit a single pixel of DEPTH_24 and nothing more, bands are as small as they get
+    const ONE_PIXEL: usize = 7;
+
+    // Where a band goes, as (dst_x, dst_y, width, height)
+    type Placed = (i16, i16, u16, u16);
+
+    fn band_list(
+        data: &[u8],
+        region: (u16, u16, u16, u16),
+        max_bytes: usize,
+    ) -> Vec<(Placed, &[u8])> {
+        bands(data, &DEPTH_24, region, max_bytes)
+            .expect("no bands")
+            .map(|b| ((b.dst_x, b.dst_y, b.width, b.height), b.data))
+            .collect()
+    }
+
+    #[test]
+    fn bands_at_an_odd_offset() {
+        let data: Vec<u8> = (0..5 * 4 * 3).collect();
+        let bands = band_list(&data, (3, 7, 5, 3), 5 * 4 * 2);
+        assert_eq!(
+            bands,
+            [((3, 7, 5, 2), &data[..40]), ((3, 9, 5, 1), &data[40..])]
+        );
+    }
+
+    #[test]
+    fn bands_of_a_single_column() {
+        let data: Vec<u8> = (0..4 * 3).collect();
+        let bands = band_list(&data, (9, 0, 1, 3), ONE_PIXEL);
+        assert_eq!(
+            bands,
+            [
+                ((9, 0, 1, 1), &data[0..4]),
+                ((9, 1, 1, 1), &data[4..8]),
+                ((9, 2, 1, 1), &data[8..12]),
+            ]
+        );
+    }
+
+    #[test]
+    fn bands_up_to_the_right_edge() {
+        // The last 3 columns of a 1920 wide root, split a pixel at a time
+        let data: Vec<u8> = (0..3 * 4).collect();
+        let bands = band_list(&data, (1917, 0, 3, 1), ONE_PIXEL);
+        assert_eq!(
+            bands,
+            [
+                ((1917, 0, 1, 1), &data[0..4]),
+                ((1918, 0, 1, 1), &data[4..8]),
+                ((1919, 0, 1, 1), &data[8..12]),
+            ]
+        );
+    }
+
+    #[test]
+    fn bands_of_a_single_row() {
+        let data = vec![0; 1920 * 4];
+        let bands = band_list(&data, (0, 1079, 1920, 1), 1920 * 4);
+        assert_eq!(bands, [((0, 1079, 1920, 1), &data[..])]);
+    }
+
+    // The widest root X allows, encoded and split as a server without BIG-REQUESTS needs it
+    #[test]
+    fn ultra_wide_regions() {
+        let buffer = gradient(32760, 3);
+        let data = encode_region(&buffer, (0, 0, 32760, 3), &DEPTH_24, 24, None);
+        assert_eq!(data.len(), 32760 * 3 * 4);
+
+        // Two rows a request
+        let bands = band_list(&data, (0, 0, 32760, 3), 262_140 - 28);
+        assert_eq!(bands.len(), 2);
+        let total: usize = bands.iter().map(|(_, data)| data.len()).sum();
+        assert_eq!(total, data.len());
+        assert_eq!(bands[1].0, (0, 2, 32760, 1));
+    }
+
+    // Carries out `request` on `root`, a DEPTH_24 drawable as wide as `stride`, the way the server
+    // would
+    fn put(root: &mut [u32], stride: usize, request: &PutImage) {
+        assert!(matches!(request.format, ZPixmap));
+        assert_eq!((request.left_pad, request.depth), (0, 24));
+
+        let (x, y) = (request.dst_x as usize, request.dst_y as usize);
+        let rows = request
+            .data
+            .chunks_exact(DEPTH_24.row_bytes(request.width as u32));
+        assert_eq!(rows.len(), request.height as usize);
+        for (row, data) in rows.enumerate() {
+            let start = (y + row) * stride + x;
+            let pixels = data.chunks_exact(4).take(request.width as usize);
+            for (out, pixel) in root[start..].iter_mut().zip(pixels) {
+                *out = u32::from_le_bytes(pixel.try_into().unwrap());
+            }
+        }
+    }
+
+    // Flushes `region` of `buffer` to a root of the same size in requests of at most `max_bytes`,
+    // checking that it shows exactly that region afterwards
+    fn flush(buffer: &Canvas, region: (u16, u16, u16, u16), max_bytes: usize) {
+        let stride = buffer.width() as usize;
+        let mut root = vec![u32::MAX; buffer.len()];
+
+        let data = encode_region(buffer, region, &DEPTH_24, 24, None);
+        let (drawable, gc) = (Drawable::None, Gcontext::none());
+        for band in bands(&data, &DEPTH_24, region, max_bytes).expect("no bands") {
+            put(&mut root, stride, &band.put_image(drawable, gc, 24));
+        }
+
+        let (x, y, width, height) = region;
+        let inside = |px: usize, py: usize| {
+            (x as usize..(x + width) as usize).contains(&px)
+                && (y as usize..(y + height) as usize).contains(&py)
+        };
+        for (i, (shown, pixel)) in root.iter().zip(buffer.iter()).enumerate() {
+            let (px, py) = (i % stride, i / stride);
+            match inside(px, py) {
+                true => assert_eq!(*shown, DEPTH_24.pixel_value(pixel), "({px}, {py})"),
+                false => assert_eq!(*shown, u32::MAX, "({px}, {py}) drawn outside {region:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn flushing_at_odd_offsets() {
+        let buffer = gradient(31, 17);
+        for max_bytes in [ONE_PIXEL, 3 * 4, 100, usize::MAX] {
+            flush(&buffer, (3, 5, 11, 7), max_bytes);
+            flush(&buffer, (1, 1, 1, 1), max_bytes);
+        }
+    }
+
+    #[test]
+    fn flushing_single_columns() {
+        let buffer = gradient(31, 17);
+        for x in [0, 15, 30] {
+            flush(&buffer, (x, 0, 1, 17), ONE_PIXEL);
+            flush(&buffer, (x, 2, 1, 9), usize::MAX);
+        }
+    }
+
+    #[test]
+    fn flushing_up_to_the_right_edge() {
+        let buffer = gradient(31, 17);
+        for max_bytes in [ONE_PIXEL, 5 * 4, 31 * 4, usize::MAX] {
+            flush(&buffer, (17, 3, 14, 6), max_bytes);
+            flush(&buffer, (0, 16, 31, 1), max_bytes);
+        }
+    }
+
+    #[test]
+    fn flushing_single_rows() {
+        let buffer = gradient(31, 17);
+        for y in [0, 8, 16] {
+            flush(&buffer, (0, y, 31, 1), 12 * 4);
+            flush(&buffer, (7, y, 9, 1), ONE_PIXEL);
+        }
+    }
+}
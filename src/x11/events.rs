@@ -0,0 +1,144 @@
+use std::os::fd::{AsRawFd, RawFd};
+
+use xcb::{
+    randr::{self, NotifyMask},
+    x::{self, Atom, ChangeWindowAttributes, Cw, EventMask, Property},
+    Extension, ProtocolError,
+};
+
+use super::BackgroundHandle;
+use crate::{CancelToken, Result, CANCEL_LATENCY};
+
+/// Something the X server let the handle know, see [`BackgroundHandle::process_pending_events`].
+#[derive(Debug)]
+pub enum ShadeEvent {
+    /// Monitors were added, removed, moved or resized, [`BackgroundHandle::monitors`] has the new
+    /// layout. Only reported on servers with RandR.
+    MonitorsChanged,
+    /// A property of the root window was changed, or deleted. Covers the current desktop, the
+    /// active window and the root pixmap properties other wallpaper setters write to, among
+    /// many others.
+    PropertyChanged { atom: Atom, deleted: bool },
+    /// A request sent without waiting for its outcome failed.
+    RequestFailed(ProtocolError),
+}
+
+impl ShadeEvent {
+    // Events of no interest to the handle, and events about other windows, are `None`
+    fn translate(event: xcb::Event, root: x::Window) -> Option<ShadeEvent> {
+        match event {
+            xcb::Event::X(x::Event::PropertyNotify(e)) if e.window() == root => {
+                Some(ShadeEvent::PropertyChanged {
+                    atom: e.atom(),
+                    deleted: e.state() == Property::Delete,
+                })
+            }
+            xcb::Event::RandR(randr::Event::ScreenChangeNotify(_) | randr::Event::Notify(_)) => {
+                Some(ShadeEvent::MonitorsChanged)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl AsRawFd for BackgroundHandle {
+    /// The file descriptor of the connection to the server, to register with an event loop of
+    /// one's own. Once readable, call [`BackgroundHandle::process_pending_events`].
+    fn as_raw_fd(&self) -> RawFd {
+        self.connection.as_raw_fd()
+    }
+}
+
+impl BackgroundHandle {
+    /// Translates every event that already arrived, without blocking. Returns nothing if there
+    /// are none.
+    ///
+    /// The first call starts listening for events, so make it once right away, before registering
+    /// [`as_raw_fd`](AsRawFd::as_raw_fd) with an event loop. xcb reads events into a queue of its
+    /// own while waiting for replies, which leaves the descriptor quiet even though events are
+    /// pending, so call this after calling into the handle as well, not only on readiness.
+    pub fn process_pending_events(&self) -> Result<Vec<ShadeEvent>> {
+        self.select_events()?;
+
+        let mut events = Vec::new();
+        loop {
+            match self.connection.poll_for_event() {
+                Ok(Some(event)) => events.extend(ShadeEvent::translate(event, self.root)),
+                Ok(None) => return Ok(events),
+                Err(xcb::Error::Protocol(e)) => events.push(ShadeEvent::RequestFailed(e)),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Blocks until at least one event arrives, then translates it along with every other one
+    /// already pending. The blocking counterpart of
+    /// [`BackgroundHandle::process_pending_events`], which every watching loop of shade is built
+    /// on.
+    pub fn wait_events(&self) -> Result<Vec<ShadeEvent>> {
+        loop {
+            let mut events = self.process_pending_events()?;
+            if events.is_empty() {
+                match self.connection.wait_for_event() {
+                    Ok(event) => events.extend(ShadeEvent::translate(event, self.root)),
+                    Err(xcb::Error::Protocol(e)) => events.push(ShadeEvent::RequestFailed(e)),
+                    Err(e) => return Err(e.into()),
+                }
+                events.extend(self.process_pending_events()?);
+            }
+
+            // Events nobody is interested in leave nothing to return, keep waiting
+            if !events.is_empty() {
+                return Ok(events);
+            }
+        }
+    }
+
+    /// [`BackgroundHandle::wait_events`], giving up with `None` once `cancel` is cancelled. xcb
+    /// cannot wait for events with a timeout, so this polls for them every [`CANCEL_LATENCY`]
+    /// instead.
+    pub fn wait_events_until(&self, cancel: &CancelToken) -> Result<Option<Vec<ShadeEvent>>> {
+        while !cancel.is_cancelled() {
+            let events = self.process_pending_events()?;
+            if !events.is_empty() {
+                return Ok(Some(events));
+            }
+            cancel.wait_timeout(CANCEL_LATENCY);
+        }
+        Ok(None)
+    }
+
+    // Selecting replaces the handle's previous event mask on the root, which only ever is this
+    // one, so doing it once per handle is enough
+    pub(crate) fn select_events(&self) -> Result<()> {
+        self.events_selected.get_or_try_init(|| -> Result<()> {
+            void_request!(
+                &self.connection,
+                &ChangeWindowAttributes {
+                    window: self.root,
+                    value_list: &[Cw::EventMask(EventMask::PROPERTY_CHANGE)],
+                }
+            )?;
+
+            if self
+                .connection
+                .active_extensions()
+                .any(|e| e == Extension::RandR)
+            {
+                void_request!(
+                    &self.connection,
+                    &randr::SelectInput {
+                        window: self.root,
+                        enable: NotifyMask::SCREEN_CHANGE
+                            | NotifyMask::CRTC_CHANGE
+                            | NotifyMask::OUTPUT_CHANGE,
+                    }
+                )?;
+            }
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+}
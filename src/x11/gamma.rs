@@ -0,0 +1,336 @@
+use std::fmt;
+
+use xcb::{
+    x::{CreatePixmap, Drawable, FreePixmap, ImageFormat::ZPixmap, PutImage},
+    Xid,
+};
+
+use super::{capture::capture, encode_region, BackgroundHandle, VisualInfo};
+use crate::{
+    patterns::{srgb_ramp, RampRow, RAMP_LEVELS, RAMP_ROWS},
+    Canvas, Error, Pixel, Result,
+};
+
+// Levels this close to black or white say little about a curve, a gamma of anything maps them
+// to about the same value
+const CURVE_LEVELS: std::ops::RangeInclusive<usize> = 16..=239;
+
+/// The outcome of [`BackgroundHandle::verify_gamma`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct GammaReport {
+    pub visual: VisualInfo,
+    /// What each level of the gray ramp came back as, per channel: `captured[c][level]`.
+    pub captured: [[u8; 256]; 3],
+    /// What each level was expected back as, the level itself unless the handle uploads at a
+    /// reduced [target depth](BackgroundHandle::set_target_depth).
+    pub expected: [[u8; 256]; 3],
+    /// Levels per channel that came back further off than the visual's precision explains.
+    pub mismatched: [usize; 3],
+    pub max_error: [u8; 3],
+    /// The exponent per channel that best maps what was uploaded to what came back, 1.0 when
+    /// nothing was applied. Around 2.2 means the values were darkened as if decoded from sRGB
+    /// once more, around 0.45 washed out as if encoded once more. NaN if no mid level came back
+    /// as anything but black or white.
+    pub gamma: [f64; 3],
+    /// Whether the ramps of a single channel came back with the other channels lit up, as by a
+    /// color matrix or swapped channels.
+    pub crosstalk: bool,
+}
+
+impl GammaReport {
+    /// Whether every level came back as uploaded, within the visual's precision. If so, shade is
+    /// not what changes the colors.
+    pub fn preserved(&self) -> bool {
+        self.mismatched == [0; 3] && !self.crosstalk
+    }
+}
+
+impl fmt::Display for GammaReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "mismatched: r {} g {} b {} of {RAMP_LEVELS} levels",
+            self.mismatched[0], self.mismatched[1], self.mismatched[2]
+        )?;
+        writeln!(
+            f,
+            "max error:  r {} g {} b {}",
+            self.max_error[0], self.max_error[1], self.max_error[2]
+        )?;
+        writeln!(
+            f,
+            "gamma:      r {:.2} g {:.2} b {:.2}",
+            self.gamma[0], self.gamma[1], self.gamma[2]
+        )?;
+        if self.crosstalk {
+            writeln!(f, "crosstalk:  single channel ramps leak into the others")?;
+        }
+
+        if self.preserved() {
+            write!(
+                f,
+                "result:     values arrive unchanged, anything off on screen happens after the \
+                 server (compositor, monitor or color settings)"
+            )
+        } else {
+            write!(
+                f,
+                "result:     values are changed on the way to the server, by it or by a proxy \
+                 in between"
+            )
+        }
+    }
+}
+
+impl BackgroundHandle {
+    /// Uploads [`srgb_ramp`] to a scratch pixmap the way [`BackgroundHandle::flush`] uploads the
+    /// buffer, reads it back and reports whether every level survived. The wallpaper is left
+    /// alone.
+    ///
+    /// shade applies no gamma or lookup table of its own, so the levels should come back exactly,
+    /// down to the visual's precision. Servers, or remote display proxies in between, that
+    /// mangle pixel data show up here; washed out colors that pass are down to the compositor
+    /// or the monitor. Fails with [`Error::UnsupportedVisual`] on PseudoColor roots, whose
+    /// pixels are palette indices rather than levels.
+    pub fn verify_gamma(&self) -> Result<GammaReport> {
+        if self.palette.is_some() {
+            return Err(Error::UnsupportedVisual(self.visual_info.class));
+        }
+
+        let ramp = srgb_ramp();
+        let (width, height) = (ramp.width() as u16, ramp.height() as u16);
+        let region = (0, 0, width, height);
+        let data = encode_region(&ramp, region, &self.format, self.target_depth(), None);
+
+        let mut expected = ramp;
+        if self.target_depth() < self.depth {
+            expected.reduce_depth(self.target_depth());
+        }
+
+        let pixmap = self.connection.generate_id();
+        void_request!(
+            &self.connection,
+            &CreatePixmap {
+                depth: self.depth,
+                pid: pixmap,
+                drawable: Drawable::Window(self.root),
+                width,
+                height,
+            }
+        )?;
+
+        let captured = self.upload_gc().and_then(|gc| {
+            void_request!(
+                &self.connection,
+                &PutImage {
+                    format: ZPixmap,
+                    drawable: Drawable::Pixmap(pixmap),
+                    gc,
+                    width,
+                    height,
+                    dst_x: 0,
+                    dst_y: 0,
+                    left_pad: 0,
+                    depth: self.depth,
+                    data: &data,
+                }
+            )?;
+            capture(
+                &self.connection,
+                Drawable::Pixmap(pixmap),
+                &self.visual_info,
+                (0, 0, width, height),
+            )
+        });
+
+        if let Err(e) = void_request!(&self.connection, &FreePixmap { pixmap }) {
+            warn!(
+                "Failed to free gamma ramp pixmap {}: {e}",
+                pixmap.resource_id()
+            );
+        }
+
+        Ok(analyze(self.visual_info, &expected, &captured?))
+    }
+}
+
+fn analyze(visual: VisualInfo, expected: &Canvas, captured: &Canvas) -> GammaReport {
+    let channels = |p: &Pixel| [p.r, p.g, p.b];
+    let row = |canvas: &Canvas, row: RampRow| {
+        let y = RAMP_ROWS.iter().position(|&r| r == row).unwrap_or(0) as u32;
+        let mut levels = [[0u8; 256]; 3];
+        for x in 0..RAMP_LEVELS {
+            let pixel = canvas.get_pixel(x, y).map_or([0; 3], channels);
+            for c in 0..3 {
+                levels[c][x as usize] = pixel[c];
+            }
+        }
+        levels
+    };
+
+    // Anything within one step of the visual's precision is rounding, not an error
+    let tolerance = [visual.red_mask, visual.green_mask, visual.blue_mask]
+        .map(|mask| 256u32.checked_shr(mask.count_ones()).unwrap_or(0).max(1) as u8);
+
+    let (want, got) = (row(expected, RampRow::Gray), row(captured, RampRow::Gray));
+    let mut mismatched = [0; 3];
+    let mut max_error = [0; 3];
+    for c in 0..3 {
+        for (&w, &g) in want[c].iter().zip(&got[c]) {
+            let error = w.abs_diff(g);
+            max_error[c] = max_error[c].max(error);
+            if error > tolerance[c] {
+                mismatched[c] += 1;
+            }
+        }
+    }
+
+    // The pure ramps only ever light up their own channel
+    let crosstalk = [RampRow::Red, RampRow::Green, RampRow::Blue]
+        .into_iter()
+        .enumerate()
+        .any(|(own, ramp)| {
+            let levels = row(captured, ramp);
+            (0..3)
+                .filter(|&c| c != own)
+                .any(|c| levels[c].iter().any(|&v| v > tolerance[c]))
+        });
+
+    GammaReport {
+        visual,
+        captured: got,
+        gamma: [0, 1, 2].map(|c| fit_gamma(&want[c], &got[c])),
+        expected: want,
+        mismatched,
+        max_error,
+        crosstalk,
+    }
+}
+
+// The median of the exponent each mid level implies, which a few odd levels cannot drag off
+fn fit_gamma(expected: &[u8; 256], captured: &[u8; 256]) -> f64 {
+    let mut exponents: Vec<f64> = CURVE_LEVELS
+        .filter(|&level| (1..255).contains(&captured[level]) && (1..255).contains(&expected[level]))
+        .map(|level| {
+            let (e, c) = (
+                expected[level] as f64 / 255.0,
+                captured[level] as f64 / 255.0,
+            );
+            c.ln() / e.ln()
+        })
+        .collect();
+
+    if exponents.is_empty() {
+        return f64::NAN;
+    }
+    exponents.sort_by(f64::total_cmp);
+    exponents[exponents.len() / 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use xcb::x::VisualClass;
+
+    use super::*;
+
+    fn visual(masks: [u32; 3]) -> VisualInfo {
+        VisualInfo {
+            visual_id: 33,
+            depth: 24,
+            bits_per_pixel: 32,
+            red_mask: masks[0],
+            green_mask: masks[1],
+            blue_mask: masks[2],
+            class: VisualClass::TrueColor,
+        }
+    }
+
+    const RGB888: [u32; 3] = [0xff0000, 0xff00, 0xff];
+    const RGB565: [u32; 3] = [0xf800, 0x7e0, 0x1f];
+
+    // The ramp as a server applying `curve` to every channel would hand it back
+    fn ramp_through(curve: impl Fn(u8) -> u8) -> Canvas {
+        let mut ramp = srgb_ramp();
+        for pixel in ramp.iter_mut() {
+            *pixel = Pixel::new(curve(pixel.r), curve(pixel.g), curve(pixel.b));
+        }
+        ramp
+    }
+
+    fn gamma(exponent: f64) -> impl Fn(u8) -> u8 {
+        move |v| ((v as f64 / 255.0).powf(exponent) * 255.0).round() as u8
+    }
+
+    #[test]
+    fn untouched_ramps_are_preserved() {
+        let report = analyze(visual(RGB888), &srgb_ramp(), &srgb_ramp());
+        assert!(report.preserved());
+        assert_eq!(report.max_error, [0; 3]);
+        assert_eq!(report.captured, report.expected);
+        for g in report.gamma {
+            assert!((g - 1.0).abs() < 1e-9, "gamma {g}");
+        }
+    }
+
+    #[test]
+    fn darkening_shows_up_as_a_gamma_above_one() {
+        let report = analyze(visual(RGB888), &srgb_ramp(), &ramp_through(gamma(2.2)));
+        assert!(!report.preserved());
+        assert!(report.mismatched.iter().all(|&m| m > 100));
+        assert!(!report.crosstalk);
+        for g in report.gamma {
+            assert!((g - 2.2).abs() < 0.05, "gamma {g}");
+        }
+    }
+
+    #[test]
+    fn washing_out_shows_up_as_a_gamma_below_one() {
+        let report = analyze(
+            visual(RGB888),
+            &srgb_ramp(),
+            &ramp_through(gamma(1.0 / 2.2)),
+        );
+        assert!(!report.preserved());
+        for g in report.gamma {
+            assert!((g - 0.45).abs() < 0.02, "gamma {g}");
+        }
+    }
+
+    #[test]
+    fn swapped_channels_are_crosstalk() {
+        let mut captured = srgb_ramp();
+        for pixel in captured.iter_mut() {
+            *pixel = Pixel::new(pixel.b, pixel.g, pixel.r);
+        }
+
+        let report = analyze(visual(RGB888), &srgb_ramp(), &captured);
+        // Gray has all three channels the same, only the single channel ramps give it away
+        assert_eq!(report.mismatched, [0; 3]);
+        assert!(report.crosstalk);
+        assert!(!report.preserved());
+    }
+
+    // A 16 bit visual drops the low bits of every level, which is no error
+    #[test]
+    fn rounding_to_the_visual_is_tolerated() {
+        let mut captured = srgb_ramp();
+        captured.reduce_depth(16);
+
+        let report = analyze(visual(RGB565), &srgb_ramp(), &captured);
+        assert!(report.preserved(), "{report}");
+        assert!(report.max_error.iter().all(|&e| e > 0));
+
+        // Off by more than a step is all the same
+        let report = analyze(visual(RGB565), &srgb_ramp(), &ramp_through(gamma(1.5)));
+        assert!(!report.preserved());
+    }
+
+    #[test]
+    fn flat_captures_have_no_gamma() {
+        let black = Canvas::new(RAMP_LEVELS, RAMP_ROWS.len() as u32);
+        let report = analyze(visual(RGB888), &srgb_ramp(), &black);
+        assert!(report.gamma.iter().all(|g| g.is_nan()));
+        // Level 1 is within a step of black
+        assert_eq!(report.mismatched, [254; 3]);
+    }
+}
@@ -0,0 +1,259 @@
+use xcb::{
+    x::{
+        ChangeGc, CreateGc, Drawable, FreeGc, Gc, Gcontext, Pixmap, PolyFillRectangle, Rectangle,
+        Visualtype,
+    },
+    Connection, Xid,
+};
+
+pub use xcb::x::{FillStyle, Gx};
+
+use super::{owner, registry, BackgroundHandle};
+use crate::{encode, Canvas, Pixel, Result};
+
+/// The subset of graphics context attributes shade makes use of. Unset attributes are left
+/// untouched by [`BackgroundHandle::configure_gc`].
+///
+/// Uploads go through a GC of their own, so only server side fills ever see these attributes.
+#[derive(Clone, Debug, Default)]
+pub struct GcConfig {
+    pub(crate) function: Option<Gx>,
+    pub(crate) foreground: Option<Pixel>,
+    pub(crate) background: Option<Pixel>,
+    pub(crate) fill_style: Option<FillStyle>,
+}
+
+impl GcConfig {
+    pub fn new() -> GcConfig {
+        GcConfig::default()
+    }
+
+    pub fn function(mut self, function: Gx) -> GcConfig {
+        self.function = Some(function);
+        self
+    }
+
+    pub fn foreground(mut self, color: Pixel) -> GcConfig {
+        self.foreground = Some(color);
+        self
+    }
+
+    pub fn background(mut self, color: Pixel) -> GcConfig {
+        self.background = Some(color);
+        self
+    }
+
+    pub fn fill_style(mut self, fill_style: FillStyle) -> GcConfig {
+        self.fill_style = Some(fill_style);
+        self
+    }
+
+    // The server expects the values ordered by their mask bit, which is the declaration order of
+    // xcb::x::Gc
+    pub(crate) fn value_list(&self, visual: &Visualtype) -> Vec<Gc> {
+        let mut values = Vec::with_capacity(4);
+
+        if let Some(function) = self.function {
+            values.push(Gc::Function(function));
+        }
+        if let Some(foreground) = &self.foreground {
+            values.push(Gc::Foreground(pixel_value(visual, foreground)));
+        }
+        if let Some(background) = &self.background {
+            values.push(Gc::Background(pixel_value(visual, background)));
+        }
+        if let Some(fill_style) = self.fill_style {
+            values.push(Gc::FillStyle(fill_style));
+        }
+
+        values
+    }
+}
+
+impl BackgroundHandle {
+    pub fn configure_gc(&self, config: GcConfig) -> Result<()> {
+        void_request!(
+            &self.connection,
+            &ChangeGc {
+                gc: self.fill_gc()?,
+                value_list: &config.value_list(&self.visual),
+            }
+        )?;
+
+        Ok(())
+    }
+
+    // PutImage in ZPixmap format ignores every attribute but the function and plane mask, which
+    // stay at their defaults. The only change ever made to this GC is its foreground, by
+    // fill_solid, which PutImage ignores. configure_gc only ever changes the separate fill GC, so
+    // uploads never depend on what fills last configured
+    pub(crate) fn upload_gc(&self) -> Result<Gcontext> {
+        self.upload_gc
+            .get_or_try_init(|| self.create_gc(&GcConfig::new()))
+            .copied()
+    }
+
+    // Whatever the buffer starts out as is also what the fill GC paints with until configured
+    // otherwise, so server side fills and a fresh buffer agree
+    pub(crate) fn fill_gc(&self) -> Result<Gcontext> {
+        self.fill_gc
+            .get_or_try_init(|| {
+                self.create_gc(
+                    &GcConfig::new()
+                        .foreground(self.clear_color.clone())
+                        .background(self.clear_color.clone()),
+                )
+            })
+            .copied()
+    }
+
+    // Setters that replace the whole buffer note whether it came out a single color, so the
+    // next flush can fill the pixmap server side rather than upload every pixel of it
+    pub(crate) fn note_solid(&self, color: Option<Pixel>) {
+        *self.solid.lock().unwrap_or_else(|e| e.into_inner()) = color;
+    }
+
+    // The color `region` of the buffer still is all of, should a setter have noted one. Anything
+    // drawn since makes the scan stop at the first pixel that differs, and the note is dropped
+    pub(crate) fn solid_region(
+        &self,
+        (x, y, width, height): (u16, u16, u16, u16),
+    ) -> Option<Pixel> {
+        let mut solid = self.solid.lock().unwrap_or_else(|e| e.into_inner());
+        let color = solid.clone()?;
+        if self.layer.is_some() {
+            return None;
+        }
+
+        let buffer = self.lock_buffer();
+        let stride = buffer.width() as usize;
+        let still = (y as usize..(y + height) as usize).all(|row| {
+            let start = row * stride + x as usize;
+            buffer[start..start + width as usize]
+                .iter()
+                .all(|p| *p == color)
+        });
+
+        if !still {
+            *solid = None;
+            return None;
+        }
+        Some(color)
+    }
+
+    // Fills `region` of the pixmap with `color` the way an upload of it would have come out.
+    // Uploads ignore the GC's foreground, so the upload GC carries it
+    pub(crate) fn fill_solid(&self, color: &Pixel, region: (u16, u16, u16, u16)) -> Result<()> {
+        let pixel = match &self.palette {
+            Some(palette) => palette.index_of(color) as u32,
+            None => {
+                let mut color = Canvas::filled(1, 1, color.clone());
+                if self.target_depth() < self.depth {
+                    color.reduce_depth(self.target_depth());
+                }
+                pixel_value(&self.visual, &color[0])
+            }
+        };
+        let gc = self.upload_gc()?;
+        let (x, y, width, height) = region;
+
+        self.send_flush_request(&ChangeGc {
+            gc,
+            value_list: &[Gc::Foreground(pixel)],
+        })?;
+        self.send_flush_request(&PolyFillRectangle {
+            drawable: Drawable::Pixmap(self.background_pixmap),
+            gc,
+            rectangles: &[Rectangle {
+                x: x as i16,
+                y: y as i16,
+                width,
+                height,
+            }],
+        })
+    }
+
+    fn create_gc(&self, config: &GcConfig) -> Result<Gcontext> {
+        let cid = self.connection.generate_id();
+
+        void_request!(
+            &self.connection,
+            &CreateGc {
+                drawable: Drawable::Pixmap(self.background_pixmap),
+                cid,
+                value_list: &config.value_list(&self.visual),
+            }
+        )?;
+
+        info!("Allocated shade gc with id {:?}", cid);
+        Ok(cid)
+    }
+}
+
+// The connection is RetainPermanent so the pixmap outlives us, which would keep the GCs around
+// with it
+impl Drop for BackgroundHandle {
+    fn drop(&mut self) {
+        // The pixmap outlives the connection, but a later load may kill its owner from now on
+        registry::unregister(self.background_pixmap.resource_id());
+
+        for e in self.take_unsynced_errors() {
+            warn!("Unchecked request failed without being synced: {e}");
+        }
+
+        for gc in [self.upload_gc.get(), self.fill_gc.get()]
+            .into_iter()
+            .flatten()
+        {
+            if let Err(e) = void_request!(&self.connection, &FreeGc { gc: *gc }) {
+                warn!("Failed to free gc {:?}: {e}", gc);
+            }
+        }
+
+        // Retained like the GCs, it would otherwise pile up with every handle ever loaded
+        owner::release(&self.connection, self.owner_window);
+    }
+}
+
+// Fills all of a fresh `pixmap` with the raw pixel value `pixel`, through a GC of its own as the
+// handle's are not created yet
+pub(crate) fn clear_pixmap(
+    connection: &Connection,
+    pixmap: Pixmap,
+    (width, height): (u16, u16),
+    pixel: u32,
+) -> Result<()> {
+    let gc = connection.generate_id();
+
+    // Sent in one go and only checked afterwards, a single round trip for all three
+    let created = connection.send_request_checked(&CreateGc {
+        drawable: Drawable::Pixmap(pixmap),
+        cid: gc,
+        value_list: &[Gc::Foreground(pixel)],
+    });
+    let filled = connection.send_request_checked(&PolyFillRectangle {
+        drawable: Drawable::Pixmap(pixmap),
+        gc,
+        rectangles: &[Rectangle {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }],
+    });
+    let freed = connection.send_request_checked(&FreeGc { gc });
+
+    for cookie in [created, filled, freed] {
+        connection.check_request(cookie).map_err(xcb::Error::from)?;
+    }
+
+    Ok(())
+}
+
+// Place each 8 bit channel into the bits its mask covers on this visual
+pub(crate) fn pixel_value(visual: &Visualtype, pixel: &Pixel) -> u32 {
+    encode::pack(
+        pixel,
+        [visual.red_mask(), visual.green_mask(), visual.blue_mask()],
+    )
+}
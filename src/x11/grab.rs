@@ -0,0 +1,42 @@
+use xcb::{
+    x::{GrabServer, UngrabServer},
+    Connection,
+};
+
+use super::BackgroundHandle;
+use crate::Result;
+
+/// Keeps the X server grabbed for as long as it is alive, ungrabbing it when dropped.
+///
+/// While grabbed, the server processes requests from this connection only, so everything sent in
+/// between reaches the screen as one step. Every other client, the window manager and compositor
+/// included, is frozen meanwhile: hold the grab just long enough to send the batch, and never
+/// wait on another client while holding it, or both will wait forever.
+#[must_use = "the server is ungrabbed as soon as the guard is dropped"]
+pub struct ServerGrab<'a> {
+    connection: &'a Connection,
+}
+
+impl<'a> ServerGrab<'a> {
+    pub(crate) fn new(connection: &'a Connection) -> Result<ServerGrab<'a>> {
+        void_request!(connection, &GrabServer {})?;
+        Ok(ServerGrab { connection })
+    }
+}
+
+impl Drop for ServerGrab<'_> {
+    fn drop(&mut self) {
+        // Nothing sensible to do about a failure here, and a dead connection releases the grab
+        // anyway
+        if let Err(e) = void_request!(self.connection, &UngrabServer {}) {
+            warn!("Failed to ungrab the server: {e}");
+        }
+    }
+}
+
+impl BackgroundHandle {
+    /// Grabs the server until the returned guard is dropped, see [`ServerGrab`].
+    pub fn grab_server(&self) -> Result<ServerGrab<'_>> {
+        ServerGrab::new(&self.connection)
+    }
+}
@@ -0,0 +1,55 @@
+use std::sync::MutexGuard;
+
+use super::BackgroundHandle;
+use crate::{canvas::History, Result};
+
+impl BackgroundHandle {
+    fn lock_history(&self) -> MutexGuard<'_, History> {
+        self.history.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Keeps up to `depth` snapshots for [`BackgroundHandle::undo`]. History is off (a depth of 0)
+    /// until enabled here; lowering the depth drops the oldest snapshots.
+    pub fn set_history_depth(&self, depth: usize) {
+        self.lock_history().set_depth(depth);
+    }
+
+    /// Run-length encodes snapshots taken from now on, trading a little time for a lot of memory
+    /// on flat or generated wallpapers.
+    pub fn set_history_compression(&self, compress: bool) {
+        self.lock_history().compress = compress;
+    }
+
+    /// Number of snapshots [`BackgroundHandle::undo`] can currently step back through.
+    pub fn history_len(&self) -> usize {
+        self.lock_history().len()
+    }
+
+    /// Snapshots the current buffer. Does nothing while history is disabled.
+    ///
+    /// The oldest snapshots are dropped to make room in the
+    /// [memory budget](super::LoadConfig::memory_budget). Fails with
+    /// [`Error::MemoryBudgetExceeded`](crate::Error::MemoryBudgetExceeded) if the buffer does not
+    /// fit it even with all of them gone.
+    pub fn push_history(&self) -> Result<()> {
+        let buffer = self.lock_buffer();
+        self.lock_history().push(&buffer)
+    }
+
+    /// Restores and flushes the most recent snapshot. Returns `false` if there was none to go back
+    /// to.
+    pub fn undo(&self) -> Result<bool> {
+        let snapshot = self
+            .lock_history()
+            .pop(self.width as u32, self.height as u32);
+
+        match snapshot {
+            Some(canvas) => {
+                *self.lock_buffer() = canvas;
+                self.flush()?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
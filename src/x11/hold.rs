@@ -0,0 +1,73 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{atomic::Ordering, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use super::BackgroundHandle;
+use crate::Canvas;
+
+/// The buffer lock as taken by the handle itself, warning on release when it was held longer
+/// than [`BackgroundHandle::set_lock_warn_threshold`] allows.
+pub(crate) struct BufferGuard<'a> {
+    guard: MutexGuard<'a, Canvas>,
+    since: Instant,
+    threshold: Option<Duration>,
+}
+
+impl Deref for BufferGuard<'_> {
+    type Target = Canvas;
+
+    fn deref(&self) -> &Canvas {
+        &self.guard
+    }
+}
+
+impl DerefMut for BufferGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Canvas {
+        &mut self.guard
+    }
+}
+
+impl Drop for BufferGuard<'_> {
+    fn drop(&mut self) {
+        let Some(threshold) = self.threshold else {
+            return;
+        };
+
+        let held = self.since.elapsed();
+        if held > threshold {
+            warn!("Buffer lock held for {held:?}, over the threshold of {threshold:?}");
+        }
+    }
+}
+
+impl BackgroundHandle {
+    // A panic while drawing leaves at worst a half drawn frame, which is no reason to refuse
+    // further access to the buffer
+    pub(crate) fn lock_buffer(&self) -> BufferGuard<'_> {
+        let guard = self.buffer.lock().unwrap_or_else(|e| e.into_inner());
+
+        // 0 stands for "off"
+        let threshold = match self.lock_warn_threshold.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros)),
+        };
+
+        BufferGuard {
+            guard,
+            since: Instant::now(),
+            threshold,
+        }
+    }
+
+    /// Warns whenever the handle itself holds the buffer lock for longer than `threshold`,
+    /// `Duration::ZERO` (the default) turning the warnings off. Flushes only hold it while
+    /// copying out the region they upload, so anything tripping this blocks drawing threads for
+    /// longer than it should. Locks taken through [`BackgroundHandle::buffer`] directly are not
+    /// timed.
+    pub fn set_lock_warn_threshold(&self, threshold: Duration) {
+        let micros = threshold.as_micros().min(u64::MAX as u128) as u64;
+        self.lock_warn_threshold.store(micros, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use xcb::Xid;
+
+use super::{BackgroundHandle, Monitor};
+use crate::{Pixel, WallpaperSource};
+
+pub(crate) type AppliedHook = Arc<dyn Fn(&AppliedInfo) + Send + Sync>;
+
+/// Where a wallpaper passed to the [`on_applied`](BackgroundHandle::on_applied) hook came from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AppliedSource {
+    File(PathBuf),
+    Color(Pixel),
+    /// Anything else, such as decoded images, generators and downloads.
+    Other,
+}
+
+impl From<&WallpaperSource> for AppliedSource {
+    fn from(source: &WallpaperSource) -> AppliedSource {
+        match source {
+            WallpaperSource::File(path) => AppliedSource::File(path.clone()),
+            WallpaperSource::Color(color) => AppliedSource::Color(color.clone()),
+            _ => AppliedSource::Other,
+        }
+    }
+}
+
+/// What the [`on_applied`](BackgroundHandle::on_applied) hook is told about a wallpaper that
+/// just went up.
+pub struct AppliedInfo<'a> {
+    pub source: AppliedSource,
+    /// The pixmap showing it, as also set in `_XROOTPMAP_ID`.
+    pub pixmap: u32,
+    /// The monitors it was laid out on, all of them unless set on a single one.
+    pub monitors: Vec<Monitor>,
+    handle: &'a BackgroundHandle,
+}
+
+impl AppliedInfo<'_> {
+    /// The colors that cover most of the wallpaper, see [`Canvas::dominant_colors`]. Computed
+    /// from the buffer on every call, so hooks that don't ask don't pay for it.
+    ///
+    /// [`Canvas::dominant_colors`]: crate::Canvas::dominant_colors
+    pub fn dominant_colors(&self, count: usize) -> Vec<Pixel> {
+        let buffer = self.handle.lock_buffer();
+        match self.monitors.as_slice() {
+            [monitor] => {
+                let area = monitor.rect().intersect(&buffer.bounds());
+                buffer
+                    .crop(area.x as u32, area.y as u32, area.width, area.height)
+                    .dominant_colors(count)
+            }
+            _ => buffer.dominant_colors(count),
+        }
+    }
+}
+
+// A wallpaper that was set but not flushed yet
+#[derive(Debug)]
+pub(crate) struct PendingApplied {
+    source: AppliedSource,
+    monitor: Option<Monitor>,
+}
+
+impl BackgroundHandle {
+    /// Calls `hook` whenever a newly set wallpaper went up: after the first successful flush
+    /// following [`set_image`](BackgroundHandle::set_image),
+    /// [`set_source`](BackgroundHandle::set_source) and the like, e.g. to regenerate color
+    /// schemes or restart a bar. Drawing onto the buffer directly does not count as setting a
+    /// wallpaper. Replaces any hook set before.
+    ///
+    /// The hook runs on the flushing thread, keep it short or hand off to a thread of its own. A
+    /// panicking hook is logged and otherwise ignored, the handle carries on as before.
+    pub fn on_applied(&self, hook: impl Fn(&AppliedInfo) + Send + Sync + 'static) {
+        *lock(&self.applied_hook) = Some(Arc::new(hook));
+    }
+
+    /// Removes the hook set with [`BackgroundHandle::on_applied`].
+    pub fn clear_on_applied(&self) {
+        *lock(&self.applied_hook) = None;
+    }
+
+    // Called by everything that sets a wallpaper, for the next flush to report
+    pub(crate) fn note_applied(&self, source: AppliedSource, monitor: Option<&Monitor>) {
+        *lock(&self.pending_applied) = Some(PendingApplied {
+            source,
+            monitor: monitor.cloned(),
+        });
+    }
+
+    // Keeps intermediate flushes, such as the frames of a transition, from reporting the
+    // wallpaper, until it is put back
+    pub(crate) fn take_pending_applied(&self) -> Option<PendingApplied> {
+        lock(&self.pending_applied).take()
+    }
+
+    pub(crate) fn restore_pending_applied(&self, pending: Option<PendingApplied>) {
+        *lock(&self.pending_applied) = pending;
+    }
+
+    // Called after every successful upload, only the first one after a wallpaper was set has
+    // anything to report
+    pub(crate) fn run_applied_hook(&self) {
+        let Some(pending) = lock(&self.pending_applied).take() else {
+            return;
+        };
+        let Some(hook) = lock(&self.applied_hook).clone() else {
+            return;
+        };
+
+        let monitors = match pending.monitor {
+            Some(monitor) => vec![monitor],
+            None => self.monitors().unwrap_or_else(|e| {
+                warn!("Failed to list the monitors for the applied hook: {e}");
+                Vec::new()
+            }),
+        };
+        let info = AppliedInfo {
+            source: pending.source,
+            pixmap: self.background_pixmap.resource_id(),
+            monitors,
+            handle: self,
+        };
+
+        // Neither lock is held while it runs, a panic has nothing to poison
+        if panic::catch_unwind(AssertUnwindSafe(|| hook(&info))).is_err() {
+            warn!("The applied hook panicked");
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,156 @@
+use std::{sync::atomic::Ordering, time::Duration};
+
+use xcb::{
+    screensaver::QueryInfo,
+    x::{self, Drawable, GetProperty, InternAtom, ATOM_ATOM, ATOM_WINDOW},
+    Extension, Xid,
+};
+
+use super::BackgroundHandle;
+use crate::Result;
+
+/// When [`BackgroundHandle::should_pause`] considers the wallpaper not worth animating.
+#[derive(Clone, Debug)]
+pub struct PausePolicy {
+    pub(crate) fullscreen: bool,
+    pub(crate) idle_after: Option<Duration>,
+}
+
+impl Default for PausePolicy {
+    fn default() -> PausePolicy {
+        PausePolicy {
+            fullscreen: true,
+            idle_after: None,
+        }
+    }
+}
+
+impl PausePolicy {
+    pub fn new() -> PausePolicy {
+        PausePolicy::default()
+    }
+
+    /// Pause while the active window is fullscreen and so covers the wallpaper, the default.
+    pub fn fullscreen(mut self, pause: bool) -> PausePolicy {
+        self.fullscreen = pause;
+        self
+    }
+
+    /// Pause once the user has not touched keyboard or mouse for `after`. Needs the
+    /// MIT-SCREEN-SAVER extension, without it this never pauses.
+    pub fn idle_after(mut self, after: Duration) -> PausePolicy {
+        self.idle_after = Some(after);
+        self
+    }
+}
+
+impl BackgroundHandle {
+    /// Marks animations on this handle as paused, see [`BackgroundHandle::should_pause`]. Drawing
+    /// and flushing keep working, it is up to the animation loop to hold off.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Whether [`BackgroundHandle::pause`] was called without a matching resume.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Whether an animation should skip its next frames, because it was paused or `policy` finds
+    /// the wallpaper hidden or unwatched. Cheap enough to ask once per frame.
+    pub fn should_pause(&self, policy: &PausePolicy) -> Result<bool> {
+        if self.is_paused() {
+            return Ok(true);
+        }
+
+        if policy.fullscreen && self.fullscreen_active()? {
+            return Ok(true);
+        }
+
+        if let Some(after) = policy.idle_after {
+            return Ok(self.idle_time()?.is_some_and(|idle| idle >= after));
+        }
+
+        Ok(false)
+    }
+
+    /// Whether the window the window manager reports as active is fullscreen. Without an EWMH
+    /// compliant window manager nothing is ever active, and this is always `false`.
+    pub fn fullscreen_active(&self) -> Result<bool> {
+        let active = cookie_request!(
+            &self.connection,
+            &GetProperty {
+                r#type: ATOM_WINDOW,
+                delete: false,
+                window: self.root,
+                property: self.ewmh_atom(b"_NET_ACTIVE_WINDOW")?,
+                long_offset: 0,
+                long_length: 1,
+            }
+        )?;
+
+        let Some(&window) = active.value::<x::Window>().first() else {
+            return Ok(false);
+        };
+        if window.is_none() {
+            return Ok(false);
+        }
+
+        // The window may have been destroyed since, which leaves nothing fullscreen either
+        let state = self
+            .connection
+            .wait_for_reply(self.connection.send_request(&GetProperty {
+                r#type: ATOM_ATOM,
+                delete: false,
+                window,
+                property: self.ewmh_atom(b"_NET_WM_STATE")?,
+                long_offset: 0,
+                long_length: 32,
+            }));
+        let state = match state {
+            Ok(state) => state,
+            Err(xcb::Error::Protocol(_)) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+
+        let fullscreen = self.ewmh_atom(b"_NET_WM_STATE_FULLSCREEN")?;
+        Ok(state.value::<x::Atom>().contains(&fullscreen))
+    }
+
+    /// Time since the last keyboard or mouse input, `None` if the server lacks the
+    /// MIT-SCREEN-SAVER extension.
+    pub fn idle_time(&self) -> Result<Option<Duration>> {
+        if !self
+            .connection
+            .active_extensions()
+            .any(|e| e == Extension::ScreenSaver)
+        {
+            return Ok(None);
+        }
+
+        let info = cookie_request!(
+            &self.connection,
+            &QueryInfo {
+                drawable: Drawable::Window(self.root),
+            }
+        )?;
+        Ok(Some(Duration::from_millis(
+            info.ms_since_user_input() as u64
+        )))
+    }
+
+    fn ewmh_atom(&self, name: &[u8]) -> Result<x::Atom> {
+        Ok(cookie_request!(
+            &self.connection,
+            &InternAtom {
+                name,
+                only_if_exists: false,
+            }
+        )?
+        .atom())
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt::{self, Write};
+
+#[cfg(feature = "serde")]
+use serde::{
+    ser::{SerializeMap, SerializeSeq},
+    Serialize, Serializer,
+};
+
+/// A report as laid out for [`PROBE_SCHEMA`](super::PROBE_SCHEMA), built once and both written
+/// out by the `to_json` methods and serialized by serde, so the two always agree.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(u64),
+    /// Written as `null` unless finite, JSON has no NaN or infinity. serde_json does the same.
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Fields in the order they are written.
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+    /// A `[major, minor]` version, `null` if absent.
+    pub(crate) fn version<T: Into<u64>>(version: Option<(T, T)>) -> Json {
+        version.map_or(Json::Null, |(major, minor)| {
+            Json::Array(vec![Json::Number(major.into()), Json::Number(minor.into())])
+        })
+    }
+
+    pub(crate) fn numbers<T: Into<u64> + Copy>(numbers: &[T]) -> Json {
+        Json::Array(numbers.iter().map(|&n| Json::Number(n.into())).collect())
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list<T>(
+            f: &mut fmt::Formatter<'_>,
+            items: &[T],
+            mut item: impl FnMut(&mut fmt::Formatter<'_>, &T) -> fmt::Result,
+        ) -> fmt::Result {
+            for (i, value) in items.iter().enumerate() {
+                if i > 0 {
+                    f.write_char(',')?;
+                }
+                item(f, value)?;
+            }
+            Ok(())
+        }
+
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(value) => write!(f, "{value}"),
+            Json::Number(value) => write!(f, "{value}"),
+            Json::Float(value) if value.is_finite() => write!(f, "{value}"),
+            Json::Float(_) => f.write_str("null"),
+            Json::String(text) => write!(f, "\"{}\"", escape(text)),
+            Json::Array(items) => {
+                f.write_char('[')?;
+                list(f, items, |f, item| write!(f, "{item}"))?;
+                f.write_char(']')
+            }
+            Json::Object(fields) => {
+                f.write_char('{')?;
+                list(f, fields, |f, (name, value)| {
+                    write!(f, "\"{name}\":{value}")
+                })?;
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Json {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            Json::Null => serializer.serialize_none(),
+            Json::Bool(value) => serializer.serialize_bool(*value),
+            Json::Number(value) => serializer.serialize_u64(*value),
+            Json::Float(value) if value.is_finite() => serializer.serialize_f64(*value),
+            Json::Float(_) => serializer.serialize_none(),
+            Json::String(text) => serializer.serialize_str(text),
+            Json::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            Json::Object(fields) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (name, value) in fields {
+                    map.serialize_entry(name, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+// The vendor string and the self test's hypotheses are the only free text
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,22 @@
+use std::sync::MutexGuard;
+
+use super::BackgroundHandle;
+use crate::{Canvas, Error, Result, Rgba8};
+
+impl BackgroundHandle {
+    /// The RGBA layer of a handle loaded with [`LoadConfig::rgba`](super::LoadConfig::rgba),
+    /// screen sized and transparent to begin with. Every flush composites it over the part of the
+    /// buffer it uploads; the buffer itself, and with it snapshots, history and captures, never
+    /// sees the layer. Flush after drawing to it, just like for the buffer.
+    ///
+    /// Fails with [`Error::NoRgbaLayer`] for handles loaded without one.
+    pub fn layer(&self) -> Result<MutexGuard<'_, Canvas<Rgba8>>> {
+        self.lock_layer().ok_or(Error::NoRgbaLayer)
+    }
+
+    // Always taken after the buffer lock where both are held
+    pub(crate) fn lock_layer(&self) -> Option<MutexGuard<'_, Canvas<Rgba8>>> {
+        let layer = self.layer.as_ref()?;
+        Some(layer.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use once_cell::sync::OnceCell;
+
+use super::{inner_load, BackgroundHandle, LoadConfig, OpenMethod};
+use crate::{Error, Result, ScalingMethod};
+
+// OpenMethod borrows its path, which would tie the handle to the caller's stack and keep it from
+// being shared between threads
+enum OwnedOpenMethod {
+    KeepExisting,
+    MakeNew,
+    LoadFromFile(ScalingMethod, PathBuf),
+}
+
+impl OwnedOpenMethod {
+    fn as_open_method(&self) -> OpenMethod<'_> {
+        match self {
+            OwnedOpenMethod::KeepExisting => OpenMethod::KeepExisting,
+            OwnedOpenMethod::MakeNew => OpenMethod::MakeNew,
+            OwnedOpenMethod::LoadFromFile(method, path) => OpenMethod::LoadFromFile(*method, path),
+        }
+    }
+}
+
+impl From<OpenMethod<'_>> for OwnedOpenMethod {
+    fn from(method: OpenMethod) -> OwnedOpenMethod {
+        match method {
+            OpenMethod::KeepExisting => OwnedOpenMethod::KeepExisting,
+            OpenMethod::MakeNew => OwnedOpenMethod::MakeNew,
+            OpenMethod::LoadFromFile(method, path) => {
+                OwnedOpenMethod::LoadFromFile(method, path.as_ref().to_path_buf())
+            }
+        }
+    }
+}
+
+/// A [`BackgroundHandle`] that connects to X only once it is first used.
+///
+/// Whichever thread uses it first does the loading while any other waits for it, and the outcome
+/// is kept: if loading failed, every later call fails with [`Error::LoadFailed`] wrapping that
+/// same error, without trying again.
+///
+/// This handle is independent of the one [`load`](super::load) hands out, only use one of the two.
+pub struct LazyHandle {
+    options: Mutex<Option<(OwnedOpenMethod, LoadConfig)>>,
+    handle: OnceCell<std::result::Result<BackgroundHandle, Arc<Error>>>,
+}
+
+impl BackgroundHandle {
+    pub fn lazy(options: OpenMethod) -> LazyHandle {
+        BackgroundHandle::lazy_with(options, LoadConfig::default())
+    }
+
+    pub fn lazy_with(options: OpenMethod, config: LoadConfig) -> LazyHandle {
+        LazyHandle {
+            options: Mutex::new(Some((options.into(), config))),
+            handle: OnceCell::new(),
+        }
+    }
+}
+
+impl LazyHandle {
+    /// The loaded handle, loading it now if this is the first use.
+    pub fn get(&self) -> Result<&BackgroundHandle> {
+        let handle = self.handle.get_or_init(|| {
+            // The cell runs this at most once, so the options are always still there
+            let (options, config) = self
+                .options
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .take()
+                .expect("lazy handle initialized twice");
+
+            inner_load(options.as_open_method(), config).map_err(Arc::new)
+        });
+
+        handle
+            .as_ref()
+            .map_err(|e| Error::LoadFailed(Arc::clone(e)))
+    }
+
+    /// Whether the handle has been loaded (or failed to) yet.
+    pub fn is_loaded(&self) -> bool {
+        self.handle.get().is_some()
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        self.get()?.flush()
+    }
+
+    pub fn set_image(&self, path: impl AsRef<Path>, method: ScalingMethod) -> Result<()> {
+        self.get()?.set_image(path, method)
+    }
+}
@@ -0,0 +1,25 @@
+use super::BackgroundHandle;
+use crate::{MemoryBudget, MemoryReport, Pixel, Rgba8};
+
+impl BackgroundHandle {
+    /// What the handle's snapshots, undo history, image cache and previous wallpaper take, along
+    /// with its buffer. Handles sharing a [budget](super::LoadConfig::memory_budget) report what
+    /// all of them take together, apart from the buffer.
+    pub fn memory_usage(&self) -> MemoryReport {
+        let buffer = self.lock_buffer().len() * std::mem::size_of::<Pixel>();
+        let layer = self
+            .lock_layer()
+            .map_or(0, |layer| layer.len() * std::mem::size_of::<Rgba8>());
+
+        MemoryReport {
+            buffer: buffer + layer,
+            ..self.memory_budget.report()
+        }
+    }
+
+    /// The budget the handle was loaded with, unlimited unless
+    /// [`LoadConfig::memory_budget`](super::LoadConfig::memory_budget) set one.
+    pub fn memory_budget(&self) -> &MemoryBudget {
+        &self.memory_budget
+    }
+}
@@ -0,0 +1,341 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use xcb::{
+    x::{
+        Atom, ChangeProperty, DeleteProperty, GetProperty, InternAtom, PropMode, Window, ATOM_ANY,
+        ATOM_CARDINAL, ATOM_NONE, ATOM_PIXMAP, ATOM_STRING,
+    },
+    Connection, VoidCookieChecked, Xid,
+};
+
+use super::{atoms::resolve_atom, connect, screen, BackgroundHandle, LoadReport};
+use crate::{Result, Scaling, ScalingMethod};
+
+// Long enough for any sane path, in the 4 byte units GetProperty counts in
+const MAX_PROPERTY_LENGTH: u32 = 4096;
+
+/// What shade last put up, as tagged on the root window for other programs to read back with
+/// [`current_metadata`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    /// Absolute path of the image file, `None` for wallpapers that did not come from one, such as
+    /// colors and downloads.
+    pub path: Option<PathBuf>,
+    /// When the image was set, to the second.
+    pub set_at: Option<SystemTime>,
+    pub scaling: Option<Scaling>,
+}
+
+// The atoms of the root properties the metadata is stored in. `_SHADE_PIXMAP` names the pixmap
+// the rest describes, as anything else replacing the wallpaper leaves them behind untouched
+pub(crate) struct MetadataAtoms {
+    utf8_string: Atom,
+    image_path: Atom,
+    set_at: Atom,
+    scaling: Atom,
+    pixmap: Atom,
+    xroot_pmap: Atom,
+    esetroot_pmap: Atom,
+}
+
+impl MetadataAtoms {
+    // With `only_if_exists`, atoms that were never created come back as ATOM_NONE. All are
+    // asked for before waiting on the first, one round trip for the lot
+    fn intern(connection: &Connection, only_if_exists: bool) -> Result<MetadataAtoms> {
+        let names: [&[u8]; 7] = [
+            b"UTF8_STRING",
+            b"_SHADE_IMAGE_PATH",
+            b"_SHADE_SET_AT",
+            b"_SHADE_SCALING",
+            b"_SHADE_PIXMAP",
+            b"_XROOTPMAP_ID",
+            b"ESETROOT_PMAP_ID",
+        ];
+        let cookies = names.map(|name| {
+            connection.send_request(&InternAtom {
+                only_if_exists,
+                name,
+            })
+        });
+
+        let mut atoms = [ATOM_NONE; 7];
+        for (atom, cookie) in atoms.iter_mut().zip(cookies) {
+            *atom = connection.wait_for_reply(cookie)?.atom();
+        }
+
+        let [utf8_string, image_path, set_at, scaling, pixmap, xroot_pmap, esetroot_pmap] = atoms;
+        Ok(MetadataAtoms {
+            utf8_string,
+            image_path,
+            set_at,
+            scaling,
+            pixmap,
+            xroot_pmap,
+            esetroot_pmap,
+        })
+    }
+
+    fn properties(&self) -> [Atom; 4] {
+        [self.image_path, self.set_at, self.scaling, self.pixmap]
+    }
+}
+
+impl BackgroundHandle {
+    fn metadata_atoms(&self) -> Result<&MetadataAtoms> {
+        self.metadata_atoms
+            .get_or_try_init(|| MetadataAtoms::intern(&self.connection, false))
+    }
+
+    // Called by everything that replaces the whole buffer, for the next flush to write once the
+    // wallpaper it describes is up
+    pub(crate) fn tag_metadata(&self, path: Option<&Path>, scaling: &Scaling) {
+        if !self.tag_metadata {
+            return;
+        }
+
+        // Made absolute now, the working directory may well have changed by the flush
+        let path = path.map(|p| std::path::absolute(p).unwrap_or_else(|_| p.to_owned()));
+        *lock(&self.pending_metadata) = Some(PendingMetadata {
+            path,
+            scaling: scaling.clone(),
+        });
+    }
+
+    // Keeps intermediate flushes, such as the frames of a transition, from tagging the wallpaper
+    // before it is up, until it is put back
+    pub(crate) fn take_pending_metadata(&self) -> Option<PendingMetadata> {
+        lock(&self.pending_metadata).take()
+    }
+
+    pub(crate) fn restore_pending_metadata(&self, pending: Option<PendingMetadata>) {
+        *lock(&self.pending_metadata) = pending;
+    }
+
+    // Called after every successful flush, only the first one after a wallpaper was set has
+    // anything to write. Failing to tag is no reason to fail the flush, so it is only logged
+    pub(crate) fn write_pending_metadata(&self) {
+        let Some(pending) = self.take_pending_metadata() else {
+            return;
+        };
+
+        if let Err(e) = self.write_metadata(&pending) {
+            warn!("Failed to tag the wallpaper with its metadata: {e}");
+        }
+    }
+
+    // Every property is sent before any is checked, one round trip for the lot
+    fn write_metadata(&self, pending: &PendingMetadata) -> Result<()> {
+        let atoms = self.metadata_atoms()?;
+        let mut cookies = Vec::with_capacity(4);
+
+        match pending.path.as_deref().map(|p| (p, p.to_str())) {
+            Some((_, Some(path))) => cookies.push(self.change_property(
+                atoms.image_path,
+                atoms.utf8_string,
+                path.as_bytes(),
+            )),
+            // UTF8_STRING has to be valid UTF-8, and a lossy path is worse than none
+            Some((path, None)) => {
+                warn!("Not tagging {path:?}, it is not valid UTF-8");
+                cookies.push(self.delete_property(atoms.image_path));
+            }
+            None => cookies.push(self.delete_property(atoms.image_path)),
+        }
+
+        // Seconds since the epoch fit a CARDINAL until 2106
+        let set_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs().min(u32::MAX as u64) as u32);
+        cookies.push(self.change_property(atoms.set_at, ATOM_CARDINAL, &[set_at]));
+
+        cookies.push(self.change_property(
+            atoms.scaling,
+            atoms.utf8_string,
+            scaling_name(&pending.scaling).as_bytes(),
+        ));
+
+        cookies.push(self.change_property(
+            atoms.pixmap,
+            ATOM_PIXMAP,
+            &[self.background_pixmap.resource_id()],
+        ));
+
+        self.check_all(cookies)
+    }
+
+    /// Removes the properties describing the wallpaper from the root window. They are otherwise
+    /// left in place when the handle goes away, just like the wallpaper they describe.
+    pub fn clear_metadata(&self) -> Result<()> {
+        // A tag still waiting for its flush would otherwise bring them back
+        self.take_pending_metadata();
+
+        let atoms = self.metadata_atoms()?;
+        let cookies = atoms
+            .properties()
+            .map(|property| self.delete_property(property));
+        self.check_all(cookies)
+    }
+
+    fn change_property<P: xcb::x::PropEl>(
+        &self,
+        property: Atom,
+        r#type: Atom,
+        data: &[P],
+    ) -> VoidCookieChecked {
+        self.connection.send_request_checked(&ChangeProperty {
+            mode: PropMode::Replace,
+            window: self.root,
+            property,
+            r#type,
+            data,
+        })
+    }
+
+    fn delete_property(&self, property: Atom) -> VoidCookieChecked {
+        self.connection.send_request_checked(&DeleteProperty {
+            window: self.root,
+            property,
+        })
+    }
+
+    // The first check waits for the server to get through all of them, the rest are answered
+    // by then. Every cookie is checked, the first error is returned
+    fn check_all(&self, cookies: impl IntoIterator<Item = VoidCookieChecked>) -> Result<()> {
+        let mut result = Ok(());
+        for cookie in cookies {
+            if let Err(e) = self.connection.check_request(cookie) {
+                result = result.and(Err(xcb::Error::from(e).into()));
+            }
+        }
+        result
+    }
+}
+
+// What the next flush tags the wallpaper with, see BackgroundHandle::tag_metadata
+#[derive(Debug)]
+pub(crate) struct PendingMetadata {
+    path: Option<PathBuf>,
+    scaling: Scaling,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Reads back what shade last put up on the default screen, over a connection of its own.
+///
+/// `None` if shade never tagged the root window, or if another program has replaced the wallpaper
+/// since. Properties that are missing or malformed are `None` on their own, the rest still being
+/// read.
+pub fn current_metadata() -> Result<Option<Metadata>> {
+    let (connection, screen_number) = connect()?;
+    let root = screen(connection.get_setup(), screen_number)?.root();
+
+    let atoms = MetadataAtoms::intern(&connection, true)?;
+    if atoms.pixmap == ATOM_NONE {
+        return Ok(None);
+    }
+
+    // The tags only describe the wallpaper they were written along with. Handles loaded with
+    // LoadConfig::root_properties may have set either property alone
+    let tagged = read_property(&connection, root, atoms.pixmap)?
+        .filter(|p| p.r#type() == ATOM_PIXMAP && p.format() == 32)
+        .and_then(|p| p.value::<u32>().first().copied());
+    let mut current = Vec::with_capacity(2);
+    for property in [
+        ("_XROOTPMAP_ID", atoms.xroot_pmap),
+        ("ESETROOT_PMAP_ID", atoms.esetroot_pmap),
+    ] {
+        current.extend(resolve_atom(
+            &connection,
+            root,
+            property,
+            &mut LoadReport::default(),
+        )?);
+    }
+    if !tagged.is_some_and(|tagged| current.contains(&tagged)) {
+        return Ok(None);
+    }
+
+    let string = |property: Atom| -> Result<Option<String>> {
+        Ok(read_property(&connection, root, property)?
+            .filter(|p| p.format() == 8 && [atoms.utf8_string, ATOM_STRING].contains(&p.r#type()))
+            .and_then(|p| String::from_utf8(p.value::<u8>().to_vec()).ok()))
+    };
+
+    let set_at = read_property(&connection, root, atoms.set_at)?
+        .filter(|p| p.r#type() == ATOM_CARDINAL && p.format() == 32)
+        .and_then(|p| p.value::<u32>().first().copied())
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs as u64));
+
+    Ok(Some(Metadata {
+        path: string(atoms.image_path)?.map(PathBuf::from),
+        set_at,
+        scaling: string(atoms.scaling)?.as_deref().and_then(parse_scaling),
+    }))
+}
+
+// `None` for atoms that were never created as well as for properties that are not set
+fn read_property(
+    connection: &Connection,
+    root: Window,
+    property: Atom,
+) -> Result<Option<xcb::x::GetPropertyReply>> {
+    if property == ATOM_NONE {
+        return Ok(None);
+    }
+
+    let reply = cookie_request!(
+        connection,
+        &GetProperty {
+            delete: false,
+            window: root,
+            property,
+            r#type: ATOM_ANY,
+            long_offset: 0,
+            long_length: MAX_PROPERTY_LENGTH,
+        }
+    )?;
+
+    Ok((reply.r#type() != ATOM_NONE).then_some(reply))
+}
+
+// E.g. "fill", or "fill linear" when resampling in linear light
+fn scaling_name(scaling: &Scaling) -> String {
+    let method = match scaling.method {
+        ScalingMethod::Center => "center",
+        ScalingMethod::Fill => "fill",
+        ScalingMethod::Max => "max",
+        ScalingMethod::Scale => "scale",
+        ScalingMethod::Tile => "tile",
+    };
+
+    match scaling.linear_light {
+        true => format!("{method} linear"),
+        false => method.to_owned(),
+    }
+}
+
+fn parse_scaling(name: &str) -> Option<Scaling> {
+    let mut words = name.split_whitespace();
+
+    let method = match words.next()? {
+        "center" => ScalingMethod::Center,
+        "fill" => ScalingMethod::Fill,
+        "max" => ScalingMethod::Max,
+        "scale" => ScalingMethod::Scale,
+        "tile" => ScalingMethod::Tile,
+        _ => return None,
+    };
+    let linear_light = match words.next() {
+        Some("linear") => true,
+        None => false,
+        Some(_) => return None,
+    };
+
+    Some(Scaling::new(method).linear_light(linear_light))
+}
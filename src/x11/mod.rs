@@ -0,0 +1,885 @@
+use image::{GrayImage, RgbImage};
+use once_cell::sync::OnceCell;
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicU8},
+        Mutex,
+    },
+};
+
+use xcb::{
+    x::{
+        ChangeWindowAttributes, ClearArea, CloseDown::RetainPermanent, CreatePixmap, Cw, Drawable,
+        Gcontext, GetGeometry, GetGeometryCookie, Pixmap, Screen, SetCloseDownMode, Setup,
+        Visualtype, Window, ATOM_NONE,
+    },
+    Connection, Extension, Xid,
+};
+
+use crate::{
+    canvas::{History, ImageCache, Reservation},
+    encode::{ServerFormat, PUT_IMAGE_HEADER_BYTES},
+    Canvas, Error, FilterChain, FrameRecorder, MemoryBudget, Pixel, Result, Rgba8, ScalingMethod,
+    WallpaperSource,
+};
+
+// Send a request without reply, check it, and return the error converted into an xcb::Error if
+// there is one
+macro_rules! void_request {
+    ($connection: expr, $request:expr ) => {
+        xcb::Connection::send_and_check_request($connection, $request).map_err(xcb::Error::from)
+    };
+}
+
+// Send a request with reply and wait for it, check it, and return the error converted into an
+// xcb::Error if there is one
+macro_rules! cookie_request {
+    ($connection: expr, $request:expr) => {{
+        let cookie = xcb::Connection::send_request($connection, $request);
+        xcb::Connection::wait_for_reply($connection, cookie)
+    }};
+}
+
+mod applier;
+mod atoms;
+mod cache;
+mod capabilities;
+mod capture;
+mod compose;
+mod compositor;
+mod config;
+mod depth;
+mod desktop;
+mod encode;
+mod events;
+mod gamma;
+mod gc;
+mod grab;
+mod history;
+mod hold;
+mod hook;
+mod idle;
+mod json;
+mod layer;
+mod lazy;
+mod memory;
+mod metadata;
+mod monitors;
+mod owner;
+mod plan;
+mod preview;
+mod previous;
+mod pseudo;
+mod registry;
+mod report;
+mod resources;
+mod screens;
+mod selftest;
+mod snapshot;
+mod sync;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod throttle;
+mod visual;
+
+use atoms::{intern_atoms, kill_pmap_atoms, set_pmap_atoms};
+use cache::DEFAULT_CACHE_MEGABYTES;
+use encode::{bands, encode_region};
+use owner::OwnerMarker;
+use pseudo::PaletteMap;
+use report::PhaseTimer;
+use resources::{Resource, ResourceGuard};
+use sync::Unchecked;
+use throttle::Throttle;
+
+pub use applier::{Applier, ApplyOutcome, Completion, Shutdown, Transition};
+pub use atoms::{parse_pixmap_property, PixmapProperty, RootProperties};
+pub use capabilities::{capabilities, Capabilities, ProbeReport, PROBE_SCHEMA};
+pub use compose::{PlanEntry, PlanTarget, WallpaperPlan};
+pub use compositor::compositor_active;
+pub use config::LoadConfig;
+pub use desktop::DesktopWallpapers;
+pub use events::ShadeEvent;
+pub use gamma::GammaReport;
+pub use gc::{FillStyle, GcConfig, Gx};
+pub use grab::ServerGrab;
+pub use hook::{AppliedInfo, AppliedSource};
+pub use idle::PausePolicy;
+pub use lazy::LazyHandle;
+pub use metadata::{current_metadata, Metadata};
+pub use monitors::{Monitor, MonitorAspect, Span};
+pub use plan::{plan, plan_with, LoadPlan};
+pub use preview::preview;
+pub use previous::PreviousWallpaper;
+pub use report::{LoadReport, LoadWarning};
+pub use screens::{screens, ScreenInfo};
+pub use selftest::{self_test, SelfTestReport};
+pub use snapshot::Snapshot;
+pub use sync::{ClearPolicy, FlushMode, FlushOptions};
+pub use visual::{probe_visuals, VisualClass, VisualInfo};
+
+pub enum OpenMethod<'a> {
+    KeepExisting,
+    MakeNew,
+    LoadFromFile(ScalingMethod, &'a dyn AsRef<Path>),
+}
+
+pub struct BackgroundHandle {
+    pub(crate) upload_gc: OnceCell<Gcontext>,
+    pub(crate) fill_gc: OnceCell<Gcontext>,
+    pub(crate) metadata_atoms: OnceCell<metadata::MetadataAtoms>,
+    pub(crate) tag_metadata: bool,
+    pub(crate) compositor: OnceCell<bool>,
+    pub(crate) events_selected: OnceCell<()>,
+    pub(crate) report: LoadReport,
+    pub(crate) clear_color: Pixel,
+    pub(crate) background_pixmap: Pixmap,
+    // Owns the selection marking the wallpaper as ours, destroyed along with the handle
+    pub(crate) owner_window: Window,
+    pub(crate) connection: Connection,
+    pub(crate) root: Window,
+    pub(crate) screen_number: i32,
+    pub(crate) width: u16,
+    pub(crate) height: u16,
+    pub(crate) depth: u8,
+    pub(crate) visual: Visualtype,
+    pub(crate) visual_info: VisualInfo,
+    // How the server lays out ZPixmap data at the root's depth, which every upload is encoded in
+    pub(crate) format: ServerFormat,
+    pub(crate) max_request_bytes: usize,
+    pub(crate) target_depth: AtomicU8,
+    pub(crate) lock_warn_threshold: AtomicU64,
+    pub(crate) max_image_pixels: AtomicU64,
+    pub(crate) paused: AtomicBool,
+    pub(crate) previous: Option<previous::PreviousCopy>,
+    // Counts the previous wallpaper against the budget for as long as the handle keeps it
+    pub(crate) _previous_memory: Option<Reservation>,
+    pub(crate) memory_budget: MemoryBudget,
+    pub(crate) root_properties: RootProperties,
+    pub(crate) palette: Option<PaletteMap>,
+    #[cfg(feature = "testing")]
+    pub(crate) put_images: Mutex<Vec<testing::PutImageRecord>>,
+    pub(crate) cache: Mutex<ImageCache>,
+    pub(crate) history: Mutex<History>,
+    pub(crate) throttle: Mutex<Throttle>,
+    pub(crate) unchecked: Mutex<Unchecked>,
+    pub(crate) applied_hook: Mutex<Option<hook::AppliedHook>>,
+    pub(crate) pending_applied: Mutex<Option<hook::PendingApplied>>,
+    pub(crate) pending_metadata: Mutex<Option<metadata::PendingMetadata>>,
+    pub(crate) monitor_aspects: Mutex<Vec<(String, monitors::MonitorAspect)>>,
+    pub(crate) plan_renderings: Mutex<Vec<compose::PlanRendering>>,
+    pub(crate) layer: Option<Mutex<Canvas<Rgba8>>>,
+    pub(crate) solid: Mutex<Option<Pixel>>,
+    /// The pixels flushes upload, to draw into directly.
+    ///
+    /// Used to be a `Mutex<Box<[Pixel]>>`. A [`Canvas`] dereferences to its `[Pixel]` slice, so
+    /// locking it and indexing, iterating or copying into the pixels works as it did, but
+    /// replacing the whole box does not: use [`BackgroundHandle::replace_pixels`] for that.
+    pub buffer: Mutex<Canvas>,
+}
+
+impl BackgroundHandle {
+    /// Uploads the buffer to the pixmap and redraws the root window with it.
+    ///
+    /// Fails with [`Error::GeometryChanged`], sending nothing, once the screen was resized since
+    /// the handle was loaded: the pixmap and buffer keep the old size, so what would go up is no
+    /// longer the wallpaper the screen needs. Handles never change size, load a new one.
+    pub fn flush(&self) -> Result<()> {
+        self.flush_with(FlushOptions::default())
+    }
+
+    /// [`BackgroundHandle::flush`] with further options.
+    pub fn flush_with(&self, options: FlushOptions) -> Result<()> {
+        self.flush_region_with(0, 0, self.width, self.height, options)
+    }
+
+    /// Uploads only the given rectangle of the buffer, clipped to the screen, and redraws it on
+    /// the root window. Much cheaper than a full flush for small, frequent updates.
+    ///
+    /// Subject to [`BackgroundHandle::set_max_upload_rate`] and
+    /// [`BackgroundHandle::set_min_flush_interval`], both off by default. Should the upload fail
+    /// partway, [`Error::FlushIncomplete`] says how far it got and the next flush sends the rest
+    /// along with whatever it is asked to. A resized screen fails like
+    /// [`BackgroundHandle::flush`] does.
+    pub fn flush_region(&self, x: u16, y: u16, width: u16, height: u16) -> Result<()> {
+        self.flush_region_with(x, y, width, height, FlushOptions::default())
+    }
+
+    /// [`BackgroundHandle::flush_region`] with further options. Flushes held back by the minimum
+    /// flush interval are merged into the next one and repaint as that one says.
+    pub fn flush_region_with(
+        &self,
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+        options: FlushOptions,
+    ) -> Result<()> {
+        let width = width.min(self.width.saturating_sub(x));
+        let height = height.min(self.height.saturating_sub(y));
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        match self.lock_throttle().admit((x, y, width, height)) {
+            Some((x, y, width, height)) => self.upload_region(x, y, width, height, options.clear),
+            None => Ok(()),
+        }
+    }
+
+    /// Repaints the whole root window from the pixmap, showing whatever flushes with
+    /// [`ClearPolicy::None`] uploaded.
+    pub fn clear_root(&self) -> Result<()> {
+        self.clear_area((0, 0, self.width, self.height))?;
+        self.connection.flush().map_err(xcb::Error::from)?;
+
+        self.write_pending_metadata();
+        Ok(())
+    }
+
+    // The root only repaints its background when told to, without a compositor nothing would
+    // show until something else exposes it
+    fn clear_area(&self, (x, y, width, height): (u16, u16, u16, u16)) -> Result<()> {
+        self.send_flush_request(&ClearArea {
+            exposures: false,
+            window: self.root,
+            x: x as i16,
+            y: y as i16,
+            width,
+            height,
+        })
+    }
+
+    pub(crate) fn upload_region(
+        &self,
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+        clear: ClearPolicy,
+    ) -> Result<()> {
+        let gc = self.upload_gc()?;
+
+        // Answered while the region is encoded, noticing a resized screen costs no round trip of
+        // its own
+        let geometry = self.connection.send_request(&GetGeometry {
+            drawable: Drawable::Window(self.root),
+        });
+
+        // A buffer set to a single color is filled server side, not a pixel of it is sent
+        if let Some(color) = self.solid_region((x, y, width, height)) {
+            self.check_geometry(geometry)?;
+            self.fill_solid(&color, (x, y, width, height))?;
+        } else {
+            self.put_region(gc, geometry, (x, y, width, height))?;
+        }
+
+        match clear {
+            ClearPolicy::Full => self.clear_area((0, 0, self.width, self.height))?,
+            ClearPolicy::Region => self.clear_area((x, y, width, height))?,
+            ClearPolicy::None => {}
+        }
+
+        // Unchecked requests may still sit in xcb's output buffer
+        self.connection.flush().map_err(xcb::Error::from)?;
+
+        // Tagged first, so the hook can already read the metadata back
+        self.write_pending_metadata();
+        self.run_applied_hook();
+        Ok(())
+    }
+
+    // Encodes `region` of the buffer and uploads it in as many PutImage requests as it takes
+    fn put_region(
+        &self,
+        gc: Gcontext,
+        geometry: GetGeometryCookie,
+        (x, y, width, height): (u16, u16, u16, u16),
+    ) -> Result<()> {
+        // Only copying the region out happens under the lock, drawing threads never wait on the
+        // upload itself, let alone a rate limited one sleeping between chunks
+        let format = self.wire_format();
+        let data = {
+            let buffer = self.lock_buffer();
+            match self.lock_layer() {
+                // The layer only ever goes into what is sent, never into the buffer
+                Some(layer) => {
+                    let mut region = buffer.crop(x as u32, y as u32, width as u32, height as u32);
+                    region.composite(&layer, -(x as i32), -(y as i32));
+                    encode_region(
+                        &region,
+                        (0, 0, width, height),
+                        &format,
+                        self.target_depth(),
+                        self.palette.as_ref(),
+                    )
+                }
+                None => encode_region(
+                    &buffer,
+                    (x, y, width, height),
+                    &format,
+                    self.target_depth(),
+                    self.palette.as_ref(),
+                ),
+            }
+        };
+        self.check_geometry(geometry)?;
+
+        let chunk_bytes = self.lock_throttle().chunk_bytes();
+
+        // Requests larger than the server takes are split, down to runs of a few pixels on
+        // servers with tiny limits. A rate limit too tight for even that falls back to the
+        // largest requests the server takes, so throttling never changes what succeeds
+        let limit = self.max_request_bytes - PUT_IMAGE_HEADER_BYTES;
+        let region = (x, y, width, height);
+        let bands = bands(
+            &data,
+            &format,
+            region,
+            chunk_bytes.unwrap_or(limit).min(limit),
+        )
+        .or_else(|| bands(&data, &format, region, limit))
+        .ok_or(Error::ImageTooLargeForRequest {
+            bytes: data.len(),
+            max: limit,
+        })?;
+
+        for band in bands {
+            let wait = self.lock_throttle().reserve(band.data.len());
+            std::thread::sleep(wait);
+
+            let request = band.put_image(Drawable::Pixmap(self.background_pixmap), gc, self.depth);
+            #[cfg(feature = "testing")]
+            self.record_put_image(&request);
+
+            // The rows above made it, the pixmap is inconsistent from this band on until the
+            // next flush sends them again
+            if let Err(e) = self.send_flush_request_retrying(&request) {
+                let top = band.dst_y as u16;
+                let done = top - y;
+                self.lock_throttle().defer((x, top, width, height - done));
+
+                return Err(Error::FlushIncomplete {
+                    updated_rows: done,
+                    rows: height,
+                    source: Box::new(e),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Fails once the screen no longer is the size the handle was loaded for, see flush
+    fn check_geometry(&self, cookie: GetGeometryCookie) -> Result<()> {
+        let geometry = self.connection.wait_for_reply(cookie)?;
+        let now = (geometry.width(), geometry.height());
+        let kept = (self.width, self.height);
+        if now != kept {
+            return Err(Error::GeometryChanged { kept, now });
+        }
+        Ok(())
+    }
+
+    // The layout encode_region produces for this handle
+    pub(crate) fn wire_format(&self) -> ServerFormat {
+        match &self.palette {
+            Some(palette) => palette.format(self.depth),
+            None => self.format,
+        }
+    }
+
+    /// Copies the buffer out as tightly packed RGB bytes, see [`Canvas::to_raw_rgb`]. The result
+    /// is `width * height * 3` bytes long, ready for e.g. `ffmpeg -f rawvideo -pix_fmt rgb24`.
+    pub fn to_raw_rgb(&self) -> Vec<u8> {
+        self.lock_buffer().to_raw_rgb()
+    }
+
+    /// Swaps the buffer's pixels for `pixels`, row-major and exactly as many as the screen has,
+    /// and returns the old ones. What assigning to the locked [`BackgroundHandle::buffer`] did
+    /// while it was a plain `Box<[Pixel]>`. Fails with [`Error::PixelCountMismatch`], leaving
+    /// the buffer alone, if the length is off.
+    pub fn replace_pixels(&self, pixels: Box<[Pixel]>) -> Result<Box<[Pixel]>> {
+        let expected = self.width as usize * self.height as usize;
+        if pixels.len() != expected {
+            return Err(Error::PixelCountMismatch {
+                expected,
+                found: pixels.len(),
+            });
+        }
+
+        let mut buffer = self.lock_buffer();
+        Ok(std::mem::replace(&mut buffer.pixels, pixels))
+    }
+
+    /// Hands the current buffer to `recorder` as its next frame. The buffer is copied out first, so
+    /// a slow recorder does not hold up drawing.
+    pub fn record_frame(&self, recorder: &mut FrameRecorder) -> Result<()> {
+        let data = self.to_raw_rgb();
+        recorder.write(self.width as u32, self.height as u32, &data)
+    }
+
+    /// Stretches the tonal range of the buffer, see [`Canvas::auto_levels`].
+    pub fn auto_levels(&self) -> Result<()> {
+        self.lock_buffer().auto_levels();
+        Ok(())
+    }
+
+    /// Maps every channel of the buffer through its own lookup table, see [`Canvas::apply_curves`]
+    /// and [`gamma_lut`](crate::gamma_lut).
+    pub fn apply_curves(&self, r: &[u8; 256], g: &[u8; 256], b: &[u8; 256]) -> Result<()> {
+        self.lock_buffer().apply_curves(r, g, b);
+        Ok(())
+    }
+
+    /// Blends a solid color over the whole buffer, see [`Canvas::tint`].
+    pub fn tint(&self, color: Pixel, opacity: f32) -> Result<()> {
+        self.lock_buffer().tint(&color, opacity);
+        Ok(())
+    }
+
+    /// Turns the whole buffer by half a turn, see [`Canvas::rotate180`]. Quarter turns would not
+    /// fit the screen, see [`Canvas::rotate90`] for turning parts of it.
+    pub fn rotate180(&self) -> Result<()> {
+        self.lock_buffer().rotate180();
+        Ok(())
+    }
+
+    /// Mirrors the whole buffer left to right, see [`Canvas::flip_h`].
+    pub fn flip_h(&self) -> Result<()> {
+        self.lock_buffer().flip_h();
+        Ok(())
+    }
+
+    /// Mirrors the whole buffer top to bottom, see [`Canvas::flip_v`].
+    pub fn flip_v(&self) -> Result<()> {
+        self.lock_buffer().flip_v();
+        Ok(())
+    }
+
+    /// Composites two images into the buffer through a grayscale mask, see
+    /// [`Canvas::blend_with_mask`].
+    pub fn blend_with_mask(&self, a: &RgbImage, b: &RgbImage, mask: &GrayImage) -> Result<()> {
+        self.lock_buffer().blend_with_mask(a, b, mask)
+    }
+
+    /// Reduces the buffer to the colors of `palette`, see [`Canvas::quantize`].
+    pub fn quantize(&self, palette: &[Pixel], dither: bool) -> Result<()> {
+        self.lock_buffer().quantize(palette, dither);
+        Ok(())
+    }
+
+    /// Runs every filter of the chain over the buffer, in order, under a single lock.
+    pub fn apply_chain(&self, chain: &FilterChain) -> Result<()> {
+        self.lock_buffer().apply_chain(chain);
+        Ok(())
+    }
+}
+
+pub(crate) fn connect() -> Result<(Connection, i32)> {
+    // RandR is only needed to tell monitors apart, see BackgroundHandle::monitors, and
+    // MIT-SCREEN-SAVER to tell how long the user has been idle, see BackgroundHandle::idle_time
+    connect_with_extensions(&[Extension::RandR, Extension::ScreenSaver])
+}
+
+// Every extension is optional, whoever uses one checks Connection::active_extensions first
+pub(crate) fn connect_with_extensions(optional: &[Extension]) -> Result<(Connection, i32)> {
+    // Without XWayland there is no server to reach, and xcb's connect error says nothing about why
+    if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        return Err(Error::NoX11);
+    }
+
+    info!("Connecting to the Xorg Server");
+    Ok(Connection::connect_with_extensions(None, &[], optional).map_err(xcb::Error::from)?)
+}
+
+// Sizes are CARD16 on the wire, but positions are INT16: anything past 32767 pixels could never
+// be drawn to, as no request can address it. Checked once up front, every coordinate within the
+// geometry then fits the i16 fields of PutImage and ClearArea as is
+pub(crate) fn drawable_geometry(width: u16, height: u16) -> bool {
+    let max = i16::MAX as u16 + 1;
+    (1..=max).contains(&width) && (1..=max).contains(&height)
+}
+
+// Screen numbers index the roots in the order the server lists them, the only numbering xcb
+// has. One past the end is refused rather than wrapped or clamped, either of which would quietly
+// draw to some other screen
+pub(crate) fn screen(setup: &Setup, screen_number: i32) -> Result<&Screen> {
+    let count = setup.roots().count();
+    if count == 0 {
+        return Err(Error::NoScreenFound);
+    }
+
+    usize::try_from(screen_number)
+        .ok()
+        .and_then(|n| setup.roots().nth(n))
+        .ok_or(Error::ScreenOutOfRange {
+            screen: screen_number,
+            count,
+        })
+}
+
+// The root pixmap properties, as a batch for atoms::intern_atoms
+const ROOT_PMAP_ATOMS: [&[u8]; 2] = [b"_XROOTPMAP_ID", b"ESETROOT_PMAP_ID"];
+
+// Held for the whole of every load in this process, whichever entry point it came through. The
+// handle cell only keeps its own initializers apart, and a set_once or lazy handle loading
+// alongside could otherwise read the properties before a concurrent load registered its pixmap,
+// and kill the connection that load is still setting up
+static LOADING: Mutex<()> = Mutex::new(());
+
+fn inner_load(open_method: OpenMethod, config: LoadConfig) -> Result<BackgroundHandle> {
+    let _loading = LOADING.lock().unwrap_or_else(|e| e.into_inner());
+    load_serialized(open_method, config)
+}
+
+// Frees everything it created on the server before returning an error, so a failed load leaves
+// nothing for the next one to trip over
+fn load_serialized(_open_method: OpenMethod, config: LoadConfig) -> Result<BackgroundHandle> {
+    let mut timer = PhaseTimer::start();
+    let mut report = LoadReport::default();
+
+    let (connection, default_screen) = connect()?;
+    let screen_number = config.screen.unwrap_or(default_screen);
+    let screen = screen(connection.get_setup(), screen_number)?;
+    if screen_number != default_screen {
+        info!("Loading on screen {screen_number} rather than the display's {default_screen}");
+    }
+
+    let root = screen.root();
+    let width = screen.width_in_pixels();
+    let height = screen.height_in_pixels();
+
+    // A zero sized pixmap is a BadValue on CreatePixmap at best, and an empty buffer to draw into
+    // at worst
+    if !drawable_geometry(width, height) {
+        return Err(Error::InvalidGeometry { width, height });
+    }
+
+    let depth = screen.root_depth();
+    let visual = *screen
+        .allowed_depths()
+        .flat_map(|d| d.visuals())
+        .find(|v| v.visual_id() == screen.root_visual())
+        .ok_or(Error::NoVisualFound)?;
+    let visual_info = VisualInfo::new(connection.get_setup(), depth, &visual);
+    let format = ServerFormat::from_setup(connection.get_setup(), depth, &visual)
+        .ok_or(Error::NoPixmapFormat(depth))?;
+    timer.lap("connect", &mut report);
+
+    // Checked before anything is created, so refusing leaves nothing behind on the server
+    let marker = OwnerMarker::new(&connection, screen_number)?;
+    let shade_owner = marker.owner(&connection, root)?;
+    if let Some(owner) = &shade_owner {
+        if !config.force && !config.take_over_from_shade {
+            return Err(Error::AlreadyOwned {
+                pid_hint: owner.pid,
+            });
+        }
+        info!("Taking over from running shade instance {:?}", owner.pid);
+    }
+    let handoff = shade_owner.is_some() && config.take_over_from_shade;
+    timer.lap("check owner", &mut report);
+
+    // Everything created from here on is freed again should loading fail
+    let mut resources = ResourceGuard::new(&connection);
+
+    let palette = match visual_info.class {
+        VisualClass::PseudoColor if config.pseudo_color => {
+            let palette = PaletteMap::new(
+                &connection,
+                connection.get_setup(),
+                root,
+                &visual,
+                &visual_info,
+            )?;
+            resources.push(Resource::Colormap(palette.colormap));
+            Some(palette)
+        }
+        _ => {
+            visual_info.check_class()?;
+            None
+        }
+    };
+
+    // Measured in 4 byte units, and already accounting for BIG-REQUESTS when the server has it
+    let max_request_bytes = connection.get_maximum_request_length() as usize * 4;
+
+    // See BackgroundHandle::fill_gc
+    let clear_color = config.clear_color.clone();
+
+    info!(
+        "Root window with id: {}, width: {}, height: {} and depth: {}",
+        root.resource_id(),
+        width,
+        height,
+        depth
+    );
+
+    let shade_pmap = {
+        let pid = connection.generate_id();
+        let request = CreatePixmap {
+            depth,
+            pid,
+            width,
+            height,
+            drawable: Drawable::Window(root),
+        };
+
+        void_request!(&connection, &request)?;
+        resources.push(Resource::Pixmap(pid));
+        info!("Allocated shade pixmap with id {:?}", pid);
+
+        // Fresh pixmaps hold whatever the server's memory did, so give it the buffer's color
+        let pixel = match &palette {
+            Some(palette) => palette.index_of(&clear_color) as u32,
+            None => gc::pixel_value(&visual, &clear_color),
+        };
+        gc::clear_pixmap(&connection, pid, (width, height), pixel)?;
+
+        pid
+    };
+    timer.lap("create pixmap", &mut report);
+
+    let mut atoms = intern_atoms(&connection, ROOT_PMAP_ATOMS, true)?;
+    // The wallpaper being replaced is read from the first property shade sets
+    let current = config.root_properties.partition(atoms).0[0];
+    timer.lap("intern atoms", &mut report);
+
+    // Nobody may set the properties between us reading them and replacing them with ours
+    let grab = ServerGrab::new(&connection)?;
+
+    // Has to happen before the kill, which frees the pixmap along with its owner
+    let (previous, previous_memory) = match config.keep_previous {
+        true => previous::copy_previous(
+            &connection,
+            root,
+            (depth, visual_info.bits_per_pixel),
+            current,
+            &config.memory_budget,
+            &mut report,
+        )?
+        .unzip(),
+        false => (None, None),
+    };
+    if let Some((pixmap, _, _)) = previous {
+        resources.push(Resource::Pixmap(pixmap));
+    }
+
+    #[cfg(feature = "testing")]
+    if testing::take_load_failure() {
+        return Err(Error::InjectedFailure);
+    }
+
+    // Read before our own pixmap replaces it in the properties
+    let handed_over = if handoff {
+        owner::read_current(
+            &connection,
+            root,
+            &visual_info,
+            (width, height),
+            current,
+            &mut report,
+        )?
+    } else {
+        None
+    };
+
+    if handoff {
+        info!("Leaving the previous shade instance alive");
+    } else if config.kill_foreign {
+        kill_pmap_atoms(
+            &connection,
+            root,
+            atoms,
+            config.root_properties,
+            &mut report,
+        )?;
+    } else {
+        info!("Leaving foreign pixmap owners alive");
+    }
+
+    timer.lap("replace previous", &mut report);
+
+    // Create these if they did not exist before (e.g. the previous InternAtom request returned
+    // ATOM_NONE). Once any client set a wallpaper they always do, so this is the rare case. Only
+    // the properties shade sets have to exist
+    let (set, _) = config.root_properties.partition(atoms);
+    report.created_atoms = set.iter().any(|&(_, atom)| atom == ATOM_NONE);
+    if report.created_atoms {
+        atoms = intern_atoms(&connection, ROOT_PMAP_ATOMS, false)?;
+
+        if atoms.contains(&ATOM_NONE) {
+            return Err(Error::FailedRootAtomCreation);
+        }
+    }
+
+    // Read back before ungrabbing, all have to name our pixmap by the time anyone looks
+    set_pmap_atoms(
+        &connection,
+        root,
+        &config.root_properties.partition(atoms).0,
+        shade_pmap.resource_id(),
+    )?;
+
+    let owner_window = marker.claim(&connection, root)?;
+    resources.push(Resource::Window(owner_window));
+
+    drop(grab);
+    timer.lap("set properties", &mut report);
+
+    // TODO This might not work on multi monitor setups
+    // TODO This also requires the monitor to be cleared
+
+    let background = connection.send_request_checked(&ChangeWindowAttributes {
+        window: root,
+        value_list: &[Cw::BackPixmap(shade_pmap)],
+    });
+    let close_down = connection.send_request_checked(&SetCloseDownMode {
+        mode: RetainPermanent,
+    });
+    for cookie in [background, close_down] {
+        connection.check_request(cookie).map_err(xcb::Error::from)?;
+    }
+
+    connection.flush().map_err(xcb::Error::from)?;
+    resources.disarm();
+    timer.lap("set background", &mut report);
+
+    // Allocated only once the server has everything it needs, so its time never delays the
+    // wallpaper showing up
+    let buffer = Canvas::filled(width as u32, height as u32, clear_color.clone());
+    timer.lap("allocate buffer", &mut report);
+
+    let mut cache = ImageCache::new(DEFAULT_CACHE_MEGABYTES);
+    cache.set_budget(config.memory_budget.clone());
+
+    let handle = BackgroundHandle {
+        connection,
+        width,
+        height,
+        depth,
+        root,
+        screen_number,
+        visual,
+        visual_info,
+        format,
+        max_request_bytes,
+        target_depth: AtomicU8::new(0),
+        lock_warn_threshold: AtomicU64::new(0),
+        max_image_pixels: AtomicU64::new(0),
+        paused: AtomicBool::new(false),
+        previous,
+        _previous_memory: previous_memory,
+        memory_budget: config.memory_budget.clone(),
+        root_properties: config.root_properties,
+        palette,
+        #[cfg(feature = "testing")]
+        put_images: Mutex::new(Vec::new()),
+        cache: Mutex::new(cache),
+        history: Mutex::new(History::new(config.memory_budget.clone())),
+        throttle: Mutex::new(Throttle::default()),
+        unchecked: Mutex::new(Unchecked::default()),
+        applied_hook: Mutex::new(None),
+        pending_applied: Mutex::new(None),
+        pending_metadata: Mutex::new(None),
+        monitor_aspects: Mutex::new(Vec::new()),
+        plan_renderings: Mutex::new(Vec::new()),
+        layer: config
+            .rgba
+            .then(|| Mutex::new(Canvas::transparent(width as u32, height as u32))),
+        solid: Mutex::new(None),
+        buffer: Mutex::new(buffer),
+        background_pixmap: shade_pmap,
+        owner_window,
+        upload_gc: OnceCell::new(),
+        fill_gc: OnceCell::new(),
+        metadata_atoms: OnceCell::new(),
+        tag_metadata: config.tag_metadata,
+        compositor: OnceCell::new(),
+        events_selected: OnceCell::new(),
+        clear_color,
+        report,
+    };
+
+    info!("Created handle");
+    registry::register(handle.background_pixmap.resource_id());
+
+    if let Some(canvas) = handed_over {
+        *handle.lock_buffer() = canvas;
+        handle.flush()?;
+    }
+
+    Ok(handle)
+}
+
+/// Loads the handle this process draws the wallpaper with, or returns it if already loaded.
+///
+/// Safe to race from several threads: they wait for whichever got there first, and should it
+/// fail, the next one tries anew with nothing of the failed attempt left behind on the server.
+/// Every caller gets either the handle or the error of an attempt of its own.
+pub fn load(options: OpenMethod) -> Result<&'static BackgroundHandle> {
+    load_with(options, LoadConfig::default())
+}
+
+/// Sets the wallpaper to `source` and disconnects, keeping no state behind. The pixmap outlives the
+/// connection, so this is all a program that only sets a wallpaper and exits needs.
+///
+/// Unlike [`load`], every call connects anew, and nothing stops it from running alongside a
+/// handle obtained from `load`.
+pub fn set_once(source: WallpaperSource, scaling: ScalingMethod) -> Result<()> {
+    // Loading already fills the pixmap with a solid color on the server, so there is nothing to
+    // render or upload, only the root to repaint
+    if let WallpaperSource::Color(color) = &source {
+        let config = LoadConfig::default().clear_color(color.clone());
+        let handle = inner_load(OpenMethod::MakeNew, config)?;
+        handle.tag_metadata(None, &scaling.into());
+        return handle.clear_root();
+    }
+
+    let handle = inner_load(OpenMethod::MakeNew, LoadConfig::default())?;
+
+    handle.set_source(&source, scaling)?;
+    handle.flush()?;
+
+    // Every request so far was checked, which already forces them out, but nothing may be left
+    // behind in xcb's buffer when the connection closes
+    handle.connection.flush().map_err(xcb::Error::from)?;
+
+    Ok(())
+}
+
+/// [`load`] with further options. Like `load`, only the first successful call has any effect.
+pub fn load_with(options: OpenMethod, config: LoadConfig) -> Result<&'static BackgroundHandle> {
+    loaded(options, config)
+}
+
+impl BackgroundHandle {
+    /// Loads a handle owned by the caller rather than the one [`load`] shares with the whole
+    /// process, e.g. to hand to an [`Applier`]. Every call connects and loads anew, the handle
+    /// lets go of the wallpaper when dropped.
+    ///
+    /// Only one handle at a time can own a screen's wallpaper: while another one of this process
+    /// or any other is alive, this fails with [`Error::AlreadyOwned`] unless `config` says to
+    /// [take over](LoadConfig::take_over_from_shade) or [force](LoadConfig::force) it.
+    pub fn open(options: OpenMethod, config: LoadConfig) -> Result<BackgroundHandle> {
+        inner_load(options, config)
+    }
+}
+
+/// [`load_with`], also returning what loading ran into along the way. Every call returns the report
+/// of the call that actually loaded the handle.
+pub fn load_with_report(
+    options: OpenMethod,
+    config: LoadConfig,
+) -> Result<(&'static BackgroundHandle, LoadReport)> {
+    loaded(options, config).map(|handle| (handle, handle.report.clone()))
+}
+
+// Threads racing to load block until the first is done. Should it fail, the next one tries
+// anew, every one of them getting either the handle or the error of its own attempt
+fn loaded(options: OpenMethod, config: LoadConfig) -> Result<&'static BackgroundHandle> {
+    static HANDLE: OnceCell<BackgroundHandle> = OnceCell::new();
+    HANDLE.get_or_try_init(|| inner_load(options, config))
+}
@@ -0,0 +1,636 @@
+use std::path::Path;
+
+use image::RgbImage;
+use xcb::{
+    randr::{GetCrtcInfo, GetOutputInfo, GetScreenResourcesCurrent, Rotation},
+    Extension, Xid,
+};
+
+use super::{AppliedSource, BackgroundHandle};
+use crate::{
+    Canvas, Pixel, PixelAspect, Placement, Rect, Result, Scaling, TextColor, WallpaperSource,
+};
+
+/// A rectangle of the root window shown by one or more outputs.
+///
+/// Outputs mirroring each other show the same pixels, so they are reported as one monitor carrying
+/// all of their names rather than once per output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Monitor {
+    pub names: Vec<String>,
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+    /// The physical size RandR reports, in millimeters as the monitor is rotated. 0 if unknown.
+    pub mm_width: u32,
+    pub mm_height: u32,
+    /// The shape of the monitor's pixels, as set with
+    /// [`BackgroundHandle::set_monitor_aspect`]. Square unless set otherwise.
+    pub pixel_aspect: PixelAspect,
+}
+
+impl Monitor {
+    /// Whether any of the outputs showing this monitor is called `name`.
+    pub fn has_name(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n == name)
+    }
+
+    /// The area of the buffer this monitor shows, e.g. for [`Canvas::view`](crate::Canvas::view).
+    pub fn rect(&self) -> Rect {
+        Rect::new(
+            self.x as i32,
+            self.y as i32,
+            self.width as u32,
+            self.height as u32,
+        )
+    }
+}
+
+/// Where the pixel aspect ratio of a monitor comes from, see
+/// [`BackgroundHandle::set_monitor_aspect`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MonitorAspect {
+    /// Pixels are taken to be square, the default.
+    #[default]
+    Square,
+    /// Derived from the physical size RandR reports against the monitor's size in pixels.
+    /// Square if the size is unknown. Reported sizes are rarely exact, so even ordinary
+    /// monitors end up very slightly off square this way.
+    Physical,
+    Fixed(PixelAspect),
+}
+
+/// How [`BackgroundHandle::set_source_with`] lays a source out when the root spans several
+/// monitors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Span {
+    /// One rendering across the whole root, so an image or gradient runs on from one monitor into
+    /// the next.
+    #[default]
+    Screen,
+    /// A rendering of its own on every monitor, sized to it. Generators run once per monitor size,
+    /// each drawing onto a canvas as large as the monitor.
+    PerMonitor,
+}
+
+// An active output as RandR reports it, before clones are merged
+struct OutputGeometry {
+    name: String,
+    crtc: u32,
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+    mm_width: u32,
+    mm_height: u32,
+}
+
+impl BackgroundHandle {
+    /// The monitors the root window is shown on, left to right then top to bottom.
+    ///
+    /// Without RandR, or with no output enabled, the whole root counts as a single monitor with no
+    /// name.
+    pub fn monitors(&self) -> Result<Vec<Monitor>> {
+        let outputs = if self
+            .connection
+            .active_extensions()
+            .any(|e| e == Extension::RandR)
+        {
+            self.outputs()?
+        } else {
+            Vec::new()
+        };
+
+        let mut monitors = merge_clones(outputs);
+        if monitors.is_empty() {
+            monitors.push(Monitor {
+                names: Vec::new(),
+                x: 0,
+                y: 0,
+                width: self.width,
+                height: self.height,
+                mm_width: 0,
+                mm_height: 0,
+                pixel_aspect: PixelAspect::SQUARE,
+            });
+        }
+
+        let aspects = self
+            .monitor_aspects
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        for monitor in &mut monitors {
+            let aspect = aspects
+                .iter()
+                .find(|(name, _)| monitor.has_name(name))
+                .map_or(MonitorAspect::Square, |(_, aspect)| *aspect);
+
+            monitor.pixel_aspect = match aspect {
+                MonitorAspect::Square => PixelAspect::SQUARE,
+                MonitorAspect::Physical => PixelAspect::from_physical(
+                    (monitor.width as u32, monitor.height as u32),
+                    (monitor.mm_width, monitor.mm_height),
+                ),
+                MonitorAspect::Fixed(aspect) => aspect,
+            };
+        }
+
+        Ok(monitors)
+    }
+
+    /// Sets where the pixel aspect ratio of the monitor showing output `name` comes from, for
+    /// projectors and TVs whose pixels are not square. Images set per monitor are then laid out
+    /// to keep their proportions on the glass, see [`Scaling::pixel_aspect`]. Whole screen
+    /// layouts are left alone, they may span monitors of different shapes.
+    pub fn set_monitor_aspect(&self, name: &str, aspect: MonitorAspect) {
+        let mut aspects = self
+            .monitor_aspects
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        aspects.retain(|(n, _)| n != name);
+        if aspect != MonitorAspect::Square {
+            aspects.push((name.to_owned(), aspect));
+        }
+    }
+
+    /// The monitor one of whose outputs is called `name`, mirrored outputs answering to any of
+    /// their names.
+    pub fn monitor(&self, name: &str) -> Result<Option<Monitor>> {
+        Ok(self.monitors()?.into_iter().find(|m| m.has_name(name)))
+    }
+
+    /// Where a `src_w` x `src_h` image set on the whole screen goes, in root window coordinates,
+    /// as [`BackgroundHandle::set_image`] and [`BackgroundHandle::set_source`] lay it out. It
+    /// is the very placement they draw with, so previews built on it match the screen exactly.
+    pub fn placement_for(&self, src_w: u32, src_h: u32, scaling: impl Into<Scaling>) -> Placement {
+        scaling
+            .into()
+            .placement(src_w, src_h, self.width as u32, self.height as u32)
+    }
+
+    /// [`BackgroundHandle::placement_for`] an image set on `monitor` alone, as
+    /// [`BackgroundHandle::set_monitor_image`] and [`Span::PerMonitor`] lay it out, the
+    /// monitor's pixel aspect included. In root window coordinates as well.
+    pub fn placement_on(
+        &self,
+        monitor: &Monitor,
+        src_w: u32,
+        src_h: u32,
+        scaling: impl Into<Scaling>,
+    ) -> Placement {
+        monitor_scaling(scaling.into(), monitor)
+            .placement(src_w, src_h, monitor.width as u32, monitor.height as u32)
+            .offset(monitor.x as i32, monitor.y as i32)
+    }
+
+    /// Uploads only the part of the buffer `monitor` shows, see
+    /// [`BackgroundHandle::flush_region`]. After changing a single monitor's content, this saves
+    /// uploading what all the others show.
+    pub fn flush_monitor(&self, monitor: &Monitor) -> Result<()> {
+        let area =
+            monitor
+                .rect()
+                .intersect(&Rect::new(0, 0, self.width as u32, self.height as u32));
+
+        self.flush_region(
+            area.x as u16,
+            area.y as u16,
+            area.width as u16,
+            area.height as u16,
+        )
+    }
+
+    /// Decodes and scales the image at `path` into the part of the buffer `monitor` shows,
+    /// leaving the rest alone. Call [`BackgroundHandle::flush_monitor`] to show it.
+    ///
+    /// Unlike [`BackgroundHandle::set_image`] this bypasses the image cache, which only holds
+    /// images prepared for the whole screen.
+    pub fn set_monitor_image(
+        &self,
+        monitor: &Monitor,
+        path: impl AsRef<Path>,
+        scaling: impl Into<Scaling>,
+    ) -> Result<()> {
+        let image = Canvas::from_path_within(
+            path.as_ref(),
+            monitor.width as u32,
+            monitor.height as u32,
+            monitor_scaling(scaling.into(), monitor),
+            self.max_image_pixels(),
+        )?;
+
+        self.lock_buffer()
+            .paste(&image, monitor.x as i32, monitor.y as i32);
+        self.note_applied(AppliedSource::File(path.as_ref().to_owned()), Some(monitor));
+        Ok(())
+    }
+
+    /// [`BackgroundHandle::set_source`], laying the source out over the monitors as `span` says.
+    /// With [`Span::PerMonitor`], whatever no monitor shows is cleared to the clear color.
+    pub fn set_source_with(
+        &self,
+        source: &WallpaperSource,
+        scaling: impl Into<Scaling>,
+        span: Span,
+    ) -> Result<()> {
+        if span == Span::Screen {
+            return self.set_source(source, scaling);
+        }
+
+        let scaling = scaling.into();
+        let canvas = lay_out_per_monitor(
+            source,
+            (self.width, self.height),
+            &self.monitors()?,
+            &scaling,
+            &self.clear_color,
+            self.max_image_pixels(),
+        )?;
+
+        *self.lock_buffer() = canvas;
+        let path = match source {
+            WallpaperSource::File(path) => Some(path.as_path()),
+            _ => None,
+        };
+        self.tag_metadata(path, &scaling);
+        self.note_applied(source.into(), None);
+        Ok(())
+    }
+
+    /// A thumbnail of the current buffer, see [`Canvas::thumbnail`](crate::Canvas::thumbnail).
+    /// The buffer is only locked for as long as it takes to copy it.
+    pub fn thumbnail(&self, max_width: u32, max_height: u32) -> RgbImage {
+        let buffer = self.lock_buffer().clone();
+        buffer.thumbnail(max_width, max_height)
+    }
+
+    /// A thumbnail of the part of the buffer `monitor` shows.
+    pub fn thumbnail_for(&self, monitor: &Monitor, max_width: u32, max_height: u32) -> RgbImage {
+        let buffer = self.lock_buffer();
+        let area = monitor.rect().intersect(&buffer.bounds());
+        let region = buffer.crop(area.x as u32, area.y as u32, area.width, area.height);
+        drop(buffer);
+        region.thumbnail(max_width, max_height)
+    }
+
+    /// The color text drawn over `area` of the buffer reads best in, see
+    /// [`Canvas::suggest_text_color`](crate::Canvas::suggest_text_color). Accents come out of
+    /// the whole buffer's colors, so text matches the wallpaper rather than the area alone.
+    pub fn suggest_text_color(&self, area: Rect) -> TextColor {
+        self.lock_buffer().suggest_text_color(area)
+    }
+
+    fn outputs(&self) -> Result<Vec<OutputGeometry>> {
+        let resources = cookie_request!(
+            &self.connection,
+            &GetScreenResourcesCurrent { window: self.root }
+        )?;
+        let timestamp = resources.config_timestamp();
+
+        let mut outputs = Vec::new();
+        for &output in resources.outputs() {
+            let info = cookie_request!(
+                &self.connection,
+                &GetOutputInfo {
+                    output,
+                    config_timestamp: timestamp,
+                }
+            )?;
+
+            // Disconnected or disabled outputs have no CRTC driving them
+            if info.crtc().is_none() {
+                continue;
+            }
+
+            let crtc = cookie_request!(
+                &self.connection,
+                &GetCrtcInfo {
+                    crtc: info.crtc(),
+                    config_timestamp: timestamp,
+                }
+            )?;
+
+            if crtc.width() == 0 || crtc.height() == 0 {
+                continue;
+            }
+
+            // The physical size is the panel's, which turns along with the picture
+            let turned = crtc
+                .rotation()
+                .intersects(Rotation::ROTATE_90 | Rotation::ROTATE_270);
+            let (mm_width, mm_height) = match turned {
+                true => (info.mm_height(), info.mm_width()),
+                false => (info.mm_width(), info.mm_height()),
+            };
+
+            outputs.push(OutputGeometry {
+                name: String::from_utf8_lossy(info.name()).into_owned(),
+                crtc: info.crtc().resource_id(),
+                x: crtc.x(),
+                y: crtc.y(),
+                width: crtc.width(),
+                height: crtc.height(),
+                mm_width,
+                mm_height,
+            });
+        }
+
+        Ok(outputs)
+    }
+}
+
+// Outputs sharing a CRTC are clones by definition. Drivers also mirror by driving separate CRTCs
+// with the same rectangle, which is just as much the same pixels. Partial overlaps are left
+// alone, those are genuinely different monitors.
+fn merge_clones(mut outputs: Vec<OutputGeometry>) -> Vec<Monitor> {
+    outputs.sort_by_key(|o| (o.x, o.y, o.crtc));
+
+    let mut monitors: Vec<Monitor> = Vec::new();
+    let mut crtcs: Vec<u32> = Vec::new();
+
+    for output in outputs {
+        let clone = monitors.iter().zip(&crtcs).position(|(m, &crtc)| {
+            crtc == output.crtc
+                || (m.x, m.y, m.width, m.height)
+                    == (output.x, output.y, output.width, output.height)
+        });
+
+        match clone {
+            Some(i) => monitors[i].names.push(output.name),
+            None => {
+                crtcs.push(output.crtc);
+                monitors.push(Monitor {
+                    names: vec![output.name],
+                    x: output.x,
+                    y: output.y,
+                    width: output.width,
+                    height: output.height,
+                    mm_width: output.mm_width,
+                    mm_height: output.mm_height,
+                    pixel_aspect: PixelAspect::SQUARE,
+                });
+            }
+        }
+    }
+
+    monitors
+}
+
+// The root's worth of pixels Span::PerMonitor lays out, a rendering of `source` on every monitor
+// and `clear` wherever none is
+fn lay_out_per_monitor(
+    source: &WallpaperSource,
+    (width, height): (u16, u16),
+    monitors: &[Monitor],
+    scaling: &Scaling,
+    clear: &Pixel,
+    max_pixels: u64,
+) -> Result<Canvas> {
+    let mut canvas = Canvas::filled(width as u32, height as u32, clear.clone());
+
+    // Monitors of the same size and pixel shape show the same rendering, there is no point in
+    // decoding or generating it again
+    let mut rendered: Vec<((u16, u16, PixelAspect), Canvas)> = Vec::new();
+    for monitor in monitors {
+        let key = (monitor.width, monitor.height, monitor.pixel_aspect);
+        let i = match rendered.iter().position(|(k, _)| *k == key) {
+            Some(i) => i,
+            None => {
+                let image = Canvas::from_source_within(
+                    source,
+                    monitor.width as u32,
+                    monitor.height as u32,
+                    monitor_scaling(scaling.clone(), monitor),
+                    max_pixels,
+                )?;
+                rendered.push((key, image));
+                rendered.len() - 1
+            }
+        };
+
+        canvas.paste(&rendered[i].1, monitor.x as i32, monitor.y as i32);
+    }
+
+    Ok(canvas)
+}
+
+// The monitor's pixel shape wins over the caller's, unless it has none of its own
+pub(super) fn monitor_scaling(scaling: Scaling, monitor: &Monitor) -> Scaling {
+    match monitor.pixel_aspect.is_square() {
+        true => scaling,
+        false => scaling.pixel_aspect(monitor.pixel_aspect),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::ScalingMethod;
+
+    fn output(
+        name: &str,
+        crtc: u32,
+        (x, y): (i16, i16),
+        (width, height): (u16, u16),
+    ) -> OutputGeometry {
+        OutputGeometry {
+            name: name.into(),
+            crtc,
+            x,
+            y,
+            width,
+            height,
+            mm_width: width as u32 / 4,
+            mm_height: height as u32 / 4,
+        }
+    }
+
+    fn layout(monitors: &[Monitor]) -> Vec<(Vec<&str>, i16, i16, u16, u16)> {
+        monitors
+            .iter()
+            .map(|m| {
+                let names = m.names.iter().map(String::as_str).collect();
+                (names, m.x, m.y, m.width, m.height)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn side_by_side_left_to_right() {
+        // The right monitor sits higher, it still comes second
+        let monitors = merge_clones(vec![
+            output("DP-1", 2, (1920, 0), (2560, 1440)),
+            output("HDMI-1", 1, (0, 360), (1920, 1080)),
+        ]);
+        assert_eq!(
+            layout(&monitors),
+            [
+                (vec!["HDMI-1"], 0, 360, 1920, 1080),
+                (vec!["DP-1"], 1920, 0, 2560, 1440),
+            ]
+        );
+        assert_eq!((monitors[1].mm_width, monitors[1].mm_height), (640, 360));
+        assert!(monitors.iter().all(|m| m.pixel_aspect.is_square()));
+    }
+
+    #[test]
+    fn stacked_top_to_bottom() {
+        let monitors = merge_clones(vec![
+            output("DP-2", 7, (0, 1080), (1920, 1080)),
+            output("DP-1", 9, (0, 0), (1920, 1080)),
+        ]);
+        assert_eq!(
+            layout(&monitors),
+            [
+                (vec!["DP-1"], 0, 0, 1920, 1080),
+                (vec!["DP-2"], 0, 1080, 1920, 1080),
+            ]
+        );
+    }
+
+    #[test]
+    fn outputs_sharing_a_crtc_are_one_monitor() {
+        // A projector cloning the laptop panel scans out of the panel's CRTC at its size
+        let monitors = merge_clones(vec![
+            output("eDP-1", 1, (0, 0), (1920, 1080)),
+            output("HDMI-1", 1, (0, 0), (1920, 1080)),
+            output("DP-1", 2, (1920, 0), (1920, 1080)),
+        ]);
+        assert_eq!(
+            layout(&monitors),
+            [
+                (vec!["eDP-1", "HDMI-1"], 0, 0, 1920, 1080),
+                (vec!["DP-1"], 1920, 0, 1920, 1080),
+            ]
+        );
+    }
+
+    #[test]
+    fn crtcs_showing_the_same_rectangle_are_one_monitor() {
+        let monitors = merge_clones(vec![
+            output("DP-2", 4, (0, 0), (1920, 1080)),
+            output("DP-1", 3, (0, 0), (1920, 1080)),
+        ]);
+        // Named in CRTC order, whatever order RandR listed them in
+        assert_eq!(
+            layout(&monitors),
+            [(vec!["DP-1", "DP-2"], 0, 0, 1920, 1080)]
+        );
+    }
+
+    #[test]
+    fn overlapping_monitors_stay_apart() {
+        let monitors = merge_clones(vec![
+            output("DP-1", 1, (0, 0), (1920, 1080)),
+            output("DP-2", 2, (960, 0), (1920, 1080)),
+            output("DP-3", 3, (0, 0), (1280, 720)),
+        ]);
+        assert_eq!(
+            layout(&monitors),
+            [
+                (vec!["DP-1"], 0, 0, 1920, 1080),
+                (vec!["DP-3"], 0, 0, 1280, 720),
+                (vec!["DP-2"], 960, 0, 1920, 1080),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_outputs_no_monitors() {
+        assert!(merge_clones(Vec::new()).is_empty());
+    }
+
+    // Red runs from 0 at the left edge of whatever it is drawn on to 255 at the right edge, and
+    // counts how often it was drawn
+    fn gradient(drawn: &Arc<AtomicUsize>) -> WallpaperSource {
+        let drawn = drawn.clone();
+        WallpaperSource::Generator(Box::new(move |canvas| {
+            drawn.fetch_add(1, Ordering::SeqCst);
+            let last = canvas.width() - 1;
+            for y in 0..canvas.height() {
+                for x in 0..canvas.width() {
+                    canvas.set_pixel(x, y, Pixel::new((x * 255 / last) as u8, 0, 0));
+                }
+            }
+        }))
+    }
+
+    fn red_at(canvas: &Canvas, x: u32, y: u32) -> u8 {
+        canvas.get_pixel(x, y).unwrap().r
+    }
+
+    // A 40x20 monitor with a 30x30 one to its right, on a 70x30 root
+    fn two_monitors() -> Vec<Monitor> {
+        merge_clones(vec![
+            output("DP-1", 1, (0, 0), (40, 20)),
+            output("DP-2", 2, (40, 0), (30, 30)),
+        ])
+    }
+
+    #[test]
+    fn per_monitor_starts_over_at_the_seam() {
+        let drawn = Arc::new(AtomicUsize::new(0));
+        let clear = Pixel::new(0, 0, 9);
+        let canvas = lay_out_per_monitor(
+            &gradient(&drawn),
+            (70, 30),
+            &two_monitors(),
+            &ScalingMethod::Fill.into(),
+            &clear,
+            u64::MAX,
+        )
+        .unwrap();
+
+        assert_eq!((canvas.width(), canvas.height()), (70, 30));
+        // Each monitor has the whole gradient, the seam jumps from its end back to its start
+        assert_eq!(red_at(&canvas, 0, 0), 0);
+        assert_eq!(red_at(&canvas, 39, 0), 255);
+        assert_eq!(red_at(&canvas, 40, 0), 0);
+        assert_eq!(red_at(&canvas, 69, 29), 255);
+        // Below the shorter monitor nothing is shown
+        assert_eq!(canvas.get_pixel(0, 20), Some(&clear));
+        assert_eq!(canvas.get_pixel(39, 29), Some(&clear));
+        assert_eq!(drawn.load(Ordering::SeqCst), 2);
+    }
+
+    // What Span::Screen renders, for comparison: the gradient carries on over the seam
+    #[test]
+    fn spanned_runs_on_over_the_seam() {
+        let drawn = Arc::new(AtomicUsize::new(0));
+        let canvas =
+            Canvas::from_source_within(&gradient(&drawn), 70, 30, ScalingMethod::Fill, u64::MAX)
+                .unwrap();
+
+        let (left, right) = (red_at(&canvas, 39, 0), red_at(&canvas, 40, 0));
+        assert!(right > left && right - left <= 4);
+        assert_eq!(red_at(&canvas, 0, 0), 0);
+        assert_eq!(red_at(&canvas, 69, 0), 255);
+    }
+
+    #[test]
+    fn monitors_of_a_size_share_a_rendering() {
+        let drawn = Arc::new(AtomicUsize::new(0));
+        let monitors = merge_clones(vec![
+            output("DP-1", 1, (0, 0), (32, 16)),
+            output("DP-2", 2, (32, 0), (32, 16)),
+        ]);
+        let canvas = lay_out_per_monitor(
+            &gradient(&drawn),
+            (64, 16),
+            &monitors,
+            &ScalingMethod::Fill.into(),
+            &Pixel::default(),
+            u64::MAX,
+        )
+        .unwrap();
+
+        assert_eq!(drawn.load(Ordering::SeqCst), 1);
+        assert_eq!(canvas.crop(0, 0, 32, 16), canvas.crop(32, 0, 32, 16));
+    }
+}
@@ -0,0 +1,270 @@
+use xcb::{
+    x::{
+        Atom, ChangeProperty, CreateWindow, Cw, DestroyWindow, Drawable, GetGeometry, GetProperty,
+        GetSelectionOwner, GetWindowAttributes, Pixmap, PropMode, SetSelectionOwner, Window,
+        WindowClass, ATOM_CARDINAL, ATOM_NONE, COPY_FROM_PARENT, CURRENT_TIME, WINDOW_NONE,
+    },
+    Connection, Xid, XidNew,
+};
+
+use super::{
+    atoms::{intern_atoms, resolve_atom},
+    capture::capture,
+    LoadReport, LoadWarning, VisualInfo,
+};
+use crate::{Canvas, Result};
+
+// Root property naming the process behind the current shade owner, followed by its marker
+// window. Only a hint: liveness is decided by the selection, the property outlives its writer
+const OWNER_PROPERTY: &[u8] = b"_SHADE_OWNER";
+
+/// The selection and property shade instances mark the wallpaper of a screen as theirs with.
+///
+/// Every resource of ours outlives the connection thanks to RetainPermanent, but selections do
+/// not: the server drops their ownership when the connection closes, so an owned selection means
+/// a shade instance is still running.
+pub(crate) struct OwnerMarker {
+    selection: Atom,
+    property: Atom,
+}
+
+/// A running shade instance found owning the wallpaper.
+pub(crate) struct ShadeOwner {
+    pub(crate) pid: Option<u32>,
+}
+
+impl OwnerMarker {
+    pub(crate) fn new(connection: &Connection, screen_number: i32) -> Result<OwnerMarker> {
+        let selection = format!("_SHADE_WALLPAPER_S{screen_number}");
+        let [selection, property] =
+            intern_atoms(connection, [selection.as_bytes(), OWNER_PROPERTY], false)?;
+
+        Ok(OwnerMarker {
+            selection,
+            property,
+        })
+    }
+
+    /// The marker as far as any shade instance ever created it on this server, `None` if none
+    /// did. Unlike [`OwnerMarker::new`] this leaves the server's atoms as they are.
+    pub(crate) fn existing(
+        connection: &Connection,
+        screen_number: i32,
+    ) -> Result<Option<OwnerMarker>> {
+        let selection = format!("_SHADE_WALLPAPER_S{screen_number}");
+        let [selection, property] =
+            intern_atoms(connection, [selection.as_bytes(), OWNER_PROPERTY], true)?;
+
+        if selection == ATOM_NONE {
+            return Ok(None);
+        }
+        Ok(Some(OwnerMarker {
+            selection,
+            property,
+        }))
+    }
+
+    pub(crate) fn owner(
+        &self,
+        connection: &Connection,
+        root: Window,
+    ) -> Result<Option<ShadeOwner>> {
+        let owner = cookie_request!(
+            connection,
+            &GetSelectionOwner {
+                selection: self.selection,
+            }
+        )?
+        .owner();
+
+        if owner == WINDOW_NONE {
+            return Ok(None);
+        }
+        // Only possible for markers found by OwnerMarker::existing
+        if self.property == ATOM_NONE {
+            return Ok(Some(ShadeOwner { pid: None }));
+        }
+
+        let property = cookie_request!(
+            connection,
+            &GetProperty {
+                delete: false,
+                window: root,
+                property: self.property,
+                r#type: ATOM_CARDINAL,
+                long_offset: 0,
+                long_length: 1,
+            }
+        )?;
+        let pid = match property.format() {
+            32 => property.value::<u32>().first().copied(),
+            _ => None,
+        };
+
+        Ok(Some(ShadeOwner { pid }))
+    }
+
+    /// Marks the wallpaper as ours, through a window that exists only to own the selection.
+    /// Whoever owned it before is sent a SelectionClear. Returns that window.
+    ///
+    /// Like everything else of a RetainPermanent connection the window outlives it, so it has to
+    /// be destroyed explicitly: by [`release`] once the handle is dropped, or here by the next
+    /// claim should its owner have exited without dropping it.
+    pub(crate) fn claim(&self, connection: &Connection, root: Window) -> Result<Window> {
+        let previous = self.marker_window(connection, root)?;
+
+        let window = connection.generate_id();
+        void_request!(
+            connection,
+            &CreateWindow {
+                depth: COPY_FROM_PARENT as u8,
+                wid: window,
+                parent: root,
+                x: -1,
+                y: -1,
+                width: 1,
+                height: 1,
+                border_width: 0,
+                class: WindowClass::InputOnly,
+                visual: COPY_FROM_PARENT,
+                value_list: &[Cw::OverrideRedirect(true)],
+            }
+        )?;
+
+        void_request!(
+            connection,
+            &SetSelectionOwner {
+                owner: window,
+                selection: self.selection,
+                time: CURRENT_TIME,
+            }
+        )?;
+
+        void_request!(
+            connection,
+            &ChangeProperty {
+                mode: PropMode::Replace,
+                window: root,
+                property: self.property,
+                r#type: ATOM_CARDINAL,
+                data: &[std::process::id(), window.resource_id()],
+            }
+        )?;
+
+        // Only once the selection is ours, so an owner still running gets its SelectionClear
+        if let Some(previous) = previous.filter(|&previous| is_marker(connection, previous)) {
+            debug!("Destroying the previous owner's window {previous:?}");
+            release(connection, previous);
+        }
+
+        debug!("Claimed wallpaper ownership with window {window:?}");
+        Ok(window)
+    }
+
+    // The window the last claim went through, as far as the property still names one
+    fn marker_window(&self, connection: &Connection, root: Window) -> Result<Option<Window>> {
+        let property = cookie_request!(
+            connection,
+            &GetProperty {
+                delete: false,
+                window: root,
+                property: self.property,
+                r#type: ATOM_CARDINAL,
+                long_offset: 0,
+                long_length: 2,
+            }
+        )?;
+
+        Ok(match property.format() {
+            // SAFETY: Only ever destroyed, once is_marker made sure it still is one
+            32 => property
+                .value::<u32>()
+                .get(1)
+                .map(|&id| unsafe { Window::new(id) }),
+            _ => None,
+        })
+    }
+}
+
+// The property outlives the window it names, whose id may since have gone to another client
+// entirely. Only a window that still looks exactly like the ones claim creates is taken for one
+fn is_marker(connection: &Connection, window: Window) -> bool {
+    let geometry = connection.send_request(&GetGeometry {
+        drawable: Drawable::Window(window),
+    });
+    let attributes = connection.send_request(&GetWindowAttributes { window });
+
+    match (
+        connection.wait_for_reply(geometry),
+        connection.wait_for_reply(attributes),
+    ) {
+        (Ok(geometry), Ok(attributes)) => {
+            (
+                geometry.x(),
+                geometry.y(),
+                geometry.width(),
+                geometry.height(),
+            ) == (-1, -1, 1, 1)
+                && attributes.class() == WindowClass::InputOnly
+                && attributes.override_redirect()
+        }
+        _ => false,
+    }
+}
+
+/// Destroys a marker window made by [`OwnerMarker::claim`]. Should this one still own the
+/// selection, it reverts to no owner, as it would have when the connection closed had the window
+/// not been retained.
+pub(crate) fn release(connection: &Connection, window: Window) {
+    // Gone already when a later claim or a kill took care of it
+    if let Err(e) = void_request!(connection, &DestroyWindow { window }) {
+        debug!("Marker window {window:?} not destroyed: {e}");
+    }
+}
+
+/// Reads back what the pixmap `property` names, usually `_XROOTPMAP_ID`, for a new owner to carry
+/// on from. Anything keeping it from being read ends up as a warning in `report`.
+pub(crate) fn read_current(
+    connection: &Connection,
+    root: Window,
+    visual: &VisualInfo,
+    (width, height): (u16, u16),
+    property: (&'static str, Atom),
+    report: &mut LoadReport,
+) -> Result<Option<Canvas>> {
+    let Some(id) = resolve_atom(connection, root, property, report)? else {
+        return Ok(None);
+    };
+    // SAFETY: Only ever used as a drawable, which the server validates
+    let pixmap = unsafe { Pixmap::new(id) };
+
+    let geometry = connection.wait_for_reply(connection.send_request(&GetGeometry {
+        drawable: Drawable::Pixmap(pixmap),
+    }));
+    match geometry {
+        Ok(g) if (g.width(), g.height(), g.depth()) == (width, height, visual.depth) => {}
+        Ok(_) => {
+            report.warn(LoadWarning::HandoffUnavailable(
+                "its pixmap does not match the screen",
+            ));
+            return Ok(None);
+        }
+        Err(xcb::Error::Protocol(_)) => {
+            report.warn(LoadWarning::HandoffUnavailable(
+                "its pixmap no longer exists",
+            ));
+            return Ok(None);
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    let canvas = capture(
+        connection,
+        Drawable::Pixmap(pixmap),
+        visual,
+        (0, 0, width, height),
+    )?;
+    report.adopted = Some(id);
+
+    Ok(Some(canvas))
+}
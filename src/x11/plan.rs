@@ -0,0 +1,123 @@
+use std::fmt;
+
+use super::{
+    atoms::foreign_pixmaps, connect, registry, screen, LoadConfig, LoadReport, OpenMethod,
+    VisualInfo,
+};
+use crate::{
+    canvas::{decode, default_pixel_limit},
+    compute_placement, Error, Result, ScalingMethod,
+};
+
+/// What [`load`](super::load) would do with the same options, as found out by [`plan`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadPlan {
+    pub width: u16,
+    pub height: u16,
+    pub visual: VisualInfo,
+    pub scaling: Option<ScalingMethod>,
+    /// Size of the image as loaded, and the size of the screen area it ends up covering, see
+    /// [`compute_placement`].
+    pub image_size: Option<((u32, u32), (u32, u32))>,
+    /// Whether the image has transparency, which is lost as it gets flattened onto black. This is
+    /// true whatever the depth, even 32 bit visuals get no alpha from shade.
+    pub transparency_lost: bool,
+    /// Pixmaps currently set as the wallpaper, whose owners would be killed.
+    pub kill: Vec<u32>,
+}
+
+impl fmt::Display for LoadPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "screen:  {}x{}", self.width, self.height)?;
+        writeln!(
+            f,
+            "visual:  {:#x} {:?}, depth {}, {} bpp, masks r {:#08x} g {:#08x} b {:#08x}",
+            self.visual.visual_id,
+            self.visual.class,
+            self.visual.depth,
+            self.visual.bits_per_pixel,
+            self.visual.red_mask,
+            self.visual.green_mask,
+            self.visual.blue_mask
+        )?;
+
+        match (&self.scaling, &self.image_size) {
+            (Some(method), Some(((iw, ih), (w, h)))) => {
+                write!(f, "image:   {iw}x{ih}, {method:?} to {w}x{h}")?;
+                if self.transparency_lost {
+                    write!(f, ", transparency flattened onto black")?;
+                }
+                writeln!(f)?
+            }
+            _ => writeln!(f, "image:   none")?,
+        }
+
+        if self.kill.is_empty() {
+            write!(f, "kill:    none")
+        } else {
+            write!(f, "kill:    owners of pixmaps {:?}", self.kill)
+        }
+    }
+}
+
+/// Finds out what [`load`](super::load) would do, without changing anything on the server.
+pub fn plan(options: &OpenMethod) -> Result<LoadPlan> {
+    plan_with(options, &LoadConfig::default())
+}
+
+/// [`plan`] for [`load_with`](super::load_with).
+pub fn plan_with(options: &OpenMethod, config: &LoadConfig) -> Result<LoadPlan> {
+    let (connection, default_screen) = connect()?;
+    let setup = connection.get_setup();
+    let screen = screen(setup, config.screen.unwrap_or(default_screen))?;
+
+    let (width, height) = (screen.width_in_pixels(), screen.height_in_pixels());
+    let visual = screen
+        .allowed_depths()
+        .flat_map(|d| d.visuals())
+        .find(|v| v.visual_id() == screen.root_visual())
+        .ok_or(Error::NoVisualFound)?;
+    let visual_info = VisualInfo::new(setup, screen.root_depth(), visual);
+    visual_info.check_class()?;
+
+    let (scaling, image_size, transparency_lost) = match options {
+        OpenMethod::LoadFromFile(method, path) => {
+            // The header alone would do for the size, but not for the color type
+            let limit = default_pixel_limit(width as u32, height as u32);
+            let image = decode::open(path.as_ref(), limit)?;
+            let size = (image.width(), image.height());
+            let (_, placed) =
+                compute_placement(size.0, size.1, width as u32, height as u32, *method);
+            let scaled = (placed.width, placed.height);
+            (
+                Some(*method),
+                Some((size, scaled)),
+                image.color().has_alpha(),
+            )
+        }
+        _ => (None, None, false),
+    };
+
+    let kill = if config.kill_foreign {
+        let mut pixmaps = foreign_pixmaps(
+            &connection,
+            screen.root(),
+            config.root_properties,
+            &mut LoadReport::default(),
+        )?;
+        pixmaps.retain(|&id| !registry::is_ours(&connection, id));
+        pixmaps
+    } else {
+        Vec::new()
+    };
+
+    Ok(LoadPlan {
+        width,
+        height,
+        visual: visual_info,
+        scaling,
+        image_size,
+        transparency_lost,
+        kill,
+    })
+}
@@ -0,0 +1,208 @@
+use xcb::{
+    x::{
+        self, ChangeProperty, ClientMessageData, CreateGc, CreateWindow, Cw, DestroyWindow,
+        Drawable, EventMask, FreeGc, GetKeyboardMapping, InternAtom, MapWindow, PropMode,
+        WindowClass, ATOM_ATOM, ATOM_STRING, ATOM_WM_NAME,
+    },
+    Connection, Xid,
+};
+
+use super::{
+    connect, drawable_geometry,
+    encode::{bands, encode_region},
+    screen, VisualInfo,
+};
+use crate::{
+    encode::{ServerFormat, PUT_IMAGE_HEADER_BYTES},
+    Canvas, Error, Result, ScalingMethod, WallpaperSource,
+};
+
+const XK_ESCAPE: u32 = 0xff1b;
+
+/// Shows what `source` would look like as a wallpaper in a regular `width` x `height` window,
+/// leaving the actual wallpaper alone. Blocks until the window is closed or Escape is pressed in
+/// it.
+pub fn preview(
+    source: &WallpaperSource,
+    method: ScalingMethod,
+    (width, height): (u16, u16),
+) -> Result<()> {
+    if !drawable_geometry(width, height) {
+        return Err(Error::InvalidGeometry { width, height });
+    }
+
+    let (connection, screen_number) = connect()?;
+    let setup = connection.get_setup();
+    let screen = screen(setup, screen_number)?;
+    let depth = screen.root_depth();
+
+    let visual = screen
+        .allowed_depths()
+        .flat_map(|d| d.visuals())
+        .find(|v| v.visual_id() == screen.root_visual())
+        .ok_or(Error::NoVisualFound)?;
+    VisualInfo::new(setup, depth, visual).check_class()?;
+    let format =
+        ServerFormat::from_setup(setup, depth, visual).ok_or(Error::NoPixmapFormat(depth))?;
+
+    let canvas = Canvas::from_source(source, width as u32, height as u32, method)?;
+
+    let window = connection.generate_id();
+    void_request!(
+        &connection,
+        &CreateWindow {
+            depth,
+            wid: window,
+            parent: screen.root(),
+            x: 0,
+            y: 0,
+            width,
+            height,
+            border_width: 0,
+            class: WindowClass::InputOutput,
+            visual: visual.visual_id(),
+            value_list: &[
+                Cw::BackPixel(screen.black_pixel()),
+                Cw::EventMask(EventMask::EXPOSURE | EventMask::KEY_PRESS),
+            ],
+        }
+    )?;
+
+    let result = show(&connection, window, &format, &canvas);
+
+    // Closing the connection would take the window along anyway, but not before the caller
+    // returns from here
+    void_request!(&connection, &DestroyWindow { window })?;
+    result
+}
+
+fn show(
+    connection: &Connection,
+    window: x::Window,
+    format: &ServerFormat,
+    canvas: &Canvas,
+) -> Result<()> {
+    let wm_delete = intern(connection, b"WM_DELETE_WINDOW")?;
+    void_request!(
+        connection,
+        &ChangeProperty {
+            mode: PropMode::Replace,
+            window,
+            property: intern(connection, b"WM_PROTOCOLS")?,
+            r#type: ATOM_ATOM,
+            data: &[wm_delete],
+        }
+    )?;
+    void_request!(
+        connection,
+        &ChangeProperty {
+            mode: PropMode::Replace,
+            window,
+            property: ATOM_WM_NAME,
+            r#type: ATOM_STRING,
+            data: b"shade preview",
+        }
+    )?;
+
+    let gc = connection.generate_id();
+    void_request!(
+        connection,
+        &CreateGc {
+            cid: gc,
+            drawable: Drawable::Window(window),
+            value_list: &[],
+        }
+    )?;
+
+    void_request!(connection, &MapWindow { window })?;
+    let escape = escape_keycodes(connection)?;
+
+    let result = loop {
+        let event = match connection.wait_for_event() {
+            Ok(event) => event,
+            Err(e) => break Err(e.into()),
+        };
+
+        match event {
+            xcb::Event::X(x::Event::Expose(e)) if e.count() == 0 => {
+                if let Err(e) = draw(connection, window, gc, format, canvas) {
+                    break Err(e);
+                }
+            }
+            xcb::Event::X(x::Event::KeyPress(e)) if escape.contains(&e.detail()) => break Ok(()),
+            xcb::Event::X(x::Event::ClientMessage(e)) => match e.data() {
+                ClientMessageData::Data32([atom, ..]) if atom == wm_delete.resource_id() => {
+                    break Ok(())
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    };
+
+    void_request!(connection, &FreeGc { gc })?;
+    result
+}
+
+// Uploads the whole canvas, in as few requests as the server's request size allows
+fn draw(
+    connection: &Connection,
+    window: x::Window,
+    gc: x::Gcontext,
+    format: &ServerFormat,
+    canvas: &Canvas,
+) -> Result<()> {
+    let (width, height) = (canvas.width() as u16, canvas.height() as u16);
+    let data = encode_region(canvas, (0, 0, width, height), format, format.depth, None);
+    let max_bytes = connection.get_maximum_request_length() as usize * 4 - PUT_IMAGE_HEADER_BYTES;
+
+    let bands = bands(&data, format, (0, 0, width, height), max_bytes).ok_or(
+        Error::ImageTooLargeForRequest {
+            bytes: data.len(),
+            max: max_bytes,
+        },
+    )?;
+
+    for band in bands {
+        void_request!(
+            connection,
+            &band.put_image(Drawable::Window(window), gc, format.depth)
+        )?;
+    }
+
+    Ok(())
+}
+
+fn intern(connection: &Connection, name: &[u8]) -> Result<x::Atom> {
+    Ok(cookie_request!(
+        connection,
+        &InternAtom {
+            name,
+            only_if_exists: false,
+        }
+    )?
+    .atom())
+}
+
+// Every keycode that produces Escape in the current keyboard layout
+fn escape_keycodes(connection: &Connection) -> Result<Vec<x::Keycode>> {
+    let setup = connection.get_setup();
+    let (min, max) = (setup.min_keycode(), setup.max_keycode());
+
+    let mapping = cookie_request!(
+        connection,
+        &GetKeyboardMapping {
+            first_keycode: min,
+            count: max - min + 1,
+        }
+    )?;
+
+    let per_keycode = mapping.keysyms_per_keycode().max(1) as usize;
+    Ok(mapping
+        .keysyms()
+        .chunks(per_keycode)
+        .enumerate()
+        .filter(|(_, keysyms)| keysyms.contains(&XK_ESCAPE))
+        .map(|(i, _)| min + i as u8)
+        .collect())
+}
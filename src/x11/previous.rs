@@ -0,0 +1,193 @@
+use xcb::{
+    x::{
+        Atom, ChangeWindowAttributes, ClearArea, CopyArea, CreateGc, CreatePixmap, Cw, Drawable,
+        FreeGc, GetGeometry, Pixmap, Window,
+    },
+    Connection, Xid, XidNew,
+};
+
+use super::{
+    atoms::{intern_atoms, resolve_atom, set_pmap_atoms},
+    BackgroundHandle, LoadReport, LoadWarning, ServerGrab, ROOT_PMAP_ATOMS,
+};
+use crate::{canvas::Reservation, Error, MemoryBudget, MemoryConsumer, Result};
+
+// The copy's pixmap and size, as the handle keeps it
+pub(crate) type PreviousCopy = (Pixmap, u16, u16);
+
+/// Copies the pixmap `property` names, usually `_XROOTPMAP_ID`, into a new one of our own, before
+/// its owner is killed and takes it along. Anything keeping it from being copied, not fitting
+/// `budget` included, ends up as a warning in `report`.
+pub(crate) fn copy_previous(
+    connection: &Connection,
+    root: Window,
+    (depth, bits_per_pixel): (u8, u8),
+    property: (&'static str, Atom),
+    budget: &MemoryBudget,
+    report: &mut LoadReport,
+) -> xcb::Result<Option<(PreviousCopy, Reservation)>> {
+    let Some(id) = resolve_atom(connection, root, property, report)? else {
+        return Ok(None);
+    };
+    // SAFETY: Only ever used as a drawable, which the server validates
+    let old = unsafe { Pixmap::new(id) };
+
+    // The property may well outlive the pixmap it names
+    let geometry = connection.wait_for_reply(connection.send_request(&GetGeometry {
+        drawable: Drawable::Pixmap(old),
+    }));
+    let geometry = match geometry {
+        Ok(geometry) => geometry,
+        Err(xcb::Error::Protocol(_)) => {
+            report.warn(LoadWarning::PreviousUnavailable(
+                "its pixmap no longer exists",
+            ));
+            return Ok(None);
+        }
+        Err(e) => return Err(e),
+    };
+
+    if geometry.depth() != depth {
+        report.warn(LoadWarning::PreviousUnavailable(
+            "its pixmap does not match the root depth",
+        ));
+        return Ok(None);
+    }
+
+    let (width, height) = (geometry.width(), geometry.height());
+    let bytes = width as usize * height as usize * bits_per_pixel.div_ceil(8) as usize;
+    let Ok(memory) = budget.reserve(MemoryConsumer::Previous, bytes) else {
+        report.warn(LoadWarning::PreviousUnavailable(
+            "it does not fit the memory budget",
+        ));
+        return Ok(None);
+    };
+
+    let pixmap = connection.generate_id();
+    void_request!(
+        connection,
+        &CreatePixmap {
+            depth,
+            pid: pixmap,
+            drawable: Drawable::Window(root),
+            width,
+            height,
+        }
+    )?;
+
+    let gc = connection.generate_id();
+    void_request!(
+        connection,
+        &CreateGc {
+            cid: gc,
+            drawable: Drawable::Pixmap(pixmap),
+            value_list: &[],
+        }
+    )?;
+    let copied = void_request!(
+        connection,
+        &CopyArea {
+            src_drawable: Drawable::Pixmap(old),
+            dst_drawable: Drawable::Pixmap(pixmap),
+            gc,
+            src_x: 0,
+            src_y: 0,
+            dst_x: 0,
+            dst_y: 0,
+            width,
+            height,
+        }
+    );
+    void_request!(connection, &FreeGc { gc })?;
+    copied?;
+
+    info!(
+        "Kept previous wallpaper {width}x{height} as pixmap {:?}",
+        pixmap
+    );
+    Ok(Some(((pixmap, width, height), memory)))
+}
+
+/// The wallpaper that was set before this handle took over, kept when
+/// [`LoadConfig::keep_previous`](super::LoadConfig::keep_previous) asked for it.
+#[derive(Clone, Copy)]
+pub struct PreviousWallpaper<'a> {
+    handle: &'a BackgroundHandle,
+    pixmap: Pixmap,
+    width: u16,
+    height: u16,
+}
+
+impl PreviousWallpaper<'_> {
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Makes the previous wallpaper the root background again and points the root pixmap
+    /// properties back at it. The handle keeps drawing into its own pixmap, which stops showing
+    /// until it is loaded anew.
+    ///
+    /// Fails with [`Error::GeometryChanged`] if the screen was resized since, as the old pixmap
+    /// would no longer cover it.
+    pub fn restore(&self) -> Result<()> {
+        let connection = &self.handle.connection;
+        let root = self.handle.root;
+
+        let geometry = cookie_request!(
+            connection,
+            &GetGeometry {
+                drawable: Drawable::Window(root),
+            }
+        )?;
+        let now = (geometry.width(), geometry.height());
+        let kept = (self.handle.width, self.handle.height);
+        if now != kept {
+            return Err(Error::GeometryChanged { kept, now });
+        }
+
+        // Consumers reading only one of the properties never see them disagree
+        let atoms = intern_atoms(connection, ROOT_PMAP_ATOMS, false)?;
+        let (set, _) = self.handle.root_properties.partition(atoms);
+        let grab = ServerGrab::new(connection)?;
+        set_pmap_atoms(connection, root, &set, self.pixmap.resource_id())?;
+        drop(grab);
+
+        void_request!(
+            connection,
+            &ChangeWindowAttributes {
+                window: root,
+                value_list: &[Cw::BackPixmap(self.pixmap)],
+            }
+        )?;
+        void_request!(
+            connection,
+            &ClearArea {
+                exposures: false,
+                window: root,
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            }
+        )?;
+
+        Ok(())
+    }
+}
+
+impl BackgroundHandle {
+    /// The wallpaper this handle replaced, if it was asked to keep it and there was one to keep.
+    pub fn previous(&self) -> Option<PreviousWallpaper<'_>> {
+        self.previous
+            .map(|(pixmap, width, height)| PreviousWallpaper {
+                handle: self,
+                pixmap,
+                width,
+                height,
+            })
+    }
+}
@@ -0,0 +1,185 @@
+use xcb::{
+    x::{
+        ChangeWindowAttributes, ColorFlag, Coloritem, Colormap, ColormapAlloc, CreateColormap, Cw,
+        InstallColormap, Setup, StoreColors, Visualtype, Window,
+    },
+    Connection,
+};
+
+use super::VisualInfo;
+use crate::{
+    canvas::Colors,
+    encode::{ByteOrder, ServerFormat},
+    Canvas, Error, Pixel, Result,
+};
+
+// Bits kept per channel when looking up palette indices, 5 makes for a 32 KiB table
+const LUT_BITS: u32 = 5;
+
+/// A private colormap for PseudoColor roots, filled with a fixed palette the buffer is quantized
+/// to on every flush.
+///
+/// Once loading succeeded the colormap is never freed: like the pixmap it has to outlive the
+/// connection for the wallpaper to keep its colors.
+pub(crate) struct PaletteMap {
+    pub(crate) colormap: Colormap,
+    // Palette index of every color, at LUT_BITS per channel
+    lut: Box<[u8]>,
+    // Rows are padded to this many bytes
+    scanline_pad: usize,
+}
+
+// A color cube as fine as the colormap allows, at most 6 levels per channel, with the cells left
+// over spent on grays
+fn palette(entries: usize) -> Vec<Pixel> {
+    let entries = entries.min(256);
+    let levels = (1..=6).rev().find(|n| n * n * n <= entries).unwrap_or(1);
+    let level = |i: usize| (i * 255 / (levels - 1).max(1)) as u8;
+
+    let mut palette = Vec::with_capacity(entries);
+    for r in 0..levels {
+        for g in 0..levels {
+            for b in 0..levels {
+                palette.push(Pixel::new(level(r), level(g), level(b)));
+            }
+        }
+    }
+
+    let grays = entries - palette.len();
+    for i in 0..grays {
+        let v = ((i + 1) * 255 / (grays + 1)) as u8;
+        palette.push(Pixel::new(v, v, v));
+    }
+
+    palette
+}
+
+impl PaletteMap {
+    /// Allocates the colormap, installs it on the root and stores the palette in it. Only visuals
+    /// with one byte per pixel are supported.
+    pub(crate) fn new(
+        connection: &Connection,
+        setup: &Setup,
+        root: Window,
+        visual: &Visualtype,
+        info: &VisualInfo,
+    ) -> Result<PaletteMap> {
+        if info.bits_per_pixel != 8 {
+            return Err(Error::UnsupportedVisual(info.class));
+        }
+
+        let scanline_pad = setup
+            .pixmap_formats()
+            .iter()
+            .find(|f| f.depth() == info.depth)
+            .map_or(8, |f| f.scanline_pad()) as usize
+            / 8;
+
+        let palette = palette(visual.colormap_entries() as usize);
+
+        let colormap = connection.generate_id();
+        void_request!(
+            connection,
+            &CreateColormap {
+                alloc: ColormapAlloc::All,
+                mid: colormap,
+                window: root,
+                visual: visual.visual_id(),
+            }
+        )?;
+
+        let items: Vec<Coloritem> = palette
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                Coloritem::new(
+                    i as u32,
+                    p.r as u16 * 257,
+                    p.g as u16 * 257,
+                    p.b as u16 * 257,
+                    ColorFlag::RED | ColorFlag::GREEN | ColorFlag::BLUE,
+                )
+            })
+            .collect();
+        void_request!(
+            connection,
+            &StoreColors {
+                cmap: colormap,
+                items: &items,
+            }
+        )?;
+
+        void_request!(
+            connection,
+            &ChangeWindowAttributes {
+                window: root,
+                value_list: &[Cw::Colormap(colormap)],
+            }
+        )?;
+        void_request!(connection, &InstallColormap { cmap: colormap })?;
+        info!("Installed private colormap with {} colors", palette.len());
+
+        let mut colors = Colors::new(&palette);
+        let lut = (0..1u32 << (3 * LUT_BITS))
+            .map(|key| {
+                let channel = |shift: u32| {
+                    let v = (key >> shift) & ((1 << LUT_BITS) - 1);
+                    (v << (8 - LUT_BITS) | v >> (2 * LUT_BITS - 8)) as u8
+                };
+                let rgb = [channel(2 * LUT_BITS), channel(LUT_BITS), channel(0)];
+                colors.nearest(rgb.map(|v| v as i32)) as u8
+            })
+            .collect();
+
+        Ok(PaletteMap {
+            colormap,
+            lut,
+            scanline_pad: scanline_pad.max(1),
+        })
+    }
+
+    /// The layout of what [`PaletteMap::encode`] makes, for a root of `depth`.
+    pub(crate) fn format(&self, depth: u8) -> ServerFormat {
+        ServerFormat {
+            depth,
+            bits_per_pixel: 8,
+            scanline_pad: (self.scanline_pad * 8) as u8,
+            byte_order: ByteOrder::LsbFirst,
+            red_mask: 0,
+            green_mask: 0,
+            blue_mask: 0,
+        }
+    }
+
+    /// The palette index `pixel` is drawn with.
+    pub(crate) fn index_of(&self, pixel: &Pixel) -> u8 {
+        let shift = 8 - LUT_BITS;
+        let key = ((pixel.r as usize >> shift) << (2 * LUT_BITS))
+            | ((pixel.g as usize >> shift) << LUT_BITS)
+            | (pixel.b as usize >> shift);
+        self.lut[key]
+    }
+
+    /// The palette indices of a rectangle of `buffer`, one byte per pixel with rows padded as the
+    /// server expects them.
+    pub(crate) fn encode(
+        &self,
+        buffer: &Canvas,
+        (x, y, width, height): (u16, u16, u16, u16),
+    ) -> Vec<u8> {
+        let stride = (width as usize).div_ceil(self.scanline_pad) * self.scanline_pad;
+        let mut data = vec![0; stride * height as usize];
+
+        let region = buffer.crop(x as u32, y as u32, width as u32, height as u32);
+        for (row, pixels) in data
+            .chunks_exact_mut(stride)
+            .zip(region.chunks_exact(width.max(1) as usize))
+        {
+            for (index, p) in row.iter_mut().zip(pixels) {
+                *index = self.index_of(p);
+            }
+        }
+
+        data
+    }
+}
@@ -0,0 +1,28 @@
+use std::sync::{Mutex, MutexGuard};
+
+use xcb::Connection;
+
+// Pixmaps of the handles this process has loaded and not dropped yet. Each handle has a
+// connection of its own, which KillClient on its pixmap would take down along with everything
+// else created on it
+static LIVE: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+fn live() -> MutexGuard<'static, Vec<u32>> {
+    LIVE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub(crate) fn register(pixmap: u32) {
+    live().push(pixmap);
+}
+
+pub(crate) fn unregister(pixmap: u32) {
+    live().retain(|&p| p != pixmap);
+}
+
+/// Whether the client owning `pixmap` is this process itself, which loading must never kill:
+/// either `connection`, or that of a handle still alive.
+pub(crate) fn is_ours(connection: &Connection, pixmap: u32) -> bool {
+    let setup = connection.get_setup();
+    let own = pixmap & !setup.resource_id_mask() == setup.resource_id_base();
+    own || live().contains(&pixmap)
+}
@@ -0,0 +1,116 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use super::BackgroundHandle;
+
+/// Something unexpected [`load_with_report`](super::load_with_report) ran into but could carry on
+/// from. Each one is logged as well when the `tracing` feature is enabled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadWarning {
+    /// The root window lacks this property, so no other client had set a wallpaper through it.
+    PropertyNotSet(&'static str),
+    /// The property exists but holds something other than a pixmap id, and was ignored.
+    NotAPixmap { property: &'static str, r#type: u32 },
+    /// The property is a pixmap, but holds no whole id, and was ignored.
+    MalformedProperty(&'static str),
+    /// The client owning this pixmap had already exited by the time it was to be killed.
+    OwnerGone(u32),
+    /// [`LoadConfig::keep_previous`](super::LoadConfig::keep_previous) was set, but the previous
+    /// wallpaper could not be kept for the given reason.
+    PreviousUnavailable(&'static str),
+    /// [`LoadConfig::take_over_from_shade`](super::LoadConfig::take_over_from_shade) was set, but
+    /// the running instance's wallpaper could not be carried over for the given reason.
+    HandoffUnavailable(&'static str),
+    /// Other clients kept replacing the root pixmap properties while their owners were killed.
+    PropertiesStillChanging { attempts: usize },
+}
+
+impl fmt::Display for LoadWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadWarning::PropertyNotSet(property) => write!(f, "{property} is not set"),
+            LoadWarning::NotAPixmap { property, r#type } => {
+                write!(f, "{property} is not a pixmap but of type atom {type}")
+            }
+            LoadWarning::MalformedProperty(property) => {
+                write!(f, "{property} is a pixmap but holds no whole id")
+            }
+            LoadWarning::OwnerGone(pixmap) => write!(f, "Owner of pixmap {pixmap} is already gone"),
+            LoadWarning::PreviousUnavailable(reason) => {
+                write!(f, "Previous wallpaper not kept, {reason}")
+            }
+            LoadWarning::HandoffUnavailable(reason) => {
+                write!(
+                    f,
+                    "Wallpaper of the running shade instance not carried over, {reason}"
+                )
+            }
+            LoadWarning::PropertiesStillChanging { attempts } => write!(
+                f,
+                "Root pixmap properties still changing after {attempts} kills, overwritten anyway"
+            ),
+        }
+    }
+}
+
+/// What loading did besides creating the handle, for tools that want to show it to their users
+/// rather than rely on logging.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    /// Pixmaps of other clients whose owners were killed.
+    pub killed: Vec<u32>,
+    /// Pixmaps of handles this process still has loaded, which are left alone: killing their
+    /// owner would close a connection of this very process.
+    pub spared: Vec<u32>,
+    /// The pixmap of the running shade instance whose wallpaper was carried over, see
+    /// [`LoadConfig::take_over_from_shade`](super::LoadConfig::take_over_from_shade).
+    pub adopted: Option<u32>,
+    /// Whether the `_XROOTPMAP_ID` or `ESETROOT_PMAP_ID` atoms had to be created, i.e. no client
+    /// had set a wallpaper through them since the server started.
+    pub created_atoms: bool,
+    pub warnings: Vec<LoadWarning>,
+    /// How long each phase of loading took, in order. Also logged at debug level.
+    pub timings: Vec<(&'static str, Duration)>,
+}
+
+impl LoadReport {
+    // Properties are re-read while killing their owners, the same finding is only worth one entry
+    pub(crate) fn warn(&mut self, warning: LoadWarning) {
+        if !self.warnings.contains(&warning) {
+            warn!("{warning}");
+            self.warnings.push(warning);
+        }
+    }
+}
+
+/// Times the phases of loading one after the other, each lap ending the current one.
+pub(crate) struct PhaseTimer {
+    since: Instant,
+}
+
+impl PhaseTimer {
+    pub(crate) fn start() -> PhaseTimer {
+        PhaseTimer {
+            since: Instant::now(),
+        }
+    }
+
+    pub(crate) fn lap(&mut self, phase: &'static str, report: &mut LoadReport) {
+        let now = Instant::now();
+        let took = now - self.since;
+        self.since = now;
+
+        debug!("Loading phase {phase:?} took {took:?}");
+        report.timings.push((phase, took));
+    }
+}
+
+impl BackgroundHandle {
+    /// What loading this handle did besides creating it, the same report
+    /// [`load_with_report`](super::load_with_report) returns.
+    pub fn load_report(&self) -> &LoadReport {
+        &self.report
+    }
+}
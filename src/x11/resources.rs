@@ -0,0 +1,65 @@
+use xcb::{
+    x::{Colormap, DestroyWindow, FreeColormap, FreePixmap, Pixmap, Window},
+    Connection,
+};
+
+/// A server side resource created along the way of loading.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Resource {
+    Pixmap(Pixmap),
+    Colormap(Colormap),
+    Window(Window),
+}
+
+/// Frees every resource it was handed when dropped, unless disarmed first.
+///
+/// Loading can fail halfway, and a caller retrying [`load`](super::load) until the session is
+/// ready must not pile up pixmaps on the server. Closing the connection frees them as well, but
+/// only until RetainPermanent is set, so failures are cleaned up explicitly rather than relying
+/// on where in loading they happen.
+pub(crate) struct ResourceGuard<'a> {
+    connection: &'a Connection,
+    resources: Vec<Resource>,
+}
+
+impl<'a> ResourceGuard<'a> {
+    pub(crate) fn new(connection: &'a Connection) -> ResourceGuard<'a> {
+        ResourceGuard {
+            connection,
+            resources: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, resource: Resource) {
+        self.resources.push(resource);
+    }
+
+    /// Keeps every resource alive, loading went through.
+    pub(crate) fn disarm(mut self) {
+        self.resources.clear();
+    }
+}
+
+impl Drop for ResourceGuard<'_> {
+    fn drop(&mut self) {
+        // Newest first, a window may well use the colormap created before it
+        for resource in self.resources.drain(..).rev() {
+            let freed = match resource {
+                Resource::Pixmap(pixmap) => {
+                    void_request!(self.connection, &FreePixmap { pixmap })
+                }
+                Resource::Colormap(cmap) => {
+                    void_request!(self.connection, &FreeColormap { cmap })
+                }
+                Resource::Window(window) => {
+                    void_request!(self.connection, &DestroyWindow { window })
+                }
+            };
+
+            match freed {
+                Ok(()) => debug!("Freed {resource:?} after failing to load"),
+                Err(e) => warn!("Failed to free {resource:?} after failing to load: {e}"),
+            }
+        }
+    }
+}
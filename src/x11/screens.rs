@@ -0,0 +1,43 @@
+use xcb::Xid;
+
+use super::connect;
+use crate::Result;
+
+/// A screen of the X display, as listed by [`screens`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScreenInfo {
+    /// The screen number, as taken by [`LoadConfig::screen`](super::LoadConfig::screen).
+    pub number: i32,
+    pub root: u32,
+    pub width: u16,
+    pub height: u16,
+    /// The physical size the server reports, in millimeters.
+    pub mm_width: u16,
+    pub mm_height: u16,
+    pub depth: u8,
+    /// Whether `DISPLAY` names this screen, the one everything goes to unless told otherwise.
+    pub default: bool,
+}
+
+/// Lists the screens of the display, in the order their numbers go, without touching the
+/// wallpaper. Most displays have a single one, screens being separate roots rather than the
+/// monitors of one, see [`BackgroundHandle::monitors`](super::BackgroundHandle::monitors).
+pub fn screens() -> Result<Vec<ScreenInfo>> {
+    let (connection, default_screen) = connect()?;
+
+    Ok(connection
+        .get_setup()
+        .roots()
+        .enumerate()
+        .map(|(number, screen)| ScreenInfo {
+            number: number as i32,
+            root: screen.root().resource_id(),
+            width: screen.width_in_pixels(),
+            height: screen.height_in_pixels(),
+            mm_width: screen.width_in_millimeters(),
+            mm_height: screen.height_in_millimeters(),
+            depth: screen.root_depth(),
+            default: number as i32 == default_screen,
+        })
+        .collect())
+}
@@ -0,0 +1,364 @@
+use std::fmt;
+
+use image::RgbImage;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+use xcb::x::{
+    CreateGc, CreatePixmap, Drawable, FreeGc, FreePixmap, ImageFormat::ZPixmap, PutImage,
+};
+
+use super::{
+    capabilities::PROBE_SCHEMA, capture::capture, connect, encode::encode_region, json::Json,
+    screen, GcConfig, VisualInfo,
+};
+use crate::{encode::ServerFormat, Canvas, Error, Pixel, Result};
+
+// Small enough to fit any request, and every coordinate still gets a distinct 5 bit code, which
+// survives even 16 bit visuals
+const PATTERN_SIZE: u16 = 32;
+
+// Every way the three channels can be mixed up, as the captured channel each expected one ended
+// up in
+const CHANNEL_ORDERS: [([usize; 3], &str); 6] = [
+    ([0, 1, 2], "RGB"),
+    ([2, 1, 0], "BGR"),
+    ([0, 2, 1], "RBG"),
+    ([1, 0, 2], "GRB"),
+    ([1, 2, 0], "GBR"),
+    ([2, 0, 1], "BRG"),
+];
+
+/// The outcome of [`self_test`].
+#[derive(Clone, Debug)]
+pub struct SelfTestReport {
+    pub visual: VisualInfo,
+    /// The test pattern as uploaded.
+    pub expected: Canvas,
+    /// The pattern as read back, `None` if the server refused the upload.
+    pub captured: Option<Canvas>,
+    /// Pixels that came back further off than the visual's precision explains.
+    pub mismatched: usize,
+    pub mean_error: [f64; 3],
+    pub max_error: [u8; 3],
+    /// The channel order that best explains the captured pattern, "RGB" when nothing is swapped.
+    pub channel_order: &'static str,
+    /// Likely explanations for whatever went wrong, empty if the round trip was faithful.
+    pub hypotheses: Vec<String>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.captured.is_some() && self.mismatched == 0 && self.hypotheses.is_empty()
+    }
+
+    /// The per pixel difference between the uploaded and captured pattern, amplified so small
+    /// rounding errors remain visible. `None` if nothing was captured.
+    pub fn diff_image(&self) -> Option<RgbImage> {
+        let captured = self.captured.as_ref()?;
+        let (width, height) = (self.expected.width(), self.expected.height());
+
+        Some(RgbImage::from_fn(width, height, |x, y| {
+            let (e, c) = match (self.expected.get_pixel(x, y), captured.get_pixel(x, y)) {
+                (Some(e), Some(c)) => (e, c),
+                _ => return image::Rgb([255, 0, 255]),
+            };
+            let diff = |a: u8, b: u8| a.abs_diff(b).saturating_mul(4);
+            image::Rgb([diff(e.r, c.r), diff(e.g, c.g), diff(e.b, c.b)])
+        }))
+    }
+
+    /// The report as a single JSON object, laid out as [`PROBE_SCHEMA`] describes: `"schema"`
+    /// and whether it `"passed"` first, then the visual as
+    /// [`Capabilities::to_json`](super::Capabilities::to_json) has it. The patterns themselves
+    /// are left out, `"captured"` only tells whether there is one, `"pixels"` how large they
+    /// are.
+    ///
+    /// Serializing with serde, under the `serde` feature, comes out the same.
+    pub fn to_json(&self) -> String {
+        self.to_value().to_string()
+    }
+
+    pub(crate) fn to_value(&self) -> Json {
+        Json::Object(vec![
+            ("schema", Json::Number(PROBE_SCHEMA.into())),
+            ("passed", Json::Bool(self.passed())),
+            ("visual", self.visual.to_value()),
+            ("captured", Json::Bool(self.captured.is_some())),
+            ("pixels", Json::Number(self.expected.len() as u64)),
+            ("mismatched", Json::Number(self.mismatched as u64)),
+            (
+                "mean_error",
+                Json::Array(self.mean_error.map(Json::Float).to_vec()),
+            ),
+            ("max_error", Json::numbers(&self.max_error)),
+            ("channel_order", Json::String(self.channel_order.into())),
+            (
+                "hypotheses",
+                Json::Array(self.hypotheses.iter().cloned().map(Json::String).collect()),
+            ),
+        ])
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for SelfTestReport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.to_value().serialize(serializer)
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let v = &self.visual;
+        writeln!(
+            f,
+            "visual:     {:#x} {:?}, depth {}, {} bpp, masks r {:#08x} g {:#08x} b {:#08x}",
+            v.visual_id, v.class, v.depth, v.bits_per_pixel, v.red_mask, v.green_mask, v.blue_mask
+        )?;
+
+        if self.captured.is_some() {
+            writeln!(
+                f,
+                "mismatched: {} of {} pixels",
+                self.mismatched,
+                self.expected.len()
+            )?;
+            writeln!(
+                f,
+                "error:      mean r {:.2} g {:.2} b {:.2}, max r {} g {} b {}",
+                self.mean_error[0],
+                self.mean_error[1],
+                self.mean_error[2],
+                self.max_error[0],
+                self.max_error[1],
+                self.max_error[2]
+            )?;
+            writeln!(f, "order:      {}", self.channel_order)?;
+        }
+
+        if self.hypotheses.is_empty() {
+            write!(f, "result:     round trip is faithful")
+        } else {
+            write!(f, "result:     round trip is off")?;
+            for hypothesis in &self.hypotheses {
+                write!(f, "\n  - {hypothesis}")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Uploads a test pattern to a scratch pixmap the way [`BackgroundHandle::flush`] uploads the
+/// buffer, reads it back and compares the two. The wallpaper itself is left alone.
+///
+/// [`BackgroundHandle::flush`]: super::BackgroundHandle::flush
+pub fn self_test() -> Result<SelfTestReport> {
+    let (connection, screen_number) = connect()?;
+    let setup = connection.get_setup();
+    let screen = screen(setup, screen_number)?;
+    let depth = screen.root_depth();
+
+    let visual = screen
+        .allowed_depths()
+        .flat_map(|d| d.visuals())
+        .find(|v| v.visual_id() == screen.root_visual())
+        .ok_or(Error::NoVisualFound)?;
+    let info = VisualInfo::new(setup, depth, visual);
+    let format =
+        ServerFormat::from_setup(setup, depth, visual).ok_or(Error::NoPixmapFormat(depth))?;
+
+    let pixmap = connection.generate_id();
+    void_request!(
+        &connection,
+        &CreatePixmap {
+            depth,
+            pid: pixmap,
+            drawable: Drawable::Window(screen.root()),
+            width: PATTERN_SIZE,
+            height: PATTERN_SIZE,
+        }
+    )?;
+
+    let gc = connection.generate_id();
+    void_request!(
+        &connection,
+        &CreateGc {
+            cid: gc,
+            drawable: Drawable::Pixmap(pixmap),
+            value_list: &GcConfig::new().value_list(visual),
+        }
+    )?;
+
+    let expected = pattern();
+    let region = (0, 0, PATTERN_SIZE, PATTERN_SIZE);
+    let data = encode_region(&expected, region, &format, depth, None);
+    let uploaded = void_request!(
+        &connection,
+        &PutImage {
+            format: ZPixmap,
+            drawable: Drawable::Pixmap(pixmap),
+            gc,
+            width: PATTERN_SIZE,
+            height: PATTERN_SIZE,
+            dst_x: 0,
+            dst_y: 0,
+            left_pad: 0,
+            depth,
+            data: &data,
+        }
+    );
+
+    let captured = match uploaded {
+        Ok(()) => Some(capture(
+            &connection,
+            Drawable::Pixmap(pixmap),
+            &info,
+            (0, 0, PATTERN_SIZE, PATTERN_SIZE),
+        )?),
+        Err(_) => None,
+    };
+
+    void_request!(&connection, &FreeGc { gc })?;
+    void_request!(&connection, &FreePixmap { pixmap })?;
+
+    Ok(analyze(info, expected, captured))
+}
+
+// Red and green encode the coordinates, so a captured pixel tells where it came from. Blue mixes
+// both, so no two channels carry the same pattern and swaps can be told apart
+fn pattern() -> Canvas {
+    let size = PATTERN_SIZE as u32;
+    let code = |v: u32| (v * 255 / (size - 1)) as u8;
+    let mut canvas = Canvas::new(size, size);
+
+    for y in 0..size {
+        for x in 0..size {
+            canvas.set_pixel(x, y, Pixel::new(code(x), code(y), code((x + y) % size)));
+        }
+    }
+
+    canvas
+}
+
+fn analyze(visual: VisualInfo, expected: Canvas, captured: Option<Canvas>) -> SelfTestReport {
+    let mut report = SelfTestReport {
+        visual,
+        expected,
+        captured: None,
+        mismatched: 0,
+        mean_error: [0.0; 3],
+        max_error: [0; 3],
+        channel_order: "RGB",
+        hypotheses: Vec::new(),
+    };
+
+    let Some(captured) = captured else {
+        report.hypotheses.push(
+            "the server rejected the upload, its length does not match the pixel format it \
+             announced"
+                .into(),
+        );
+        return report;
+    };
+
+    let channels = |p: &Pixel| [p.r, p.g, p.b];
+    let expected = &report.expected;
+
+    // The order that leaves the least error is the one the channels actually ended up in
+    let (order, name) = CHANNEL_ORDERS
+        .into_iter()
+        .min_by_key(|(order, _)| {
+            expected
+                .iter()
+                .zip(captured.iter())
+                .map(|(e, c)| {
+                    let (e, c) = (channels(e), channels(c));
+                    (0..3)
+                        .map(|i| e[i].abs_diff(c[order[i]]) as u64)
+                        .sum::<u64>()
+                })
+                .sum::<u64>()
+        })
+        .unwrap();
+
+    report.channel_order = name;
+    if order != [0, 1, 2] {
+        report.hypotheses.push(format!(
+            "channels arrive as {name}: they are stored elsewhere than the visual's masks \
+             describe"
+        ));
+    }
+
+    // Anything within one step of the visual's precision is rounding, not an error
+    let tolerance = [visual.red_mask, visual.green_mask, visual.blue_mask]
+        .map(|mask| 256u32.checked_shr(mask.count_ones()).unwrap_or(0).max(1) as u8);
+
+    let mut sums = [0u64; 3];
+    for (e, c) in expected.iter().zip(captured.iter()) {
+        let (e, c) = (channels(e), channels(c));
+        let mut off = false;
+
+        for i in 0..3 {
+            let error = e[i].abs_diff(c[i]);
+            sums[i] += error as u64;
+            report.max_error[i] = report.max_error[i].max(error);
+            off |= error > tolerance[i];
+        }
+
+        report.mismatched += off as usize;
+    }
+    report.mean_error = sums.map(|s| s as f64 / expected.len().max(1) as f64);
+
+    let bits = [visual.red_mask, visual.green_mask, visual.blue_mask].map(u32::count_ones);
+    if bits.iter().any(|&b| b < 8) && report.mismatched > 0 {
+        report.hypotheses.push(format!(
+            "the visual keeps {}/{}/{} bits per channel, but the upload comes back off by more \
+             than that explains",
+            bits[0], bits[1], bits[2]
+        ));
+    }
+
+    if let Some(shift) = shear(&captured, order) {
+        report.hypotheses.push(format!(
+            "each row is shifted {shift} pixels further than the one above: the row stride shade \
+             sends differs from the one the server expects"
+        ));
+    }
+
+    report.captured = Some(captured);
+    report
+}
+
+// Decodes which column each captured pixel came from, and checks whether rows drift by a steady
+// amount, the telltale diagonal of a stride mismatch
+fn shear(captured: &Canvas, order: [usize; 3]) -> Option<i64> {
+    let size = PATTERN_SIZE as i64;
+    let decode = |v: u8| (v as i64 * (size - 1) + 127) / 255;
+
+    let row_offset = |y: u32| -> Option<i64> {
+        let mut offsets: Vec<i64> = (0..PATTERN_SIZE as u32)
+            .filter_map(|x| {
+                let p = captured.get_pixel(x, y)?;
+                let red = [p.r, p.g, p.b][order[0]];
+                Some((decode(red) - x as i64).rem_euclid(size))
+            })
+            .collect();
+        offsets.sort_unstable();
+        offsets.get(offsets.len() / 2).copied()
+    };
+
+    let first = row_offset(0)?;
+    let second = row_offset(1)?;
+    let shift = (second - first).rem_euclid(size);
+
+    if shift == 0 {
+        return None;
+    }
+
+    // A single odd row is noise, a constant drift over most rows is shearing
+    let steady = (0..PATTERN_SIZE as u32)
+        .filter(|&y| row_offset(y) == Some((first + shift * y as i64).rem_euclid(size)))
+        .count();
+
+    (steady * 2 > PATTERN_SIZE as usize).then_some(shift)
+}
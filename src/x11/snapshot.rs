@@ -0,0 +1,83 @@
+use super::BackgroundHandle;
+use crate::{canvas::Reservation, Canvas, Error, MemoryConsumer, Pixel, Rect, Result};
+
+/// A copy of (part of) the buffer, see [`BackgroundHandle::snapshot`]. Counts against the
+/// handle's memory budget for as long as it, or any clone of it, lives.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pixels: Canvas,
+    rect: Rect,
+    geometry: (u32, u32),
+    _memory: Reservation,
+}
+
+impl PartialEq for Snapshot {
+    fn eq(&self, other: &Snapshot) -> bool {
+        (&self.pixels, self.rect, self.geometry) == (&other.pixels, other.rect, other.geometry)
+    }
+}
+
+impl Snapshot {
+    /// Where in the buffer the snapshot was taken, clipped to it.
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    pub fn pixels(&self) -> &Canvas {
+        &self.pixels
+    }
+}
+
+impl BackgroundHandle {
+    /// Copies `rect` of the buffer aside, or all of it for `None`, to be put back later with
+    /// [`BackgroundHandle::restore`].
+    ///
+    /// Fails with [`Error::MemoryBudgetExceeded`] if the copy does not fit the
+    /// [memory budget](super::LoadConfig::memory_budget).
+    pub fn snapshot(&self, rect: Option<Rect>) -> Result<Snapshot> {
+        let buffer = self.lock_buffer();
+        let rect = rect.map_or(buffer.bounds(), |r| r.intersect(&buffer.bounds()));
+
+        let bytes = rect.width as usize * rect.height as usize * std::mem::size_of::<Pixel>();
+        let memory = self
+            .memory_budget
+            .reserve(MemoryConsumer::Snapshots, bytes)?;
+
+        Ok(Snapshot {
+            pixels: buffer.crop(rect.x as u32, rect.y as u32, rect.width, rect.height),
+            rect,
+            geometry: (buffer.width(), buffer.height()),
+            _memory: memory,
+        })
+    }
+
+    /// Puts a snapshot back into the buffer, with its top left corner at `dst` or where it was
+    /// taken from for `None`. Pixels are copied as they are, whatever the buffer's blend mode.
+    /// Call [`BackgroundHandle::flush_region`] to show it.
+    ///
+    /// Fails with [`Error::StaleSnapshot`] if the buffer has been replaced by one of another size
+    /// since, as the snapshot no longer lines up with it.
+    pub fn restore(&self, snapshot: &Snapshot, dst: Option<(i32, i32)>) -> Result<()> {
+        let mut buffer = self.lock_buffer();
+
+        let now = (buffer.width(), buffer.height());
+        if now != snapshot.geometry {
+            return Err(Error::StaleSnapshot {
+                taken: snapshot.geometry,
+                now,
+            });
+        }
+
+        let (x, y) = dst.unwrap_or((snapshot.rect.x, snapshot.rect.y));
+        buffer.paste(&snapshot.pixels, x, y);
+        Ok(())
+    }
+
+    /// Copies `canvas` into the buffer with its top left corner at (x, y), clipping whatever
+    /// falls outside, e.g. the [`pixels`](Snapshot::pixels) of a snapshot after
+    /// [`Canvas::rotate90`]. Pixels are copied as they are, whatever the buffer's blend mode.
+    /// Call [`BackgroundHandle::flush_region`] to show it.
+    pub fn paste(&self, canvas: &Canvas, x: i32, y: i32) {
+        self.lock_buffer().paste(canvas, x, y);
+    }
+}
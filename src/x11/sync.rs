@@ -0,0 +1,175 @@
+use std::{sync::MutexGuard, time::Duration};
+
+use xcb::{
+    x::{self, GetInputFocus},
+    ProtocolError, RequestWithoutReply, VoidCookieChecked,
+};
+
+use super::BackgroundHandle;
+use crate::{Error, Result};
+
+// Past this many unchecked requests the oldest are checked on the spot, keeping memory bounded for
+// callers that never sync. Their errors are kept for the next sync
+const MAX_UNCHECKED: usize = 1024;
+
+// How often a chunk is sent before its flush gives up, and how long to wait before the first
+// retry, doubling after that
+const CHUNK_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(20);
+
+// Only a server that ran short of memory may well take the same request a moment later, anything
+// else would fail again just the same
+fn retryable(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::XCBInteral(xcb::Error::Protocol(ProtocolError::X(
+            x::Error::Alloc(_),
+            _
+        )))
+    )
+}
+
+/// How flushes wait for the server, see [`BackgroundHandle::set_flush_mode`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlushMode {
+    /// Every request is checked before returning, so a flush reports its own errors. Costs a
+    /// round trip to the server per request.
+    #[default]
+    Checked,
+    /// Requests are only sent, their errors are collected by [`BackgroundHandle::sync`]. Much
+    /// faster for animations, which can sync every so often instead.
+    Unchecked,
+}
+
+/// What a flush repaints on the root window for the uploaded pixels to show, see
+/// [`FlushOptions::clear`].
+///
+/// Every repaint also makes pseudo transparent windows, such as terminals, redraw their
+/// background, which flickers when many small updates each trigger one. Compositing managers
+/// need the repaint just as much, as its damage is how they notice the wallpaper changed, so the
+/// default is the same whether [`BackgroundHandle::compositor_active`] or not.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClearPolicy {
+    /// Repaints the whole root window.
+    Full,
+    /// Repaints just the flushed region.
+    #[default]
+    Region,
+    /// Repaints nothing, e.g. to batch several regional flushes and then repaint once with
+    /// [`BackgroundHandle::clear_root`]. Without a compositor the new pixels stay hidden until
+    /// then.
+    None,
+}
+
+/// Options for [`BackgroundHandle::flush_with`] and [`BackgroundHandle::flush_region_with`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlushOptions {
+    pub(crate) clear: ClearPolicy,
+}
+
+impl FlushOptions {
+    pub fn new() -> FlushOptions {
+        FlushOptions::default()
+    }
+
+    /// What to repaint once uploaded, [`ClearPolicy::Region`] by default.
+    pub fn clear(mut self, clear: ClearPolicy) -> FlushOptions {
+        self.clear = clear;
+        self
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Unchecked {
+    mode: FlushMode,
+    cookies: Vec<VoidCookieChecked>,
+    errors: Vec<Error>,
+}
+
+impl BackgroundHandle {
+    fn lock_unchecked(&self) -> MutexGuard<'_, Unchecked> {
+        self.unchecked.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Checks every outstanding request, moving their errors along with the ones found earlier
+    // out of `unchecked`
+    fn check_unchecked(&self, unchecked: &mut Unchecked) -> Vec<Error> {
+        self.check_cookies(unchecked);
+        std::mem::take(&mut unchecked.errors)
+    }
+
+    fn check_cookies(&self, unchecked: &mut Unchecked) {
+        for cookie in std::mem::take(&mut unchecked.cookies) {
+            if let Err(e) = self.connection.check_request(cookie) {
+                unchecked.errors.push(xcb::Error::from(e).into());
+            }
+        }
+    }
+
+    /// Sends a request of the flush path, checked or not depending on the [`FlushMode`].
+    pub(crate) fn send_flush_request<R: RequestWithoutReply>(&self, request: &R) -> Result<()> {
+        let mut unchecked = self.lock_unchecked();
+        if unchecked.mode == FlushMode::Checked {
+            drop(unchecked);
+            return void_request!(&self.connection, request).map_err(Error::from);
+        }
+
+        unchecked
+            .cookies
+            .push(self.connection.send_request_checked(request));
+
+        if unchecked.cookies.len() > MAX_UNCHECKED {
+            self.check_cookies(&mut unchecked);
+        }
+        Ok(())
+    }
+
+    /// [`BackgroundHandle::send_flush_request`], retrying a few times on errors that may go away
+    /// on their own. Only checked requests can be retried, unchecked ones fail later in a sync.
+    pub(crate) fn send_flush_request_retrying<R: RequestWithoutReply>(
+        &self,
+        request: &R,
+    ) -> Result<()> {
+        let mut attempt = 1;
+        loop {
+            match self.send_flush_request(request) {
+                Err(e) if attempt < CHUNK_ATTEMPTS && retryable(&e) => {
+                    let delay = RETRY_DELAY * 2u32.pow(attempt - 1);
+                    warn!("Chunk upload failed ({e}), retrying in {delay:?}");
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Switches how [`BackgroundHandle::flush`] and [`BackgroundHandle::flush_region`] wait for
+    /// the server. Errors from requests sent unchecked are still collected when switching back.
+    pub fn set_flush_mode(&self, mode: FlushMode) {
+        debug!("Flush mode set to {mode:?}");
+        self.lock_unchecked().mode = mode;
+    }
+
+    pub fn flush_mode(&self) -> FlushMode {
+        self.lock_unchecked().mode
+    }
+
+    /// Waits for the server to process everything sent so far and returns the errors of requests
+    /// sent since the last sync in [`FlushMode::Unchecked`]. Fails only if the connection itself
+    /// does, in which case the collected errors are kept for the next call.
+    pub fn sync(&self) -> Result<Vec<Error>> {
+        // Any reply at all means every earlier request has been processed, so checking the
+        // cookies below needs no further round trips
+        cookie_request!(&self.connection, &GetInputFocus {})?;
+
+        let mut unchecked = self.lock_unchecked();
+        Ok(self.check_unchecked(&mut unchecked))
+    }
+
+    // Errors nobody synced for, so they at least end up in the log
+    pub(crate) fn take_unsynced_errors(&self) -> Vec<Error> {
+        let mut unchecked = self.lock_unchecked();
+        self.check_unchecked(&mut unchecked)
+    }
+}
@@ -0,0 +1,106 @@
+//! Hooks for checking what flushes send without a server to inspect, and what failed loads leave
+//! behind, only built with the `testing` feature.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use xcb::x::PutImage;
+
+use super::{
+    encode::{bands, encode_region},
+    BackgroundHandle,
+};
+use crate::{encode::ServerFormat, Canvas};
+
+// How many of the coming loads are still to fail, see fail_loads
+static FAILING_LOADS: AtomicUsize = AtomicUsize::new(0);
+
+/// One PutImage request as [`BackgroundHandle::flush`] sent it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PutImageRecord {
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+    pub depth: u8,
+    pub data: Vec<u8>,
+}
+
+/// The bytes a flush of `region` would send for `buffer` to a root laid out as `format`, with the
+/// target depth set to `target_depth` (0 for unset). Needs no connection, so encoding can be
+/// checked for any format whatever the local server uses.
+pub fn put_image_payload(
+    buffer: &Canvas,
+    region: (u16, u16, u16, u16),
+    format: &ServerFormat,
+    target_depth: u8,
+) -> Vec<u8> {
+    let target_depth = match target_depth {
+        0 => format.depth,
+        depth => depth,
+    };
+    encode_region(buffer, region, format, target_depth, None)
+}
+
+/// The PutImage requests a flush of `region` would be split into, at most `rows` rows each as a
+/// rate limit or the request size would have it. See [`put_image_payload`] for the other
+/// arguments.
+pub fn put_image_requests(
+    buffer: &Canvas,
+    region: (u16, u16, u16, u16),
+    format: &ServerFormat,
+    target_depth: u8,
+    rows: usize,
+) -> Vec<PutImageRecord> {
+    let data = put_image_payload(buffer, region, format, target_depth);
+    let max_bytes = rows
+        .max(1)
+        .saturating_mul(format.row_bytes(region.2 as u32));
+
+    bands(&data, format, region, max_bytes)
+        .into_iter()
+        .flatten()
+        .map(|band| PutImageRecord {
+            x: band.dst_x,
+            y: band.dst_y,
+            width: band.width,
+            height: band.height,
+            depth: format.depth,
+            data: band.data.to_vec(),
+        })
+        .collect()
+}
+
+/// Makes the next `count` loads in this process fail with
+/// [`Error::InjectedFailure`](crate::Error::InjectedFailure) once they created their pixmaps, as
+/// a load failing halfway would. Replaces whatever count was left from an earlier call.
+pub fn fail_loads(count: usize) {
+    FAILING_LOADS.store(count, Ordering::SeqCst);
+}
+
+// Uses up one of the failures fail_loads asked for, if any are left
+pub(crate) fn take_load_failure() -> bool {
+    FAILING_LOADS
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+        .is_ok()
+}
+
+impl BackgroundHandle {
+    pub(crate) fn record_put_image(&self, request: &PutImage<'_>) {
+        self.put_images
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(PutImageRecord {
+                x: request.dst_x,
+                y: request.dst_y,
+                width: request.width,
+                height: request.height,
+                depth: request.depth,
+                data: request.data.to_vec(),
+            });
+    }
+
+    /// Every PutImage sent since the last call, oldest first.
+    pub fn take_put_images(&self) -> Vec<PutImageRecord> {
+        std::mem::take(&mut self.put_images.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    sync::MutexGuard,
+    time::{Duration, Instant},
+};
+
+use super::{BackgroundHandle, ClearPolicy};
+use crate::Result;
+
+// Target length of one rate limited chunk, short enough that other traffic on the link gets a
+// word in between them
+const CHUNK_DURATION: Duration = Duration::from_millis(50);
+
+pub(crate) type Region = (u16, u16, u16, u16);
+
+/// Per handle upload limits, both off by default.
+#[derive(Default)]
+pub(crate) struct Throttle {
+    // Bytes per second, 0 for unlimited
+    max_rate: u64,
+    min_interval: Duration,
+    // When everything sent so far should have gone through the link at `max_rate`
+    link_free: Option<Instant>,
+    last_flush: Option<Instant>,
+    // Bounding box of flushes coalesced into the next one
+    pending: Option<Region>,
+}
+
+fn union(a: Region, b: Region) -> Region {
+    let x = a.0.min(b.0);
+    let y = a.1.min(b.1);
+    let right = (a.0 as u32 + a.2 as u32).max(b.0 as u32 + b.2 as u32);
+    let bottom = (a.1 as u32 + a.3 as u32).max(b.1 as u32 + b.3 as u32);
+
+    (x, y, (right - x as u32) as u16, (bottom - y as u32) as u16)
+}
+
+impl Throttle {
+    /// The region to upload now, or `None` if the last flush was too recent, in which case
+    /// `region` is remembered and goes out with the next one.
+    pub(crate) fn admit(&mut self, region: Region) -> Option<Region> {
+        let region = match self.pending.take() {
+            Some(pending) => union(pending, region),
+            None => region,
+        };
+
+        let now = Instant::now();
+        if let Some(last) = self.last_flush {
+            if now.duration_since(last) < self.min_interval {
+                self.pending = Some(region);
+                return None;
+            }
+        }
+
+        self.last_flush = Some(now);
+        Some(region)
+    }
+
+    /// Makes sure `region` goes out with the next flush, whatever that flush is asked to send.
+    pub(crate) fn defer(&mut self, region: Region) {
+        self.pending = Some(match self.pending.take() {
+            Some(pending) => union(pending, region),
+            None => region,
+        });
+    }
+
+    /// Takes the coalesced region along with how long to wait before the interval allows it out.
+    pub(crate) fn take_pending(&mut self) -> Option<(Region, Duration)> {
+        let region = self.pending.take()?;
+        let wait = self
+            .last_flush
+            .map(|last| (last + self.min_interval).saturating_duration_since(Instant::now()))
+            .unwrap_or_default();
+
+        self.last_flush = Some(Instant::now() + wait);
+        Some((region, wait))
+    }
+
+    /// How many bytes to send per request, `None` when unlimited.
+    pub(crate) fn chunk_bytes(&self) -> Option<usize> {
+        (self.max_rate != 0).then(|| (self.max_rate as f64 * CHUNK_DURATION.as_secs_f64()) as usize)
+    }
+
+    /// Books `bytes` onto the link and returns how long to sleep before sending them.
+    pub(crate) fn reserve(&mut self, bytes: usize) -> Duration {
+        if self.max_rate == 0 {
+            return Duration::ZERO;
+        }
+
+        let now = Instant::now();
+        let start = self.link_free.map_or(now, |free| free.max(now));
+        self.link_free = Some(start + Duration::from_secs_f64(bytes as f64 / self.max_rate as f64));
+        start - now
+    }
+}
+
+impl BackgroundHandle {
+    pub(crate) fn lock_throttle(&self) -> MutexGuard<'_, Throttle> {
+        self.throttle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Caps the upload bandwidth of this handle, 0 meaning unlimited (the default). Flushes are
+    /// split into chunks with sleeps in between, so over a slow link, e.g. X forwarded through
+    /// SSH, an animation can no longer starve everything else of bandwidth.
+    pub fn set_max_upload_rate(&self, bytes_per_sec: u64) {
+        let mut throttle = self.lock_throttle();
+        throttle.max_rate = bytes_per_sec;
+        throttle.link_free = None;
+    }
+
+    /// Flushes coming in less than `interval` after the previous one are not sent but merged into
+    /// the next, `Duration::ZERO` (the default) sends every flush. Call
+    /// [`BackgroundHandle::flush_pending`] after the last frame so it is not left behind.
+    pub fn set_min_flush_interval(&self, interval: Duration) {
+        self.lock_throttle().min_interval = interval;
+    }
+
+    /// Waits out the minimum flush interval and uploads whatever flushes were held back,
+    /// repainting just that region. Returns `false` if there was nothing pending.
+    pub fn flush_pending(&self) -> Result<bool> {
+        let Some(((x, y, width, height), wait)) = self.lock_throttle().take_pending() else {
+            return Ok(false);
+        };
+
+        std::thread::sleep(wait);
+        self.upload_region(x, y, width, height, ClearPolicy::Region)?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,102 @@
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+use xcb::x::{Screen, Setup, Visualtype};
+
+pub use xcb::x::VisualClass;
+
+use super::{connect, json::Json, BackgroundHandle};
+use crate::{Error, Result};
+
+/// How a visual lays out its pixels, as reported by the server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VisualInfo {
+    pub visual_id: u32,
+    pub depth: u8,
+    pub bits_per_pixel: u8,
+    pub red_mask: u32,
+    pub green_mask: u32,
+    pub blue_mask: u32,
+    pub class: VisualClass,
+}
+
+impl VisualInfo {
+    pub(crate) fn new(setup: &Setup, depth: u8, visual: &Visualtype) -> VisualInfo {
+        // Every depth a screen allows has a matching pixmap format, should a broken server skip
+        // one the depth itself is the best guess
+        let bits_per_pixel = setup
+            .pixmap_formats()
+            .iter()
+            .find(|f| f.depth() == depth)
+            .map_or(depth, |f| f.bits_per_pixel());
+
+        VisualInfo {
+            visual_id: visual.visual_id(),
+            depth,
+            bits_per_pixel,
+            red_mask: visual.red_mask(),
+            green_mask: visual.green_mask(),
+            blue_mask: visual.blue_mask(),
+            class: visual.class(),
+        }
+    }
+
+    // The visual as it appears in the probe reports, see PROBE_SCHEMA. The class goes out as
+    // its name
+    pub(crate) fn to_value(self) -> Json {
+        Json::Object(vec![
+            ("visual_id", Json::Number(self.visual_id.into())),
+            ("depth", Json::Number(self.depth.into())),
+            ("bits_per_pixel", Json::Number(self.bits_per_pixel.into())),
+            ("red_mask", Json::Number(self.red_mask.into())),
+            ("green_mask", Json::Number(self.green_mask.into())),
+            ("blue_mask", Json::Number(self.blue_mask.into())),
+            ("class", Json::String(format!("{:?}", self.class))),
+        ])
+    }
+
+    /// Fails for visuals whose pixels index a colormap rather than hold the color itself, which
+    /// packing by channel masks would turn into garbage. DirectColor does pass, its colormap only
+    /// remaps each channel and is usually left at identity.
+    pub(crate) fn check_class(&self) -> Result<()> {
+        debug!("Root visual {:#x} is {:?}", self.visual_id, self.class);
+
+        match self.class {
+            VisualClass::TrueColor | VisualClass::DirectColor => Ok(()),
+            class => Err(Error::UnsupportedVisual(class)),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for VisualInfo {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.to_value().serialize(serializer)
+    }
+}
+
+impl BackgroundHandle {
+    /// The root visual the buffer is encoded for.
+    pub fn visual_info(&self) -> VisualInfo {
+        self.visual_info
+    }
+}
+
+/// Lists every visual of every depth the default screen allows, without touching the wallpaper.
+pub fn probe_visuals() -> Result<Vec<VisualInfo>> {
+    let (connection, screen_number) = connect()?;
+    let setup = connection.get_setup();
+    let screen = super::screen(setup, screen_number)?;
+
+    Ok(screen_visuals(setup, screen).collect())
+}
+
+fn screen_visuals<'a>(
+    setup: &'a Setup,
+    screen: &'a Screen,
+) -> impl Iterator<Item = VisualInfo> + 'a {
+    screen.allowed_depths().flat_map(move |d| {
+        d.visuals()
+            .iter()
+            .map(move |v| VisualInfo::new(setup, d.depth(), v))
+    })
+}
@@ -0,0 +1,223 @@
+// Names every public item, so that removing or renaming one, or changing the signature of a free
+// function, fails to compile here before it reaches a release. Nothing is called that needs an X
+// server; a new item should be added here along with it.
+
+use std::path::PathBuf;
+
+use shade::{
+    canvas::{Filter, FilterChain, Frame, FrameBudget, FrameRecorder, ImageCache},
+    encode::{chunk_plan, encode_rows, ByteOrder, Chunk, ServerFormat, PUT_IMAGE_HEADER_BYTES},
+    patterns::{srgb_ramp, srgb_ramp_sized, RampRow, RAMP_LEVELS, RAMP_ROWS},
+    BlendMode, CacheStats, CancelToken, Canvas, CanvasView, CatmullRom, Error, IntegerScaler,
+    MemoryBudget, MemoryConsumer, MemoryReport, Pixel, PixelAspect, PixelFormat, Placement,
+    PreparedImage, Rect, Resampling, Result, Rgb8, Rgba8, Scaler, Scaling, ScalingMethod,
+    TextColor, WallpaperSource, CANCEL_LATENCY, MIN_TEXT_CONTRAST,
+};
+
+#[test]
+fn free_functions() {
+    let _: fn(Option<&[PathBuf]>) -> Vec<PathBuf> = shade::discover_wallpapers;
+    let _: fn(u8) -> Option<[u32; 3]> = shade::channel_bits;
+    let _: fn(u32, u32, u32, u32, ScalingMethod) -> (Rect, Rect) = shade::compute_placement;
+    let _: fn(u32, u32, u32, u32, ScalingMethod, PixelAspect) -> (Rect, Rect) =
+        shade::compute_placement_with_aspect;
+    let _: fn(f64, f64) -> f64 = shade::contrast_ratio;
+    let _: fn(f32) -> [u8; 256] = shade::gamma_lut;
+    let _: fn(&Pixel) -> f64 = shade::relative_luminance;
+    let _: fn() -> Vec<&'static str> = shade::supported_formats;
+    let _: fn() -> Canvas = srgb_ramp;
+    let _: fn(u32) -> Canvas = srgb_ramp_sized;
+    let _: fn(&ServerFormat, u16, u16, usize) -> Option<Vec<Chunk>> = chunk_plan;
+    let _: fn(&ServerFormat, Vec<&'static [Pixel]>) -> Vec<u8> = encode_rows;
+}
+
+#[test]
+fn types_and_constants() {
+    fn named<T: ?Sized>() {}
+
+    named::<(
+        BlendMode,
+        CacheStats,
+        CancelToken,
+        Canvas,
+        CanvasView<'static>,
+    )>();
+    named::<(CatmullRom, Error, IntegerScaler, MemoryBudget, MemoryReport)>();
+    named::<(
+        Pixel,
+        PixelAspect,
+        Placement,
+        PreparedImage,
+        Rect,
+        Resampling,
+    )>();
+    named::<(
+        Rgb8,
+        Rgba8,
+        Scaling,
+        ScalingMethod,
+        TextColor,
+        WallpaperSource,
+    )>();
+    named::<(
+        Filter,
+        FilterChain,
+        Frame,
+        FrameBudget,
+        FrameRecorder,
+        ImageCache,
+    )>();
+    named::<(ByteOrder, Chunk, ServerFormat, RampRow, Result<()>)>();
+    named::<MemoryConsumer>();
+    named::<dyn Scaler>();
+    fn format<F: PixelFormat>() {}
+    format::<Rgb8>();
+    format::<Rgba8>();
+
+    let _: (u32, usize, [RampRow; 4]) = (RAMP_LEVELS, PUT_IMAGE_HEADER_BYTES, RAMP_ROWS);
+    let _ = (CANCEL_LATENCY, MIN_TEXT_CONTRAST);
+}
+
+#[cfg(feature = "x11")]
+#[test]
+fn x11() {
+    use shade::{
+        x11::{parse_pixmap_property, FillStyle, Gx, PixmapProperty, VisualClass},
+        AppliedInfo, AppliedSource, Applier, ApplyOutcome, BackgroundHandle, Capabilities,
+        ClearPolicy, Completion, DesktopWallpapers, FlushMode, FlushOptions, GammaReport, GcConfig,
+        LazyHandle, LoadConfig, LoadPlan, LoadReport, LoadWarning, Metadata, Monitor,
+        MonitorAspect, OpenMethod, PausePolicy, PlanEntry, PlanTarget, PreviousWallpaper,
+        ProbeReport, RootProperties, ScreenInfo, SelfTestReport, ServerGrab, ShadeEvent, Shutdown,
+        Snapshot, Span, Transition, VisualInfo, WallpaperPlan, PROBE_SCHEMA,
+    };
+
+    fn named<T: ?Sized>() {}
+
+    let _: fn() -> Result<Capabilities> = shade::capabilities;
+    let _: fn(&xcb::Connection, i32) -> Result<bool> = shade::compositor_active;
+    let _: fn() -> Result<Option<Metadata>> = shade::current_metadata;
+    let _: fn(OpenMethod) -> Result<&'static BackgroundHandle> = shade::load;
+    let _: fn(OpenMethod, LoadConfig) -> Result<&'static BackgroundHandle> = shade::load_with;
+    let _: fn(OpenMethod, LoadConfig) -> Result<(&'static BackgroundHandle, LoadReport)> =
+        shade::load_with_report;
+    let _: fn(OpenMethod, LoadConfig) -> Result<BackgroundHandle> = BackgroundHandle::open;
+    let _: fn(&OpenMethod) -> Result<LoadPlan> = shade::plan;
+    let _: fn(&OpenMethod, &LoadConfig) -> Result<LoadPlan> = shade::plan_with;
+    let _: fn(&WallpaperSource, ScalingMethod, (u16, u16)) -> Result<()> = shade::preview;
+    let _: fn() -> Result<Vec<VisualInfo>> = shade::probe_visuals;
+    let _: fn() -> Result<Vec<ScreenInfo>> = shade::screens;
+    let _: fn() -> Result<SelfTestReport> = shade::self_test;
+    let _: fn(WallpaperSource, ScalingMethod) -> Result<()> = shade::set_once;
+    let _ = parse_pixmap_property;
+
+    named::<(
+        AppliedInfo,
+        AppliedSource,
+        Applier,
+        ApplyOutcome,
+        ClearPolicy,
+        Completion,
+    )>();
+    named::<(
+        DesktopWallpapers,
+        FlushMode,
+        FlushOptions,
+        GammaReport,
+        GcConfig,
+    )>();
+    named::<(
+        LazyHandle,
+        LoadWarning,
+        Monitor,
+        MonitorAspect,
+        PausePolicy,
+        PlanEntry,
+    )>();
+    named::<(
+        PlanTarget,
+        PreviousWallpaper,
+        ProbeReport,
+        RootProperties,
+        ServerGrab,
+    )>();
+    named::<(
+        ShadeEvent,
+        Shutdown,
+        Snapshot,
+        Span,
+        Transition,
+        WallpaperPlan,
+    )>();
+    named::<(FillStyle, Gx, PixmapProperty, VisualClass)>();
+
+    let _: u32 = PROBE_SCHEMA;
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn testing() {
+    use shade::x11::testing::{fail_loads, put_image_payload, put_image_requests, PutImageRecord};
+
+    let _: fn(usize) = fail_loads;
+    let _ = (put_image_payload, put_image_requests);
+    let _: Option<PutImageRecord> = None;
+}
+
+#[cfg(feature = "adaptive")]
+#[test]
+fn adaptive() {
+    use shade::{
+        adaptive::{run, run_until, AdaptiveOptions, AdaptiveTint, Debounce},
+        BackgroundHandle,
+    };
+
+    fn named<T: ?Sized>() {}
+
+    let _: fn(&BackgroundHandle, &AdaptiveOptions) -> Result<()> = run;
+    let _: fn(&BackgroundHandle, &AdaptiveOptions, &CancelToken) -> Result<()> = run_until;
+    named::<(AdaptiveTint, Debounce<()>)>();
+}
+
+#[cfg(feature = "power")]
+#[test]
+fn power() {
+    use shade::power::{power_source, PowerMonitor, PowerPolicy, PowerSource};
+
+    fn named<T: ?Sized>() {}
+
+    let _: fn() -> PowerSource = power_source;
+    named::<(PowerMonitor, PowerPolicy)>();
+}
+
+#[cfg(feature = "signals")]
+#[test]
+fn daemon() {
+    use shade::daemon::{run, Daemon, DaemonEvent, DaemonOptions, SIGNALS};
+
+    fn named<T: ?Sized>() {}
+
+    let _: fn(WallpaperSource, DaemonOptions) -> Result<()> = run;
+    let _: &[i32] = SIGNALS;
+    named::<(Daemon, DaemonEvent)>();
+}
+
+#[test]
+fn prelude() {
+    use shade::prelude::*;
+
+    let mut canvas = Canvas::new(4, 2);
+    canvas.fill(Pixel::new(1, 2, 3));
+    let _: (Rect, Scaling, ScalingMethod) = (
+        Rect::default(),
+        Scaling::new(ScalingMethod::Max),
+        ScalingMethod::Fill,
+    );
+    let _ = WallpaperSource::Color(canvas[0].clone());
+
+    #[cfg(feature = "x11")]
+    {
+        let _: fn(OpenMethod) -> shade::Result<&'static BackgroundHandle> = load;
+        let _: fn(OpenMethod, Options) -> shade::Result<&'static BackgroundHandle> = load_with;
+        let _: LoadConfig = Options::default();
+    }
+}
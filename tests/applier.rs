@@ -0,0 +1,145 @@
+#![cfg(feature = "x11")]
+
+mod common;
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use shade::{
+    Applier, ApplyOutcome, BackgroundHandle, LoadConfig, OpenMethod, Pixel, ScalingMethod,
+    Shutdown, Transition, WallpaperSource,
+};
+
+const SLOW_FADE: Transition = Transition::Fade {
+    duration: Duration::from_secs(1),
+    steps: 50,
+};
+
+fn open() -> BackgroundHandle {
+    BackgroundHandle::open(OpenMethod::MakeNew, LoadConfig::default()).expect("failed to load")
+}
+
+// A source that notes down whether it was ever rendered
+fn generator(color: Pixel, rendered: &Arc<AtomicBool>) -> WallpaperSource {
+    let rendered = rendered.clone();
+    WallpaperSource::Generator(Box::new(move |canvas| {
+        rendered.store(true, Ordering::SeqCst);
+        canvas.fill(color.clone());
+    }))
+}
+
+fn outcomes(applier: Applier, shutdown: Shutdown) -> (BackgroundHandle, Vec<(u64, ApplyOutcome)>) {
+    let (handle, completions) = applier.shutdown(shutdown);
+    let mut outcomes: Vec<_> = completions
+        .into_iter()
+        .map(|c| (c.ticket, c.outcome))
+        .collect();
+    outcomes.sort_by_key(|(ticket, _)| *ticket);
+    (handle, outcomes)
+}
+
+fn buffer_color(handle: &BackgroundHandle) -> Pixel {
+    handle.buffer.lock().unwrap()[0].clone()
+}
+
+#[test]
+fn burst_applies_only_the_latest() {
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let applier = Applier::new(open(), ScalingMethod::Fill, SLOW_FADE);
+    let first = applier.submit(WallpaperSource::Color(Pixel::new(255, 0, 0)));
+    // Well into the fade of the first by now
+    thread::sleep(Duration::from_millis(200));
+
+    let rendered = Arc::new(AtomicBool::new(false));
+    let second = applier.submit(generator(Pixel::new(0, 255, 0), &rendered));
+    let last = applier.submit(WallpaperSource::Color(Pixel::new(0, 0, 255)));
+
+    let (handle, outcomes) = outcomes(applier, Shutdown::ApplyPending);
+    assert!(matches!(
+        outcomes.as_slice(),
+        [
+            (f, ApplyOutcome::Superseded),
+            (s, ApplyOutcome::Superseded),
+            (l, ApplyOutcome::Applied),
+        ] if (*f, *s, *l) == (first, second, last)
+    ));
+    assert!(
+        !rendered.load(Ordering::SeqCst),
+        "a superseded source was rendered"
+    );
+    assert_eq!(buffer_color(&handle), Pixel::new(0, 0, 255));
+}
+
+#[test]
+fn every_ticket_completes_once() {
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let applier = Applier::new(open(), ScalingMethod::Fill, Transition::Cut);
+    let tickets: Vec<u64> = (0..20u8)
+        .map(|i| applier.submit(WallpaperSource::Color(Pixel::new(i, i, i))))
+        .collect();
+
+    let (handle, outcomes) = outcomes(applier, Shutdown::ApplyPending);
+    let completed: Vec<u64> = outcomes.iter().map(|(ticket, _)| *ticket).collect();
+    assert_eq!(completed, tickets);
+    assert!(outcomes
+        .iter()
+        .all(|(_, o)| matches!(o, ApplyOutcome::Applied | ApplyOutcome::Superseded)));
+    assert!(matches!(outcomes.last(), Some((_, ApplyOutcome::Applied))));
+    assert_eq!(buffer_color(&handle), Pixel::new(19, 19, 19));
+}
+
+#[test]
+fn shutdown_applying_pending() {
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let applier = Applier::new(open(), ScalingMethod::Fill, SLOW_FADE);
+    let ticket = applier.submit(WallpaperSource::Color(Pixel::new(10, 20, 30)));
+
+    let (handle, outcomes) = outcomes(applier, Shutdown::ApplyPending);
+    assert!(matches!(
+        outcomes.as_slice(),
+        [(t, ApplyOutcome::Applied)] if *t == ticket
+    ));
+    // The fade ran to its end rather than stopping where it was
+    assert_eq!(buffer_color(&handle), Pixel::new(10, 20, 30));
+    handle.flush().expect("the handle handed back still works");
+}
+
+#[test]
+fn shutdown_dropping_pending() {
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let applier = Applier::new(open(), ScalingMethod::Fill, SLOW_FADE);
+    let fading = applier.submit(WallpaperSource::Color(Pixel::new(255, 255, 255)));
+    thread::sleep(Duration::from_millis(200));
+
+    let rendered = Arc::new(AtomicBool::new(false));
+    let pending = applier.submit(generator(Pixel::new(0, 255, 0), &rendered));
+
+    let (handle, outcomes) = outcomes(applier, Shutdown::DropPending);
+    assert!(matches!(
+        outcomes.as_slice(),
+        [(f, ApplyOutcome::Dropped), (p, ApplyOutcome::Dropped)] if (*f, *p) == (fading, pending)
+    ));
+    assert!(
+        !rendered.load(Ordering::SeqCst),
+        "a dropped source was rendered"
+    );
+    handle.flush().expect("the handle handed back still works");
+}
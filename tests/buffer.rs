@@ -0,0 +1,87 @@
+#![cfg(feature = "x11")]
+
+mod common;
+
+use shade::{canvas::Rect, BackgroundHandle, Error, LoadConfig, OpenMethod, Pixel};
+
+fn open() -> BackgroundHandle {
+    BackgroundHandle::open(OpenMethod::MakeNew, LoadConfig::default()).expect("failed to load")
+}
+
+// The way code written against the plain Box<[Pixel]> buffer draws
+#[test]
+fn buffer_indexes_like_a_slice() {
+    let Some(_server) = common::server(16, 8) else {
+        return;
+    };
+
+    let handle = open();
+    {
+        let mut buffer = handle.buffer.lock().unwrap();
+        assert_eq!(buffer.len(), 16 * 8);
+        buffer[0] = Pixel::new(1, 2, 3);
+        buffer[16..32].fill(Pixel::new(4, 5, 6));
+        for pixel in buffer.iter_mut().skip(32) {
+            *pixel = Pixel::new(7, 8, 9);
+        }
+    }
+    handle.flush().unwrap();
+
+    let buffer = handle.buffer.lock().unwrap();
+    assert_eq!(buffer[0], Pixel::new(1, 2, 3));
+    assert_eq!(buffer[17], Pixel::new(4, 5, 6));
+    assert_eq!(buffer[16 * 8 - 1], Pixel::new(7, 8, 9));
+}
+
+#[test]
+fn replacing_pixels() {
+    let Some(_server) = common::server(16, 8) else {
+        return;
+    };
+
+    let handle = open();
+    let pixels = vec![Pixel::new(9, 9, 9); 16 * 8].into_boxed_slice();
+    let old = handle.replace_pixels(pixels).unwrap();
+    assert_eq!(old.len(), 16 * 8);
+    assert_eq!(handle.buffer.lock().unwrap()[5], Pixel::new(9, 9, 9));
+
+    let short = vec![Pixel::default(); 16].into_boxed_slice();
+    assert!(matches!(
+        handle.replace_pixels(short),
+        Err(Error::PixelCountMismatch {
+            expected: 128,
+            found: 16,
+        })
+    ));
+    assert_eq!(handle.buffer.lock().unwrap()[5], Pixel::new(9, 9, 9));
+}
+
+#[test]
+fn pasting_turned_snapshots_clips_at_the_edges() {
+    let Some(_server) = common::server(16, 8) else {
+        return;
+    };
+
+    let handle = open();
+    {
+        let mut buffer = handle.buffer.lock().unwrap();
+        buffer.fill(Pixel::new(1, 1, 1));
+        for (x, y) in (0..4).flat_map(|x| (0..2).map(move |y| (x, y))) {
+            buffer.set_pixel(x, y, Pixel::new(x as u8, y as u8, 100));
+        }
+    }
+
+    let snapshot = handle.snapshot(Some(Rect::new(0, 0, 4, 2))).unwrap();
+    let turned = snapshot.pixels().rotate90();
+    assert_eq!((turned.width(), turned.height()), (2, 4));
+
+    // Hanging off the top right and bottom left corners, a single pixel lands each time
+    handle.paste(&turned, 15, -3);
+    handle.paste(&turned, -1, 7);
+
+    let buffer = handle.buffer.lock().unwrap();
+    assert_eq!(buffer.get_pixel(15, 0), Some(&Pixel::new(3, 1, 100)));
+    assert_eq!(buffer.get_pixel(0, 7), Some(&Pixel::new(0, 0, 100)));
+    let touched = buffer.iter().filter(|p| p.b == 100).count();
+    assert_eq!(touched, 4 * 2 + 2);
+}
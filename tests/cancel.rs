@@ -0,0 +1,212 @@
+#![cfg(feature = "x11")]
+
+mod common;
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use shade::{
+    Applier, ApplyOutcome, BackgroundHandle, CancelToken, DesktopWallpapers, LoadConfig,
+    OpenMethod, Pixel, ScalingMethod, Transition, WallpaperSource, CANCEL_LATENCY,
+};
+use xcb::x;
+
+// Everything cancelled has to have stopped within this, well above CANCEL_LATENCY plus whatever
+// frame was under way
+const STOP_WITHIN: Duration = Duration::from_millis(500);
+
+fn open() -> BackgroundHandle {
+    BackgroundHandle::open(OpenMethod::MakeNew, LoadConfig::default()).expect("failed to load")
+}
+
+fn buffer_color(handle: &BackgroundHandle) -> Pixel {
+    handle.buffer.lock().unwrap()[0].clone()
+}
+
+// Polls until the buffer shows `color`, failing after a second
+fn wait_for_color(handle: &BackgroundHandle, color: &Pixel) {
+    let start = Instant::now();
+    while buffer_color(handle) != *color {
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "never showed {color:?}"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+// What a window manager would do on switching desktops
+fn switch_desktop(desktop: u32) {
+    let (connection, screen) = xcb::Connection::connect(None).unwrap();
+    let root = connection
+        .get_setup()
+        .roots()
+        .nth(screen as usize)
+        .unwrap()
+        .root();
+    let atom = connection
+        .wait_for_reply(connection.send_request(&x::InternAtom {
+            only_if_exists: false,
+            name: b"_NET_CURRENT_DESKTOP",
+        }))
+        .unwrap()
+        .atom();
+    connection
+        .send_and_check_request(&x::ChangeProperty {
+            mode: x::PropMode::Replace,
+            window: root,
+            property: atom,
+            r#type: x::ATOM_CARDINAL,
+            data: &[desktop],
+        })
+        .unwrap();
+}
+
+#[test]
+fn applier_stops_fading_once_cancelled() {
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let cancel = CancelToken::new();
+    let fade = Transition::Fade {
+        duration: Duration::from_secs(5),
+        steps: 100,
+    };
+    let applier = Applier::with_cancel(open(), ScalingMethod::Fill, fade, cancel.clone());
+    let fading = applier.submit(WallpaperSource::Color(Pixel::new(255, 0, 0)));
+    thread::sleep(Duration::from_millis(200));
+
+    cancel.cancel();
+    let completion = applier
+        .completions()
+        .recv_timeout(STOP_WITHIN)
+        .expect("the fade went on after cancelling");
+    assert_eq!(completion.ticket, fading);
+    assert!(matches!(completion.outcome, ApplyOutcome::Dropped));
+
+    // Nothing is applied any more, but the handle still comes back
+    let late = applier.submit(WallpaperSource::Color(Pixel::new(0, 255, 0)));
+    let completion = applier.completions().recv_timeout(CANCEL_LATENCY).unwrap();
+    assert_eq!(completion.ticket, late);
+    assert!(matches!(completion.outcome, ApplyOutcome::Dropped));
+
+    let (handle, rest) = applier.shutdown(Default::default());
+    assert!(rest.is_empty());
+    assert_ne!(buffer_color(&handle), Pixel::new(0, 255, 0));
+}
+
+#[test]
+fn applier_cancelled_while_idle() {
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let cancel = CancelToken::new();
+    let applier =
+        Applier::with_cancel(open(), ScalingMethod::Fill, Transition::Cut, cancel.clone());
+    cancel.cancel();
+
+    let start = Instant::now();
+    let (_, rest) = applier.shutdown(Default::default());
+    assert!(rest.is_empty());
+    assert!(start.elapsed() < STOP_WITHIN);
+}
+
+#[test]
+fn following_desktops_until_cancelled() {
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let red = Pixel::new(255, 0, 0);
+    let blue = Pixel::new(0, 0, 255);
+    let handle = open();
+    let wallpapers = DesktopWallpapers::new()
+        .desktop(0, WallpaperSource::Color(red.clone()))
+        .desktop(1, WallpaperSource::Color(blue.clone()));
+    switch_desktop(0);
+
+    let cancel = CancelToken::new();
+    thread::scope(|scope| {
+        let follower =
+            scope.spawn(|| handle.follow_desktops_until(&wallpapers, ScalingMethod::Fill, &cancel));
+
+        wait_for_color(&handle, &red);
+        switch_desktop(1);
+        wait_for_color(&handle, &blue);
+
+        let start = Instant::now();
+        cancel.cancel();
+        follower.join().unwrap().expect("following failed");
+        assert!(start.elapsed() < STOP_WITHIN);
+    });
+
+    // Switches after cancelling are no longer followed
+    switch_desktop(0);
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(buffer_color(&handle), blue);
+}
+
+#[test]
+fn following_desktops_already_cancelled() {
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let handle = open();
+    let wallpapers =
+        DesktopWallpapers::new().desktop(0, WallpaperSource::Color(Pixel::new(1, 2, 3)));
+    switch_desktop(0);
+
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    let start = Instant::now();
+    handle
+        .follow_desktops_until(&wallpapers, ScalingMethod::Fill, &cancel)
+        .expect("following failed");
+    assert!(start.elapsed() < STOP_WITHIN);
+}
+
+#[cfg(feature = "adaptive")]
+#[test]
+fn adaptive_tinting_until_cancelled() {
+    use shade::adaptive::{run_until, AdaptiveOptions};
+
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let handle = open();
+    let options = AdaptiveOptions::default();
+    let cancel = CancelToken::new();
+    thread::scope(|scope| {
+        let tinting = scope.spawn(|| run_until(&handle, &options, &cancel));
+        // Long enough for the first focus change to settle and the loop to go idle
+        thread::sleep(Duration::from_millis(500));
+
+        let start = Instant::now();
+        cancel.cancel();
+        tinting.join().unwrap().expect("tinting failed");
+        assert!(start.elapsed() < STOP_WITHIN);
+    });
+}
+
+#[cfg(feature = "adaptive")]
+#[test]
+fn adaptive_tinting_already_cancelled() {
+    use shade::adaptive::{run_until, AdaptiveOptions};
+
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let handle = open();
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    let start = Instant::now();
+    run_until(&handle, &AdaptiveOptions::default(), &cancel).expect("tinting failed");
+    assert!(start.elapsed() < STOP_WITHIN);
+}
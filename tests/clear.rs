@@ -0,0 +1,58 @@
+#![cfg(feature = "x11")]
+
+mod common;
+
+use shade::{BackgroundHandle, LoadConfig, OpenMethod, Pixel};
+
+#[test]
+fn new_pixmaps_start_out_in_the_clear_color() {
+    let Some(_server) = common::server(32, 16) else {
+        return;
+    };
+
+    let red = Pixel::new(255, 0, 0);
+    let handle = BackgroundHandle::open(
+        OpenMethod::MakeNew,
+        LoadConfig::default().clear_color(red.clone()),
+    )
+    .expect("failed to load");
+
+    // Nothing was flushed, the root repaints from what the pixmap was cleared to
+    handle.clear_root().unwrap();
+    assert!(common::capture_root(0, 0, 32, 16).iter().all(|p| *p == red));
+    assert!(handle.buffer.lock().unwrap().iter().all(|p| *p == red));
+}
+
+#[test]
+fn the_default_clear_color_is_black() {
+    let Some(_server) = common::server(32, 16) else {
+        return;
+    };
+
+    let handle =
+        BackgroundHandle::open(OpenMethod::MakeNew, LoadConfig::default()).expect("failed to load");
+
+    let black = Pixel::new(0, 0, 0);
+    handle.clear_root().unwrap();
+    assert!(common::capture_root(0, 0, 32, 16)
+        .iter()
+        .all(|p| *p == black));
+    assert!(handle.buffer.lock().unwrap().iter().all(|p| *p == black));
+}
+
+// The clear is a fill on the server, not an upload of the buffer
+#[cfg(feature = "testing")]
+#[test]
+fn clearing_uploads_nothing() {
+    let Some(_server) = common::server(32, 16) else {
+        return;
+    };
+
+    let handle = BackgroundHandle::open(
+        OpenMethod::MakeNew,
+        LoadConfig::default().clear_color(Pixel::new(0, 0, 255)),
+    )
+    .expect("failed to load");
+    handle.clear_root().unwrap();
+    assert!(handle.take_put_images().is_empty());
+}
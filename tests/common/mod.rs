@@ -0,0 +1,224 @@
+// Shared by the tests that need an X server. Each gets a fresh Xvfb of its own, or $DISPLAY with
+// SHADE_TEST_DISPLAY=1. Without either the test is skipped, as there is nothing to run it on,
+// unless SHADE_REQUIRE_XVFB=1 is set: then a missing Xvfb fails the test instead, so a run that
+// is meant to cover the server cannot pass without touching one
+#![allow(dead_code)]
+
+use std::{
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::{Mutex, MutexGuard},
+};
+
+// Tests of a binary run on threads of their own, but DISPLAY is one for the whole process and
+// loads kill each other's pixmaps, so servers are handed out one at a time
+static SERVER: Mutex<()> = Mutex::new(());
+
+pub struct Server {
+    xvfb: Option<Child>,
+    _turn: MutexGuard<'static, ()>,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let Some(xvfb) = &mut self.xvfb else {
+            return;
+        };
+        // Asked to terminate rather than killed, so it removes its lock file and socket
+        let terminated = Command::new("kill")
+            .arg(xvfb.id().to_string())
+            .status()
+            .is_ok_and(|status| status.success());
+        if !terminated {
+            let _ = xvfb.kill();
+        }
+        let _ = xvfb.wait();
+    }
+}
+
+/// A server of `width`x`height` at depth 24, `None` if none can be had.
+pub fn server(width: u16, height: u16) -> Option<Server> {
+    server_with_screens(&[(width, height)])
+}
+
+/// A server with a depth 24 screen of each size, numbered in order, `None` if none can be had.
+/// With SHADE_TEST_DISPLAY=1 the screens are whatever $DISPLAY has, with SHADE_REQUIRE_XVFB=1
+/// having none panics.
+pub fn server_with_screens(sizes: &[(u16, u16)]) -> Option<Server> {
+    let turn = SERVER.lock().unwrap_or_else(|e| e.into_inner());
+
+    if std::env::var_os("SHADE_TEST_DISPLAY").is_some() {
+        return Some(Server {
+            xvfb: None,
+            _turn: turn,
+        });
+    }
+
+    // Xvfb picks a free display itself and writes its number to stdout once it is up
+    let mut args: Vec<String> = ["-displayfd", "1", "-nolisten", "tcp"]
+        .map(String::from)
+        .into();
+    for (number, (width, height)) in sizes.iter().enumerate() {
+        args.extend([
+            "-screen".into(),
+            number.to_string(),
+            format!("{width}x{height}x24"),
+        ]);
+    }
+    let child = Command::new("Xvfb")
+        .args(&args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut xvfb = match child {
+        Ok(child) => child,
+        Err(e) if std::env::var_os("SHADE_REQUIRE_XVFB").is_some() => {
+            panic!("Xvfb could not be started ({e}) but SHADE_REQUIRE_XVFB is set")
+        }
+        Err(e) => {
+            eprintln!("skipped: Xvfb could not be started ({e}), set SHADE_TEST_DISPLAY=1");
+            return None;
+        }
+    };
+
+    let mut display = String::new();
+    let stdout = xvfb.stdout.take().expect("stdout is piped");
+    let _ = BufReader::new(stdout).read_line(&mut display);
+    let server = Server {
+        xvfb: Some(xvfb),
+        _turn: turn,
+    };
+    let display = display.trim();
+    assert!(!display.is_empty(), "Xvfb did not come up");
+
+    std::env::set_var("DISPLAY", format!(":{display}"));
+    Some(server)
+}
+
+/// What the root window shows in the given rectangle, read through a connection of its own and
+/// decoded for a depth 24 root, row by row.
+pub fn capture_root(x: i16, y: i16, width: u16, height: u16) -> Vec<shade::Pixel> {
+    use xcb::x;
+
+    let (connection, screen) = xcb::Connection::connect(None).unwrap();
+    let setup = connection.get_setup();
+    let root = setup.roots().nth(screen as usize).unwrap();
+    let visual = root
+        .allowed_depths()
+        .flat_map(|d| d.visuals())
+        .find(|v| v.visual_id() == root.root_visual())
+        .unwrap();
+    let bits_per_pixel = setup
+        .pixmap_formats()
+        .iter()
+        .find(|f| f.depth() == root.root_depth())
+        .unwrap()
+        .bits_per_pixel() as usize;
+    let msb_first = setup.image_byte_order() == x::ImageOrder::MsbFirst;
+
+    let reply = connection
+        .wait_for_reply(connection.send_request(&x::GetImage {
+            format: x::ImageFormat::ZPixmap,
+            drawable: x::Drawable::Window(root.root()),
+            x,
+            y,
+            width,
+            height,
+            plane_mask: u32::MAX,
+        }))
+        .unwrap();
+
+    let channel = |value: u32, mask: u32| ((value & mask) >> mask.trailing_zeros()) as u8;
+    reply
+        .data()
+        .chunks_exact(bits_per_pixel / 8)
+        .map(|bytes| {
+            let value = match msb_first {
+                true => bytes.iter().fold(0u32, |v, &b| v << 8 | b as u32),
+                false => bytes.iter().rev().fold(0u32, |v, &b| v << 8 | b as u32),
+            };
+            shade::Pixel::new(
+                channel(value, visual.red_mask()),
+                channel(value, visual.green_mask()),
+                channel(value, visual.blue_mask()),
+            )
+        })
+        .collect()
+}
+
+/// How many resources of `kind`, e.g. `"PIXMAP"`, all clients of the server hold between them,
+/// as X-Resource counts them.
+pub fn server_resources(kind: &str) -> u32 {
+    use xcb::{res, x};
+
+    let (connection, _) =
+        xcb::Connection::connect_with_extensions(None, &[xcb::Extension::Res], &[]).unwrap();
+    let atom = connection
+        .wait_for_reply(connection.send_request(&x::InternAtom {
+            only_if_exists: false,
+            name: kind.as_bytes(),
+        }))
+        .unwrap()
+        .atom();
+
+    let clients = connection
+        .wait_for_reply(connection.send_request(&res::QueryClients {}))
+        .unwrap();
+    clients
+        .clients()
+        .iter()
+        .map(|client| {
+            let resources = connection
+                .wait_for_reply(connection.send_request(&res::QueryClientResources {
+                    xid: client.resource_base,
+                }))
+                .unwrap();
+            resources
+                .types()
+                .iter()
+                .filter(|t| t.resource_type == atom)
+                .map(|t| t.count)
+                .sum::<u32>()
+        })
+        .sum()
+}
+
+/// The pixmap the root property `name`, e.g. `"_XROOTPMAP_ID"`, names, `None` if it is unset.
+pub fn root_pixmap(name: &str) -> Option<u32> {
+    root_pixmap_on(None, name)
+}
+
+/// [`root_pixmap`] on the root of `screen`, or of the screen DISPLAY names for `None`.
+pub fn root_pixmap_on(screen: Option<usize>, name: &str) -> Option<u32> {
+    use xcb::x;
+
+    let (connection, default_screen) = xcb::Connection::connect(None).unwrap();
+    let root = connection
+        .get_setup()
+        .roots()
+        .nth(screen.unwrap_or(default_screen as usize))
+        .unwrap()
+        .root();
+    let atom = connection
+        .wait_for_reply(connection.send_request(&x::InternAtom {
+            only_if_exists: true,
+            name: name.as_bytes(),
+        }))
+        .unwrap()
+        .atom();
+    if atom == x::ATOM_NONE {
+        return None;
+    }
+
+    let reply = connection
+        .wait_for_reply(connection.send_request(&x::GetProperty {
+            delete: false,
+            window: root,
+            property: atom,
+            r#type: x::ATOM_PIXMAP,
+            long_offset: 0,
+            long_length: 1,
+        }))
+        .unwrap();
+    reply.value::<u32>().first().copied()
+}
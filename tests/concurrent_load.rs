@@ -0,0 +1,61 @@
+#![cfg(feature = "testing")]
+
+// load() hands out one handle for the whole process, so this file tests it alone
+
+mod common;
+
+use std::{sync::Barrier, thread};
+
+use shade::{load, x11::testing, Error, OpenMethod};
+
+const THREADS: usize = 16;
+const FAILING: usize = 3;
+
+#[test]
+fn racing_loads_end_up_with_one_pixmap() {
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let pixmaps = common::server_resources("PIXMAP");
+    testing::fail_loads(FAILING);
+
+    let start = Barrier::new(THREADS);
+    let results: Vec<_> = thread::scope(|scope| {
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| {
+                scope.spawn(|| {
+                    start.wait();
+                    load(OpenMethod::MakeNew).map(|handle| handle as *const _ as usize)
+                })
+            })
+            .collect();
+        threads.into_iter().map(|t| t.join().unwrap()).collect()
+    });
+
+    // Every failure is one of the injected ones, every other thread got the same handle
+    let failed = results
+        .iter()
+        .filter(|r| matches!(r, Err(Error::InjectedFailure)))
+        .count();
+    assert_eq!(failed, FAILING);
+    let mut handles: Vec<usize> = results
+        .iter()
+        .filter_map(|r| r.as_ref().ok())
+        .copied()
+        .collect();
+    assert_eq!(handles.len(), THREADS - FAILING);
+    handles.dedup();
+    assert_eq!(handles.len(), 1);
+
+    // Nothing of the failed attempts is left, and the one pixmap is the wallpaper
+    assert_eq!(common::server_resources("PIXMAP"), pixmaps + 1);
+    let wallpaper = common::root_pixmap("_XROOTPMAP_ID");
+    assert!(wallpaper.is_some());
+    assert_eq!(common::root_pixmap("ESETROOT_PMAP_ID"), wallpaper);
+
+    load(OpenMethod::MakeNew)
+        .unwrap()
+        .flush()
+        .expect("failed to flush");
+}
@@ -0,0 +1,57 @@
+#![cfg(feature = "testing")]
+
+mod common;
+
+use shade::{x11::testing, BackgroundHandle, Error, LoadConfig, OpenMethod};
+
+fn open(config: LoadConfig) -> shade::Result<BackgroundHandle> {
+    BackgroundHandle::open(OpenMethod::MakeNew, config)
+}
+
+#[test]
+fn failed_loads_leave_nothing_behind() {
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let pixmaps = common::server_resources("PIXMAP");
+    let windows = common::server_resources("WINDOW");
+
+    testing::fail_loads(5);
+    for _ in 0..5 {
+        assert!(matches!(
+            open(LoadConfig::default()),
+            Err(Error::InjectedFailure)
+        ));
+        assert_eq!(common::server_resources("PIXMAP"), pixmaps);
+        assert_eq!(common::server_resources("WINDOW"), windows);
+    }
+
+    // The retry after them goes through, with just the one pixmap to show for it
+    let _handle = open(LoadConfig::default()).expect("failed to load");
+    assert_eq!(common::server_resources("PIXMAP"), pixmaps + 1);
+}
+
+// Keeping the previous wallpaper copies it into a pixmap of its own, freed along with ours. The
+// first handle still owns the wallpaper, hence forcing
+#[test]
+fn failed_loads_free_the_previous_copy() {
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let first = open(LoadConfig::default()).expect("failed to load");
+    let pixmaps = common::server_resources("PIXMAP");
+
+    testing::fail_loads(3);
+    for _ in 0..3 {
+        assert!(matches!(
+            open(LoadConfig::default().keep_previous(true).force(true)),
+            Err(Error::InjectedFailure)
+        ));
+        assert_eq!(common::server_resources("PIXMAP"), pixmaps);
+    }
+
+    // Failing before the kill, the first handle was never touched
+    first.flush().expect("the first handle still works");
+}
@@ -0,0 +1,144 @@
+#![cfg(feature = "x11")]
+
+mod common;
+
+use shade::{BackgroundHandle, LoadConfig, LoadWarning, OpenMethod};
+use xcb::{x, Xid, XidNew};
+
+// Another wallpaper setter: a pixmap of its own, named by both root properties
+struct Setter {
+    connection: xcb::Connection,
+    pixmap: u32,
+}
+
+impl Setter {
+    fn set(retain: bool) -> Setter {
+        let (connection, screen) = xcb::Connection::connect(None).unwrap();
+        let screen = connection.get_setup().roots().nth(screen as usize).unwrap();
+        let root = screen.root();
+
+        let pixmap: x::Pixmap = connection.generate_id();
+        connection
+            .send_and_check_request(&x::CreatePixmap {
+                depth: screen.root_depth(),
+                pid: pixmap,
+                drawable: x::Drawable::Window(root),
+                width: 8,
+                height: 8,
+            })
+            .unwrap();
+
+        for name in [&b"_XROOTPMAP_ID"[..], b"ESETROOT_PMAP_ID"] {
+            let atom = connection
+                .wait_for_reply(connection.send_request(&x::InternAtom {
+                    only_if_exists: false,
+                    name,
+                }))
+                .unwrap()
+                .atom();
+            connection
+                .send_and_check_request(&x::ChangeProperty {
+                    mode: x::PropMode::Replace,
+                    window: root,
+                    property: atom,
+                    r#type: x::ATOM_PIXMAP,
+                    data: &[pixmap.resource_id()],
+                })
+                .unwrap();
+        }
+
+        // What setters that exit right away do, so the pixmap outlives them
+        if retain {
+            connection
+                .send_and_check_request(&x::SetCloseDownMode {
+                    mode: x::CloseDown::RetainPermanent,
+                })
+                .unwrap();
+        }
+
+        Setter {
+            pixmap: pixmap.resource_id(),
+            connection,
+        }
+    }
+
+    // Disconnects without retaining anything, and waits for the server to have freed the pixmap
+    fn exit(self) -> u32 {
+        let pixmap = self.pixmap;
+        drop(self.connection);
+
+        let (connection, _) = xcb::Connection::connect(None).unwrap();
+        // SAFETY: Only ever used as a drawable, which the server validates
+        let drawable = x::Drawable::Pixmap(unsafe { x::Pixmap::new(pixmap) });
+        while connection
+            .wait_for_reply(connection.send_request(&x::GetGeometry { drawable }))
+            .is_ok()
+        {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        pixmap
+    }
+
+    fn alive(&self) -> bool {
+        self.connection
+            .wait_for_reply(self.connection.send_request(&x::GetInputFocus {}))
+            .is_ok()
+    }
+}
+
+fn open() -> BackgroundHandle {
+    BackgroundHandle::open(OpenMethod::MakeNew, LoadConfig::default()).expect("failed to load")
+}
+
+#[test]
+fn running_owners_are_killed() {
+    let Some(_server) = common::server(32, 32) else {
+        return;
+    };
+
+    let setter = Setter::set(false);
+    let handle = open();
+    let report = handle.load_report();
+    assert_eq!(report.killed, [setter.pixmap]);
+    assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+    assert!(!setter.alive());
+}
+
+// Exited with its resources retained, killing frees them
+#[test]
+fn retained_owners_are_killed() {
+    let Some(_server) = common::server(32, 32) else {
+        return;
+    };
+
+    let pixmap = Setter::set(true).pixmap;
+    let pixmaps = common::server_resources("PIXMAP");
+    let handle = open();
+    assert_eq!(handle.load_report().killed, [pixmap]);
+    // Its pixmap went, ours came
+    assert_eq!(common::server_resources("PIXMAP"), pixmaps);
+}
+
+// Exited before the kill, leaving only its id behind in the properties. The server hands its
+// client slot to the next connection, which may well be the load's own, so the id is either
+// found to belong to nobody or to this very process. Neither is killed
+#[test]
+fn owners_gone_before_the_kill_are_tolerated() {
+    let Some(_server) = common::server(32, 32) else {
+        return;
+    };
+
+    let pixmap = Setter::set(false).exit();
+    let handle = open();
+    let report = handle.load_report();
+    assert!(
+        report.warnings.contains(&LoadWarning::OwnerGone(pixmap)) || report.spared == [pixmap],
+        "{report:?}"
+    );
+    assert_eq!(
+        common::root_pixmap("_XROOTPMAP_ID"),
+        common::root_pixmap("ESETROOT_PMAP_ID")
+    );
+    assert_ne!(common::root_pixmap("_XROOTPMAP_ID"), Some(pixmap));
+    handle.flush().expect("failed to flush");
+}
@@ -0,0 +1,40 @@
+#![cfg(feature = "x11")]
+
+mod common;
+
+use shade::{BackgroundHandle, LoadConfig, OpenMethod, Pixel};
+
+#[test]
+fn xvfb_preserves_every_level() {
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let handle =
+        BackgroundHandle::open(OpenMethod::MakeNew, LoadConfig::default()).expect("failed to load");
+    let report = handle.verify_gamma().expect("failed to verify");
+    assert!(report.preserved(), "{report}");
+    assert_eq!(report.max_error, [0; 3]);
+}
+
+// The ramp goes to a scratch pixmap, the wallpaper stays as it was
+#[test]
+fn verifying_leaves_the_wallpaper_alone() {
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let red = Pixel::new(255, 0, 0);
+    let handle = BackgroundHandle::open(
+        OpenMethod::MakeNew,
+        LoadConfig::default().clear_color(red.clone()),
+    )
+    .expect("failed to load");
+    let pixmaps = common::server_resources("PIXMAP");
+    handle.verify_gamma().expect("failed to verify");
+
+    assert_eq!(common::server_resources("PIXMAP"), pixmaps);
+    handle.clear_root().unwrap();
+    assert!(common::capture_root(0, 0, 64, 64).iter().all(|p| *p == red));
+    assert!(handle.buffer.lock().unwrap().iter().all(|p| *p == red));
+}
@@ -0,0 +1,51 @@
+#![cfg(feature = "x11")]
+
+mod common;
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use shade::{BackgroundHandle, LoadConfig, OpenMethod, Pixel};
+
+// 256x256 at 4 bytes a pixel is 256 KiB, a second's worth at this rate
+const SLOW_RATE: u64 = 256 * 1024;
+
+#[test]
+fn drawing_goes_on_during_a_slow_flush() {
+    let Some(_server) = common::server(256, 256) else {
+        return;
+    };
+
+    let handle =
+        BackgroundHandle::open(OpenMethod::MakeNew, LoadConfig::default()).expect("failed to load");
+    // Anything but a single color, which would be filled server side instead of uploaded
+    for (i, pixel) in handle.buffer.lock().unwrap().iter_mut().enumerate() {
+        *pixel = Pixel::new(i as u8, (i >> 8) as u8, 0);
+    }
+    handle.set_max_upload_rate(SLOW_RATE);
+
+    thread::scope(|scope| {
+        let flushing = scope.spawn(|| {
+            let start = Instant::now();
+            handle.flush().expect("failed to flush");
+            start.elapsed()
+        });
+        // Well into the upload by now
+        thread::sleep(Duration::from_millis(200));
+
+        let start = Instant::now();
+        handle.buffer.lock().unwrap()[0] = Pixel::new(1, 2, 3);
+        let waited = start.elapsed();
+
+        assert!(!flushing.is_finished(), "the flush was not slowed down");
+        assert!(
+            waited < Duration::from_millis(100),
+            "waited {waited:?} on the buffer lock"
+        );
+        assert!(flushing.join().unwrap() > Duration::from_millis(500));
+    });
+
+    assert_eq!(handle.buffer.lock().unwrap()[0], Pixel::new(1, 2, 3));
+}
@@ -0,0 +1,64 @@
+#![cfg(feature = "x11")]
+
+mod common;
+
+use shade::{BackgroundHandle, Error, LoadConfig, MemoryBudget, MemoryConsumer, OpenMethod};
+
+// A 32x32 buffer at 3 bytes a pixel
+const SCREEN: usize = 32 * 32 * 3;
+
+fn open(budget: &MemoryBudget) -> BackgroundHandle {
+    let config = LoadConfig::default().memory_budget(budget.clone());
+    BackgroundHandle::open(OpenMethod::MakeNew, config).expect("failed to load")
+}
+
+#[test]
+fn snapshots_beyond_the_budget_are_denied() {
+    let Some(_server) = common::server(32, 32) else {
+        return;
+    };
+
+    let budget = MemoryBudget::bytes(SCREEN + SCREEN / 2);
+    let handle = open(&budget);
+    let snapshot = handle.snapshot(None).unwrap();
+    assert!(matches!(
+        handle.snapshot(None),
+        Err(Error::MemoryBudgetExceeded {
+            consumer: MemoryConsumer::Snapshots,
+            requested: SCREEN,
+            ..
+        })
+    ));
+
+    let usage = handle.memory_usage();
+    assert_eq!(usage.snapshots, SCREEN);
+    assert_eq!(usage.buffer, SCREEN);
+    assert_eq!(usage.limit, Some(SCREEN + SCREEN / 2));
+
+    drop(snapshot);
+    assert_eq!(handle.memory_usage().snapshots, 0);
+    assert!(handle.snapshot(None).is_ok());
+}
+
+// The history makes room for itself, but never at the snapshots' expense
+#[test]
+fn history_and_snapshots_share_the_budget() {
+    let Some(_server) = common::server(32, 32) else {
+        return;
+    };
+
+    let budget = MemoryBudget::bytes(3 * SCREEN);
+    let handle = open(&budget);
+    handle.set_history_depth(10);
+    let _snapshot = handle.snapshot(None).unwrap();
+    for _ in 0..5 {
+        handle.push_history().unwrap();
+    }
+
+    let usage = handle.memory_usage();
+    assert_eq!(usage.snapshots, SCREEN);
+    assert_eq!(usage.history, 2 * SCREEN);
+    assert_eq!(handle.history_len(), 2);
+    assert_eq!(usage.available(), Some(0));
+    assert_eq!(budget.report(), shade::MemoryReport { buffer: 0, ..usage });
+}
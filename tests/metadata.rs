@@ -0,0 +1,57 @@
+#![cfg(feature = "x11")]
+
+mod common;
+
+use shade::{BackgroundHandle, LoadConfig, OpenMethod, Pixel, ScalingMethod, WallpaperSource};
+
+#[test]
+fn wallpapers_are_tagged_once_flushed() {
+    let Some(_server) = common::server(16, 8) else {
+        return;
+    };
+
+    let handle =
+        BackgroundHandle::open(OpenMethod::MakeNew, LoadConfig::default()).expect("failed to load");
+    handle
+        .set_source(
+            &WallpaperSource::Color(Pixel::new(1, 2, 3)),
+            ScalingMethod::Center,
+        )
+        .unwrap();
+    // Set but not up yet
+    assert_eq!(shade::current_metadata().unwrap(), None);
+
+    handle.flush().unwrap();
+    let metadata = shade::current_metadata().unwrap().expect("no metadata");
+    assert_eq!(metadata.path, None);
+    assert_eq!(
+        metadata.scaling.map(|s| s.method),
+        Some(ScalingMethod::Center)
+    );
+    assert!(metadata.set_at.is_some());
+
+    handle.clear_metadata().unwrap();
+    assert_eq!(shade::current_metadata().unwrap(), None);
+}
+
+#[test]
+fn untagged_handles_leave_the_root_alone() {
+    let Some(_server) = common::server(16, 8) else {
+        return;
+    };
+
+    let handle = BackgroundHandle::open(
+        OpenMethod::MakeNew,
+        LoadConfig::default().tag_metadata(false),
+    )
+    .expect("failed to load");
+    handle
+        .set_source(
+            &WallpaperSource::Color(Pixel::new(1, 2, 3)),
+            ScalingMethod::Fill,
+        )
+        .unwrap();
+    handle.flush().unwrap();
+
+    assert_eq!(shade::current_metadata().unwrap(), None);
+}
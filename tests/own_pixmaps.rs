@@ -0,0 +1,35 @@
+#![cfg(feature = "x11")]
+
+mod common;
+
+use shade::{plan_with, BackgroundHandle, LoadConfig, OpenMethod};
+
+fn open(config: LoadConfig) -> BackgroundHandle {
+    BackgroundHandle::open(OpenMethod::MakeNew, config).expect("failed to load")
+}
+
+// Killing the owner of the first pixmap would take down the first handle's connection
+#[test]
+fn loading_twice_spares_the_first_handle() {
+    let Some(_server) = common::server(32, 32) else {
+        return;
+    };
+
+    let first = open(LoadConfig::default());
+    let first_pixmap = common::root_pixmap("_XROOTPMAP_ID").unwrap();
+
+    // The first handle still owns the wallpaper, hence forcing
+    let plan = plan_with(&OpenMethod::MakeNew, &LoadConfig::default().force(true)).unwrap();
+    assert!(plan.kill.is_empty());
+
+    let second = open(LoadConfig::default().force(true));
+    let report = second.load_report();
+    assert_eq!(report.spared, [first_pixmap]);
+    assert!(report.killed.is_empty());
+    assert_ne!(common::root_pixmap("_XROOTPMAP_ID"), Some(first_pixmap));
+
+    first
+        .flush()
+        .expect("the first handle's connection was killed");
+    second.flush().expect("failed to flush");
+}
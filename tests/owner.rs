@@ -0,0 +1,76 @@
+#![cfg(feature = "x11")]
+
+mod common;
+
+use std::mem;
+
+use shade::{BackgroundHandle, LoadConfig, OpenMethod};
+use xcb::x;
+
+fn open(config: LoadConfig) -> BackgroundHandle {
+    BackgroundHandle::open(OpenMethod::MakeNew, config).expect("failed to load")
+}
+
+// The marker windows on the root, which claim makes 1x1 InputOnly at -1, -1
+fn markers() -> usize {
+    let (connection, screen) = xcb::Connection::connect(None).unwrap();
+    let root = connection
+        .get_setup()
+        .roots()
+        .nth(screen as usize)
+        .unwrap()
+        .root();
+    let tree = connection
+        .wait_for_reply(connection.send_request(&x::QueryTree { window: root }))
+        .unwrap();
+
+    tree.children()
+        .iter()
+        .filter(|&&window| {
+            let geometry = connection.wait_for_reply(connection.send_request(&x::GetGeometry {
+                drawable: x::Drawable::Window(window),
+            }));
+            let attributes = connection
+                .wait_for_reply(connection.send_request(&x::GetWindowAttributes { window }));
+            matches!(
+                (geometry, attributes),
+                (Ok(g), Ok(a)) if (g.x(), g.y(), g.width(), g.height()) == (-1, -1, 1, 1)
+                    && a.class() == x::WindowClass::InputOnly
+            )
+        })
+        .count()
+}
+
+#[test]
+fn dropping_the_handle_releases_its_marker() {
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    let handle = open(LoadConfig::default());
+    assert_eq!(markers(), 1);
+    drop(handle);
+    assert_eq!(markers(), 0);
+
+    // Nothing owns the wallpaper any more, loading needs no force
+    for _ in 0..3 {
+        drop(open(LoadConfig::default()));
+    }
+    assert_eq!(markers(), 0);
+}
+
+#[test]
+fn claiming_destroys_a_leftover_marker() {
+    let Some(_server) = common::server(64, 64) else {
+        return;
+    };
+
+    // As if its owner had exited without dropping it, the window stays
+    mem::forget(open(LoadConfig::default()));
+    assert_eq!(markers(), 1);
+
+    let handle = open(LoadConfig::default().take_over_from_shade(true));
+    assert_eq!(markers(), 1);
+    drop(handle);
+    assert_eq!(markers(), 0);
+}
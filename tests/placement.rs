@@ -0,0 +1,82 @@
+#![cfg(feature = "x11")]
+
+mod common;
+
+use image::{DynamicImage, Rgb, RgbImage};
+use shade::{
+    BackgroundHandle, LoadConfig, OpenMethod, Pixel, Rect, ScalingMethod, WallpaperSource,
+};
+
+fn open() -> BackgroundHandle {
+    BackgroundHandle::open(OpenMethod::MakeNew, LoadConfig::default()).expect("failed to load")
+}
+
+fn white(width: u32, height: u32) -> WallpaperSource {
+    WallpaperSource::Image(DynamicImage::ImageRgb8(RgbImage::from_pixel(
+        width,
+        height,
+        Rgb([255, 255, 255]),
+    )))
+}
+
+// The buffer is white exactly within `target`, black everywhere else in `area`
+fn assert_drawn_within(handle: &BackgroundHandle, area: Rect, target: Rect) {
+    let buffer = handle.buffer.lock().unwrap();
+    for y in area.y..area.y + area.height as i32 {
+        for x in area.x..area.x + area.width as i32 {
+            let inside = x >= target.x
+                && x < target.x + target.width as i32
+                && y >= target.y
+                && y < target.y + target.height as i32;
+            let pixel = buffer.get_pixel(x as u32, y as u32).unwrap();
+            assert_eq!(
+                *pixel == Pixel::new(255, 255, 255),
+                inside,
+                "({x}, {y}) against {target:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn placements_match_the_buffer() {
+    let Some(_server) = common::server(64, 48) else {
+        return;
+    };
+
+    let handle = open();
+    let screen = Rect::new(0, 0, 64, 48);
+    for ((width, height), method) in [
+        ((40, 10), ScalingMethod::Max),
+        ((10, 40), ScalingMethod::Max),
+        ((20, 30), ScalingMethod::Center),
+        ((100, 100), ScalingMethod::Center),
+    ] {
+        handle.set_source(&white(width, height), method).unwrap();
+        let placement = handle.placement_for(width, height, method);
+        assert_drawn_within(&handle, screen, placement.target);
+    }
+}
+
+#[test]
+fn monitor_placements_match_the_buffer() {
+    let Some(_server) = common::server(64, 48) else {
+        return;
+    };
+
+    let path = std::env::temp_dir().join(format!("shade-placement-{}.png", std::process::id()));
+    RgbImage::from_pixel(30, 10, Rgb([255, 255, 255]))
+        .save(&path)
+        .unwrap();
+
+    let handle = open();
+    for monitor in handle.monitors().unwrap() {
+        handle.buffer.lock().unwrap().fill(Pixel::new(0, 0, 0));
+        handle
+            .set_monitor_image(&monitor, &path, ScalingMethod::Max)
+            .unwrap();
+        let placement = handle.placement_on(&monitor, 30, 10, ScalingMethod::Max);
+        assert_drawn_within(&handle, monitor.rect(), placement.target);
+    }
+    let _ = std::fs::remove_file(path);
+}
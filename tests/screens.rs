@@ -0,0 +1,67 @@
+#![cfg(feature = "x11")]
+
+mod common;
+
+use shade::{screens, BackgroundHandle, Error, LoadConfig, OpenMethod};
+
+const SIZES: [(u16, u16); 2] = [(64, 48), (40, 30)];
+
+// Only a fresh Xvfb is known to have both screens
+fn two_screens() -> Option<common::Server> {
+    let server = common::server_with_screens(&SIZES)?;
+    if screens().unwrap().len() != SIZES.len() {
+        eprintln!("skipped: the display does not have exactly two screens");
+        return None;
+    }
+    Some(server)
+}
+
+fn open(config: LoadConfig) -> shade::Result<BackgroundHandle> {
+    BackgroundHandle::open(OpenMethod::MakeNew, config)
+}
+
+#[test]
+fn screens_are_listed_in_order() {
+    let Some(_server) = two_screens() else {
+        return;
+    };
+
+    let screens = screens().unwrap();
+    let listed: Vec<_> = screens
+        .iter()
+        .map(|s| (s.number, s.width, s.height, s.depth, s.default))
+        .collect();
+    assert_eq!(listed, [(0, 64, 48, 24, true), (1, 40, 30, 24, false)]);
+    assert_ne!(screens[0].root, screens[1].root);
+}
+
+#[test]
+fn loading_on_a_chosen_screen() {
+    let Some(_server) = two_screens() else {
+        return;
+    };
+
+    let handle = open(LoadConfig::default().screen(1)).expect("failed to load");
+    assert_eq!(handle.buffer.lock().unwrap().len(), 40 * 30);
+    handle.flush().expect("failed to flush");
+
+    // Only the chosen screen's root got the wallpaper
+    assert!(common::root_pixmap_on(Some(1), "_XROOTPMAP_ID").is_some());
+    assert_eq!(common::root_pixmap_on(Some(0), "_XROOTPMAP_ID"), None);
+}
+
+#[test]
+fn screens_out_of_range_are_refused() {
+    let Some(_server) = two_screens() else {
+        return;
+    };
+
+    for screen in [2, -1, i32::MAX] {
+        assert!(matches!(
+            open(LoadConfig::default().screen(screen)),
+            Err(Error::ScreenOutOfRange { screen: s, count: 2 }) if s == screen
+        ));
+    }
+    // Refusing left nothing behind on the default screen either
+    assert_eq!(common::root_pixmap("_XROOTPMAP_ID"), None);
+}